@@ -1,6 +1,36 @@
 import { Component } from 'preact';
 import moment from 'moment';
 
+// Re-encode a base64 field as space-separated hex bytes
+function base64ToHex(value) {
+  try {
+    return Array.from(atob(value), c => c.charCodeAt(0).toString(16).padStart(2, '0')).join(' ');
+  } catch (e) {
+    return value;
+  }
+}
+
+// A binary field that can be shown in its original encoding or as hex
+class BinaryValue extends Component {
+  constructor (props) {
+    super(props)
+    this.state = { hex: false };
+  }
+
+  render() {
+    const { value, binary } = this.props;
+    const { hex } = this.state;
+    return (
+      <span class='binary' title={binary.length + ' bytes'}>
+        {hex ? base64ToHex(value) : value}
+        <button class='binary-toggle' onClick={() => this.setState({ hex: !hex })}>
+          {hex ? binary.encoding : 'hex'}
+        </button>
+      </span>
+    );
+  }
+}
+
 class LogStream extends Component {
   constructor (props) {
     super(props)
@@ -91,7 +121,7 @@ class LogStream extends Component {
                           <label title={key}>
                             <i style={{backgroundColor: item.color}} />
                           </label>
-                          {item.value}
+                          {item.binary ? <BinaryValue value={item.value} binary={item.binary} /> : item.value}
                         </li>
                       ))}
                   </ul>
@@ -0,0 +1,247 @@
+use crate::channel_manager::ChannelManager;
+use crate::models::{CloseEvent, CloseReason, SseEvent};
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{sse, IntoResponse, Response, Sse},
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+
+/// How often per-bucket event rates are sampled
+const RATE_SAMPLE_SECS: u64 = 5;
+
+/// Something that happened on the instance, as seen by operators
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum AdminEvent {
+    ChannelCreated {
+        bucket: String,
+    },
+    Suspension {
+        bucket: String,
+        suspended: bool,
+    },
+    Subscribers {
+        bucket: String,
+        change: SubscriberChange,
+        subscribers: usize,
+    },
+    /// Events per second over the last sample, for buckets that received any
+    Rates {
+        rates: BTreeMap<String, f64>,
+    },
+    /// Outcome of a garbage collection pass
+    Gc {
+        kept: usize,
+        removed: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriberChange {
+    Joined,
+    Left,
+}
+
+impl AdminEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            AdminEvent::ChannelCreated { .. } => "channel_created",
+            AdminEvent::Suspension { .. } => "suspension",
+            AdminEvent::Subscribers { .. } => "subscribers",
+            AdminEvent::Rates { .. } => "rates",
+            AdminEvent::Gc { .. } => "gc",
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BucketSummary {
+    bucket: String,
+    subscribers: usize,
+    suspended: bool,
+}
+
+/// A server's activity, fanned out to its admin overview streams
+#[derive(Clone)]
+pub struct Overview {
+    sender: broadcast::Sender<SseEvent>,
+}
+
+impl Default for Overview {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(256).0,
+        }
+    }
+}
+
+impl Overview {
+    /// Send an event to any admin overview streams
+    pub fn publish(&self, event: AdminEvent) {
+        // Skip serializing when nobody is watching
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.sender.send(SseEvent {
+            event_type: event.event_type().to_string(),
+            data: serde_json::to_string(&event).unwrap(),
+            seq: None,
+        });
+    }
+
+    /// End every admin overview stream with a `close` event
+    pub fn close_subscribers(&self, reason: CloseReason) {
+        let _ = self.sender.send(SseEvent {
+            event_type: "close".to_string(),
+            data: serde_json::to_string(&CloseEvent { reason }).unwrap(),
+            seq: None,
+        });
+    }
+}
+
+/// Publish per-bucket event rates every few seconds, for as long as the task runs
+pub async fn run_rate_sampler(channel_manager: Arc<RwLock<ChannelManager>>, overview: Overview) {
+    let mut previous: HashMap<String, u64> = HashMap::new();
+    loop {
+        tokio::time::sleep(Duration::from_secs(RATE_SAMPLE_SECS)).await;
+        let current: HashMap<String, u64> = channel_manager
+            .read()
+            .await
+            .channels()
+            .iter()
+            .map(|channel| (channel.name().to_string(), channel.last_seq()))
+            .collect();
+        let rates = sample_rates(&previous, &current, RATE_SAMPLE_SECS);
+        if !rates.is_empty() {
+            overview.publish(AdminEvent::Rates { rates });
+        }
+        previous = current;
+    }
+}
+
+/// Events per second for each bucket whose last sequence number moved between samples
+fn sample_rates(
+    previous: &HashMap<String, u64>,
+    current: &HashMap<String, u64>,
+    interval_secs: u64,
+) -> BTreeMap<String, f64> {
+    current
+        .iter()
+        .filter_map(|(bucket, &seq)| {
+            // New buckets have no baseline yet
+            let published = seq.saturating_sub(*previous.get(bucket)?);
+            (published > 0).then(|| (bucket.clone(), published as f64 / interval_secs as f64))
+        })
+        .collect()
+}
+
+fn is_admin(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(token) = &state.config.admin_token else {
+        return false;
+    };
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| presented.trim() == token.expose())
+}
+
+/// Stream server-wide activity, starting with a snapshot of every bucket
+pub async fn get_overview(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if state.config.admin_token.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if !is_admin(&state, &headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    // Subscribe before taking the snapshot so nothing falls between the two
+    let mut receiver = state.overview.sender.subscribe();
+    let buckets: Vec<BucketSummary> = state
+        .channel_manager
+        .read()
+        .await
+        .channels()
+        .iter()
+        .map(|channel| BucketSummary {
+            bucket: channel.name().to_string(),
+            subscribers: channel.subscriber_count(),
+            suspended: channel.is_suspended(),
+        })
+        .collect();
+    let snapshot = SseEvent {
+        event_type: "snapshot".to_string(),
+        data: serde_json::to_string(&buckets).unwrap(),
+        seq: None,
+    };
+
+    let stream = async_stream::stream! {
+        yield snapshot;
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.event_type == "close" => {
+                    yield event;
+                    break;
+                }
+                Ok(event) => yield event,
+                // An operator view can afford to skip a few events under load
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+    let stream = stream.map(|event| -> Result<sse::Event, Infallible> {
+        Ok(sse::Event::default()
+            .event(&event.event_type)
+            .data(event.data))
+    });
+
+    let (mut parts, body) = Sse::new(stream)
+        .keep_alive(sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+        .into_parts();
+    parts
+        .headers
+        .extend(state.config.proxy_profile.stream_headers());
+    Response::from_parts(parts, body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_rates() {
+        let previous = HashMap::from([("a".to_string(), 10), ("b".to_string(), 4)]);
+        let current = HashMap::from([
+            ("a".to_string(), 20),
+            ("b".to_string(), 4),
+            ("c".to_string(), 7),
+        ]);
+        let rates = sample_rates(&previous, &current, 5);
+        assert_eq!(rates, BTreeMap::from([("a".to_string(), 2.0)]));
+    }
+
+    #[test]
+    fn test_event_shape() {
+        let event = AdminEvent::Subscribers {
+            bucket: "my-bucket".to_string(),
+            change: SubscriberChange::Joined,
+            subscribers: 3,
+        };
+        assert_eq!(event.event_type(), "subscribers");
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"bucket":"my-bucket","change":"joined","subscribers":3}"#
+        );
+    }
+}
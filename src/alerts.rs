@@ -0,0 +1,348 @@
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::models::LogEvent;
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use tracing::info;
+
+const MAX_ALERT_RULES_PER_BUCKET: usize = 10;
+const MAX_ALERT_SAMPLES: usize = 3;
+
+fn default_threshold() -> usize {
+    1
+}
+
+fn default_window_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    #[default]
+    Warning,
+    /// Critical rules also page via PagerDuty when configured
+    Critical,
+}
+
+/// What a rule watches for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    /// Fire when at least `threshold` matching events arrive within the window
+    #[default]
+    Match,
+    /// Fire when fewer than `threshold` matching events arrive within the window, e.g.
+    /// because a producer has died
+    Heartbeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    /// Field to compare against `value`; when unset, `value` is matched against the raw line
+    #[serde(default)]
+    pub field: Option<String>,
+    /// Value to look for; heartbeats without one count every event
+    #[serde(default)]
+    pub value: String,
+    #[serde(default)]
+    pub kind: AlertKind,
+    /// Number of matching events within the window needed to fire
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub severity: AlertSeverity,
+}
+
+impl AlertRule {
+    fn matches(&self, event: &LogEvent) -> bool {
+        match &self.field {
+            Some(field) => event
+                .fields
+                .get(field)
+                .is_some_and(|data| data.value == self.value),
+            None => event.raw.contains(&self.value),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AlertRuleList {
+    pub rules: Vec<AlertRule>,
+}
+
+/// A rule that has just started firing or has cleared
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub kind: AlertKind,
+    pub status: AlertStatus,
+    pub severity: AlertSeverity,
+    pub count: usize,
+    #[serde(rename = "windowSecs")]
+    pub window_secs: u64,
+    pub samples: Vec<String>,
+}
+
+/// Sliding-window evaluation state for a single rule
+pub struct AlertState {
+    rule: AlertRule,
+    hits: VecDeque<i64>,
+    samples: VecDeque<String>,
+    firing: bool,
+    /// When the rule was first evaluated; heartbeats wait a full window before firing
+    armed_at: Option<i64>,
+}
+
+impl AlertState {
+    pub fn new(rule: AlertRule) -> Self {
+        Self {
+            rule,
+            hits: VecDeque::new(),
+            samples: VecDeque::new(),
+            firing: false,
+            armed_at: None,
+        }
+    }
+
+    pub fn rule(&self) -> &AlertRule {
+        &self.rule
+    }
+
+    /// Drop sample lines that mention `value`
+    pub fn forget_samples(&mut self, value: &str) {
+        self.samples.retain(|sample| !sample.contains(value));
+    }
+
+    /// Record an event against the rule, returning an alert if the rule changed state
+    pub fn record(&mut self, event: &LogEvent) -> Option<AlertEvent> {
+        if self.rule.matches(event) {
+            self.hits.push_back(event.time);
+            self.samples.push_back(event.raw.clone());
+            if self.samples.len() > MAX_ALERT_SAMPLES {
+                self.samples.pop_front();
+            }
+        }
+
+        self.evaluate(event.time)
+    }
+
+    /// Re-evaluate the window at the given time (epoch ms), returning an alert if the rule changed state
+    pub fn evaluate(&mut self, now: i64) -> Option<AlertEvent> {
+        // Drop hits that have fallen out of the window
+        let window_ms = (self.rule.window_secs * 1000) as i64;
+        let cutoff = now - window_ms;
+        while self.hits.front().is_some_and(|&time| time <= cutoff) {
+            self.hits.pop_front();
+        }
+
+        let armed_at = *self.armed_at.get_or_insert(now);
+        let above_threshold = self.hits.len() >= self.rule.threshold;
+        let firing = match self.rule.kind {
+            AlertKind::Match => above_threshold,
+            AlertKind::Heartbeat => !above_threshold && now - armed_at >= window_ms,
+        };
+        if firing == self.firing {
+            return None;
+        }
+
+        self.firing = firing;
+        Some(AlertEvent {
+            rule: self.rule.name.clone(),
+            kind: self.rule.kind,
+            status: if self.firing {
+                AlertStatus::Firing
+            } else {
+                AlertStatus::Resolved
+            },
+            severity: self.rule.severity,
+            count: self.hits.len(),
+            window_secs: self.rule.window_secs,
+            samples: self.samples.iter().cloned().collect(),
+        })
+    }
+}
+
+pub async fn get_alerts(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AlertRuleList>, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let rules = match channel {
+        Some(channel) => channel.alert_rules().await,
+        None => Vec::new(),
+    };
+
+    Ok(Json(AlertRuleList { rules }))
+}
+
+/// Check a bucket's alert rules before they replace its current ones
+pub fn validate_alert_rules(rules: &[AlertRule]) -> Result<(), String> {
+    if rules.len() > MAX_ALERT_RULES_PER_BUCKET {
+        return Err(format!(
+            "A bucket can have at most {} alert rules",
+            MAX_ALERT_RULES_PER_BUCKET
+        ));
+    }
+
+    if rules
+        .iter()
+        .any(|rule| rule.threshold == 0 || rule.window_secs == 0)
+    {
+        return Err("Alert rules need a non-zero threshold and window".to_string());
+    }
+
+    if rules
+        .iter()
+        .any(|rule| rule.kind == AlertKind::Match && rule.value.is_empty())
+    {
+        return Err("Match rules need a value".to_string());
+    }
+
+    Ok(())
+}
+
+/// Replace the set of alert rules for a bucket
+pub async fn put_alerts(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(list): Json<AlertRuleList>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if let Err(message) = validate_alert_rules(&list.rules) {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!(
+        "Registered {} alert rules for bucket {}",
+        list.rules.len(),
+        bucket_id
+    );
+    let previous = channel.set_alert_rules(list.rules.clone()).await;
+    channel.publish_config_change(
+        "alerts",
+        &previous,
+        &list.rules,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn event(raw: &str, time: i64) -> LogEvent {
+        LogEvent {
+            seq: 0,
+            time,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: raw.to_string(),
+            raw_ansi: None,
+            fields: HashMap::new(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[test]
+    fn test_alert_fires_once_per_window() {
+        let mut state = AlertState::new(AlertRule {
+            name: "errors".to_string(),
+            field: None,
+            value: "ERROR".to_string(),
+            kind: AlertKind::Match,
+            threshold: 2,
+            window_secs: 10,
+            severity: AlertSeverity::Critical,
+        });
+
+        assert!(state.record(&event("ERROR one", 0)).is_none());
+        assert!(state.record(&event("all good", 1_000)).is_none());
+
+        let alert = state.record(&event("ERROR two", 2_000)).unwrap();
+        assert_eq!(alert.status, AlertStatus::Firing);
+        assert_eq!(alert.count, 2);
+        assert_eq!(alert.samples, vec!["ERROR one", "ERROR two"]);
+
+        // Still firing, so no repeat notification
+        assert!(state.record(&event("ERROR three", 3_000)).is_none());
+
+        // Window has passed, rule resolves and can fire again
+        let alert = state.evaluate(30_000).unwrap();
+        assert_eq!(alert.status, AlertStatus::Resolved);
+        assert!(state.evaluate(30_500).is_none());
+        assert!(state.record(&event("ERROR four", 31_000)).is_none());
+        assert!(state.record(&event("ERROR five", 32_000)).is_some());
+    }
+
+    #[test]
+    fn test_heartbeat_fires_when_quiet() {
+        let mut state = AlertState::new(AlertRule {
+            name: "producer".to_string(),
+            field: None,
+            value: String::new(),
+            kind: AlertKind::Heartbeat,
+            threshold: 1,
+            window_secs: 10,
+            severity: AlertSeverity::Warning,
+        });
+
+        // A new rule gets a full window before it can fire
+        assert!(state.evaluate(0).is_none());
+        assert!(state.evaluate(5_000).is_none());
+        assert!(state.record(&event("tick", 6_000)).is_none());
+        assert!(state.evaluate(15_000).is_none());
+
+        let alert = state.evaluate(16_000).unwrap();
+        assert_eq!(alert.status, AlertStatus::Firing);
+        assert_eq!(alert.kind, AlertKind::Heartbeat);
+        assert!(state.evaluate(20_000).is_none());
+
+        let alert = state.record(&event("tick", 21_000)).unwrap();
+        assert_eq!(alert.status, AlertStatus::Resolved);
+    }
+}
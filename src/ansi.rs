@@ -0,0 +1,159 @@
+//! ANSI escape sequences in lines piped from terminal programs, which color their output
+
+const ESC: char = '\u{1b}';
+const BEL: char = '\u{7}';
+
+/// Foreground color in effect
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    Default,
+    /// Red or yellow, and the level it suggests
+    Level(&'static str),
+    Other,
+}
+
+/// A line with its escape sequences taken out
+#[derive(Debug, PartialEq)]
+pub struct Stripped {
+    pub text: String,
+    /// What the color of the first colored text suggests: red for errors, yellow for
+    /// warnings
+    pub level: Option<&'static str>,
+}
+
+/// Remove escape sequences from a line, or `None` if it has none
+pub fn strip(input: &str) -> Option<Stripped> {
+    if !input.contains(ESC) {
+        return None;
+    }
+
+    let mut text = String::with_capacity(input.len());
+    let mut first_color = None;
+    let mut color = Color::Default;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            if !c.is_whitespace() && color != Color::Default {
+                first_color.get_or_insert(color);
+            }
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // Control sequence: parameters, then intermediates, then one final byte
+            Some('[') => {
+                let mut params = String::new();
+                while let Some(c) = chars.next_if(|c| ('\u{30}'..='\u{3f}').contains(c)) {
+                    params.push(c);
+                }
+                while chars
+                    .next_if(|c| ('\u{20}'..='\u{2f}').contains(c))
+                    .is_some()
+                {}
+                if chars.next() == Some('m') {
+                    color = sgr_color(&params, color);
+                }
+            }
+            // Operating system command, such as a window title or hyperlink, up to BEL or ST
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == BEL || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // Character set designations and the like have an intermediate byte first
+            Some(c) if ('\u{20}'..='\u{2f}').contains(&c) => {
+                chars.next();
+            }
+            // Any other escape is two characters long
+            _ => {}
+        }
+    }
+
+    let level = match first_color {
+        Some(Color::Level(level)) => Some(level),
+        _ => None,
+    };
+    Some(Stripped { text, level })
+}
+
+/// The foreground color after a Select Graphic Rendition sequence, starting from the one
+/// already in effect
+fn sgr_color(params: &str, mut color: Color) -> Color {
+    let mut codes = params
+        .split([';', ':'])
+        .map(|code| code.parse::<u8>().unwrap_or(0));
+    while let Some(code) = codes.next() {
+        color = match code {
+            0 | 39 => Color::Default,
+            31 | 91 => Color::Level("error"),
+            33 | 93 => Color::Level("warn"),
+            30..=37 | 90..=97 => Color::Other,
+            // 256-color palette entries share the basic colors' numbers; true color is
+            // left unread
+            38 => match codes.next() {
+                Some(5) => match codes.next() {
+                    Some(1 | 9) => Color::Level("error"),
+                    Some(3 | 11) => Color::Level("warn"),
+                    _ => Color::Other,
+                },
+                Some(2) => {
+                    codes.nth(2);
+                    Color::Other
+                }
+                _ => color,
+            },
+            // Background colors take the same extra codes
+            48 => {
+                match codes.next() {
+                    Some(5) => {
+                        codes.next();
+                    }
+                    Some(2) => {
+                        codes.nth(2);
+                    }
+                    _ => {}
+                }
+                color
+            }
+            _ => color,
+        };
+    }
+    color
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip() {
+        assert_eq!(strip("plain"), None);
+
+        let stripped =
+            strip("\u{1b}[2m12:00:00\u{1b}[0m \u{1b}[1;31mERROR\u{1b}[0m disk full").unwrap();
+        assert_eq!(stripped.text, "12:00:00 ERROR disk full");
+        assert_eq!(stripped.level, Some("error"));
+
+        let stripped = strip("\u{1b}[38;5;11mWARN\u{1b}[39m slow \u{1b}[31mquery").unwrap();
+        assert_eq!(stripped.text, "WARN slow query");
+        assert_eq!(stripped.level, Some("warn"));
+
+        let stripped = strip("\u{1b}[32mINFO\u{1b}[0m started \u{1b}[31m").unwrap();
+        assert_eq!(stripped.text, "INFO started ");
+        assert_eq!(stripped.level, None);
+    }
+
+    #[test]
+    fn test_other_sequences() {
+        let stripped =
+            strip("\u{1b}]8;;https://example.com\u{7}link\u{1b}]8;;\u{1b}\\ \u{1b}[2K\u{1b}(Bdone")
+                .unwrap();
+        assert_eq!(stripped.text, "link done");
+
+        // A sequence cut off by truncation just ends the line
+        assert_eq!(strip("tail \u{1b}[3").unwrap().text, "tail ");
+    }
+}
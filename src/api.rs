@@ -0,0 +1,89 @@
+use crate::ids;
+use axum::{
+    extract::{Path, Request},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+
+/// Versioned home of the per-bucket API
+pub const BUCKETS_PREFIX: &str = "/api/v1/buckets";
+
+/// Write token usage, for instances with `TOKENS_FILE` set
+pub const TOKENS_PREFIX: &str = "/api/v1/tokens";
+
+/// WebSocket that multiplexes several bucket streams
+pub const STREAM_PATH: &str = "/api/v1/stream";
+
+/// Buckets associated with the token a request presents
+pub const MY_BUCKETS_PATH: &str = "/api/v1/my/buckets";
+
+/// Operator endpoints, for instances with `ADMIN_TOKEN` set
+pub const ADMIN_PREFIX: &str = "/api/v1/admin";
+
+/// Split a request path into its bucket ID and the sub-route under it, for both the
+/// versioned and the legacy unversioned layout
+pub fn bucket_path(path: &str) -> Option<(&str, &str)> {
+    let path = path.strip_prefix(BUCKETS_PREFIX).unwrap_or(path);
+    let path = path.strip_prefix('/')?;
+    match path.split_once('/') {
+        Some((bucket_id, rest)) => Some((bucket_id, rest)),
+        None => Some((path, "")),
+    }
+}
+
+/// Keep reserved names from ever being used as buckets, on any bucket route
+pub async fn reject_reserved(
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if params
+        .get("bucket_id")
+        .is_some_and(|bucket_id| ids::is_reserved(bucket_id))
+    {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Point callers of the unversioned bucket API at its replacement
+pub async fn mark_deprecated(
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Nested routes only see the path below the bucket
+    let bucket_id = params.get("bucket_id").map_or("", String::as_str);
+    let successor = format!("{}/{}{}", BUCKETS_PREFIX, bucket_id, request.uri().path());
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert("Deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&format!("<{}>; rel=\"successor-version\"", successor))
+    {
+        headers.insert("Link", link);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_path() {
+        assert_eq!(bucket_path("/my-bucket"), Some(("my-bucket", "")));
+        assert_eq!(
+            bucket_path("/my-bucket/integrations/slack"),
+            Some(("my-bucket", "integrations/slack"))
+        );
+        assert_eq!(
+            bucket_path("/api/v1/buckets/my-bucket/webhooks"),
+            Some(("my-bucket", "webhooks"))
+        );
+    }
+}
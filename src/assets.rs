@@ -0,0 +1,99 @@
+use axum::{
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+use std::borrow::Cow;
+
+/// The built viewer, embedded so the binary runs from any directory
+#[derive(RustEmbed)]
+#[folder = "client/dist/"]
+struct Dist;
+
+pub fn index_html() -> Cow<'static, str> {
+    match Dist::get("index.html")
+        .expect("client/dist/index.html is built")
+        .data
+    {
+        Cow::Borrowed(bytes) => String::from_utf8_lossy(bytes),
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
+
+/// Serve a file from `client/dist/assets`, preferring a precompressed `.gz` variant
+pub async fn serve_asset(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    let path = format!("assets/{}", path);
+    let Some(file) = Dist::get(&path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        file.metadata.mimetype().parse().unwrap(),
+    );
+    response_headers.insert(header::VARY, "Accept-Encoding".parse().unwrap());
+
+    let gzipped = accepts_gzip(&headers)
+        .then(|| Dist::get(&format!("{}.gz", path)))
+        .flatten();
+    let file = match gzipped {
+        Some(gzipped) => {
+            response_headers.insert(header::CONTENT_ENCODING, "gzip".parse().unwrap());
+            gzipped
+        }
+        None => file,
+    };
+
+    // Each encoding is its own representation, so it gets its own tag
+    let etag = format!("\"{}\"", hex(&file.metadata.sha256_hash()));
+    response_headers.insert(header::ETAG, etag.parse().unwrap());
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag))
+    {
+        return (StatusCode::NOT_MODIFIED, response_headers).into_response();
+    }
+
+    (response_headers, file.data).into_response()
+}
+
+fn accepts_gzip(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|coding| {
+                let mut parts = coding.split(';');
+                let name = parts.next().unwrap_or_default().trim();
+                let refused = parts.any(|param| param.trim().replace(' ', "") == "q=0");
+                name.eq_ignore_ascii_case("gzip") && !refused
+            })
+        })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept_encoding(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_ENCODING, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_accepts_gzip() {
+        assert!(accepts_gzip(&accept_encoding("gzip, deflate, br")));
+        assert!(accepts_gzip(&accept_encoding("br;q=1.0, GZIP;q=0.5")));
+        assert!(!accepts_gzip(&accept_encoding("gzip;q=0")));
+        assert!(!accepts_gzip(&accept_encoding("br")));
+        assert!(!accepts_gzip(&HeaderMap::new()));
+    }
+}
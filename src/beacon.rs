@@ -0,0 +1,129 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::tokens;
+use crate::{AppState, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+/// Beacons are meant for single events, so keep bodies small
+const MAX_BEACON_BODY_SIZE: usize = 64 * 1024; // 64KB
+/// Query string / form field carrying the log line
+const BEACON_MESSAGE_FIELD: &str = "m";
+
+#[derive(Debug, Deserialize)]
+pub struct BeaconParams {
+    m: Option<String>,
+}
+
+/// `GET /{bucket_id}/log?m=<line>` for clients that can only make simple requests
+pub async fn get_beacon(
+    Path(bucket_id): Path<String>,
+    Query(params): Query<BeaconParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let line = params.m.ok_or(StatusCode::BAD_REQUEST)?;
+    let size = line.len();
+    publish_beacon(&state, &bucket_id, &headers, vec![line], size).await
+}
+
+/// `navigator.sendBeacon`-friendly POST accepting a text body or `m=` form fields
+pub async fn post_beacon(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    if body.len() > MAX_BEACON_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let is_form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+
+    let lines: Vec<String> = if is_form {
+        form_urlencoded::parse(&body)
+            .filter(|(key, _)| key == BEACON_MESSAGE_FIELD)
+            .map(|(_, value)| value.into_owned())
+            .collect()
+    } else {
+        vec![String::from_utf8(body.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)?]
+    };
+
+    publish_beacon(&state, &bucket_id, &headers, lines, body.len()).await
+}
+
+async fn publish_beacon(
+    state: &AppState,
+    bucket_id: &str,
+    headers: &HeaderMap,
+    lines: Vec<String>,
+    size: usize,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let token = match tokens::authorize(state, headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let lines: Vec<&str> = lines
+        .iter()
+        .flat_map(|line| line.split('\n'))
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Err(rejection) = check_event_count(state, lines.len(), "send one event per beacon") {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+
+    // Beacons must never be cached, otherwise repeated events would be swallowed
+    let no_store = [(header::CACHE_CONTROL, "no-store")];
+
+    let Some(channel) = channel else {
+        warn!(
+            "Discarding beacon for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok((StatusCode::NO_CONTENT, no_store).into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    info!(
+        "New beacon for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+
+    if let Some(token) = &token {
+        token.record(lines.len() as u64, size as u64, chrono::Utc::now());
+    }
+
+    match ingest_lines(state, &channel, &lines).await {
+        IngestOutcome::Accepted => Ok((StatusCode::NO_CONTENT, no_store).into_response()),
+        IngestOutcome::Suspended => {
+            Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+        }
+        IngestOutcome::Paused => Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response()),
+    }
+}
@@ -0,0 +1,255 @@
+use crate::compression::{gunzip, is_gzip, DecompressError};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::{tokens, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tracing::{info, warn};
+
+/// What Elasticsearch reports for one action in a bulk request
+#[derive(Debug, Serialize)]
+struct ItemResult {
+    #[serde(rename = "_index")]
+    index: String,
+    #[serde(rename = "_id")]
+    id: String,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ItemError>,
+}
+
+#[derive(Debug, Serialize)]
+struct ItemError {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    reason: String,
+}
+
+/// The documents in a bulk body, and the per-action results shippers check for failures
+#[derive(Debug, Default)]
+struct Bulk {
+    documents: Vec<String>,
+    items: Vec<BTreeMap<&'static str, ItemResult>>,
+    errors: bool,
+}
+
+#[derive(Serialize)]
+struct BulkResponse {
+    took: u128,
+    errors: bool,
+    items: Vec<BTreeMap<&'static str, ItemResult>>,
+}
+
+/// Decode a bulk body of action lines, each followed by a document unless it is a delete.
+/// The action metadata only names the index and ID to echo back; every document is kept,
+/// with updates contributing their partial `doc`.
+fn decode(body: &str, bucket_id: &str) -> Result<Bulk, &'static str> {
+    let mut bulk = Bulk::default();
+    let mut lines = body.lines().filter(|line| !line.trim().is_empty());
+
+    while let Some(line) = lines.next() {
+        let Ok(Value::Object(action)) = serde_json::from_str::<Value>(line) else {
+            return Err("Malformed action/metadata line, expected a JSON object");
+        };
+        let Some((name, metadata)) = action.iter().next().filter(|_| action.len() == 1) else {
+            return Err("Malformed action/metadata line, expected a single action");
+        };
+        let (action, result, status) = match name.as_str() {
+            "index" => ("index", "created", 201),
+            "create" => ("create", "created", 201),
+            "update" => ("update", "updated", 200),
+            "delete" => ("delete", "not_found", 404),
+            _ => return Err("Unknown bulk action, expected index, create, update or delete"),
+        };
+
+        let mut item = ItemResult {
+            index: metadata
+                .get("_index")
+                .and_then(Value::as_str)
+                .unwrap_or(bucket_id)
+                .to_string(),
+            id: metadata
+                .get("_id")
+                .and_then(Value::as_str)
+                .map_or_else(|| uuid::Uuid::new_v4().simple().to_string(), String::from),
+            status,
+            result: Some(result),
+            error: None,
+        };
+
+        if action != "delete" {
+            let Some(source) = lines.next() else {
+                return Err("Bulk body ended before a document line");
+            };
+            let document = match serde_json::from_str::<Value>(source) {
+                Ok(Value::Object(document)) if action == "update" => {
+                    document.get("doc").and_then(Value::as_object).cloned()
+                }
+                Ok(Value::Object(document)) => Some(document),
+                _ => {
+                    item.status = 400;
+                    item.result = None;
+                    item.error = Some(ItemError {
+                        kind: "document_parsing_exception",
+                        reason: "Document is not a JSON object".to_string(),
+                    });
+                    bulk.errors = true;
+                    None
+                }
+            };
+            match document {
+                Some(document) => bulk.documents.push(Value::Object(document).to_string()),
+                // Scripted updates have nothing to log
+                None if item.error.is_none() => item.result = Some("noop"),
+                None => {}
+            }
+        }
+
+        bulk.items.push(BTreeMap::from([(action, item)]));
+    }
+
+    Ok(bulk)
+}
+
+/// `POST /{bucket_id}/_bulk`: the Elasticsearch bulk API, as used by Filebeat and Logstash
+pub async fn post_bulk(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    let started = Instant::now();
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    // Beats and Logstash gzip bulk bodies when compression is on
+    let decompressed = if is_gzip(&body) {
+        match gunzip(&body, MAX_LOG_BODY_SIZE) {
+            Ok(decompressed) => decompressed,
+            Err(DecompressError::TooLarge) => return Err(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(DecompressError::Invalid) => {
+                return Ok((StatusCode::BAD_REQUEST, "Body is not valid gzip").into_response())
+            }
+        }
+    } else {
+        body.to_vec()
+    };
+    let Ok(text) = std::str::from_utf8(&decompressed) else {
+        return Ok((StatusCode::BAD_REQUEST, "Body is not UTF-8").into_response());
+    };
+    let bulk = match decode(text, &bucket_id) {
+        Ok(bulk) => bulk,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    if let Err(rejection) = check_event_count(&state, bulk.documents.len(), "lower the bulk size") {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    // Shippers need per-item results either way, so unviewed buckets still answer in full
+    if let Some(channel) = channel.filter(|_| !bulk.documents.is_empty()) {
+        if channel.is_suspended() {
+            return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+        }
+
+        info!(
+            "New bulk documents for bucket {}: {} events",
+            bucket_id,
+            bulk.documents.len()
+        );
+        if let Some(token) = &token {
+            token.record(
+                bulk.documents.len() as u64,
+                body.len() as u64,
+                chrono::Utc::now(),
+            );
+        }
+
+        let lines: Vec<&str> = bulk.documents.iter().map(String::as_str).collect();
+        match ingest_lines(&state, &channel, &lines).await {
+            IngestOutcome::Accepted => {}
+            IngestOutcome::Suspended => {
+                return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+            }
+            IngestOutcome::Paused => {
+                return Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response())
+            }
+        }
+    } else if !bulk.documents.is_empty() {
+        warn!(
+            "Discarding bulk documents for bucket with no viewers: {}",
+            bucket_id
+        );
+    }
+
+    Ok(Json(BulkResponse {
+        took: started.elapsed().as_millis(),
+        errors: bulk.errors,
+        items: bulk.items,
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let body = concat!(
+            "{\"index\":{\"_index\":\"filebeat-8\"}}\n",
+            "{\"@timestamp\":\"2024-01-01T00:00:00Z\",\"message\":\"hello\"}\n",
+            "{\"create\":{\"_id\":\"a1\"}}\n",
+            "not json\n",
+            "{\"delete\":{\"_id\":\"a2\"}}\n",
+            "{\"update\":{\"_id\":\"a3\"}}\n",
+            "{\"doc\":{\"status\":\"done\"}}\n",
+        );
+        let bulk = decode(body, "bucket").unwrap();
+
+        assert_eq!(bulk.documents.len(), 2);
+        assert!(bulk.documents[0].contains("hello"));
+        assert_eq!(bulk.documents[1], r#"{"status":"done"}"#);
+        assert!(bulk.errors);
+
+        let response = serde_json::to_value(&bulk.items).unwrap();
+        assert_eq!(response[0]["index"]["_index"], "filebeat-8");
+        assert_eq!(response[0]["index"]["status"], 201);
+        assert_eq!(response[1]["create"]["_index"], "bucket");
+        assert_eq!(response[1]["create"]["status"], 400);
+        assert_eq!(response[2]["delete"]["_id"], "a2");
+        assert_eq!(response[3]["update"]["result"], "updated");
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_actions() {
+        assert!(decode("{\"index\":{}}\n", "bucket").is_err());
+        assert!(decode("{\"upsert\":{}}\n{}\n", "bucket").is_err());
+        assert!(decode("[]\n", "bucket").is_err());
+    }
+}
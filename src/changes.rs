@@ -0,0 +1,204 @@
+use crate::{tokens, AppState};
+use axum::http::HeaderMap;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Keys whose values are credentials; a change to one is reported without the values
+const SECRET_KEYS: &[&str] = &["routing_key", "webhook_url", "url"];
+const REDACTED: &str = "[redacted]";
+
+/// One value that differs between two versions of a configuration
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    /// JSON Pointer (RFC 6901) to the value within the section
+    pub path: String,
+    /// Absent when the value was added
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<Value>,
+    /// Absent when the value was removed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<Value>,
+}
+
+/// A change to a bucket's configuration, sent to subscribers as a `config-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigChange {
+    pub time: i64,
+    /// Which configuration changed, such as `settings` or `webhooks`
+    pub section: &'static str,
+    /// ID of the token the change was made with, or `None` for an anonymous change
+    pub actor: Option<String>,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Who is making a configuration change, as far as log-bin can tell
+pub fn actor(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    tokens::identify(state, headers).map(|account| account.id().to_string())
+}
+
+/// A configuration as JSON with its secrets masked, for anyone who can read the bucket
+pub fn redacted<T: Serialize>(config: &T) -> Value {
+    let mut value = serde_json::to_value(config).unwrap_or(Value::Null);
+    redact(&mut value);
+    value
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) && !value.is_null() {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// Compare two versions of a configuration, object keys and array items one by one
+pub fn diff(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_into(String::new(), Some(before), Some(after), &mut changes);
+    changes
+}
+
+fn diff_into(
+    path: String,
+    before: Option<&Value>,
+    after: Option<&Value>,
+    changes: &mut Vec<FieldChange>,
+) {
+    // Objects that appear or disappear whole are reported field by field, so secrets in
+    // them are still redacted
+    let empty = Map::new();
+    let absent = |value: Option<&Value>| value.is_none_or(Value::is_null);
+    let objects = match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => Some((before, after)),
+        (Some(Value::Object(before)), after) if absent(after) => Some((before, &empty)),
+        (before, Some(Value::Object(after))) if absent(before) => Some((&empty, after)),
+        _ => None,
+    };
+    if let Some((before, after)) = objects {
+        let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let escaped = key.replace('~', "~0").replace('/', "~1");
+            diff_into(
+                format!("{}/{}", path, escaped),
+                before.get(key),
+                after.get(key),
+                changes,
+            );
+        }
+        return;
+    }
+
+    match (before, after) {
+        (Some(Value::Array(before)), Some(Value::Array(after))) => {
+            for i in 0..before.len().max(after.len()) {
+                diff_into(
+                    format!("{}/{}", path, i),
+                    before.get(i),
+                    after.get(i),
+                    changes,
+                );
+            }
+        }
+        // A null and a missing value both mean unset, as serde reads them
+        (before, after) if before != after && !(absent(before) && absent(after)) => {
+            let secret = path
+                .rsplit('/')
+                .next()
+                .is_some_and(|key| SECRET_KEYS.contains(&key));
+            let show = |value: Option<&Value>| {
+                value.map(|value| {
+                    if secret {
+                        Value::from(REDACTED)
+                    } else {
+                        value.clone()
+                    }
+                })
+            };
+            changes.push(FieldChange {
+                path,
+                before: show(before),
+                after: show(after),
+            });
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff() {
+        let before = json!({
+            "normalize_keys": false,
+            "retention": [{"severity": "debug", "max_age_secs": 60}],
+            "slack": {"webhook_url": "https://hooks.example/a"},
+        });
+        let after = json!({
+            "normalize_keys": true,
+            "retention": [
+                {"severity": "debug", "max_age_secs": 120},
+                {"severity": "info", "max_age_secs": 600},
+            ],
+            "slack": {"webhook_url": "https://hooks.example/b"},
+            "a/b": 1,
+        });
+        let changes = diff(&before, &after);
+        let paths: Vec<&str> = changes.iter().map(|change| change.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/a~1b",
+                "/normalize_keys",
+                "/retention/0/max_age_secs",
+                "/retention/1/max_age_secs",
+                "/retention/1/severity",
+                "/slack/webhook_url"
+            ]
+        );
+        assert_eq!(changes[0].before, None);
+        assert_eq!(changes[2].after, Some(json!(120)));
+        assert_eq!(changes[3].before, None);
+        assert_eq!(changes[5].before, Some(json!(REDACTED)));
+
+        assert!(diff(&before, &before).is_empty());
+
+        assert!(diff(&json!({"template": null}), &json!({})).is_empty());
+
+        let removed = diff(&before["slack"], &Value::Null);
+        assert_eq!(removed[0].path, "/webhook_url");
+        assert_eq!(removed[0].before, Some(json!(REDACTED)));
+        assert_eq!(removed[0].after, None);
+    }
+
+    #[test]
+    fn test_redacted() {
+        let config = json!({
+            "webhook_url": "https://hooks.example/a",
+            "template": "{rule}",
+            "nested": [{"routing_key": "abc"}],
+            "webhooks": [{"url": "https://hooks.example/T0/B0/secret", "events": []}],
+        });
+        assert_eq!(
+            redacted(&config),
+            json!({
+                "webhook_url": REDACTED,
+                "template": "{rule}",
+                "nested": [{"routing_key": REDACTED}],
+                "webhooks": [{"url": REDACTED, "events": []}],
+            })
+        );
+    }
+}
@@ -1,92 +1,349 @@
-use crate::models::{LogEvent, SseEvent, StatsEvent, SuspensionEvent};
-use crate::MAX_LOG_LINES_PER_MINUTE;
+use crate::admin::{AdminEvent, Overview, SubscriberChange};
+use crate::alerts::{AlertEvent, AlertKind, AlertRule, AlertSeverity, AlertState, AlertStatus};
+use crate::changes::{self, ConfigChange};
+use crate::erase::{Redaction, Tombstone};
+use crate::grok::GrokSet;
+use crate::history::{HistoryBackend, HistoryStore};
+use crate::idempotency::{IdempotencyCache, KeyStatus};
+use crate::ingest_urls::{IngestUrl, IngestUrls};
+use crate::integrations::bucket_link;
+use crate::integrations::pagerduty::{self, PagerDutyConfig};
+use crate::integrations::slack::{self, SlackConfig};
+use crate::metrics::{ParseOutcomeCounters, METRICS};
+use crate::models::{
+    CloseEvent, CloseReason, GapEvent, ImportEvent, LogEvent, PauseEvent, SseEvent, StatsEvent,
+    SuspensionEvent,
+};
+use crate::multiline::PendingEvent;
+use crate::pacing::Pacer;
+use crate::parsers::{Columns, ParseOutcome};
+use crate::pattern::BucketPattern;
+use crate::pause::{PauseMode, PauseState};
+use crate::routing::RouteRule;
+use crate::settings::BucketSettings;
+use crate::stats::{self, StatsHistory, StatsSample};
+use crate::webhooks::{self, Webhook, WebhookEvent};
+use crate::{MAX_LOG_LINES_PER_MINUTE, SUSPENSION_DURATION_SECS};
 use futures_util::stream::Stream;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
-use tokio::sync::RwLock;
-use tracing::{info, warn};
+use tokio::sync::{Mutex, MutexGuard, RwLock};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-const HISTORY_SIZE: usize = 10;
 const GC_WAIT_MS: u64 = 10000;
+/// How long a configured bucket is kept without writes or config changes once nobody watches it
+const CONFIGURED_IDLE_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+const CLOSE_EVENT_TYPE: &str = "close";
 
 /// Guard that removes a client from the clients map when dropped
 struct ClientGuard {
+    bucket: String,
     client_id: String,
     clients: Arc<RwLock<HashMap<String, ()>>>,
+    overview: Overview,
 }
 
 impl Drop for ClientGuard {
     fn drop(&mut self) {
+        let bucket = std::mem::take(&mut self.bucket);
         let client_id = self.client_id.clone();
         let clients = self.clients.clone();
+        let overview = self.overview.clone();
         tokio::spawn(async move {
-            clients.write().await.remove(&client_id);
+            let mut clients = clients.write().await;
+            clients.remove(&client_id);
             info!("Client {} disconnected and removed", client_id);
+            overview.publish(AdminEvent::Subscribers {
+                bucket,
+                change: SubscriberChange::Left,
+                subscribers: clients.len(),
+            });
         });
     }
 }
 
+/// What every bucket on a server shares
+#[derive(Clone, Default)]
+pub struct ChannelContext {
+    /// Address the server is reached at, for links to buckets in notifications
+    pub public_url: Arc<str>,
+    /// Where bucket activity is reported to operators
+    pub overview: Overview,
+}
+
 pub struct Channel {
+    name: String,
+    context: ChannelContext,
     sender: broadcast::Sender<SseEvent>,
-    history: Arc<RwLock<Vec<SseEvent>>>,
+    history: RwLock<Box<dyn HistoryStore>>,
     clients: Arc<RwLock<HashMap<String, ()>>>,
     // Rate limiting fields
     suspended: AtomicBool,
+    /// Set once the bucket is provisioned or configured, so it outlives its viewers
+    configured: AtomicBool,
+    /// When the bucket was last written to or configured, in milliseconds
+    active_at: AtomicI64,
     log_count_current_minute: AtomicU64,
     current_minute_timestamp: AtomicU64,
+    suspended_at: AtomicU64,
+    last_seq: AtomicU64,
+    /// Earliest `expires_at` in history, or `i64::MAX` when no event has a time to live
+    next_expiry: AtomicI64,
+    webhooks: RwLock<Vec<Webhook>>,
+    alerts: RwLock<Vec<AlertState>>,
+    slack: RwLock<Option<SlackConfig>>,
+    pagerduty: RwLock<Option<PagerDutyConfig>>,
+    idempotency_keys: std::sync::Mutex<IdempotencyCache>,
+    settings: RwLock<BucketSettings>,
+    parse_outcomes: ParseOutcomeCounters,
+    tombstones: RwLock<Vec<Tombstone>>,
+    stats_history: RwLock<StatsHistory>,
+    pause: RwLock<Option<PauseState>>,
+    ingest_urls: RwLock<IngestUrls>,
+    routes: RwLock<Vec<RouteRule>>,
+    grok: RwLock<Arc<GrokSet>>,
+    pattern: RwLock<Option<Arc<BucketPattern>>>,
+    pacer: Arc<Pacer>,
+    upload_turn: Mutex<()>,
+    columns: RwLock<Option<Arc<Columns>>>,
+    pending_event: Mutex<Option<PendingEvent>>,
 }
 
 impl Channel {
-    pub fn new(_name: String) -> Self {
+    pub fn new(name: String, history: Box<dyn HistoryStore>, context: ChannelContext) -> Self {
         let (sender, _) = broadcast::channel(100);
+        // Carry on numbering from any history that survived a restart
+        let events = history.events();
+        let last_seq = events.last().map_or(0, |event| event.seq);
+        let next_expiry = next_expiry(&events);
         Self {
+            name,
+            context,
             sender,
-            history: Arc::new(RwLock::new(Vec::new())),
+            history: RwLock::new(history),
             clients: Arc::new(RwLock::new(HashMap::new())),
             suspended: AtomicBool::new(false),
+            configured: AtomicBool::new(false),
+            active_at: AtomicI64::new(chrono::Utc::now().timestamp_millis()),
             log_count_current_minute: AtomicU64::new(0),
             current_minute_timestamp: AtomicU64::new(0),
+            suspended_at: AtomicU64::new(0),
+            last_seq: AtomicU64::new(last_seq),
+            next_expiry: AtomicI64::new(next_expiry),
+            webhooks: RwLock::new(Vec::new()),
+            alerts: RwLock::new(Vec::new()),
+            slack: RwLock::new(None),
+            pagerduty: RwLock::new(None),
+            idempotency_keys: std::sync::Mutex::default(),
+            settings: RwLock::new(BucketSettings::default()),
+            parse_outcomes: ParseOutcomeCounters::new(),
+            tombstones: RwLock::new(Vec::new()),
+            stats_history: RwLock::new(StatsHistory::new(last_seq)),
+            pause: RwLock::new(None),
+            ingest_urls: RwLock::new(IngestUrls::default()),
+            routes: RwLock::new(Vec::new()),
+            grok: RwLock::new(Arc::default()),
+            pattern: RwLock::new(None),
+            pacer: Arc::new(Pacer::default()),
+            upload_turn: Mutex::new(()),
+            columns: RwLock::new(None),
+            pending_event: Mutex::new(None),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Public link to the bucket's viewer
+    pub fn link(&self) -> String {
+        bucket_link(&self.context.public_url, &self.name)
+    }
+
+    /// Sequence number of the most recent log event
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq.load(Ordering::Relaxed)
+    }
+
     pub fn subscriber_count(&self) -> usize {
         self.sender.receiver_count()
     }
 
-    pub async fn subscribe(&self) -> Pin<Box<dyn Stream<Item = SseEvent> + Send>> {
+    /// Stream history and then live events; `resume_after` is the last sequence number a
+    /// reconnecting client saw, so it only gets what it missed
+    pub async fn subscribe(
+        &self,
+        resume_after: Option<u64>,
+    ) -> Pin<Box<dyn Stream<Item = SseEvent> + Send>> {
         let client_id = Uuid::new_v4().to_string();
-        self.clients.write().await.insert(client_id.clone(), ());
+        {
+            let mut clients = self.clients.write().await;
+            clients.insert(client_id.clone(), ());
+            self.context.overview.publish(AdminEvent::Subscribers {
+                bucket: self.name.clone(),
+                change: SubscriberChange::Joined,
+                subscribers: clients.len(),
+            });
+        }
 
         let mut receiver = self.sender.subscribe();
-        let history = self.history.read().await.clone();
+        let history: Vec<SseEvent> = self
+            .history
+            .read()
+            .await
+            .events()
+            .iter()
+            .filter(|event| resume_after.is_none_or(|after| event.seq > after))
+            .filter_map(log_sse_event)
+            .collect();
 
         // Create a guard that will remove the client when the stream is dropped
         let _guard = ClientGuard {
+            bucket: self.name.clone(),
             client_id,
             clients: self.clients.clone(),
+            overview: self.context.overview.clone(),
         };
 
         Box::pin(async_stream::stream! {
             // Move guard into the stream so it's dropped when the stream is dropped
             let _guard = _guard;
 
-            // Send history first
-            for event in history {
+            let mut last_seq = resume_after;
+
+            // Send history first, then stream new events
+            let mut history = history.into_iter();
+            loop {
+                let event = match history.next() {
+                    Some(event) => event,
+                    None => match receiver.recv().await {
+                        Ok(event) => event,
+                        // Missed events show up as a gap in sequence numbers below
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => {
+                            METRICS.subscriber_closes.inc(CloseReason::BucketDeleted);
+                            if let Some(close) = close_sse_event(CloseReason::BucketDeleted) {
+                                yield close;
+                            }
+                            break;
+                        }
+                    },
+                };
+
+                if event.event_type == CLOSE_EVENT_TYPE {
+                    yield event;
+                    break;
+                }
+
+                if let Some(seq) = event.seq {
+                    // Events published while subscribing can arrive both in history and live
+                    if last_seq.is_some_and(|last| seq <= last) {
+                        continue;
+                    }
+                    if let Some(gap) = last_seq
+                        .filter(|&last| seq > last + 1)
+                        .and_then(|last| gap_sse_event(last + 1, seq - 1))
+                    {
+                        yield gap;
+                    }
+                    last_seq = Some(seq);
+                }
+
                 yield event;
             }
+        })
+    }
 
-            // Then stream new events
-            while let Ok(event) = receiver.recv().await {
-                yield event;
+    /// Stream a stats snapshot, then JSON Patch changes to it as counts move. The stream
+    /// counts as a connection, and ends like a viewer's when the bucket closes.
+    pub fn subscribe_stats(self: &Arc<Self>) -> Pin<Box<dyn Stream<Item = SseEvent> + Send>> {
+        let mut receiver = self.sender.subscribe();
+        let channel = self.clone();
+
+        Box::pin(async_stream::stream! {
+            let mut previous = serde_json::to_value(channel.get_stats()).unwrap_or_default();
+            yield SseEvent {
+                event_type: "stats".to_string(),
+                data: previous.to_string(),
+                seq: None,
+            };
+
+            let mut ticks = tokio::time::interval(stats::STATS_PATCH_INTERVAL);
+            ticks.tick().await;
+            loop {
+                let received = tokio::select! {
+                    _ = ticks.tick() => None,
+                    received = receiver.recv() => Some(received),
+                };
+                match received {
+                    None => {
+                        let current = serde_json::to_value(channel.get_stats()).unwrap_or_default();
+                        let patch = stats::json_patch(&previous, &current);
+                        if !patch.is_empty() {
+                            previous = current;
+                            yield SseEvent {
+                                event_type: "patch".to_string(),
+                                data: serde_json::Value::from(patch).to_string(),
+                                seq: None,
+                            };
+                        }
+                    }
+                    Some(Ok(event)) if event.event_type == CLOSE_EVENT_TYPE => {
+                        yield event;
+                        break;
+                    }
+                    Some(Ok(_)) | Some(Err(broadcast::error::RecvError::Lagged(_))) => {}
+                    Some(Err(broadcast::error::RecvError::Closed)) => {
+                        if let Some(close) = close_sse_event(CloseReason::BucketDeleted) {
+                            yield close;
+                        }
+                        break;
+                    }
+                }
             }
         })
     }
 
+    /// End every open stream on this bucket with a `close` event
+    pub fn close_subscribers(&self, reason: CloseReason) {
+        let Some(close) = close_sse_event(reason) else {
+            return;
+        };
+        let closed = self.sender.send(close).unwrap_or(0);
+        for _ in 0..closed {
+            METRICS.subscriber_closes.inc(reason);
+        }
+    }
+
+    /// Keep the bucket through garbage collection, now that it holds configuration
+    pub fn mark_configured(&self) {
+        self.configured.store(true, Ordering::Relaxed);
+        self.mark_active();
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.configured.load(Ordering::Relaxed)
+    }
+
+    fn mark_active(&self) {
+        self.active_at
+            .store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    /// Whether garbage collection should keep the bucket while nobody watches it: it's
+    /// configured, or has heartbeat rules since a producer going quiet is what they catch,
+    /// and it has seen a write or config change within [`CONFIGURED_IDLE_MS`]
+    async fn is_pinned(&self, now: i64) -> bool {
+        now - self.active_at.load(Ordering::Relaxed) < CONFIGURED_IDLE_MS
+            && (self.is_configured() || self.has_heartbeat_rules().await)
+    }
+
     /// Check if bucket is suspended
     pub fn is_suspended(&self) -> bool {
         self.suspended.load(Ordering::Relaxed)
@@ -98,11 +355,7 @@ impl Channel {
             return false;
         }
 
-        let now_minutes = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            / 60;
+        let now_minutes = now_secs() / 60;
 
         let stored_minute = self.current_minute_timestamp.load(Ordering::Relaxed);
 
@@ -119,6 +372,7 @@ impl Channel {
                 .fetch_add(count, Ordering::Relaxed)
                 + count;
             if new_count > MAX_LOG_LINES_PER_MINUTE {
+                self.suspended_at.store(now_secs(), Ordering::Relaxed);
                 self.suspended.store(true, Ordering::Relaxed);
                 warn!(
                     "Channel suspended due to rate limit exceeded: {} logs in current minute",
@@ -131,70 +385,585 @@ impl Channel {
         true
     }
 
+    /// Lines the bucket can still take this minute before its rate limit suspends it
+    pub fn rate_budget(&self) -> u64 {
+        if self.is_suspended() {
+            return 0;
+        }
+        if self.current_minute_timestamp.load(Ordering::Relaxed) != now_secs() / 60 {
+            return MAX_LOG_LINES_PER_MINUTE;
+        }
+        MAX_LOG_LINES_PER_MINUTE
+            .saturating_sub(self.log_count_current_minute.load(Ordering::Relaxed))
+    }
+
+    /// Columns from the last W3C `#Fields:` directive or CSV header row sent to this bucket
+    pub async fn columns(&self) -> Option<Arc<Columns>> {
+        self.columns.read().await.clone()
+    }
+
+    pub async fn set_columns(&self, columns: Arc<Columns>) {
+        *self.columns.write().await = Some(columns);
+    }
+
+    /// Take the event held back from the last post, to continue it with the next one
+    pub async fn take_pending_event(&self) -> Option<PendingEvent> {
+        self.pending_event.lock().await.take()
+    }
+
+    /// Take the held event if it was held before `held_before` (epoch ms)
+    pub async fn take_pending_event_held_before(&self, held_before: i64) -> Option<PendingEvent> {
+        let mut pending = self.pending_event.lock().await;
+        match &*pending {
+            Some(event) if event.held_at < held_before => pending.take(),
+            _ => None,
+        }
+    }
+
+    /// Hold back a post's last event in case the next post continues it
+    pub async fn hold_pending_event(&self, event: PendingEvent) {
+        *self.pending_event.lock().await = Some(event);
+    }
+
+    /// Wait for this bucket's turn to publish an uploaded file, so files don't interleave
+    pub async fn upload_turn(&self) -> MutexGuard<'_, ()> {
+        self.upload_turn.lock().await
+    }
+
+    /// Check if a suspension has run its course and can be lifted
+    pub fn suspension_expired(&self) -> bool {
+        self.is_suspended()
+            && now_secs().saturating_sub(self.suspended_at.load(Ordering::Relaxed))
+                >= SUSPENSION_DURATION_SECS
+    }
+
+    /// Lift a suspension and notify subscribers
+    pub async fn lift_suspension(&self) {
+        self.log_count_current_minute.store(0, Ordering::Relaxed);
+        self.suspended.store(false, Ordering::Relaxed);
+        info!("Suspension lifted for channel {}", self.name);
+        self.publish_suspension(false).await;
+    }
+
     pub async fn publish_suspension(&self, suspended: bool) {
-        let event = SuspensionEvent { suspended };
-        let data = serde_json::to_string(&event).unwrap();
-        let sse_event = SseEvent {
-            event_type: "suspension".to_string(),
-            data,
-        };
-        let _ = self.sender.send(sse_event);
+        self.context.overview.publish(AdminEvent::Suspension {
+            bucket: self.name.clone(),
+            suspended,
+        });
+
+        self.broadcast("suspension", &SuspensionEvent { suspended });
+
+        if let Some(config) = self.slack.read().await.as_ref() {
+            slack::notify_suspension(config, &self.name, &self.link(), suspended);
+        }
+
+        self.notify_webhooks(if suspended {
+            WebhookEvent::Suspended
+        } else {
+            WebhookEvent::Unsuspended
+        })
+        .await;
     }
 
-    pub async fn publish_log(&self, event: LogEvent) {
-        let data = serde_json::to_string(&event).unwrap();
-        let sse_event = SseEvent {
-            event_type: "log".to_string(),
-            data,
-        };
+    /// Hold back writes until [`Channel::resume`]. Pausing again only changes the mode.
+    pub async fn pause(&self, mode: PauseMode) {
+        let mut pause = self.pause.write().await;
+        let pause = pause.get_or_insert_with(PauseState::default);
+        pause.mode = mode;
+        let buffered = pause.buffered.len();
+        self.publish_pause(PauseEvent {
+            paused: true,
+            mode: Some(mode),
+            buffered,
+        });
+    }
+
+    /// Take writes again, returning the lines buffered while paused
+    pub async fn resume(&self) -> Vec<String> {
+        let buffered = self
+            .pause
+            .write()
+            .await
+            .take()
+            .map(|pause| pause.buffered)
+            .unwrap_or_default();
+        self.publish_pause(PauseEvent {
+            paused: false,
+            mode: None,
+            buffered: buffered.len(),
+        });
+        buffered
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.pause.read().await.is_some()
+    }
+
+    /// While paused, buffer `lines` or refuse them, returning whether they were buffered.
+    /// Returns `None` when the bucket isn't paused.
+    pub async fn hold_if_paused(&self, lines: &[&str]) -> Option<bool> {
+        let mut pause = self.pause.write().await;
+        pause.as_mut().map(|pause| pause.hold(lines))
+    }
+
+    fn publish_pause(&self, event: PauseEvent) {
+        self.broadcast("paused", &event);
+    }
+
+    pub async fn settings(&self) -> BucketSettings {
+        self.settings.read().await.clone()
+    }
+
+    /// Replace the bucket's settings, returning the previous ones
+    pub async fn set_settings(&self, settings: BucketSettings) -> BucketSettings {
+        self.pacer.configure(settings.pacing.clone());
+        std::mem::replace(&mut *self.settings.write().await, settings)
+    }
+
+    /// Hold an idempotency key for a batch unless another batch has it; see `Reservation`
+    pub fn reserve_idempotency_key(&self, key: &str) -> KeyStatus {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.idempotency_keys.lock().unwrap().reserve(key, now)
+    }
+
+    pub fn accept_idempotency_key(&self, key: &str) {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.idempotency_keys.lock().unwrap().accept(key, now);
+    }
+
+    pub fn release_idempotency_key(&self, key: &str) {
+        self.idempotency_keys.lock().unwrap().release(key);
+    }
+
+    pub async fn routes(&self) -> Vec<RouteRule> {
+        self.routes.read().await.clone()
+    }
+
+    pub async fn set_routes(&self, routes: Vec<RouteRule>) -> Vec<RouteRule> {
+        std::mem::replace(&mut *self.routes.write().await, routes)
+    }
+
+    pub async fn grok(&self) -> Arc<GrokSet> {
+        self.grok.read().await.clone()
+    }
 
-        // Add to history
+    pub async fn set_grok(&self, grok: Arc<GrokSet>) -> Arc<GrokSet> {
+        std::mem::replace(&mut *self.grok.write().await, grok)
+    }
+
+    pub async fn pattern(&self) -> Option<Arc<BucketPattern>> {
+        self.pattern.read().await.clone()
+    }
+
+    pub async fn set_pattern(
+        &self,
+        pattern: Option<Arc<BucketPattern>>,
+    ) -> Option<Arc<BucketPattern>> {
+        std::mem::replace(&mut *self.pattern.write().await, pattern)
+    }
+
+    pub async fn mint_ingest_url(
+        &self,
+        label: Option<String>,
+        ttl_ms: i64,
+        now: i64,
+    ) -> Option<IngestUrl> {
+        self.ingest_urls.write().await.mint(label, ttl_ms, now)
+    }
+
+    pub async fn ingest_urls(&self) -> Vec<IngestUrl> {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.ingest_urls.write().await.list(now)
+    }
+
+    pub async fn ingest_url_valid(&self, nonce: &str, now: i64) -> bool {
+        self.ingest_urls.read().await.is_valid(nonce, now)
+    }
+
+    pub async fn revoke_ingest_url(&self, nonce: &str) -> bool {
+        self.ingest_urls.write().await.revoke(nonce)
+    }
+
+    pub async fn webhooks(&self) -> Vec<Webhook> {
+        self.webhooks.read().await.clone()
+    }
+
+    pub async fn set_webhooks(&self, webhooks: Vec<Webhook>) -> Vec<Webhook> {
+        std::mem::replace(&mut *self.webhooks.write().await, webhooks)
+    }
+
+    pub async fn notify_webhooks(&self, event: WebhookEvent) {
+        let webhooks = self.webhooks.read().await;
+        webhooks::deliver(&self.name, &webhooks, event);
+    }
+
+    /// Recently published log events, oldest first
+    pub async fn history(&self) -> Vec<LogEvent> {
+        self.history.read().await.events()
+    }
+
+    /// Number and publish an event, returning its sequence number, or `None` if it couldn't
+    /// be encoded and was dropped
+    pub async fn publish_log(&self, mut event: LogEvent) -> Option<u64> {
+        // Number, record and broadcast under the history lock so sequence order is delivery order
         let mut history = self.history.write().await;
-        history.push(sse_event.clone());
-        if history.len() > HISTORY_SIZE {
-            history.remove(0);
+        event.seq = self.last_seq.load(Ordering::Relaxed) + 1;
+        let Some(sse_event) = log_sse_event(&event) else {
+            // Leave the sequence number unused so subscribers don't see a gap
+            drop(history);
+            let _ = self
+                .sender
+                .send(serialization_error_event("log", Some(&event.raw)));
+            return None;
+        };
+        self.last_seq.store(event.seq, Ordering::Relaxed);
+        self.mark_active();
+
+        history.push(&event);
+        if let Some(expires_at) = event.expires_at {
+            self.next_expiry.fetch_min(expires_at, Ordering::Relaxed);
         }
+
+        // Broadcast to all subscribers, held back if the bucket paces bursts
+        self.pacer.send(&self.sender, sse_event);
         drop(history);
 
-        // Broadcast to all subscribers
-        let _ = self.sender.send(sse_event);
+        // Evaluate alert rules against the new event
+        let fired: Vec<AlertEvent> = self
+            .alerts
+            .write()
+            .await
+            .iter_mut()
+            .filter_map(|state| state.record(&event))
+            .collect();
+        for alert in fired {
+            self.publish_alert(alert).await;
+        }
+        Some(event.seq)
     }
 
-    pub async fn publish_stats(&self, stats: StatsEvent) {
-        let data = serde_json::to_string(&stats).unwrap();
-        let sse_event = SseEvent {
-            event_type: "stats".to_string(),
-            data,
+    /// Re-evaluate alert windows so rules resolve even when no new events arrive
+    pub async fn sweep_alerts(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let changed: Vec<AlertEvent> = self
+            .alerts
+            .write()
+            .await
+            .iter_mut()
+            .filter_map(|state| state.evaluate(now))
+            .collect();
+        for alert in changed {
+            self.publish_alert(alert).await;
+        }
+    }
+
+    pub async fn publish_alert(&self, alert: AlertEvent) {
+        info!(
+            "Alert {} is {:?} in channel {}",
+            alert.rule, alert.status, self.name
+        );
+
+        if alert.status == AlertStatus::Firing {
+            if let Some(config) = self.slack.read().await.as_ref() {
+                slack::notify_alert(config, &self.name, &self.link(), &alert);
+            }
+        }
+
+        if alert.severity == AlertSeverity::Critical {
+            if let Some(config) = self.pagerduty.read().await.as_ref() {
+                pagerduty::notify_alert(config, &self.name, &self.link(), &alert);
+            }
+        }
+
+        if alert.kind == AlertKind::Heartbeat && alert.status == AlertStatus::Firing {
+            self.notify_webhooks(WebhookEvent::HeartbeatMissed).await;
+        }
+
+        self.broadcast("alert", &alert);
+    }
+
+    /// Drop history events that have outlived their time to live or the retention rule for
+    /// their severity
+    pub async fn apply_retention(&self, now: i64) -> usize {
+        let settings = self.settings().await;
+        let expiring = self.next_expiry.load(Ordering::Relaxed) <= now;
+        if settings.retention.is_empty() && !expiring {
+            return 0;
+        }
+
+        let mut history = self.history.write().await;
+        let removed = history.retain(&|event| {
+            event.expires_at.is_none_or(|expires_at| expires_at > now)
+                && settings.retains(event, now)
+        });
+        if expiring {
+            self.next_expiry
+                .store(next_expiry(&history.events()), Ordering::Relaxed);
+        }
+        removed.len()
+    }
+
+    /// Remove retained events whose `field` is `value` and tell subscribers which are gone
+    pub async fn erase(&self, field: &str, value: &str) -> Tombstone {
+        let matches = |event: &LogEvent| {
+            event
+                .fields
+                .get(field)
+                .is_some_and(|data| data.value == value)
         };
-        let _ = self.sender.send(sse_event);
+        let removed = self.history.write().await.retain(&|event| !matches(event));
+
+        // Alert samples are raw lines, so drop any that could hold the value
+        for state in self.alerts.write().await.iter_mut() {
+            state.forget_samples(value);
+        }
+
+        let tombstone = Tombstone {
+            time: chrono::Utc::now().timestamp_millis(),
+            field: field.to_string(),
+            count: removed.len(),
+            seqs: removed.iter().map(|event| event.seq).collect(),
+        };
+        self.tombstones.write().await.push(tombstone.clone());
+
+        self.broadcast("erase", &tombstone);
+        tombstone
+    }
+
+    /// Remove retained events numbered `from` to `to` and tell subscribers about the gap
+    pub async fn redact(&self, from: u64, to: u64) -> Redaction {
+        let removed = self
+            .history
+            .write()
+            .await
+            .retain(&|event| !(from..=to).contains(&event.seq));
+
+        for state in self.alerts.write().await.iter_mut() {
+            for event in &removed {
+                state.forget_samples(&event.raw);
+            }
+        }
+
+        let redaction = Redaction {
+            time: chrono::Utc::now().timestamp_millis(),
+            from,
+            to,
+            count: removed.len(),
+        };
+        self.broadcast("redacted", &redaction);
+        redaction
+    }
+
+    pub async fn tombstones(&self) -> Vec<Tombstone> {
+        self.tombstones.read().await.clone()
+    }
+
+    pub async fn alert_rules(&self) -> Vec<AlertRule> {
+        self.alerts
+            .read()
+            .await
+            .iter()
+            .map(|state| state.rule().clone())
+            .collect()
+    }
+
+    /// Whether a rule watches for silence, which only works while the bucket is kept
+    pub async fn has_heartbeat_rules(&self) -> bool {
+        self.alerts
+            .read()
+            .await
+            .iter()
+            .any(|state| state.rule().kind == AlertKind::Heartbeat)
+    }
+
+    pub async fn set_alert_rules(&self, rules: Vec<AlertRule>) -> Vec<AlertRule> {
+        let states = rules.into_iter().map(AlertState::new).collect();
+        let previous = std::mem::replace(&mut *self.alerts.write().await, states);
+        previous.iter().map(|state| state.rule().clone()).collect()
+    }
+
+    pub async fn slack(&self) -> Option<SlackConfig> {
+        self.slack.read().await.clone()
+    }
+
+    pub async fn set_slack(&self, config: Option<SlackConfig>) -> Option<SlackConfig> {
+        std::mem::replace(&mut *self.slack.write().await, config)
+    }
+
+    pub async fn pagerduty(&self) -> Option<PagerDutyConfig> {
+        self.pagerduty.read().await.clone()
+    }
+
+    pub async fn set_pagerduty(&self, config: Option<PagerDutyConfig>) -> Option<PagerDutyConfig> {
+        std::mem::replace(&mut *self.pagerduty.write().await, config)
+    }
+
+    /// Tell subscribers what changed in one section of the bucket's configuration, and who
+    /// changed it. Nothing is sent when the new configuration is the same as the old.
+    pub fn publish_config_change<T: Serialize>(
+        &self,
+        section: &'static str,
+        before: &T,
+        after: &T,
+        actor: Option<String>,
+    ) {
+        let (Ok(before), Ok(after)) = (serde_json::to_value(before), serde_json::to_value(after))
+        else {
+            return;
+        };
+        let changes = changes::diff(&before, &after);
+        if changes.is_empty() {
+            return;
+        }
+        self.mark_configured();
+        let change = ConfigChange {
+            time: chrono::Utc::now().timestamp_millis(),
+            section,
+            actor,
+            changes,
+        };
+        self.broadcast("config-changed", &change);
+    }
+
+    pub async fn publish_stats(&self, stats: StatsEvent) {
+        self.broadcast("stats", &stats);
+    }
+
+    pub fn publish_import(&self, import: ImportEvent) {
+        self.broadcast("import", &import);
+    }
+
+    /// Send an event to every subscriber, or an `error` event if it can't be encoded
+    fn broadcast<T: Serialize>(&self, event_type: &str, value: &T) {
+        let event = sse_event(event_type, value, None)
+            .unwrap_or_else(|| serialization_error_event(event_type, None));
+        let _ = self.sender.send(event);
+    }
+
+    /// Count a parsed line towards the bucket's and the server's parse outcomes
+    pub fn record_parse_outcome(&self, outcome: ParseOutcome) {
+        self.parse_outcomes.inc(outcome);
+        METRICS.parse_outcomes.inc(outcome);
+    }
+
+    /// Add a point to the bucket's rolling event rate and subscriber history
+    pub async fn sample_stats(&self) {
+        let now = chrono::Utc::now().timestamp_millis();
+        self.stats_history
+            .write()
+            .await
+            .record(now, self.last_seq(), self.subscriber_count());
+    }
+
+    pub async fn stats_history(&self) -> Vec<StatsSample> {
+        self.stats_history.read().await.samples()
     }
 
     pub fn get_stats(&self) -> StatsEvent {
         let clients = futures::executor::block_on(self.clients.read());
-        let client_ids: Vec<String> = clients.keys().cloned().collect();
+        // Sorted so consecutive snapshots only differ when clients do
+        let mut client_ids: Vec<String> = clients.keys().cloned().collect();
+        client_ids.sort();
         StatsEvent {
             client_count: client_ids.len(),
             conn_count: self.subscriber_count(),
             clients: client_ids,
+            parse_outcomes: self.parse_outcomes.snapshot().into_iter().collect(),
+        }
+    }
+}
+
+/// Encode an SSE event, counting and logging a value that can't be serialized rather
+/// than panicking whichever handler happened to publish it
+fn sse_event<T: Serialize>(event_type: &str, value: &T, seq: Option<u64>) -> Option<SseEvent> {
+    match serde_json::to_string(value) {
+        Ok(data) => Some(SseEvent {
+            event_type: event_type.to_string(),
+            data,
+            seq,
+        }),
+        Err(e) => {
+            METRICS.serialization_failures.inc();
+            error!("Failed to serialize {} event: {}", event_type, e);
+            None
         }
     }
 }
 
+/// Tell subscribers an event was dropped because it couldn't be encoded
+fn serialization_error_event(event_type: &str, raw: Option<&str>) -> SseEvent {
+    let mut error = serde_json::json!({
+        "error": "serialization",
+        "eventType": event_type,
+    });
+    if let Some(raw) = raw {
+        error["raw"] = raw.into();
+    }
+    SseEvent {
+        event_type: "error".to_string(),
+        data: error.to_string(),
+        seq: None,
+    }
+}
+
+fn log_sse_event(event: &LogEvent) -> Option<SseEvent> {
+    sse_event("log", event, Some(event.seq))
+}
+
+fn close_sse_event(reason: CloseReason) -> Option<SseEvent> {
+    sse_event(CLOSE_EVENT_TYPE, &CloseEvent { reason }, None)
+}
+
+/// Tell a subscriber that the log events numbered `from..=to` will never reach it
+fn gap_sse_event(from: u64, to: u64) -> Option<SseEvent> {
+    sse_event("gap", &GapEvent { from, to }, None)
+}
+
+fn next_expiry(events: &[LogEvent]) -> i64 {
+    events
+        .iter()
+        .filter_map(|event| event.expires_at)
+        .min()
+        .unwrap_or(i64::MAX)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 pub struct ChannelManager {
     channels: HashMap<String, Arc<Channel>>,
+    history: HistoryBackend,
+    context: ChannelContext,
 }
 
 impl ChannelManager {
-    pub fn new() -> Self {
+    pub fn new(history: HistoryBackend, context: ChannelContext) -> Self {
         Self {
             channels: HashMap::new(),
+            history,
+            context,
         }
     }
 
     pub fn get_or_create_channel(&mut self, name: &str) -> Arc<Channel> {
+        let history = &self.history;
+        let context = &self.context;
         self.channels
             .entry(name.to_string())
-            .or_insert_with(|| Arc::new(Channel::new(name.to_string())))
+            .or_insert_with(|| {
+                context.overview.publish(AdminEvent::ChannelCreated {
+                    bucket: name.to_string(),
+                });
+                Arc::new(Channel::new(
+                    name.to_string(),
+                    history.open(name),
+                    context.clone(),
+                ))
+            })
             .clone()
     }
 
@@ -202,12 +971,28 @@ impl ChannelManager {
         self.channels.get(name).cloned()
     }
 
+    pub fn channels(&self) -> Vec<Arc<Channel>> {
+        self.channels.values().cloned().collect()
+    }
+
+    /// End every open stream on every bucket, e.g. before shutting down
+    pub fn close_subscribers(&self, reason: CloseReason) {
+        for channel in self.channels.values() {
+            channel.close_subscribers(reason);
+        }
+    }
+
     pub async fn garbage_collect(&mut self) {
+        let now = chrono::Utc::now().timestamp_millis();
         let mut to_remove = Vec::new();
 
         for (name, channel) in &self.channels {
-            // Only consider for removal if there are no subscribers and it's not suspended
-            if channel.subscriber_count() == 0 {
+            if channel.suspension_expired() {
+                channel.lift_suspension().await;
+            }
+
+            // Only consider for removal if there are no subscribers and it's not pinned
+            if channel.subscriber_count() == 0 && !channel.is_pinned(now).await {
                 let name_clone = name.clone();
                 let channel_clone = channel.clone();
 
@@ -223,13 +1008,213 @@ impl ChannelManager {
             }
         }
 
+        let mut removed = Vec::new();
         for name in to_remove {
             if let Some(channel) = self.channels.get(&name) {
-                if channel.subscriber_count() == 0 {
+                if channel.subscriber_count() == 0 && !channel.is_pinned(now).await {
                     info!("Removing channel: {}", name);
+                    channel.notify_webhooks(WebhookEvent::Expired).await;
                     self.channels.remove(&name);
+                    self.history.remove(&name);
+                    removed.push(name);
                 }
             }
         }
+
+        self.context.overview.publish(AdminEvent::Gc {
+            kept: self.channels.len(),
+            removed,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::MemoryHistory;
+    use futures_util::StreamExt;
+
+    fn event(raw: &str) -> LogEvent {
+        LogEvent {
+            seq: 0,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: raw.to_string(),
+            raw_ansi: None,
+            fields: HashMap::new(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_reports_gap() {
+        let channel = Channel::new(
+            "test".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        for i in 0..15 {
+            channel.publish_log(event(&format!("line {}", i))).await;
+        }
+
+        // History only retains the last 10 events (6..=15), so 3..=5 are gone
+        let mut stream = channel.subscribe(Some(2)).await;
+        let gap = stream.next().await.unwrap();
+        assert_eq!(gap.event_type, "gap");
+        assert_eq!(gap.data, r#"{"from":3,"to":5}"#);
+
+        let first = stream.next().await.unwrap();
+        assert_eq!(first.seq, Some(6));
+    }
+
+    #[tokio::test]
+    async fn test_redact_range() {
+        let channel = Channel::new(
+            "test".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        for i in 0..5 {
+            channel.publish_log(event(&format!("line {}", i))).await;
+        }
+
+        let redaction = channel.redact(2, 3).await;
+        assert_eq!(redaction.count, 2);
+        let seqs: Vec<u64> = channel.history().await.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![1, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_expired_events_dropped() {
+        let channel = Channel::new(
+            "test".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        channel.publish_log(event("kept")).await;
+        for expires_at in [1_000, 2_000] {
+            channel
+                .publish_log(LogEvent {
+                    expires_at: Some(expires_at),
+                    ..event("ephemeral")
+                })
+                .await;
+        }
+
+        assert_eq!(channel.apply_retention(500).await, 0);
+        assert_eq!(channel.apply_retention(1_000).await, 1);
+        assert_eq!(channel.apply_retention(1_500).await, 0);
+        assert_eq!(channel.apply_retention(2_500).await, 1);
+        let raws: Vec<String> = channel.history().await.into_iter().map(|e| e.raw).collect();
+        assert_eq!(raws, vec!["kept"]);
+    }
+
+    /// Stands in for a value that can't be encoded, like a non-string map key
+    struct Unserializable;
+
+    impl Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("not representable"))
+        }
+    }
+
+    #[test]
+    fn test_serialization_failure_is_counted() {
+        let before = METRICS.serialization_failures.get();
+        assert!(sse_event("log", &Unserializable, Some(1)).is_none());
+        assert!(METRICS.serialization_failures.get() > before);
+
+        let error = serialization_error_event("log", Some("raw line"));
+        assert_eq!(error.event_type, "error");
+        let data: serde_json::Value = serde_json::from_str(&error.data).unwrap();
+        assert_eq!(data["raw"], "raw line");
+    }
+
+    #[tokio::test]
+    async fn test_close_ends_stream() {
+        let channel = Channel::new(
+            "test".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        let mut stream = channel.subscribe(None).await;
+
+        channel.close_subscribers(CloseReason::Shutdown);
+        let close = stream.next().await.unwrap();
+        assert_eq!(close.event_type, "close");
+        assert_eq!(close.data, r#"{"reason":"shutdown"}"#);
+        assert!(stream.next().await.is_none());
+
+        // Dropping the bucket closes the broadcast channel under open streams
+        let mut stream = channel.subscribe(None).await;
+        drop(channel);
+        let close = stream.next().await.unwrap();
+        assert_eq!(close.data, r#"{"reason":"bucket_deleted"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_erase() {
+        let channel = Channel::new(
+            "test".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        for user in ["alice", "bob", "alice"] {
+            let mut event = event(user);
+            event.fields = crate::parsers::create_fields(HashMap::from([(
+                "user".to_string(),
+                user.to_string(),
+            )]));
+            channel.publish_log(event).await;
+        }
+
+        let tombstone = channel.erase("user", "alice").await;
+        assert_eq!(tombstone.count, 2);
+        assert_eq!(tombstone.seqs, vec![1, 3]);
+        let history = channel.history().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].raw, "bob");
+        assert_eq!(channel.tombstones().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_garbage_collect_keeps_configured_buckets() {
+        let mut manager = ChannelManager::new(HistoryBackend::Memory, ChannelContext::default());
+        manager.get_or_create_channel("idle");
+        let watched = manager.get_or_create_channel("watched");
+        let rule: AlertRule =
+            serde_json::from_value(serde_json::json!({"name": "alive", "kind": "heartbeat"}))
+                .unwrap();
+        watched.set_alert_rules(vec![rule]).await;
+
+        manager
+            .get_or_create_channel("provisioned")
+            .mark_configured();
+        let configured = manager.get_or_create_channel("configured");
+        configured.publish_config_change("settings", &false, &true, None);
+        manager
+            .get_or_create_channel("unchanged")
+            .publish_config_change("settings", &true, &true, None);
+
+        let abandoned = manager.get_or_create_channel("abandoned");
+        abandoned.mark_configured();
+        abandoned
+            .active_at
+            .fetch_sub(CONFIGURED_IDLE_MS, Ordering::Relaxed);
+
+        manager.garbage_collect().await;
+        assert!(manager.get_channel("idle").is_none());
+        assert!(manager.get_channel("watched").is_some());
+        assert!(manager.get_channel("provisioned").is_some());
+        assert!(manager.get_channel("configured").is_some());
+        assert!(manager.get_channel("unchanged").is_none());
+        assert!(manager.get_channel("abandoned").is_none());
     }
 }
@@ -0,0 +1,122 @@
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::io::Read;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Largest zstd window accepted, 8 MiB, as the zstd CLI itself allows by default
+const MAX_ZSTD_WINDOW_LOG: u32 = 23;
+
+#[derive(Debug, PartialEq)]
+pub enum DecompressError {
+    /// Decompressed output exceeded the allowed size
+    TooLarge,
+    /// Input was not valid for the encoding
+    Invalid,
+}
+
+/// Check whether a buffer starts with the gzip magic bytes
+pub fn is_gzip(input: &[u8]) -> bool {
+    input.starts_with(&GZIP_MAGIC)
+}
+
+/// Check whether a buffer starts with a zlib header using deflate
+pub fn is_zlib(input: &[u8]) -> bool {
+    match input {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Decompress a zlib buffer, refusing to produce more than `limit` bytes
+pub fn inflate(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    read_limited(ZlibDecoder::new(input), limit)
+}
+
+/// Decompress a raw deflate stream without a zlib header, refusing to produce more than
+/// `limit` bytes
+pub fn inflate_raw(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    read_limited(DeflateDecoder::new(input), limit)
+}
+
+/// Decompress a gzip buffer, refusing to produce more than `limit` bytes
+pub fn gunzip(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    read_limited(GzDecoder::new(input), limit)
+}
+
+/// Decompress a zstd buffer, refusing to produce more than `limit` bytes
+pub fn unzstd(input: &[u8], limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut decoder =
+        zstd::stream::read::Decoder::new(input).map_err(|_| DecompressError::Invalid)?;
+    // Frames can ask for a window of up to 2 GiB, which would be allocated up front
+    decoder
+        .window_log_max(MAX_ZSTD_WINDOW_LOG)
+        .map_err(|_| DecompressError::Invalid)?;
+    read_limited(decoder, limit)
+}
+
+/// Drain a decoder into memory, failing once the output grows past `limit`
+fn read_limited(reader: impl Read, limit: usize) -> Result<Vec<u8>, DecompressError> {
+    let mut output = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|_| DecompressError::Invalid)?;
+
+    if output.len() > limit {
+        return Err(DecompressError::TooLarge);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_gunzip_roundtrip() {
+        let compressed = gzip(b"line one\nline two\n");
+        assert!(is_gzip(&compressed));
+        assert_eq!(gunzip(&compressed, 1024).unwrap(), b"line one\nline two\n");
+    }
+
+    #[test]
+    fn test_inflate_roundtrip() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{}").unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(is_zlib(&compressed));
+        assert!(!is_zlib(b"{}"));
+        assert_eq!(inflate(&compressed, 1024).unwrap(), b"{}");
+
+        let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"{}").unwrap();
+        assert_eq!(
+            inflate_raw(&encoder.finish().unwrap(), 1024).unwrap(),
+            b"{}"
+        );
+    }
+
+    #[test]
+    fn test_unzstd() {
+        let compressed = zstd::encode_all(&[b'a'; 4096][..], 0).unwrap();
+        assert_eq!(unzstd(&compressed, 4096).unwrap().len(), 4096);
+        assert_eq!(unzstd(&compressed, 1024), Err(DecompressError::TooLarge));
+        assert_eq!(unzstd(b"not zstd", 1024), Err(DecompressError::Invalid));
+    }
+
+    #[test]
+    fn test_gunzip_limit() {
+        let compressed = gzip(&[b'a'; 4096]);
+        assert_eq!(gunzip(&compressed, 1024), Err(DecompressError::TooLarge));
+        assert_eq!(gunzip(b"not gzip", 1024), Err(DecompressError::Invalid));
+    }
+}
@@ -0,0 +1,392 @@
+use crate::ids::IdScheme;
+use crate::proxy::ProxyProfile;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 8080;
+const DEFAULT_CORS_MAX_AGE_SECS: u64 = 60 * 60;
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_CLIENT_IDLE_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MAX_SUBSCRIBERS_PER_IP: usize = 50;
+const DEFAULT_MAX_SUBSCRIBERS_TOTAL: usize = 10_000;
+const DEFAULT_MAX_CLOCK_SKEW_SECS: u64 = 5 * 60;
+const DEFAULT_MAX_EVENTS_PER_REQUEST: usize = 10_000;
+const DEFAULT_HISTORY_FILE_SIZE: u64 = 1024 * 1024;
+const DEFAULT_HISTORY_MAX_FILES: usize = 1024;
+const DEFAULT_IMPORT_DEDUP_WINDOW: usize = 10_000;
+const DEFAULT_PUBLIC_URL: &str = "https://log-bin.fastly.dev";
+/// Tokio's own default
+const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+
+/// Runtime configuration, read from environment variables at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `PORT`: port to listen on
+    pub port: u16,
+    /// `CORS_ALLOWED_ORIGINS`: comma-separated origins allowed to make cross-origin
+    /// requests. When unset, any origin may read and post to buckets.
+    pub cors_allowed_origins: Option<Vec<String>>,
+    /// `CORS_MAX_AGE`: how long browsers may cache preflight responses, in seconds
+    pub cors_max_age: Duration,
+    /// `REQUEST_TIMEOUT`: how long a request may take to send its body and get a response,
+    /// in seconds
+    pub request_timeout: Duration,
+    /// `CLIENT_IDLE_TIMEOUT`: how long a connection may go without the client reading
+    /// before it is dropped, in seconds. Mostly affects SSE subscribers that stop reading.
+    pub client_idle_timeout: Duration,
+    /// `MAX_SUBSCRIBERS_PER_IP`: concurrent stream connections allowed from one client IP
+    /// across all buckets
+    pub max_subscribers_per_ip: usize,
+    /// `MAX_SUBSCRIBERS_TOTAL`: concurrent stream connections allowed across the whole
+    /// server; new subscribers beyond this are shed with a 503
+    pub max_subscribers_total: usize,
+    /// `CLIENT_IP_HEADER`: request header carrying the client IP when running behind a
+    /// proxy or CDN, e.g. `Fastly-Client-IP`. When unset, the peer address is used.
+    pub client_ip_header: Option<String>,
+    /// `RULES_FILE`: JSON file of custom parser and transform rules, reloaded when it changes
+    pub rules_file: Option<PathBuf>,
+    /// `API_ONLY`: don't serve the web viewer, even if it was built in; bucket pages
+    /// return JSON instead
+    pub api_only: bool,
+    /// `MAX_CLOCK_SKEW`: how far, in seconds, an event's own timestamp may be from the time
+    /// it was received before it is clamped and flagged
+    pub max_clock_skew: Duration,
+    /// `MAX_EVENTS_PER_REQUEST`: events a single ingest request may carry; larger batches
+    /// are refused with a 413 so shippers split them
+    pub max_events_per_request: usize,
+    /// `BLOCKED_ID_WORDS`: comma-separated words that generated bucket IDs must not contain
+    pub blocked_id_words: Vec<String>,
+    /// `ID_WORDLIST`: file of words, one per line, to generate bucket IDs from instead of
+    /// the built-in English list
+    pub id_wordlist: Option<PathBuf>,
+    /// `ID_GENERATOR`: how new bucket IDs are made: `memorable` word-word-number IDs,
+    /// time-ordered `uuidv7` or random `nanoid`
+    pub id_generator: IdScheme,
+    /// `ID_PREFIX`: text to start every generated bucket ID with, e.g. `payments-`
+    pub id_prefix: Option<String>,
+    /// `FASTLY_SERVICE_IDS`: comma-separated IDs of the Fastly services allowed to stream
+    /// logs here. When unset, the logging challenge approves any service.
+    pub fastly_service_ids: Option<Vec<String>>,
+    /// `PROXY_PROFILE`: the CDN or proxy in front of the server (`generic`, `fastly`,
+    /// `nginx` or `cloudflare`), used to pick stream response headers
+    pub proxy_profile: ProxyProfile,
+    /// `HISTORY_DIR`: directory for per-bucket history ring files, which keep thousands of
+    /// events across restarts. When unset, only the last few events are kept, in memory.
+    pub history_dir: Option<PathBuf>,
+    /// `HISTORY_FILE_SIZE`: size in bytes of each new history ring file
+    pub history_file_size: u64,
+    /// `HISTORY_MAX_FILES`: most history ring files kept in `HISTORY_DIR`; buckets beyond
+    /// it keep their history in memory
+    pub history_max_files: usize,
+    /// `IMPORT_DEDUP_WINDOW`: how many of a bucket's most recent retained events imported
+    /// and replayed lines are checked against, skipping exact repeats. `0` turns this off.
+    pub import_dedup_window: usize,
+    /// `REUSE_PORT`: bind with `SO_REUSEPORT` so a new server can start on the same port
+    /// while the old one drains its streams
+    pub reuse_port: bool,
+    /// `WORKER_THREADS`: async runtime worker threads; defaults to the number of CPUs
+    pub worker_threads: usize,
+    /// `MAX_BLOCKING_THREADS`: cap on threads for blocking work such as file reads
+    pub max_blocking_threads: usize,
+    /// `SOCKET_SEND_BUFFER`: kernel send buffer size per connection, in bytes. When unset,
+    /// the OS default is used.
+    pub socket_send_buffer: Option<usize>,
+    /// `SOCKET_RECV_BUFFER`: kernel receive buffer size per connection, in bytes
+    pub socket_recv_buffer: Option<usize>,
+    /// `TOKENS_FILE`: JSON file of write tokens and their quotas. Writes made with a token
+    /// (`Authorization: Bearer ...`) count towards its quotas.
+    pub tokens_file: Option<PathBuf>,
+    /// `REQUIRE_WRITE_TOKEN`: reject writes that don't present a token from `TOKENS_FILE`
+    pub require_write_token: bool,
+    /// `HTTP3_PORT`: UDP port for an HTTP/3 (QUIC) listener alongside the TCP one. Needs
+    /// `TLS_CERT` and `TLS_KEY`, and a binary built with the `http3` feature.
+    pub http3_port: Option<u16>,
+    /// `TLS_CERT`: PEM certificate chain for the HTTP/3 listener
+    #[cfg(feature = "http3")]
+    pub tls_cert: Option<PathBuf>,
+    /// `TLS_KEY`: PEM private key for the HTTP/3 listener
+    #[cfg(feature = "http3")]
+    pub tls_key: Option<PathBuf>,
+    /// `ADMIN_TOKEN`: bearer token for the admin endpoints. When unset, they are disabled.
+    pub admin_token: Option<Secret>,
+    /// `GELF_UDP_PORT`: UDP port for a GELF listener. Needs a binary built with the
+    /// `gelf-udp` feature.
+    pub gelf_udp_port: Option<u16>,
+    /// `GELF_DEFAULT_BUCKET`: bucket for GELF datagrams that don't name one with `_bucket`
+    #[cfg(feature = "gelf-udp")]
+    pub gelf_default_bucket: Option<String>,
+    /// `FLUENT_FORWARD_PORT`: TCP port for a Fluent Forward listener, publishing to the
+    /// bucket named by each message's tag. Needs a binary built with the `fluent-forward`
+    /// feature.
+    pub fluent_forward_port: Option<u16>,
+    /// `OTLP_GRPC_PORT`: TCP port for an OTLP/gRPC logs receiver, usually 4317. Needs a
+    /// binary built with the `otlp-grpc` feature.
+    pub otlp_grpc_port: Option<u16>,
+    /// `SYSLOG_TCP_PORT`: TCP port for an RFC 6587 syslog listener, publishing to
+    /// `SYSLOG_BUCKET`
+    pub syslog_tcp_port: Option<u16>,
+    /// `SYSLOG_BUCKET`: bucket that messages from the syslog and RELP listeners are
+    /// published to
+    pub syslog_bucket: Option<String>,
+    /// `RELP_PORT`: TCP port for a RELP listener, so rsyslog's `omrelp` can deliver with
+    /// acknowledgements, publishing to `SYSLOG_BUCKET`
+    pub relp_port: Option<u16>,
+    /// `PUBLIC_URL`: address the server is reached at, used for bucket links in
+    /// notifications and feeds
+    pub public_url: String,
+}
+
+/// A value that is kept out of debug output such as `--print-effective-config`
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[redacted]")
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_lookup(|_| None)
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    /// Read the configuration from `lookup` instead of the environment
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let cors_allowed_origins = lookup("CORS_ALLOWED_ORIGINS").map(|origins| {
+            origins
+                .split(',')
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect()
+        });
+
+        Self {
+            port: lookup("PORT")
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(DEFAULT_PORT),
+            cors_allowed_origins,
+            cors_max_age: Duration::from_secs(
+                lookup("CORS_MAX_AGE")
+                    .and_then(|age| age.parse().ok())
+                    .unwrap_or(DEFAULT_CORS_MAX_AGE_SECS),
+            ),
+            request_timeout: secs(&lookup, "REQUEST_TIMEOUT", DEFAULT_REQUEST_TIMEOUT_SECS),
+            client_idle_timeout: secs(
+                &lookup,
+                "CLIENT_IDLE_TIMEOUT",
+                DEFAULT_CLIENT_IDLE_TIMEOUT_SECS,
+            ),
+            max_subscribers_per_ip: lookup("MAX_SUBSCRIBERS_PER_IP")
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(DEFAULT_MAX_SUBSCRIBERS_PER_IP),
+            max_subscribers_total: lookup("MAX_SUBSCRIBERS_TOTAL")
+                .and_then(|max| max.parse().ok())
+                .unwrap_or(DEFAULT_MAX_SUBSCRIBERS_TOTAL),
+            client_ip_header: lookup("CLIENT_IP_HEADER").filter(|header| !header.is_empty()),
+            rules_file: lookup("RULES_FILE")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            api_only: lookup("API_ONLY").is_some_and(|value| value == "1" || value == "true"),
+            max_clock_skew: Duration::from_secs(
+                lookup("MAX_CLOCK_SKEW")
+                    .and_then(|skew| skew.parse().ok())
+                    .unwrap_or(DEFAULT_MAX_CLOCK_SKEW_SECS),
+            ),
+            max_events_per_request: count(&lookup, "MAX_EVENTS_PER_REQUEST")
+                .unwrap_or(DEFAULT_MAX_EVENTS_PER_REQUEST),
+            blocked_id_words: lookup("BLOCKED_ID_WORDS")
+                .map(|words| comma_list(&words))
+                .unwrap_or_default(),
+            id_wordlist: lookup("ID_WORDLIST")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            id_generator: lookup("ID_GENERATOR")
+                .and_then(|scheme| scheme.parse().ok())
+                .unwrap_or_default(),
+            id_prefix: lookup("ID_PREFIX").filter(|prefix| !prefix.is_empty()),
+            fastly_service_ids: lookup("FASTLY_SERVICE_IDS")
+                .map(|ids| comma_list(&ids))
+                .filter(|ids| !ids.is_empty()),
+            proxy_profile: lookup("PROXY_PROFILE")
+                .and_then(|profile| profile.parse().ok())
+                .unwrap_or_default(),
+            history_dir: lookup("HISTORY_DIR")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            history_file_size: lookup("HISTORY_FILE_SIZE")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_HISTORY_FILE_SIZE),
+            history_max_files: count(&lookup, "HISTORY_MAX_FILES")
+                .unwrap_or(DEFAULT_HISTORY_MAX_FILES),
+            import_dedup_window: lookup("IMPORT_DEDUP_WINDOW")
+                .and_then(|window| window.parse().ok())
+                .unwrap_or(DEFAULT_IMPORT_DEDUP_WINDOW),
+            reuse_port: lookup("REUSE_PORT").is_some_and(|value| value == "1" || value == "true"),
+            worker_threads: count(&lookup, "WORKER_THREADS").unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |threads| threads.get())
+            }),
+            max_blocking_threads: count(&lookup, "MAX_BLOCKING_THREADS")
+                .unwrap_or(DEFAULT_MAX_BLOCKING_THREADS),
+            socket_send_buffer: count(&lookup, "SOCKET_SEND_BUFFER"),
+            socket_recv_buffer: count(&lookup, "SOCKET_RECV_BUFFER"),
+            tokens_file: lookup("TOKENS_FILE")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            require_write_token: lookup("REQUIRE_WRITE_TOKEN")
+                .is_some_and(|value| value == "1" || value == "true"),
+            http3_port: lookup("HTTP3_PORT").and_then(|port| port.parse().ok()),
+            #[cfg(feature = "http3")]
+            tls_cert: lookup("TLS_CERT")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            #[cfg(feature = "http3")]
+            tls_key: lookup("TLS_KEY")
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from),
+            admin_token: lookup("ADMIN_TOKEN")
+                .filter(|token| !token.is_empty())
+                .map(Secret),
+            gelf_udp_port: lookup("GELF_UDP_PORT").and_then(|port| port.parse().ok()),
+            #[cfg(feature = "gelf-udp")]
+            gelf_default_bucket: lookup("GELF_DEFAULT_BUCKET").filter(|bucket| !bucket.is_empty()),
+            fluent_forward_port: lookup("FLUENT_FORWARD_PORT").and_then(|port| port.parse().ok()),
+            otlp_grpc_port: lookup("OTLP_GRPC_PORT").and_then(|port| port.parse().ok()),
+            syslog_tcp_port: lookup("SYSLOG_TCP_PORT").and_then(|port| port.parse().ok()),
+            syslog_bucket: lookup("SYSLOG_BUCKET").filter(|bucket| !bucket.is_empty()),
+            relp_port: lookup("RELP_PORT").and_then(|port| port.parse().ok()),
+            public_url: lookup("PUBLIC_URL")
+                .map(|url| url.trim().trim_end_matches('/').to_string())
+                .filter(|url| !url.is_empty())
+                .unwrap_or_else(|| DEFAULT_PUBLIC_URL.to_string()),
+        }
+    }
+}
+
+/// Split a comma-separated value, dropping empty entries
+fn comma_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Read a non-zero number
+fn count(lookup: impl Fn(&str) -> Option<String>, key: &str) -> Option<usize> {
+    lookup(key)
+        .and_then(|value| value.parse().ok())
+        .filter(|&count| count > 0)
+}
+
+/// Read a non-zero duration in seconds, falling back to `default`
+fn secs(lookup: impl Fn(&str) -> Option<String>, key: &str, default: u64) -> Duration {
+    let secs = lookup(key)
+        .and_then(|value| value.parse().ok())
+        .filter(|&secs| secs > 0)
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config(&[]);
+        assert_eq!(config.port, DEFAULT_PORT);
+        assert!(config.cors_allowed_origins.is_none());
+        assert_eq!(config.cors_max_age.as_secs(), DEFAULT_CORS_MAX_AGE_SECS);
+        assert_eq!(
+            config.request_timeout.as_secs(),
+            DEFAULT_REQUEST_TIMEOUT_SECS
+        );
+        assert_eq!(
+            config.max_subscribers_per_ip,
+            DEFAULT_MAX_SUBSCRIBERS_PER_IP
+        );
+        assert!(config.client_ip_header.is_none());
+        assert!(!config.api_only);
+        assert!(config.fastly_service_ids.is_none());
+        assert_eq!(config.proxy_profile, ProxyProfile::Generic);
+        assert_eq!(config.id_generator, IdScheme::Memorable);
+        assert_eq!(config.public_url, DEFAULT_PUBLIC_URL);
+        assert!(config.history_dir.is_none());
+        assert!(config.admin_token.is_none());
+        assert_eq!(
+            config.max_events_per_request,
+            DEFAULT_MAX_EVENTS_PER_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_admin_token_redacted() {
+        let config = config(&[("ADMIN_TOKEN", "hunter2")]);
+        assert_eq!(config.admin_token.as_ref().unwrap().expose(), "hunter2");
+        assert!(!format!("{:?}", config).contains("hunter2"));
+    }
+
+    #[test]
+    fn test_fastly_service_ids() {
+        let ids = config(&[("FASTLY_SERVICE_IDS", "abc123, def456,")]).fastly_service_ids;
+        assert_eq!(ids.unwrap(), vec!["abc123", "def456"]);
+        let ids = config(&[("FASTLY_SERVICE_IDS", " ")]).fastly_service_ids;
+        assert!(ids.is_none());
+    }
+
+    #[test]
+    fn test_runtime_tuning() {
+        assert!(config(&[]).worker_threads > 0);
+
+        let config = config(&[("WORKER_THREADS", "2"), ("MAX_BLOCKING_THREADS", "0")]);
+        assert_eq!(config.worker_threads, 2);
+        // The runtime can't run without blocking threads, so zero falls back to the default
+        assert_eq!(config.max_blocking_threads, DEFAULT_MAX_BLOCKING_THREADS);
+        assert!(config.socket_send_buffer.is_none());
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let config = config(&[("REQUEST_TIMEOUT", "5"), ("CLIENT_IDLE_TIMEOUT", "0")]);
+        assert_eq!(config.request_timeout.as_secs(), 5);
+        // Zero would drop every connection immediately, so it falls back to the default
+        assert_eq!(
+            config.client_idle_timeout.as_secs(),
+            DEFAULT_CLIENT_IDLE_TIMEOUT_SECS
+        );
+    }
+
+    #[test]
+    fn test_cors_origins() {
+        let config = config(&[(
+            "CORS_ALLOWED_ORIGINS",
+            "https://a.example, https://b.example/,",
+        )]);
+        assert_eq!(
+            config.cors_allowed_origins.unwrap(),
+            vec!["https://a.example", "https://b.example"]
+        );
+    }
+}
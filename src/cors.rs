@@ -0,0 +1,182 @@
+use crate::api::bucket_path;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header, request::Parts, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::warn;
+
+/// Bucket sub-routes that configure a bucket; these are never reachable cross-origin
+const CONFIG_ROUTES: &[&str] = &[
+    "webhooks",
+    "alerts",
+    "routes",
+    "integrations",
+    "settings",
+    "erase",
+    "events",
+    "pause",
+    "resume",
+    "ingest-urls",
+    "grok",
+    "parser",
+];
+
+pub(crate) fn is_config_route(path: &str) -> bool {
+    bucket_path(path)
+        .and_then(|(_, rest)| rest.split('/').next())
+        .is_some_and(|segment| CONFIG_ROUTES.contains(&segment))
+}
+
+/// Whether a request configures a bucket, through a config sub-route or by provisioning
+/// the bucket itself
+pub(crate) fn is_config_request(method: &Method, path: &str) -> bool {
+    is_config_route(path)
+        || (*method == Method::PUT && bucket_path(path).is_some_and(|(_, rest)| rest.is_empty()))
+}
+
+fn is_read_method(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD)
+}
+
+/// Decide whether `origin` may make a `method` request to `path`
+pub(crate) async fn origin_allowed(
+    state: &AppState,
+    origin: &str,
+    method: &Method,
+    path: &str,
+) -> bool {
+    if is_config_request(method, path) {
+        return false;
+    }
+
+    // Without an explicit origin list, keep the permissive behaviour for public routes
+    let Some(allowed_origins) = &state.config.cors_allowed_origins else {
+        return true;
+    };
+
+    if !allowed_origins.iter().any(|allowed| allowed == origin) {
+        return false;
+    }
+
+    if is_read_method(method) {
+        return true;
+    }
+
+    // Writes additionally need the bucket to opt in to the origin
+    let Some((bucket_id, _)) = bucket_path(path) else {
+        return false;
+    };
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+    match channel {
+        Some(channel) => channel.settings().await.allows_origin(origin),
+        None => false,
+    }
+}
+
+/// The method a request wants to use, looking through preflights
+fn requested_method(parts: &Parts) -> Method {
+    if parts.method == Method::OPTIONS {
+        if let Some(method) = parts
+            .headers
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|value| Method::from_bytes(value.as_bytes()).ok())
+        {
+            return method;
+        }
+    }
+    parts.method.clone()
+}
+
+pub fn layer(state: AppState) -> CorsLayer {
+    let max_age = state.config.cors_max_age;
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::async_predicate(
+            move |origin: HeaderValue, parts: &Parts| {
+                let state = state.clone();
+                let method = requested_method(parts);
+                let path = parts.uri.path().to_string();
+                async move {
+                    match origin.to_str() {
+                        Ok(origin) => origin_allowed(&state, origin, &method, &path).await,
+                        Err(_) => false,
+                    }
+                }
+            },
+        ))
+        .allow_methods([Method::GET, Method::HEAD, Method::POST])
+        .allow_headers(Any)
+        .expose_headers([header::CONTENT_TYPE, header::ETAG, header::CONTENT_RANGE])
+        .max_age(max_age)
+}
+
+/// Browsers send simple cross-origin POSTs without a preflight, so reject writes the
+/// CORS policy would not allow before they reach a handler. Config writes from a foreign
+/// origin are refused even when no origin list is configured.
+pub async fn guard_cross_origin_writes(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_read_method(request.method()) || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let origin = request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok());
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok());
+
+    if let Some(origin) = origin {
+        let same_origin = host.is_some_and(|host| origin.split("://").nth(1) == Some(host));
+        let path = request.uri().path();
+        if !same_origin && !origin_allowed(&state, origin, request.method(), path).await {
+            warn!(
+                "Rejected cross-origin {} to {} from {}",
+                request.method(),
+                path,
+                origin
+            );
+            return (
+                StatusCode::FORBIDDEN,
+                "Cross-origin writes are not enabled for this bucket",
+            )
+                .into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_config_route() {
+        assert!(is_config_route("/my-bucket/webhooks"));
+        assert!(is_config_route("/my-bucket/integrations/slack"));
+        assert!(is_config_route("/my-bucket/grok"));
+        assert!(is_config_route("/api/v1/buckets/my-bucket/parser"));
+        assert!(!is_config_route("/my-bucket"));
+        assert!(!is_config_route("/my-bucket/log"));
+        assert!(is_config_route("/api/v1/buckets/my-bucket/alerts"));
+        assert!(!is_config_route("/api/v1/buckets/my-bucket"));
+        assert!(is_config_request(&Method::PUT, "/api/v1/buckets/my-bucket"));
+        assert!(!is_config_request(
+            &Method::POST,
+            "/api/v1/buckets/my-bucket"
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::AppState;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Well-known bucket fed by the built-in sample log generator
+pub const DEMO_BUCKET_ID: &str = "demo";
+
+const DEMO_MIN_INTERVAL_MS: u64 = 300;
+const DEMO_MAX_INTERVAL_MS: u64 = 1500;
+
+const METHODS: &[&str] = &["GET", "GET", "GET", "POST", "PUT", "DELETE"];
+const PATHS: &[&str] = &[
+    "/",
+    "/api/users",
+    "/api/orders",
+    "/api/orders/1234",
+    "/login",
+    "/static/app.js",
+    "/healthz",
+];
+const STATUSES: &[u16] = &[200, 200, 200, 200, 201, 204, 301, 304, 404, 500, 503];
+const SERVICES: &[&str] = &["frontend", "checkout", "auth", "inventory"];
+const REGIONS: &[&str] = &["LHR", "JFK", "SJC", "NRT", "FRA"];
+const ERRORS: &[&str] = &[
+    "connection reset by peer",
+    "upstream request timeout",
+    "database pool exhausted",
+    "failed to decode request body",
+];
+
+/// Small xorshift generator; sample data doesn't need anything stronger
+struct Rng(u64);
+
+impl Rng {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64;
+        Self(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn range(&mut self, min: u64, max: u64) -> u64 {
+        min + self.next() % (max - min + 1)
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.next() as usize % items.len()]
+    }
+}
+
+/// Generate a single sample log line in one of the supported formats
+fn sample_line(rng: &mut Rng) -> String {
+    let status = *rng.pick(STATUSES);
+    let level = match status {
+        500.. => "error",
+        400..=499 => "warn",
+        _ => "info",
+    };
+
+    match rng.range(0, 9) {
+        // JSON access log
+        0..=4 => serde_json::json!({
+            "level": level,
+            "service": rng.pick(SERVICES),
+            "method": rng.pick(METHODS),
+            "path": rng.pick(PATHS),
+            "status": status,
+            "duration_ms": rng.range(1, 800),
+            "pop": rng.pick(REGIONS),
+        })
+        .to_string(),
+        // Structured key=value line
+        5..=7 => format!(
+            "level={}, service={}, msg=\"cache {}\", key=\"{}\", ttl={}",
+            level,
+            rng.pick(SERVICES),
+            if rng.range(0, 1) == 0 { "hit" } else { "miss" },
+            rng.pick(PATHS),
+            rng.range(30, 3600)
+        ),
+        // Plain text error
+        _ => format!(
+            "ERROR [{}] {}: {} (attempt {})",
+            rng.pick(SERVICES),
+            rng.pick(PATHS),
+            rng.pick(ERRORS),
+            rng.range(1, 3)
+        ),
+    }
+}
+
+/// Feed the demo bucket, for as long as the task runs.
+/// Lines are only generated while someone is watching.
+pub async fn run_generator(state: AppState) {
+    info!("Demo generator started for bucket: {}", DEMO_BUCKET_ID);
+    let mut rng = Rng::new();
+
+    loop {
+        let delay = rng.range(DEMO_MIN_INTERVAL_MS, DEMO_MAX_INTERVAL_MS);
+        tokio::time::sleep(tokio::time::Duration::from_millis(delay)).await;
+
+        let channel = state
+            .channel_manager
+            .read()
+            .await
+            .get_channel(DEMO_BUCKET_ID);
+        let Some(channel) = channel else {
+            continue;
+        };
+        if channel.subscriber_count() == 0 {
+            continue;
+        }
+
+        let line = sample_line(&mut rng);
+        if let IngestOutcome::Suspended = ingest_lines(&state, &channel, &[line.as_str()]).await {
+            warn!("Demo bucket was suspended by the rate limiter");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::ParsedEvent;
+
+    #[test]
+    fn test_sample_lines_cover_formats() {
+        let mut rng = Rng::new();
+        let mut parsers = std::collections::HashSet::new();
+
+        for _ in 0..500 {
+            let mut event = ParsedEvent::new(sample_line(&mut rng));
+            event.parse();
+            parsers.insert(event.parser);
+        }
+
+        assert!(parsers.contains(&Some("json".to_string())));
+        assert!(parsers.contains(&Some("structuredHeaders".to_string())));
+        assert!(parsers.contains(&None));
+    }
+}
@@ -0,0 +1,109 @@
+const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+const UTF16LE_BOM: [u8; 2] = [0xff, 0xfe];
+const UTF16BE_BOM: [u8; 2] = [0xfe, 0xff];
+
+/// Text encodings recognized in uploaded and imported files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+/// Decode a file to text, honouring a byte-order mark and otherwise guessing between
+/// UTF-8, BOM-less UTF-16 and Latin-1, so files from Windows tooling don't become mojibake
+pub fn decode(bytes: &[u8]) -> (String, Encoding) {
+    if let Some(rest) = bytes.strip_prefix(&UTF8_BOM) {
+        return (String::from_utf8_lossy(rest).into_owned(), Encoding::Utf8);
+    }
+    if let Some(rest) = bytes.strip_prefix(&UTF16LE_BOM) {
+        return (decode_utf16(rest, u16::from_le_bytes), Encoding::Utf16Le);
+    }
+    if let Some(rest) = bytes.strip_prefix(&UTF16BE_BOM) {
+        return (decode_utf16(rest, u16::from_be_bytes), Encoding::Utf16Be);
+    }
+
+    if let Some(encoding) = sniff_utf16(bytes) {
+        let text = match encoding {
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            _ => decode_utf16(bytes, u16::from_be_bytes),
+        };
+        return (text, encoding);
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text.to_string(), Encoding::Utf8),
+        // Every byte is a valid Latin-1 character, so this is the fallback of last resort
+        Err(_) => (bytes.iter().map(|&b| b as char).collect(), Encoding::Latin1),
+    }
+}
+
+/// Whether a file starting with `prefix` is UTF-16, by its byte-order mark or its zeros
+pub fn is_utf16(prefix: &[u8]) -> bool {
+    prefix.starts_with(&UTF16LE_BOM)
+        || prefix.starts_with(&UTF16BE_BOM)
+        || sniff_utf16(prefix).is_some()
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Mostly-ASCII UTF-16 has a zero in every other byte, which UTF-8 text never does
+fn sniff_utf16(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let pairs = bytes.len() / 2;
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    let even_zeros = bytes.iter().step_by(2).filter(|&&b| b == 0).count();
+    if odd_zeros * 10 >= pairs * 9 && even_zeros == 0 {
+        Some(Encoding::Utf16Le)
+    } else if even_zeros * 10 >= pairs * 9 && odd_zeros == 0 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            decode("caf\u{e9}\n".as_bytes()),
+            ("caf\u{e9}\n".to_string(), Encoding::Utf8)
+        );
+        assert_eq!(
+            decode(b"\xef\xbb\xbfa=1"),
+            ("a=1".to_string(), Encoding::Utf8)
+        );
+        assert_eq!(
+            decode(b"caf\xe9"),
+            ("caf\u{e9}".to_string(), Encoding::Latin1)
+        );
+
+        let mut bom = UTF16LE_BOM.to_vec();
+        bom.extend(utf16le("level=warn \u{2603}"));
+        assert_eq!(
+            decode(&bom),
+            ("level=warn \u{2603}".to_string(), Encoding::Utf16Le)
+        );
+        assert_eq!(
+            decode(&utf16le("level=info\r\n")),
+            ("level=info\r\n".to_string(), Encoding::Utf16Le)
+        );
+    }
+}
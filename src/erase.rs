@@ -0,0 +1,149 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::{changes, tokens, AppState};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Events to erase: those whose `field` has exactly `value`
+#[derive(Debug, Deserialize)]
+pub struct EraseRequest {
+    pub field: String,
+    pub value: String,
+}
+
+/// A record that an erasure happened. The erased value itself is not kept.
+#[derive(Debug, Clone, Serialize)]
+pub struct Tombstone {
+    pub time: i64,
+    pub field: String,
+    /// Number of retained events removed
+    pub count: usize,
+    /// Sequence numbers of the removed events, so viewers can drop them too
+    pub seqs: Vec<u64>,
+}
+
+/// Events to delete: sequence numbers `from` to `to`, inclusive
+#[derive(Debug, Deserialize)]
+pub struct SeqRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+/// A gap left in a bucket's history, sent to subscribers as a `redacted` event
+#[derive(Debug, Clone, Serialize)]
+pub struct Redaction {
+    pub time: i64,
+    pub from: u64,
+    pub to: u64,
+    /// Number of retained events removed; earlier ones may already have aged out
+    pub count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TombstoneList {
+    pub tombstones: Vec<Tombstone>,
+}
+
+pub async fn get_erasures(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<TombstoneList> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let tombstones = match channel {
+        Some(channel) => channel.tombstones().await,
+        None => Vec::new(),
+    };
+    Json(TombstoneList { tombstones })
+}
+
+/// Remove every retained event matching a field value, e.g. for a subject erasure request
+pub async fn post_erase(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<EraseRequest>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel else {
+        return Ok(Json(Tombstone {
+            time: chrono::Utc::now().timestamp_millis(),
+            field: request.field,
+            count: 0,
+            seqs: Vec::new(),
+        })
+        .into_response());
+    };
+
+    let tombstone = channel.erase(&request.field, &request.value).await;
+    info!(
+        target: "audit",
+        "Erased {} events from bucket {} by field {} ({})",
+        tombstone.count,
+        bucket_id,
+        tombstone.field,
+        changes::actor(&state, &headers).as_deref().unwrap_or("anonymous")
+    );
+
+    Ok(Json(tombstone).into_response())
+}
+
+/// Remove a contiguous slice of history, e.g. after a secret was posted by mistake
+pub async fn delete_events(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Query(range): Query<SeqRange>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+    if range.from == 0 || range.from > range.to {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "`from` and `to` must be sequence numbers with from <= to",
+        )
+            .into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let redaction = channel.redact(range.from, range.to).await;
+    info!(
+        target: "audit",
+        "Deleted {} events ({}-{}) from bucket {}",
+        redaction.count,
+        redaction.from,
+        redaction.to,
+        bucket_id
+    );
+
+    Ok(Json(redaction).into_response())
+}
@@ -0,0 +1,292 @@
+use crate::models::LogEvent;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::Value;
+use std::ops::Range;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    /// Only export events with at least this sequence number
+    from_seq: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeFormat {
+    EpochMs,
+    Rfc3339,
+}
+
+/// `?tz=` and `?timefmt=` options for endpoints that return events
+#[derive(Debug, Default, Deserialize)]
+pub struct TimeOptions {
+    tz: Option<String>,
+    timefmt: Option<TimeFormat>,
+}
+
+impl TimeOptions {
+    /// Validate the options, rejecting unknown time zones
+    pub fn formatter(&self) -> Result<TimeFormatter, (StatusCode, String)> {
+        let tz = match &self.tz {
+            Some(tz) => tz.parse::<Tz>().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Unknown time zone: {}", tz),
+                )
+            })?,
+            None => Tz::UTC,
+        };
+
+        // Asking for a time zone implies wanting readable times
+        let format = self.timefmt.unwrap_or(match self.tz {
+            Some(_) => TimeFormat::Rfc3339,
+            None => TimeFormat::EpochMs,
+        });
+
+        Ok(TimeFormatter { tz, format })
+    }
+}
+
+pub struct TimeFormatter {
+    tz: Tz,
+    format: TimeFormat,
+}
+
+impl TimeFormatter {
+    /// Serialize an event with its timestamps in the requested format
+    pub fn event_json(&self, event: &LogEvent) -> Value {
+        let mut value = serde_json::to_value(event).unwrap();
+        if self.format == TimeFormat::EpochMs {
+            return value;
+        }
+
+        for key in ["time", "reportedTime"] {
+            if let Some(time) = value.get_mut(key) {
+                if let Some(formatted) = time.as_i64().and_then(|ms| self.rfc3339(ms)) {
+                    *time = Value::String(formatted);
+                }
+            }
+        }
+        value
+    }
+
+    fn rfc3339(&self, ms: i64) -> Option<String> {
+        let time = Utc.timestamp_millis_opt(ms).single()?;
+        Some(time.with_timezone(&self.tz).to_rfc3339())
+    }
+}
+
+/// Download a bucket's retained history as newline-delimited JSON
+///
+/// Interrupted downloads can resume with either `?from_seq=` or a single `Range: bytes=`.
+pub async fn get_export(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<ExportQuery>,
+    Query(time): Query<TimeOptions>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let formatter = match time.formatter() {
+        Ok(formatter) => formatter,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let events = match channel {
+        Some(channel) => channel.history().await,
+        None => Vec::new(),
+    };
+
+    // The newest sequence number identifies the export contents exactly
+    let etag = format!("\"{}\"", events.last().map_or(0, |event| event.seq));
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, etag.parse().unwrap());
+    response_headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let from_seq = query.from_seq.unwrap_or(0);
+    let mut body = String::new();
+    for event in events.iter().filter(|event| event.seq >= from_seq) {
+        body.push_str(&formatter.event_json(event).to_string());
+        body.push('\n');
+    }
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        "application/x-ndjson".parse().unwrap(),
+    );
+    response_headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{}.ndjson\"", bucket_id)
+            .parse()
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+    );
+    response_headers.insert(header::ACCEPT_RANGES, "bytes".parse().unwrap());
+
+    // A range only applies if the client is resuming the same version of the export
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches(&headers, &etag));
+    let Some(range) = range else {
+        return Ok((response_headers, body).into_response());
+    };
+
+    match parse_range(range, body.len()) {
+        Some(range) => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, body.len())
+                    .parse()
+                    .unwrap(),
+            );
+            let partial = body.into_bytes()[range].to_vec();
+            Ok((StatusCode::PARTIAL_CONTENT, response_headers, partial).into_response())
+        }
+        None => {
+            response_headers.insert(
+                header::CONTENT_RANGE,
+                format!("bytes */{}", body.len()).parse().unwrap(),
+            );
+            Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response())
+        }
+    }
+}
+
+/// Whether an `If-Range` precondition, if any, matches the current ETag
+fn if_range_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .is_none_or(|value| value.trim() == etag)
+}
+
+/// Parse a single `bytes=` range against a body of `len` bytes, as a half-open range
+fn parse_range(header: &str, len: usize) -> Option<Range<usize>> {
+    let spec = header.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        // Multipart byte ranges aren't worth supporting for exports
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        // Suffix range: the last `n` bytes
+        ("", suffix) => {
+            let suffix: usize = suffix.parse().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.checked_sub(1)?),
+        (start, end) => {
+            let end: usize = end.parse().ok()?;
+            (start.parse().ok()?, end.min(len.checked_sub(1)?))
+        }
+    };
+
+    (start <= end && start < len).then_some(start..end + 1)
+}
+
+/// Whether an `If-None-Match` header matches the current ETag
+pub(crate) fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').map(str::trim).any(|tag| {
+                // If-None-Match uses weak comparison
+                tag == "*" || tag.trim_start_matches("W/") == etag
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_none_match_header(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        assert!(if_none_match(&if_none_match_header("\"42\""), "\"42\""));
+        assert!(if_none_match(
+            &if_none_match_header("\"1\", W/\"42\""),
+            "\"42\""
+        ));
+        assert!(if_none_match(&if_none_match_header("*"), "\"42\""));
+        assert!(!if_none_match(&if_none_match_header("\"41\""), "\"42\""));
+        assert!(!if_none_match(&HeaderMap::new(), "\"42\""));
+    }
+
+    #[test]
+    fn test_time_formatting() {
+        let event: LogEvent = LogEvent {
+            seq: 1,
+            time: 1_704_067_201_500,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: "hello".to_string(),
+            raw_ansi: None,
+            fields: Default::default(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        };
+
+        let options: TimeOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(
+            options.formatter().unwrap().event_json(&event)["time"],
+            1_704_067_201_500i64
+        );
+
+        let options: TimeOptions = serde_json::from_str(r#"{"tz":"Europe/London"}"#).unwrap();
+        assert_eq!(
+            options.formatter().unwrap().event_json(&event)["time"],
+            "2024-01-01T00:00:01.500+00:00"
+        );
+
+        let options: TimeOptions =
+            serde_json::from_str(r#"{"tz":"Asia/Tokyo","timefmt":"rfc3339"}"#).unwrap();
+        assert_eq!(
+            options.formatter().unwrap().event_json(&event)["time"],
+            "2024-01-01T09:00:01.500+09:00"
+        );
+
+        let options: TimeOptions = serde_json::from_str(r#"{"tz":"Mars/Olympus"}"#).unwrap();
+        assert!(options.formatter().is_err());
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-9", 100), Some(0..10));
+        assert_eq!(parse_range("bytes=90-", 100), Some(90..100));
+        assert_eq!(parse_range("bytes=-10", 100), Some(90..100));
+        assert_eq!(parse_range("bytes=50-500", 100), Some(50..100));
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=0-1,5-6", 100), None);
+        assert_eq!(parse_range("items=0-1", 100), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::export::if_none_match;
+use crate::integrations::bucket_link;
+use crate::models::LogEvent;
+use crate::severity::Severity;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+/// Entries in a feed, newest first
+const MAX_FEED_ENTRIES: usize = 50;
+/// Characters of an event's first line used as its entry title
+const ENTRY_TITLE_LENGTH: usize = 120;
+/// Fields that usually hold an event's human-readable message, preferred as its title
+const MESSAGE_KEYS: &[&str] = &["message", "msg"];
+
+/// `GET /{bucket_id}/feed.atom`: recent error and critical events as an Atom feed
+pub async fn get_feed(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    // Feed readers poll buckets whether or not anyone is viewing, so unknown ones are empty
+    let events = match channel {
+        Some(channel) => channel.history().await,
+        None => Vec::new(),
+    };
+    let entries: Vec<&LogEvent> = events
+        .iter()
+        .rev()
+        .filter(|event| Severity::of(event).is_some_and(|severity| severity >= Severity::Error))
+        .take(MAX_FEED_ENTRIES)
+        .collect();
+
+    let etag = format!("\"{}\"", entries.first().map_or(0, |event| event.seq));
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, etag.parse().unwrap());
+    response_headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        "application/atom+xml; charset=utf-8".parse().unwrap(),
+    );
+    let updated = entries
+        .first()
+        .map_or_else(|| Utc::now().timestamp_millis(), |event| event.time);
+    Ok((
+        response_headers,
+        render(
+            &bucket_link(&state.config.public_url, &bucket_id),
+            &bucket_id,
+            &entries,
+            updated,
+        ),
+    )
+        .into_response())
+}
+
+fn render(link: &str, bucket_id: &str, entries: &[&LogEvent], updated: i64) -> String {
+    let link = escape(link);
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <id>{}</id>\n", link));
+    feed.push_str(&format!(
+        "  <title>Errors in {}</title>\n",
+        escape(bucket_id)
+    ));
+    feed.push_str(&format!("  <link rel=\"alternate\" href=\"{}\"/>\n", link));
+    feed.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}/feed.atom\"/>\n",
+        link
+    ));
+    feed.push_str(&format!("  <updated>{}</updated>\n", timestamp(updated)));
+    feed.push_str("  <author><name>log-bin</name></author>\n");
+
+    for event in entries {
+        let severity = Severity::of(event).map_or("error", Severity::as_str);
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!("    <id>{}#{}</id>\n", link, event.seq));
+        feed.push_str(&format!(
+            "    <title>[{}] {}</title>\n",
+            severity,
+            escape(&title(event))
+        ));
+        feed.push_str(&format!("    <link href=\"{}\"/>\n", link));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            timestamp(event.time)
+        ));
+        feed.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            escape(&event.raw)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// The first line of an event's message, shortened to fit a feed reader's list
+fn title(event: &LogEvent) -> String {
+    let message = MESSAGE_KEYS
+        .iter()
+        .find_map(|key| event.fields.get(*key))
+        .map_or(event.raw.as_str(), |field| field.value.as_str());
+    let line = message.lines().next().unwrap_or_default();
+    match line.char_indices().nth(ENTRY_TITLE_LENGTH) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+fn timestamp(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Escape markup, and drop control characters such as ANSI escapes that XML can't hold
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::ParsedEvent;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("\u{1b}[31m<b>\"a\" & b</b>\u{1b}[0m"),
+            "[31m&lt;b&gt;&quot;a&quot; &amp; b&lt;/b&gt;[0m"
+        );
+    }
+
+    #[test]
+    fn test_title() {
+        let mut event = LogEvent {
+            seq: 1,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: "first\nsecond".to_string(),
+            raw_ansi: None,
+            fields: HashMap::new(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        };
+        assert_eq!(title(&event), "first");
+
+        event.raw = "é".repeat(200);
+        assert_eq!(title(&event).chars().count(), ENTRY_TITLE_LENGTH + 1);
+
+        let mut message = ParsedEvent::new(r#"{"msg":"disk full"}"#.to_string());
+        message.parse();
+        event.fields = message.fields;
+        assert_eq!(title(&event), "disk full");
+    }
+}
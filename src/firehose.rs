@@ -0,0 +1,332 @@
+use crate::compression::{gunzip, is_gzip};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+/// Unique ID of the delivery, which Firehose expects echoed back in the reply
+const REQUEST_ID_HEADER: &str = "X-Amz-Firehose-Request-Id";
+/// Base64 records take a third more room than the logs they carry, so allow for that
+const MAX_FIREHOSE_BODY_SIZE: usize = MAX_LOG_BODY_SIZE * 2;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeliveryRequest {
+    #[serde(default)]
+    request_id: String,
+    records: Vec<Record>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Record {
+    data: String,
+}
+
+/// The reply Firehose checks for its `requestId`, with an error message when it failed
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeliveryReply {
+    request_id: String,
+    timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_message: Option<String>,
+}
+
+/// A batch CloudWatch Logs subscription filters deliver through Firehose, gzipped
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloudWatchBatch {
+    message_type: String,
+    #[serde(default)]
+    log_group: String,
+    #[serde(default)]
+    log_stream: String,
+    #[serde(default)]
+    log_events: Vec<CloudWatchEvent>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudWatchEvent {
+    timestamp: i64,
+    message: String,
+}
+
+fn reply(request_id: &str, status: StatusCode, error_message: Option<String>) -> Response {
+    let reply = DeliveryReply {
+        request_id: request_id.to_string(),
+        timestamp: chrono::Utc::now().timestamp_millis(),
+        error_message,
+    };
+    (status, Json(reply)).into_response()
+}
+
+fn error_reply(request_id: &str, status: StatusCode, message: impl Into<String>) -> Response {
+    reply(request_id, status, Some(message.into()))
+}
+
+/// Decode the records in a delivery into log lines. Records holding a CloudWatch Logs batch
+/// become one line per log event; anything else is split into lines as plain text.
+fn decode(records: &[Record]) -> Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    for (number, record) in records.iter().enumerate() {
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(record.data.trim())
+            .map_err(|_| format!("Record {} is not valid base64", number))?;
+        let data = if is_gzip(&data) {
+            gunzip(&data, MAX_LOG_BODY_SIZE)
+                .map_err(|_| format!("Record {} is not valid gzip", number))?
+        } else {
+            data
+        };
+
+        if let Ok(batch) = serde_json::from_slice::<CloudWatchBatch>(&data) {
+            // Control messages only check the destination is reachable
+            if batch.message_type == "DATA_MESSAGE" {
+                lines.extend(
+                    batch
+                        .log_events
+                        .iter()
+                        .map(|event| cloudwatch_line(&batch, event)),
+                );
+            }
+            continue;
+        }
+
+        let text = std::str::from_utf8(&data)
+            .map_err(|_| format!("Record {} is not valid UTF-8", number))?;
+        lines.extend(
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(String::from),
+        );
+    }
+    Ok(lines)
+}
+
+/// Render a CloudWatch log event with its log group, stream and time merged in
+fn cloudwatch_line(batch: &CloudWatchBatch, event: &CloudWatchEvent) -> String {
+    let mut object = match serde_json::from_str(&event.message) {
+        Ok(Value::Object(object)) => object,
+        _ => Map::from_iter([("message".to_string(), event.message.trim_end().into())]),
+    };
+    object
+        .entry("logGroup")
+        .or_insert(batch.log_group.as_str().into());
+    object
+        .entry("logStream")
+        .or_insert(batch.log_stream.as_str().into());
+    if let Some(time) = DateTime::from_timestamp_millis(event.timestamp) {
+        let time = time.to_rfc3339_opts(SecondsFormat::Millis, true);
+        object.entry("time").or_insert(time.into());
+    }
+    Value::Object(object).to_string()
+}
+
+/// `POST /firehose`: a Kinesis Data Firehose HTTP endpoint destination. Events go to the
+/// bucket mapped in `TOKENS_FILE` to the access key configured on the delivery stream.
+pub async fn post_delivery(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let bucket_id =
+        tokens::identify(&state, &headers).and_then(|token| token.bucket().map(String::from));
+    let Some(bucket_id) = bucket_id else {
+        let request_id = request_id(&headers);
+        return error_reply(
+            &request_id,
+            StatusCode::UNAUTHORIZED,
+            "The access key must be a write token mapped to a bucket",
+        );
+    };
+    accept_delivery(state, headers, bucket_id, body).await
+}
+
+/// `POST /{bucket_id}/firehose`: a Kinesis Data Firehose HTTP endpoint destination for a
+/// named bucket
+pub async fn post_bucket_delivery(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    accept_delivery(state, headers, bucket_id, body).await
+}
+
+fn request_id(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+async fn accept_delivery(
+    state: AppState,
+    headers: HeaderMap,
+    bucket_id: String,
+    body: Bytes,
+) -> Response {
+    let mut request_id = request_id(&headers);
+    if bucket_id == DEMO_BUCKET_ID {
+        return error_reply(
+            &request_id,
+            StatusCode::FORBIDDEN,
+            "The demo bucket is read-only",
+        );
+    }
+    if ids::is_reserved(&bucket_id) {
+        return error_reply(&request_id, StatusCode::NOT_FOUND, "No such bucket");
+    }
+    if body.len() > MAX_FIREHOSE_BODY_SIZE {
+        return error_reply(
+            &request_id,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Lower the delivery stream's buffer size",
+        );
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err((status, message)) => return error_reply(&request_id, status, message),
+    };
+
+    // Firehose can gzip the whole request when content encoding is enabled
+    let body = if is_gzip(&body) {
+        match gunzip(&body, MAX_FIREHOSE_BODY_SIZE) {
+            Ok(body) => body,
+            Err(_) => {
+                return error_reply(
+                    &request_id,
+                    StatusCode::BAD_REQUEST,
+                    "Body is not valid gzip",
+                )
+            }
+        }
+    } else {
+        body.to_vec()
+    };
+    let delivery: DeliveryRequest = match serde_json::from_slice(&body) {
+        Ok(delivery) => delivery,
+        Err(error) => {
+            return error_reply(
+                &request_id,
+                StatusCode::BAD_REQUEST,
+                format!("Body is not a Firehose delivery: {}", error),
+            )
+        }
+    };
+    if request_id.is_empty() {
+        request_id = delivery.request_id;
+    }
+    let lines = match decode(&delivery.records) {
+        Ok(lines) => lines,
+        Err(message) => return error_reply(&request_id, StatusCode::BAD_REQUEST, message),
+    };
+    if let Err((status, message)) = check_event_count(&state, lines.len(), "lower the buffer size")
+    {
+        return error_reply(&request_id, status, message);
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel.filter(|_| !lines.is_empty()) else {
+        if !lines.is_empty() {
+            warn!(
+                "Discarding Firehose delivery for bucket with no viewers: {}",
+                bucket_id
+            );
+        }
+        return reply(&request_id, StatusCode::OK, None);
+    };
+
+    if channel.is_suspended() {
+        return error_reply(
+            &request_id,
+            StatusCode::TOO_MANY_REQUESTS,
+            SUSPENSION_REASON_TEXT,
+        );
+    }
+
+    info!(
+        "New Firehose delivery for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+    if let Some(token) = &token {
+        let now = chrono::Utc::now();
+        token.record(lines.len() as u64, body.len() as u64, now);
+        token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => reply(&request_id, StatusCode::OK, None),
+        IngestOutcome::Suspended => error_reply(
+            &request_id,
+            StatusCode::TOO_MANY_REQUESTS,
+            SUSPENSION_REASON_TEXT,
+        ),
+        IngestOutcome::Paused => {
+            error_reply(&request_id, StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn record(data: &[u8]) -> Record {
+        Record {
+            data: base64::engine::general_purpose::STANDARD.encode(data),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let batch = r#"{"messageType":"DATA_MESSAGE","logGroup":"/aws/lambda/api","logStream":"2024/01/01/abc","logEvents":[{"id":"1","timestamp":1700000000500,"message":"START RequestId: 42\n"},{"id":"2","timestamp":1700000001000,"message":"{\"level\":\"error\",\"logGroup\":\"own\"}"}]}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(batch.as_bytes()).unwrap();
+        let control = r#"{"messageType":"CONTROL_MESSAGE","logEvents":[{"id":"","timestamp":0,"message":"CWL CONTROL MESSAGE"}]}"#;
+
+        let records = [
+            record(&encoder.finish().unwrap()),
+            record(control.as_bytes()),
+            record(b"plain one\n\nplain two\n"),
+        ];
+        let lines = decode(&records).unwrap();
+        assert_eq!(lines.len(), 4);
+
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["message"], "START RequestId: 42");
+        assert_eq!(first["logGroup"], "/aws/lambda/api");
+        assert_eq!(first["time"], "2023-11-14T22:13:20.500Z");
+        let second: Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["level"], "error");
+        assert_eq!(second["logGroup"], "own");
+        assert_eq!(&lines[2..], ["plain one", "plain two"]);
+
+        let invalid = [Record {
+            data: "not base64!".to_string(),
+        }];
+        assert!(decode(&invalid).is_err());
+    }
+}
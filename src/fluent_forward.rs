@@ -0,0 +1,303 @@
+use crate::compression::gunzip;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ids;
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::AppState;
+use rmpv::decode::read_value_ref;
+use rmpv::Value;
+use serde_json::{Map, Number};
+use std::io::ErrorKind;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, warn};
+
+/// Largest Forward message buffered while it arrives; fluent-bit's chunks are a few MB
+const MAX_FORWARD_MESSAGE_SIZE: usize = 8 * 1024 * 1024;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+/// Fluentd's EventTime extension: big-endian seconds then nanoseconds
+const EVENT_TIME_EXT: i8 = 0;
+
+/// The entries of one Forward message, and the chunk to acknowledge if the sender asked
+#[derive(Debug)]
+struct ForwardMessage {
+    tag: String,
+    lines: Vec<String>,
+    chunk: Option<String>,
+}
+
+/// Decode a Forward message in any of its modes: Message (`[tag, time, record]`),
+/// Forward (`[tag, [[time, record], ...]]`) and (Compressed)PackedForward, where the
+/// entries are concatenated msgpack in a binary, gzipped if the options say so.
+fn decode_message(message: &Value) -> Result<ForwardMessage, &'static str> {
+    let Some(array) = message.as_array().filter(|array| array.len() >= 2) else {
+        return Err("Forward messages must be arrays of a tag and entries");
+    };
+    let tag = array[0].as_str().ok_or("Forward tags must be strings")?;
+
+    let (entries, options) = match &array[1] {
+        Value::Array(entries) => (entries.clone(), array.get(2)),
+        Value::Binary(_) | Value::String(_) => {
+            let options = array.get(2);
+            let packed = match &array[1] {
+                Value::Binary(bytes) => bytes.as_slice(),
+                _ => array[1].as_str().unwrap_or_default().as_bytes(),
+            };
+            let compressed = option(options, "compressed").is_some_and(|c| c == "gzip");
+            let packed = if compressed {
+                gunzip(packed, MAX_FORWARD_MESSAGE_SIZE)
+                    .map_err(|_| "PackedForward entries are not valid gzip")?
+            } else {
+                packed.to_vec()
+            };
+            (unpack_entries(&packed)?, options)
+        }
+        time => {
+            let record = array.get(2).ok_or("Forward messages need a record")?;
+            (
+                vec![Value::Array(vec![time.clone(), record.clone()])],
+                array.get(3),
+            )
+        }
+    };
+
+    let lines = entries.iter().map(entry_line).collect::<Result<_, _>>()?;
+    Ok(ForwardMessage {
+        tag: tag.to_string(),
+        lines,
+        chunk: option(options, "chunk").map(String::from),
+    })
+}
+
+fn option<'a>(options: Option<&'a Value>, key: &str) -> Option<&'a str> {
+    options?
+        .as_map()?
+        .iter()
+        .find(|(name, _)| name.as_str() == Some(key))
+        .and_then(|(_, value)| value.as_str())
+}
+
+fn unpack_entries(mut packed: &[u8]) -> Result<Vec<Value>, &'static str> {
+    let mut entries = Vec::new();
+    while !packed.is_empty() {
+        let entry =
+            read_value_ref(&mut packed).map_err(|_| "PackedForward entries are truncated")?;
+        entries.push(entry.to_owned());
+    }
+    Ok(entries)
+}
+
+/// Render a `[time, record]` entry as a JSON line, adding the event time as `time`
+/// unless the record has its own
+fn entry_line(entry: &Value) -> Result<String, &'static str> {
+    let Some([time, Value::Map(record)]) = entry.as_array().map(Vec::as_slice) else {
+        return Err("Forward entries must be a time and a record");
+    };
+
+    let mut object = to_object(record);
+    if !object.contains_key("time") {
+        if let Some(seconds) = event_time(time) {
+            object.insert("time".to_string(), seconds);
+        }
+    }
+    Ok(serde_json::Value::Object(object).to_string())
+}
+
+/// Event times are whole seconds, or an EventTime with nanoseconds
+fn event_time(time: &Value) -> Option<serde_json::Value> {
+    if let Some(seconds) = time.as_u64() {
+        return Some(seconds.into());
+    }
+    let (EVENT_TIME_EXT, data) = time.as_ext()? else {
+        return None;
+    };
+    let data: [u8; 8] = data.try_into().ok()?;
+    let seconds = u32::from_be_bytes(data[..4].try_into().ok()?);
+    let nanos = u32::from_be_bytes(data[4..].try_into().ok()?);
+    Number::from_f64(seconds as f64 + nanos as f64 / 1e9).map(serde_json::Value::Number)
+}
+
+fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Nil | Value::Ext(..) => serde_json::Value::Null,
+        Value::Boolean(b) => (*b).into(),
+        Value::Integer(i) => match i.as_i64() {
+            Some(i) => i.into(),
+            None => i.as_u64().map_or(serde_json::Value::Null, Into::into),
+        },
+        Value::F32(f) => Number::from_f64(*f as f64).map_or(serde_json::Value::Null, Into::into),
+        Value::F64(f) => Number::from_f64(*f).map_or(serde_json::Value::Null, Into::into),
+        Value::String(s) => String::from_utf8_lossy(s.as_bytes()).into(),
+        Value::Binary(bytes) => String::from_utf8_lossy(bytes).into(),
+        Value::Array(items) => items.iter().map(to_json).collect(),
+        Value::Map(entries) => to_object(entries).into(),
+    }
+}
+
+/// Msgpack maps can have any keys; non-string ones are written out as msgpack text
+fn to_object(entries: &[(Value, Value)]) -> Map<String, serde_json::Value> {
+    entries
+        .iter()
+        .map(|(key, value)| {
+            let key = key.as_str().map_or_else(|| key.to_string(), String::from);
+            (key, to_json(value))
+        })
+        .collect()
+}
+
+/// The `{"ack": chunk}` reply that tells an at-least-once sender the chunk arrived
+fn ack(chunk: &str) -> Vec<u8> {
+    let reply = Value::Map(vec![(Value::from("ack"), Value::from(chunk))]);
+    let mut bytes = Vec::new();
+    rmpv::encode::write_value(&mut bytes, &reply).expect("Writing to a Vec can't fail");
+    bytes
+}
+
+/// Accept Fluentd and Fluent Bit forwarders on `listener`, publishing each message to
+/// the bucket named by its tag
+pub fn spawn(listener: TcpListener, state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!("Fluent forwarder connected from {}", peer);
+                    tokio::spawn(serve(stream, state.clone()));
+                }
+                Err(e) => warn!("Fluent Forward accept failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn serve(mut stream: TcpStream, state: AppState) {
+    let mut buffer = Vec::new();
+    let mut read_buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let read = match stream.read(&mut read_buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        buffer.extend_from_slice(&read_buffer[..read]);
+
+        loop {
+            let (message, consumed) = {
+                let mut reader = buffer.as_slice();
+                match read_value_ref(&mut reader) {
+                    Ok(message) => (message.to_owned(), buffer.len() - reader.len()),
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(e) => {
+                        debug!("Dropping Fluent forwarder sending invalid msgpack: {}", e);
+                        return;
+                    }
+                }
+            };
+            buffer.drain(..consumed);
+
+            let message = match decode_message(&message) {
+                Ok(message) => message,
+                Err(reason) => {
+                    debug!("Dropping Fluent forwarder: {}", reason);
+                    return;
+                }
+            };
+            // Without an ack the sender retries, which is what paused buckets want
+            if !publish(&state, &message).await {
+                continue;
+            }
+            if let Some(chunk) = &message.chunk {
+                if stream.write_all(&ack(chunk)).await.is_err() {
+                    return;
+                }
+            }
+        }
+
+        if buffer.len() > MAX_FORWARD_MESSAGE_SIZE {
+            debug!("Dropping Fluent forwarder sending an oversized message");
+            return;
+        }
+    }
+}
+
+/// Publish a message's entries to its tag's bucket, returning whether it was taken. Like
+/// other writes, messages for buckets with no viewers are accepted and discarded.
+async fn publish(state: &AppState, message: &ForwardMessage) -> bool {
+    let bucket_id = &message.tag;
+    if bucket_id == DEMO_BUCKET_ID || ids::is_reserved(bucket_id) {
+        return true;
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+    let Some(channel) = channel else {
+        return true;
+    };
+
+    let lines: Vec<&str> = message.lines.iter().map(String::as_str).collect();
+    matches!(
+        ingest_lines(state, &channel, &lines).await,
+        IngestOutcome::Accepted
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        rmpv::encode::write_value(&mut bytes, value).unwrap();
+        bytes
+    }
+
+    fn record(message: &str) -> Value {
+        Value::Map(vec![(Value::from("log"), Value::from(message))])
+    }
+
+    #[test]
+    fn test_decode_modes() {
+        let event_time = Value::Ext(EVENT_TIME_EXT, [0, 0, 0, 10, 0x1d, 0xcd, 0x65, 0].to_vec());
+        let message = Value::Array(vec![
+            Value::from("app.web"),
+            event_time.clone(),
+            record("hello"),
+        ]);
+        let decoded = decode_message(&message).unwrap();
+        assert_eq!(decoded.tag, "app.web");
+        assert_eq!(decoded.lines, vec![r#"{"log":"hello","time":10.5}"#]);
+        assert_eq!(decoded.chunk, None);
+
+        let forward = Value::Array(vec![
+            Value::from("app.web"),
+            Value::Array(vec![
+                Value::Array(vec![Value::from(1), record("one")]),
+                Value::Array(vec![Value::from(2), record("two")]),
+            ]),
+        ]);
+        assert_eq!(decode_message(&forward).unwrap().lines.len(), 2);
+
+        let mut packed = encode(&Value::Array(vec![event_time, record("one")]));
+        packed.extend(encode(&Value::Array(vec![Value::from(2), record("two")])));
+        let options = Value::Map(vec![(Value::from("chunk"), Value::from("abc"))]);
+        let packed_forward =
+            Value::Array(vec![Value::from("app.web"), Value::Binary(packed), options]);
+        let decoded = decode_message(&packed_forward).unwrap();
+        assert_eq!(decoded.lines[1], r#"{"log":"two","time":2}"#);
+        assert_eq!(decoded.chunk.as_deref(), Some("abc"));
+        assert_eq!(
+            rmpv::decode::read_value(&mut ack("abc").as_slice()).unwrap(),
+            Value::Map(vec![(Value::from("ack"), Value::from("abc"))])
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid() {
+        assert!(decode_message(&Value::from("app.web")).is_err());
+        assert!(decode_message(&Value::Array(vec![Value::from(1), Value::from(2)])).is_err());
+        let truncated = Value::Array(vec![
+            Value::from("app.web"),
+            Value::Binary(vec![0x92, 0x01]),
+        ]);
+        assert!(decode_message(&truncated).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use crate::compression::{gunzip, inflate, is_gzip, is_zlib, DecompressError};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::severity::Severity;
+use crate::{tokens, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+/// GELF's default level when a message doesn't give one (syslog ALERT)
+const DEFAULT_GELF_LEVEL: u64 = 1;
+/// Deprecated GELF fields that shippers still send, kept under their own names
+const LEGACY_GELF_FIELDS: &[&str] = &["facility", "file", "line"];
+
+/// Decompress a GELF payload, which may be gzip, zlib or plain JSON
+fn decompress(body: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let result = if is_gzip(body) {
+        gunzip(body, MAX_LOG_BODY_SIZE)
+    } else if is_zlib(body) {
+        inflate(body, MAX_LOG_BODY_SIZE)
+    } else {
+        return Ok(body.to_vec());
+    };
+    result.map_err(|e| match e {
+        DecompressError::TooLarge => "Decompressed body is too large",
+        DecompressError::Invalid => "Body is not valid gzip or zlib",
+    })
+}
+
+/// Decode a GELF payload into one JSON line per message. Bodies hold a single message,
+/// or several separated by newlines as Graylog's bulk HTTP input accepts.
+pub fn decode(body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let body = decompress(body)?;
+    let body = std::str::from_utf8(&body).map_err(|_| "Body is not UTF-8")?;
+
+    let messages: Vec<Value> = match serde_json::from_str(body) {
+        Ok(message) => vec![message],
+        Err(_) => body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()
+            .map_err(|_| "Body is not GELF JSON")?,
+    };
+
+    messages.iter().map(message_line).collect()
+}
+
+/// Decode a single GELF datagram payload, returning the bucket it names with `_bucket`
+/// and its line
+#[cfg(feature = "gelf-udp")]
+pub fn decode_datagram(payload: &[u8]) -> Result<(Option<String>, String), &'static str> {
+    let payload = decompress(payload)?;
+    let mut message: Value =
+        serde_json::from_slice(&payload).map_err(|_| "Datagram is not GELF JSON")?;
+    let bucket = message
+        .as_object_mut()
+        .and_then(|message| message.remove("_bucket"))
+        .and_then(|bucket| bucket.as_str().map(String::from));
+    Ok((bucket, message_line(&message)?))
+}
+
+/// Render a GELF message as a JSON object of the fields log-bin's parsers understand
+fn message_line(message: &Value) -> Result<String, &'static str> {
+    let Value::Object(message) = message else {
+        return Err("GELF messages must be JSON objects");
+    };
+    let Some(short_message) = message
+        .get("short_message")
+        .and_then(Value::as_str)
+        .filter(|text| !text.is_empty())
+    else {
+        return Err("GELF messages need a short_message");
+    };
+
+    let mut object = Map::new();
+    object.insert("message".to_string(), Value::from(short_message));
+    for key in ["full_message", "host"] {
+        if let Some(value) = message.get(key) {
+            object.insert(key.to_string(), value.clone());
+        }
+    }
+    // GELF timestamps are seconds with an optional fraction, which `time` already accepts
+    if let Some(timestamp) = message.get("timestamp").filter(|value| value.is_number()) {
+        object.insert("time".to_string(), timestamp.clone());
+    }
+
+    let level = message
+        .get("level")
+        .and_then(Value::as_u64)
+        .unwrap_or(DEFAULT_GELF_LEVEL);
+    if let Some(severity) = Severity::parse(&level.to_string()).filter(|_| level <= 7) {
+        object.insert("level".to_string(), Value::from(severity.as_str()));
+    }
+
+    for key in LEGACY_GELF_FIELDS {
+        if let Some(value) = message.get(*key) {
+            object.insert(key.to_string(), value.clone());
+        }
+    }
+
+    // Additional fields drop their underscore unless that would shadow a standard field
+    for (key, value) in message {
+        let Some(name) = key.strip_prefix('_').filter(|name| !name.is_empty()) else {
+            continue;
+        };
+        let name = if object.contains_key(name) {
+            key.as_str()
+        } else {
+            name
+        };
+        object.insert(name.to_string(), value.clone());
+    }
+
+    Ok(Value::Object(object).to_string())
+}
+
+/// `POST /{bucket_id}/gelf`: GELF over HTTP, as sent by Graylog-compatible shippers
+pub async fn post_gelf(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    // Graylog answers 202, and some shippers treat anything else as a failure
+    let Some(channel) = channel else {
+        warn!("Discarding GELF for bucket with no viewers: {}", bucket_id);
+        return Ok(StatusCode::ACCEPTED.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    let lines = match decode(&body) {
+        Ok(lines) => lines,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    if let Err(rejection) = check_event_count(&state, lines.len(), "split the batch") {
+        return Ok(rejection.into_response());
+    }
+
+    info!(
+        "New GELF messages for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+
+    if let Some(token) = &token {
+        token.record(lines.len() as u64, body.len() as u64, chrono::Utc::now());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => Ok(StatusCode::ACCEPTED.into_response()),
+        IngestOutcome::Suspended => {
+            Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+        }
+        IngestOutcome::Paused => Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_decode() {
+        let body = br#"{"version":"1.1","host":"web-1","short_message":"Disk full","timestamp":1700000000.5,"level":3,"_user_id":42,"_host":"shadowed"}"#;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        let lines = decode(&encoder.finish().unwrap()).unwrap();
+
+        let event: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(event["message"], "Disk full");
+        assert_eq!(event["host"], "web-1");
+        assert_eq!(event["level"], "error");
+        assert_eq!(event["time"], 1700000000.5);
+        assert_eq!(event["user_id"], 42);
+        assert_eq!(event["_host"], "shadowed");
+        assert!(event.get("version").is_none());
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid() {
+        assert!(decode(br#"{"host":"web-1"}"#).is_err());
+        assert!(decode(b"not json").is_err());
+
+        let bulk = b"{\"short_message\":\"one\"}\n{\"short_message\":\"two\",\"level\":6}\n";
+        let lines = decode(bulk).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""level":"critical""#));
+    }
+}
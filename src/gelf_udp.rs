@@ -0,0 +1,152 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::gelf;
+use crate::ids;
+use crate::ingest::ingest_lines;
+use crate::AppState;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tracing::{debug, warn};
+
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+/// Magic, message ID, sequence number and sequence count
+const CHUNK_HEADER_SIZE: usize = 12;
+/// GELF limits a message to 128 chunks
+const MAX_CHUNKS: usize = 128;
+/// GELF says to drop a message whose chunks don't all arrive within five seconds
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Partly received messages held at once, so stray chunks can't use unbounded memory
+const MAX_PENDING_MESSAGES: usize = 1000;
+const MAX_DATAGRAM_SIZE: usize = 65_535;
+
+struct PendingMessage {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    started: Instant,
+}
+
+/// Reassembles chunked GELF messages
+#[derive(Default)]
+struct Reassembler {
+    pending: HashMap<[u8; 8], PendingMessage>,
+}
+
+impl Reassembler {
+    /// Accept a datagram, returning a complete message payload once one is available
+    fn accept(&mut self, datagram: &[u8], now: Instant) -> Option<Vec<u8>> {
+        if !datagram.starts_with(&CHUNK_MAGIC) {
+            return Some(datagram.to_vec());
+        }
+        if datagram.len() < CHUNK_HEADER_SIZE {
+            return None;
+        }
+
+        let id: [u8; 8] = datagram[2..10].try_into().unwrap();
+        let (sequence, count) = (datagram[10] as usize, datagram[11] as usize);
+        if count == 0 || count > MAX_CHUNKS || sequence >= count {
+            return None;
+        }
+
+        self.pending
+            .retain(|_, message| now.duration_since(message.started) < CHUNK_TIMEOUT);
+        if !self.pending.contains_key(&id) && self.pending.len() >= MAX_PENDING_MESSAGES {
+            return None;
+        }
+
+        let message = self.pending.entry(id).or_insert_with(|| PendingMessage {
+            chunks: vec![None; count],
+            received: 0,
+            started: now,
+        });
+        if message.chunks.len() != count {
+            return None;
+        }
+        if message.chunks[sequence].is_none() {
+            message.chunks[sequence] = Some(datagram[CHUNK_HEADER_SIZE..].to_vec());
+            message.received += 1;
+        }
+        if message.received < count {
+            return None;
+        }
+
+        let message = self.pending.remove(&id)?;
+        Some(message.chunks.into_iter().flatten().flatten().collect())
+    }
+}
+
+/// Publish GELF datagrams received on `socket` to the bucket each one names
+pub fn spawn(socket: UdpSocket, state: AppState) {
+    tokio::spawn(async move {
+        let mut reassembler = Reassembler::default();
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (length, peer) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("GELF UDP receive failed: {}", e);
+                    continue;
+                }
+            };
+            let Some(payload) = reassembler.accept(&buffer[..length], Instant::now()) else {
+                continue;
+            };
+
+            let (bucket, line) = match gelf::decode_datagram(&payload) {
+                Ok(decoded) => decoded,
+                Err(message) => {
+                    debug!("Dropped GELF datagram from {}: {}", peer, message);
+                    continue;
+                }
+            };
+            let Some(bucket_id) = bucket.or_else(|| state.config.gelf_default_bucket.clone())
+            else {
+                debug!("Dropped GELF datagram from {} with no bucket", peer);
+                continue;
+            };
+            if bucket_id == DEMO_BUCKET_ID || ids::is_reserved(&bucket_id) {
+                continue;
+            }
+
+            let channel = {
+                let manager = state.channel_manager.read().await;
+                manager.get_channel(&bucket_id)
+            };
+            // Datagrams get no reply, so suspended or paused buckets just drop them
+            if let Some(channel) = channel {
+                ingest_lines(&state, &channel, &[line.as_str()]).await;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: u8, sequence: u8, count: u8, data: &[u8]) -> Vec<u8> {
+        let mut chunk = CHUNK_MAGIC.to_vec();
+        chunk.extend([id; 8]);
+        chunk.extend([sequence, count]);
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn test_reassemble() {
+        let mut reassembler = Reassembler::default();
+        let now = Instant::now();
+        assert_eq!(reassembler.accept(b"{}", now), Some(b"{}".to_vec()));
+
+        assert_eq!(reassembler.accept(&chunk(1, 1, 2, b"world"), now), None);
+        assert_eq!(
+            reassembler.accept(&chunk(1, 0, 2, b"hello "), now),
+            Some(b"hello world".to_vec())
+        );
+
+        // Messages that don't complete in time are dropped
+        assert_eq!(reassembler.accept(&chunk(2, 0, 2, b"a"), now), None);
+        let later = now + CHUNK_TIMEOUT;
+        assert_eq!(reassembler.accept(&chunk(2, 1, 2, b"b"), later), None);
+        assert_eq!(reassembler.accept(&chunk(3, 2, 2, b"c"), later), None);
+    }
+}
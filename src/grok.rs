@@ -0,0 +1,443 @@
+//! Grok patterns: regexes assembled from named building blocks, written `%{NAME}` or
+//! `%{NAME:field}`, so bespoke formats can be parsed without writing a regex from scratch
+
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::parsers::{create_fields, ParseOutcome, ParsedEvent};
+use crate::rules::{compile_pattern, MAX_PATTERN_SIZE};
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::info;
+
+const MAX_GROK_PARSERS_PER_BUCKET: usize = 10;
+const MAX_GROK_PATTERNS_PER_BUCKET: usize = 50;
+/// Deepest chain of patterns referring to patterns, which also stops cycles
+const MAX_EXPANSION_DEPTH: usize = 16;
+
+/// The built-in library, after Logstash's. Its patterns lean on lookaround and atomic
+/// groups, which the regex engine lacks, so these are close equivalents without them.
+const PATTERNS: &[(&str, &str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    (
+        "EMAILLOCALPART",
+        r"[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+(?:\.[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+)*",
+    ),
+    ("EMAILADDRESS", r"%{EMAILLOCALPART}@%{HOSTNAME}"),
+    ("HTTPDUSER", r"%{EMAILADDRESS}|%{USER}"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("BASE10NUM", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    ("NUMBER", r"%{BASE10NUM}"),
+    ("BASE16NUM", r"[+-]?(?:0x)?[0-9A-Fa-f]+"),
+    ("POSINT", r"[1-9][0-9]*"),
+    ("NONNEGINT", r"[0-9]+"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'"#),
+    ("QS", r"%{QUOTEDSTRING}"),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    ("MAC", r"(?:[A-Fa-f0-9]{2}[:-]){5}[A-Fa-f0-9]{2}"),
+    (
+        "IPV6",
+        r"(?:[0-9A-Fa-f]{0,4}:){2,7}(?:[0-9A-Fa-f]{1,4}|%{IPV4})?",
+    ),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9]{2}|[1-9]?[0-9])",
+    ),
+    ("IP", r"%{IPV6}|%{IPV4}"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b",
+    ),
+    ("IPORHOST", r"%{IP}|%{HOSTNAME}"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("UNIXPATH", r"(?:/[^/\s]*)+"),
+    ("PATH", r"%{UNIXPATH}"),
+    ("URIPROTO", r"[A-Za-z][A-Za-z0-9+.-]*"),
+    ("URIHOST", r"%{IPORHOST}(?::%{POSINT})?"),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+    ("URIPARAM", r"\?[A-Za-z0-9$.+!*'|(){},~@#%&/=:;_?\-\[\]<>]*"),
+    ("URIPATHPARAM", r"%{URIPATH}(?:%{URIPARAM})?"),
+    (
+        "URI",
+        r"%{URIPROTO}://(?:%{USER}(?::[^@]*)?@)?%{URIHOST}(?:%{URIPATHPARAM})?",
+    ),
+    (
+        "MONTH",
+        r"\b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]un(?:e)?|[Jj]ul(?:y)?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"0?[1-9]|1[0-2]"),
+    ("MONTHDAY", r"0[1-9]|[12][0-9]|3[01]|[1-9]"),
+    (
+        "DAY",
+        r"Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?",
+    ),
+    ("YEAR", r"[0-9]{4}|[0-9]{2}"),
+    ("HOUR", r"2[0123]|[01]?[0-9]"),
+    ("MINUTE", r"[0-5][0-9]"),
+    ("SECOND", r"(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?"),
+    ("TIME", r"%{HOUR}:%{MINUTE}(?::%{SECOND})?"),
+    ("DATE_US", r"%{MONTHNUM}[/-]%{MONTHDAY}[/-]%{YEAR}"),
+    ("DATE_EU", r"%{MONTHDAY}[./-]%{MONTHNUM}[./-]%{YEAR}"),
+    ("DATE", r"%{DATE_US}|%{DATE_EU}"),
+    ("DATESTAMP", r"%{DATE}[- ]%{TIME}"),
+    ("ISO8601_TIMEZONE", r"Z|[+-]%{HOUR}(?::?%{MINUTE})"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    ("PROG", r"[\x21-\x5a\x5c\x5e-\x7e]+"),
+    ("SYSLOGPROG", r"%{PROG:program}(?:\[%{POSINT:pid}\])?"),
+    ("SYSLOGHOST", r"%{IPORHOST}"),
+    (
+        "SYSLOGFACILITY",
+        r"<%{NONNEGINT:facility}\.%{NONNEGINT:priority}>",
+    ),
+    (
+        "SYSLOGBASE",
+        r"%{SYSLOGTIMESTAMP:timestamp} (?:%{SYSLOGFACILITY} )?%{SYSLOGHOST:logsource} %{SYSLOGPROG}:",
+    ),
+    ("SYSLOGLINE", r"%{SYSLOGBASE} ?%{GREEDYDATA:message}"),
+    (
+        "LOGLEVEL",
+        r"[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo(?:rmation)?|INFO(?:RMATION)?|[Ww]arn(?:ing)?|WARN(?:ING)?|[Ee]rr(?:or)?|ERR(?:OR)?|[Cc]rit(?:ical)?|CRIT(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|[Ee]merg(?:ency)?|EMERG(?:ENCY)?",
+    ),
+    (
+        "COMMONAPACHELOG",
+        r#"%{IPORHOST:clientip} %{HTTPDUSER:ident} %{HTTPDUSER:auth} \[%{HTTPDATE:timestamp}\] "(?:%{WORD:verb} %{NOTSPACE:request}(?: HTTP/%{NUMBER:httpversion})?|%{DATA:rawrequest})" %{NUMBER:response} (?:%{NUMBER:bytes}|-)"#,
+    ),
+    (
+        "COMBINEDAPACHELOG",
+        r"%{COMMONAPACHELOG} %{QS:referrer} %{QS:agent}",
+    ),
+];
+
+/// A grok expression compiled to a regex
+#[derive(Debug)]
+pub struct Grok {
+    regex: Regex,
+    /// Field names for the regex's `grokN` groups, by N
+    fields: Vec<String>,
+}
+
+impl Grok {
+    /// Compile an expression, looking references up in `patterns` before the library
+    pub fn compile(expression: &str, patterns: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        let pattern = expand(expression, patterns, 0, &mut fields)?;
+        let regex = compile_pattern(&pattern).map_err(|e| e.to_string())?;
+        Ok(Self { regex, fields })
+    }
+
+    /// The fields captured from a line, and how much of the line the match covered
+    pub fn parse(&self, input: &str) -> Option<(HashMap<String, String>, f32)> {
+        let captures = self.regex.captures(input)?;
+        let mut data = HashMap::new();
+        for group in self.regex.capture_names().flatten() {
+            let Some(value) = captures.name(group) else {
+                continue;
+            };
+            // Groups the expression named itself, with `(?P<name>...)`, keep their names
+            let field = group
+                .strip_prefix("grok")
+                .and_then(|n| n.parse::<usize>().ok())
+                .and_then(|n| self.fields.get(n))
+                .map_or(group, String::as_str);
+            data.insert(field.to_string(), value.as_str().to_string());
+        }
+        let matched = captures.get(0).map_or(0, |m| m.len());
+        Some((data, matched as f32 / input.len().max(1) as f32))
+    }
+}
+
+/// Replace each `%{NAME}`, `%{NAME:field}` or `%{NAME:field:type}` in an expression with
+/// the pattern it names, capturing the fields. Types are accepted for compatibility, but
+/// every field is a string. Patterns that each refer to the next several times grow the
+/// expression exponentially, so expanding stops once it is too big to compile anyway.
+fn expand(
+    expression: &str,
+    patterns: &BTreeMap<String, String>,
+    depth: usize,
+    fields: &mut Vec<String>,
+) -> Result<String, String> {
+    if depth > MAX_EXPANSION_DEPTH {
+        return Err("Grok patterns refer to each other too deeply, or in a loop".to_string());
+    }
+
+    let mut expanded = String::new();
+    let mut rest = expression;
+    while let Some(start) = rest.find("%{") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed %{{ in {:?}", expression))?;
+        let reference = &rest[start + 2..start + end];
+        let mut parts = reference.splitn(3, ':');
+        let name = parts.next().unwrap_or_default();
+        let field = parts.next().filter(|field| !field.is_empty());
+
+        let pattern = patterns
+            .get(name)
+            .map(String::as_str)
+            .or_else(|| library(name))
+            .ok_or_else(|| format!("Unknown grok pattern {:?}", name))?;
+        let inner = expand(pattern, patterns, depth + 1, fields)?;
+        match field {
+            Some(field) => {
+                expanded.push_str(&format!("(?P<grok{}>{})", fields.len(), inner));
+                fields.push(field.to_string());
+            }
+            None => expanded.push_str(&format!("(?:{})", inner)),
+        }
+        if expanded.len() > MAX_PATTERN_SIZE {
+            return Err("Grok patterns expand to an expression that is too large".to_string());
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+fn library(name: &str) -> Option<&'static str> {
+    PATTERNS
+        .iter()
+        .find(|(pattern, _)| *pattern == name)
+        .map(|(_, pattern)| *pattern)
+}
+
+/// A named grok expression that events can be parsed with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GrokParser {
+    pub name: String,
+    pub pattern: String,
+}
+
+/// A bucket's grok parsers and its own patterns, which they can refer to as well as the
+/// library's
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrokConfig {
+    pub patterns: BTreeMap<String, String>,
+    pub parsers: Vec<GrokParser>,
+}
+
+/// A bucket's grok parsers, compiled
+#[derive(Debug, Default)]
+pub struct GrokSet {
+    pub config: GrokConfig,
+    parsers: Vec<(String, Grok)>,
+}
+
+impl GrokSet {
+    /// Compile a bucket's grok configuration, checking it as it goes
+    pub fn compile(config: GrokConfig) -> Result<Self, String> {
+        if config.parsers.len() > MAX_GROK_PARSERS_PER_BUCKET {
+            return Err(format!(
+                "A bucket can have at most {} grok parsers",
+                MAX_GROK_PARSERS_PER_BUCKET
+            ));
+        }
+        if config.patterns.len() > MAX_GROK_PATTERNS_PER_BUCKET {
+            return Err(format!(
+                "A bucket can have at most {} grok patterns",
+                MAX_GROK_PATTERNS_PER_BUCKET
+            ));
+        }
+
+        let parsers = config
+            .parsers
+            .iter()
+            .map(|parser| {
+                Grok::compile(&parser.pattern, &config.patterns)
+                    .map(|grok| (parser.name.clone(), grok))
+                    .map_err(|e| format!("grok parser {}: {}", parser.name, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { config, parsers })
+    }
+
+    /// Parse an event none of the built-in parsers understood with the first grok parser
+    /// that matches it
+    pub fn apply(&self, event: &mut ParsedEvent) {
+        if event.parser.is_some() {
+            return;
+        }
+        for (name, grok) in &self.parsers {
+            if let Some((data, coverage)) = grok.parse(&event.input_string) {
+                event.parser = Some(name.clone());
+                event.outcome = ParseOutcome::Grok;
+                // A pattern that only matches part of the line is less sure of it
+                event.confidence = Some((coverage * 100.0).round() / 100.0);
+                event.fields = create_fields(data);
+                return;
+            }
+        }
+    }
+}
+
+pub async fn get_grok(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<GrokConfig> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) => Json(channel.grok().await.config.clone()),
+        None => Json(GrokConfig::default()),
+    }
+}
+
+/// Replace a bucket's grok parsers and patterns
+pub async fn put_grok(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(config): Json<GrokConfig>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let grok = match GrokSet::compile(config) {
+        Ok(grok) => Arc::new(grok),
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!(
+        "Registered {} grok parsers for bucket {}",
+        grok.config.parsers.len(),
+        bucket_id
+    );
+    let previous = channel.set_grok(grok.clone()).await;
+    channel.publish_config_change(
+        "grok",
+        &previous.config,
+        &grok.config,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_library() {
+        let grok = Grok::compile("%{COMBINEDAPACHELOG}", &BTreeMap::new()).unwrap();
+        let (data, coverage) = grok
+            .parse(r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08""#)
+            .unwrap();
+        assert_eq!(data["clientip"], "127.0.0.1");
+        assert_eq!(data["auth"], "frank");
+        assert_eq!(data["timestamp"], "10/Oct/2000:13:55:36 -0700");
+        assert_eq!(data["verb"], "GET");
+        assert_eq!(data["request"], "/apache_pb.gif");
+        assert_eq!(data["response"], "200");
+        assert_eq!(data["agent"], r#""Mozilla/4.08""#);
+        assert_eq!(coverage, 1.0);
+
+        let grok = Grok::compile("%{SYSLOGLINE}", &BTreeMap::new()).unwrap();
+        let (data, _) = grok
+            .parse("Oct 11 22:14:15 mymachine su[230]: 'su root' failed for lonvick")
+            .unwrap();
+        assert_eq!(data["logsource"], "mymachine");
+        assert_eq!(data["program"], "su");
+        assert_eq!(data["pid"], "230");
+        assert_eq!(data["message"], "'su root' failed for lonvick");
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let patterns = BTreeMap::from([
+            ("ORDER".to_string(), r"ord-[0-9]+".to_string()),
+            (
+                "ORDERLINE".to_string(),
+                "%{LOGLEVEL:level} %{ORDER:order}".to_string(),
+            ),
+        ]);
+        let grok = Grok::compile(
+            "%{TIMESTAMP_ISO8601:time} %{ORDERLINE} (?P<note>.*)",
+            &patterns,
+        )
+        .unwrap();
+        let (data, _) = grok
+            .parse("2024-01-01T12:00:00Z WARN ord-42 payment retried")
+            .unwrap();
+        assert_eq!(data["time"], "2024-01-01T12:00:00Z");
+        assert_eq!(data["level"], "WARN");
+        assert_eq!(data["order"], "ord-42");
+        assert_eq!(data["note"], "payment retried");
+        assert!(grok.parse("no order here").is_none());
+
+        assert!(Grok::compile("%{NOPE}", &patterns).is_err());
+        assert!(Grok::compile("%{WORD", &patterns).is_err());
+        let looped = BTreeMap::from([("A".to_string(), "%{A}".to_string())]);
+        assert!(Grok::compile("%{A}", &looped).is_err());
+
+        // Each pattern refers to the next eight times, for 8^6 timestamps
+        let fanned: BTreeMap<String, String> = (0..6)
+            .map(|i| (format!("P{}", i), format!("%{{P{}}}", i + 1).repeat(8)))
+            .chain([("P6".to_string(), "%{TIMESTAMP_ISO8601}".to_string())])
+            .collect();
+        let error = Grok::compile("%{P0}", &fanned).unwrap_err();
+        assert!(error.contains("too large"), "{}", error);
+    }
+
+    #[test]
+    fn test_grok_set() {
+        let config: GrokConfig = serde_json::from_str(
+            r#"{"parsers": [{"name": "orders", "pattern": "^%{WORD:action} order %{INT:id}$"}]}"#,
+        )
+        .unwrap();
+        let grok = GrokSet::compile(config).unwrap();
+
+        let mut event = ParsedEvent::new("shipped order 7".to_string());
+        event.parse();
+        grok.apply(&mut event);
+        assert_eq!(event.parser.as_deref(), Some("orders"));
+        assert_eq!(event.outcome, ParseOutcome::Grok);
+        assert_eq!(event.fields["id"].value, "7");
+
+        let config = GrokConfig {
+            parsers: vec![GrokParser {
+                name: "broken".to_string(),
+                pattern: "%{MISSING}".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(GrokSet::compile(config).is_err());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::compression::{gunzip, is_gzip};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, MAX_LOG_BODY_SIZE};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+/// Envelope fields kept alongside the event when they don't clash with its own
+const METADATA_FIELDS: &[&str] = &["host", "source", "sourcetype", "index"];
+
+/// A Splunk HEC reply: the HTTP status and the `{"text", "code"}` body collectors check
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HecStatus {
+    status: StatusCode,
+    code: u8,
+    text: &'static str,
+}
+
+const SUCCESS: HecStatus = HecStatus {
+    status: StatusCode::OK,
+    code: 0,
+    text: "Success",
+};
+const TOKEN_DISABLED: HecStatus = HecStatus {
+    status: StatusCode::FORBIDDEN,
+    code: 1,
+    text: "Token is disabled",
+};
+const TOKEN_REQUIRED: HecStatus = HecStatus {
+    status: StatusCode::UNAUTHORIZED,
+    code: 2,
+    text: "Token is required",
+};
+const INVALID_TOKEN: HecStatus = HecStatus {
+    status: StatusCode::FORBIDDEN,
+    code: 4,
+    text: "Invalid token",
+};
+const NO_DATA: HecStatus = HecStatus {
+    status: StatusCode::BAD_REQUEST,
+    code: 5,
+    text: "No data",
+};
+const INVALID_FORMAT: HecStatus = HecStatus {
+    status: StatusCode::BAD_REQUEST,
+    code: 6,
+    text: "Invalid data format",
+};
+const INCORRECT_INDEX: HecStatus = HecStatus {
+    status: StatusCode::BAD_REQUEST,
+    code: 7,
+    text: "Incorrect index",
+};
+const SERVER_BUSY: HecStatus = HecStatus {
+    status: StatusCode::SERVICE_UNAVAILABLE,
+    code: 9,
+    text: "Server is busy",
+};
+const EVENT_REQUIRED: HecStatus = HecStatus {
+    status: StatusCode::BAD_REQUEST,
+    code: 12,
+    text: "Event field is required",
+};
+const EVENT_BLANK: HecStatus = HecStatus {
+    status: StatusCode::BAD_REQUEST,
+    code: 13,
+    text: "Event field cannot be blank",
+};
+
+#[derive(Serialize)]
+struct HecReply {
+    text: &'static str,
+    code: u8,
+    #[serde(
+        rename = "invalid-event-number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    invalid_event_number: Option<usize>,
+}
+
+impl HecStatus {
+    fn reply(self, invalid_event_number: Option<usize>) -> Response {
+        let reply = HecReply {
+            text: self.text,
+            code: self.code,
+            invalid_event_number,
+        };
+        (self.status, Json(reply)).into_response()
+    }
+}
+
+impl IntoResponse for HecStatus {
+    fn into_response(self) -> Response {
+        self.reply(None)
+    }
+}
+
+/// Decode the event objects in a HEC body, which are sent back to back rather than in an
+/// array, into one JSON line each. Errors carry the number of the offending event.
+fn decode(body: &str) -> Result<Vec<String>, (HecStatus, Option<usize>)> {
+    let mut lines = Vec::new();
+    for (number, object) in serde_json::Deserializer::from_str(body)
+        .into_iter::<Value>()
+        .enumerate()
+    {
+        match object {
+            Ok(Value::Object(object)) => {
+                lines.push(event_line(object).map_err(|status| (status, Some(number)))?)
+            }
+            _ => return Err((INVALID_FORMAT, Some(number))),
+        }
+    }
+    if lines.is_empty() {
+        return Err((NO_DATA, None));
+    }
+    Ok(lines)
+}
+
+/// Render a HEC envelope as its event, with `fields`, metadata and `time` merged in
+fn event_line(mut envelope: Map<String, Value>) -> Result<String, HecStatus> {
+    let mut line = match envelope.remove("event") {
+        None => return Err(EVENT_REQUIRED),
+        Some(Value::Null) => return Err(EVENT_BLANK),
+        Some(Value::String(text)) if text.trim().is_empty() => return Err(EVENT_BLANK),
+        Some(Value::Object(event)) => event,
+        Some(message) => Map::from_iter([("message".to_string(), message)]),
+    };
+
+    if let Some(Value::Object(fields)) = envelope.remove("fields") {
+        for (key, value) in fields {
+            line.entry(key).or_insert(value);
+        }
+    }
+    for key in METADATA_FIELDS {
+        if let Some(value) = envelope.remove(*key) {
+            line.entry(key.to_string()).or_insert(value);
+        }
+    }
+
+    // HEC times are epoch seconds with an optional fraction, as a number or a string
+    let time = match envelope.remove("time") {
+        Some(Value::String(time)) => time.parse::<f64>().ok().map(Value::from),
+        Some(time) if time.is_number() => Some(time),
+        _ => None,
+    };
+    if let Some(time) = time {
+        line.entry("time").or_insert(time);
+    }
+
+    Ok(Value::Object(line).to_string())
+}
+
+/// `POST /services/collector/event`: the Splunk HTTP Event Collector. Events go to the
+/// bucket mapped to the presented token in `TOKENS_FILE`.
+pub async fn post_event(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+    }
+
+    if state.tokens.is_none() {
+        return TOKEN_DISABLED.into_response();
+    }
+    if !headers.contains_key(header::AUTHORIZATION) {
+        return TOKEN_REQUIRED.into_response();
+    }
+    let Some(token) = tokens::identify(&state, &headers) else {
+        return INVALID_TOKEN.into_response();
+    };
+    let now = chrono::Utc::now();
+    if token.over_quota(now) {
+        return SERVER_BUSY.into_response();
+    }
+    let Some(bucket_id) = token
+        .bucket()
+        .filter(|bucket| *bucket != DEMO_BUCKET_ID && !ids::is_reserved(bucket))
+    else {
+        return INCORRECT_INDEX.into_response();
+    };
+
+    let body = if is_gzip(&body) {
+        match gunzip(&body, MAX_LOG_BODY_SIZE) {
+            Ok(body) => body,
+            Err(_) => return INVALID_FORMAT.into_response(),
+        }
+    } else {
+        body.to_vec()
+    };
+    let Ok(body) = std::str::from_utf8(&body) else {
+        return INVALID_FORMAT.into_response();
+    };
+    let lines = match decode(body) {
+        Ok(lines) => lines,
+        Err((status, number)) => return status.reply(number),
+    };
+    if let Err(rejection) = check_event_count(&state, lines.len(), "split the batch") {
+        return rejection.into_response();
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+    let Some(channel) = channel else {
+        warn!(
+            "Discarding HEC events for bucket with no viewers: {}",
+            bucket_id
+        );
+        return SUCCESS.into_response();
+    };
+
+    // Collectors retry on a busy server, which suits both suspended and paused buckets
+    if channel.is_suspended() {
+        return SERVER_BUSY.into_response();
+    }
+
+    info!(
+        "New HEC events for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+    token.record(lines.len() as u64, body.len() as u64, now);
+    token.associate(bucket_id, BucketRelation::Written, now.timestamp_millis());
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => SUCCESS.into_response(),
+        IngestOutcome::Suspended | IngestOutcome::Paused => SERVER_BUSY.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let body = concat!(
+            r#"{"time":"1700000000.5","host":"web-1","event":{"message":"hi","host":"inner"},"fields":{"env":"prod"}}"#,
+            "\n",
+            r#"{"event":"plain text","sourcetype":"syslog"}"#,
+        );
+        let lines = decode(body).unwrap();
+
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["message"], "hi");
+        assert_eq!(first["host"], "inner");
+        assert_eq!(first["env"], "prod");
+        assert_eq!(first["time"], 1700000000.5);
+
+        let second: Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["message"], "plain text");
+        assert_eq!(second["sourcetype"], "syslog");
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid() {
+        assert_eq!(decode("").unwrap_err(), (NO_DATA, None));
+        assert_eq!(
+            decode(r#"{"event":"ok"}{"fields":{}}"#).unwrap_err(),
+            (EVENT_REQUIRED, Some(1))
+        );
+        assert_eq!(
+            decode(r#"{"event":""}"#).unwrap_err(),
+            (EVENT_BLANK, Some(0))
+        );
+        assert_eq!(
+            decode(r#"{"event":"ok"} nope"#).unwrap_err(),
+            (INVALID_FORMAT, Some(1))
+        );
+    }
+}
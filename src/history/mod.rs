@@ -0,0 +1,203 @@
+mod ring;
+
+use crate::models::LogEvent;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::warn;
+
+pub use ring::RingFile;
+
+const RING_FILE_EXTENSION: &str = "ring";
+
+/// Number of events kept per bucket by the in-memory store
+const MEMORY_HISTORY_SIZE: usize = 10;
+
+/// Where a bucket keeps the recent events that new subscribers are sent first
+pub trait HistoryStore: Send + Sync {
+    /// Append an event, dropping the oldest ones if the store is full
+    fn push(&mut self, event: &LogEvent);
+
+    /// Retained events, oldest first
+    fn events(&self) -> Vec<LogEvent>;
+
+    /// Permanently remove the events `keep` rejects, returning the removed events
+    fn retain(&mut self, keep: &dyn Fn(&LogEvent) -> bool) -> Vec<LogEvent>;
+}
+
+/// The last few events, held in memory and lost on restart
+pub struct MemoryHistory {
+    events: VecDeque<LogEvent>,
+    capacity: usize,
+}
+
+impl MemoryHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+}
+
+impl Default for MemoryHistory {
+    fn default() -> Self {
+        Self::new(MEMORY_HISTORY_SIZE)
+    }
+}
+
+impl HistoryStore for MemoryHistory {
+    fn push(&mut self, event: &LogEvent) {
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event.clone());
+    }
+
+    fn events(&self) -> Vec<LogEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&LogEvent) -> bool) -> Vec<LogEvent> {
+        let (kept, removed): (Vec<_>, Vec<_>) =
+            self.events.drain(..).partition(|event| keep(event));
+        self.events = kept.into();
+        removed
+    }
+}
+
+/// Which [`HistoryStore`] new buckets get
+#[derive(Debug, Clone, Default)]
+pub enum HistoryBackend {
+    #[default]
+    Memory,
+    /// A fixed-size memory-mapped ring file per bucket in `dir`, kept across restarts. Once
+    /// `max_files` exist, further buckets keep their history in memory.
+    RingFile {
+        dir: PathBuf,
+        size: u64,
+        max_files: usize,
+        /// Ring files in `dir`, counting those left by earlier runs
+        files: Arc<AtomicUsize>,
+    },
+}
+
+impl HistoryBackend {
+    /// Keep history in ring files in `dir`, creating it if needed
+    pub fn ring_files(dir: &Path, size: u64, max_files: usize) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let mut files = 0;
+        for entry in std::fs::read_dir(dir)? {
+            if is_ring_file(&entry?.path()) {
+                files += 1;
+            }
+        }
+        Ok(HistoryBackend::RingFile {
+            dir: dir.to_path_buf(),
+            size,
+            max_files,
+            files: Arc::new(AtomicUsize::new(files)),
+        })
+    }
+
+    /// Open the store for a bucket, falling back to memory if its file can't be used
+    pub fn open(&self, bucket_id: &str) -> Box<dyn HistoryStore> {
+        match self {
+            HistoryBackend::Memory => Box::new(MemoryHistory::default()),
+            HistoryBackend::RingFile {
+                dir,
+                size,
+                max_files,
+                files,
+            } => {
+                let path = ring_file_path(dir, bucket_id);
+                let created = !path.exists();
+                if created
+                    && files
+                        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                            (count < *max_files).then_some(count + 1)
+                        })
+                        .is_err()
+                {
+                    warn!(
+                        "Keeping history for {} in memory: {} history files already exist",
+                        bucket_id, max_files
+                    );
+                    return Box::new(MemoryHistory::default());
+                }
+                match RingFile::open(&path, *size) {
+                    Ok(ring) => Box::new(ring),
+                    Err(e) => {
+                        if created {
+                            files.fetch_sub(1, Ordering::Relaxed);
+                        }
+                        warn!("Keeping history for {} in memory: {}", bucket_id, e);
+                        Box::new(MemoryHistory::default())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Delete a bucket's stored history, once the bucket itself is gone
+    pub fn remove(&self, bucket_id: &str) {
+        let HistoryBackend::RingFile { dir, files, .. } = self else {
+            return;
+        };
+        match std::fs::remove_file(ring_file_path(dir, bucket_id)) {
+            Ok(()) => {
+                files.fetch_sub(1, Ordering::Relaxed);
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove history for {}: {}", bucket_id, e),
+        }
+    }
+}
+
+fn ring_file_path(dir: &Path, bucket_id: &str) -> PathBuf {
+    // Bucket IDs are user-chosen, so never use them as file names directly
+    let name = format!(
+        "{:x}.{}",
+        Sha256::digest(bucket_id.as_bytes()),
+        RING_FILE_EXTENSION
+    );
+    dir.join(name)
+}
+
+fn is_ring_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == RING_FILE_EXTENSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_files_are_capped_and_removed() {
+        let dir = std::env::temp_dir().join(format!("log-bin-history-{}", uuid::Uuid::new_v4()));
+        let backend = HistoryBackend::ring_files(&dir, ring::MIN_RING_FILE_SIZE, 1).unwrap();
+        let ring_files = || std::fs::read_dir(&dir).unwrap().count();
+
+        backend.open("first");
+        assert_eq!(ring_files(), 1);
+        // Reopening a bucket's file doesn't count against the cap
+        backend.open("first");
+        backend.open("second");
+        assert_eq!(ring_files(), 1);
+
+        backend.remove("first");
+        assert_eq!(ring_files(), 0);
+        backend.open("second");
+        assert_eq!(ring_files(), 1);
+
+        // Files left by an earlier run count too
+        let backend = HistoryBackend::ring_files(&dir, ring::MIN_RING_FILE_SIZE, 1).unwrap();
+        backend.open("third");
+        assert_eq!(ring_files(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
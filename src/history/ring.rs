@@ -0,0 +1,396 @@
+use super::HistoryStore;
+use crate::models::LogEvent;
+use flate2::Crc;
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+
+// File layout:
+//
+//   0..8      FILE_MAGIC
+//   8..12     FORMAT_VERSION
+//   32..128   two state slots, written alternately so a torn write leaves the other intact
+//   128..     ring of records: RECORD_MAGIC, payload length, payload CRC-32, JSON payload
+//
+// A record that would run past the end of the file starts again at the beginning instead,
+// with WRAP_MAGIC left where it would have gone.
+
+const FILE_MAGIC: &[u8; 8] = b"LOGBRING";
+const FORMAT_VERSION: u32 = 1;
+const SLOTS_START: usize = 32;
+const SLOT_SIZE: usize = 48;
+const DATA_START: usize = 128;
+const RECORD_MAGIC: u32 = 0x4c4f_4752;
+const WRAP_MAGIC: u32 = 0x5752_4150;
+const RECORD_HEADER: usize = 12;
+
+/// Smallest ring file worth having
+pub const MIN_RING_FILE_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct RingState {
+    /// Offset of the oldest record in the data region
+    head: usize,
+    /// Offset the next record is written at
+    tail: usize,
+    count: usize,
+}
+
+/// A bucket's history in a fixed-size memory-mapped file, overwriting the oldest events
+/// once it is full. Records are checksummed, so a crash mid-write loses at most the
+/// events being written; flushing to disk is left to the OS.
+pub struct RingFile {
+    map: MmapMut,
+    state: RingState,
+    generation: u64,
+    /// Reused serialization buffer, so publishing doesn't allocate once it has grown
+    scratch: Vec<u8>,
+}
+
+impl RingFile {
+    /// Open the ring file at `path`, creating it with `size` bytes if it doesn't exist.
+    /// An existing file keeps its size.
+    pub fn open(path: &Path, size: u64) -> io::Result<Self> {
+        if size < MIN_RING_FILE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("ring files need at least {} bytes", MIN_RING_FILE_SIZE),
+            ));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() < MIN_RING_FILE_SIZE {
+            file.set_len(size)?;
+        }
+
+        // SAFETY: ring files are named after a hash of the bucket ID and only opened by
+        // that bucket's channel, which holds the store behind a lock, so nothing else in
+        // this process maps or resizes the file while the map is alive.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        let mut ring = Self {
+            map,
+            state: RingState::default(),
+            generation: 0,
+            scratch: Vec::new(),
+        };
+        if &ring.map[..8] == FILE_MAGIC && ring.read_u32(8) == FORMAT_VERSION {
+            ring.recover();
+        } else {
+            ring.format();
+        }
+        Ok(ring)
+    }
+
+    fn capacity(&self) -> usize {
+        self.map.len() - DATA_START
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        u32::from_le_bytes(self.map[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn read_u64(&self, offset: usize) -> u64 {
+        u64::from_le_bytes(self.map[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn write_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        self.map[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn format(&mut self) {
+        self.map[..DATA_START].fill(0);
+        self.write_bytes(0, FILE_MAGIC);
+        self.write_bytes(8, &FORMAT_VERSION.to_le_bytes());
+        self.state = RingState::default();
+        self.generation = 0;
+        self.save_state();
+    }
+
+    /// Write the state to the older of the two slots
+    fn save_state(&mut self) {
+        self.generation += 1;
+        let mut slot = [0u8; SLOT_SIZE];
+        slot[0..8].copy_from_slice(&self.generation.to_le_bytes());
+        slot[8..16].copy_from_slice(&(self.state.head as u64).to_le_bytes());
+        slot[16..24].copy_from_slice(&(self.state.tail as u64).to_le_bytes());
+        slot[24..32].copy_from_slice(&(self.state.count as u64).to_le_bytes());
+        let crc = checksum(&slot[..32]);
+        slot[32..36].copy_from_slice(&crc.to_le_bytes());
+
+        let offset = SLOTS_START + (self.generation % 2) as usize * SLOT_SIZE;
+        self.write_bytes(offset, &slot);
+    }
+
+    /// The newest intact state slot, as (generation, state)
+    fn load_state(&self) -> Option<(u64, RingState)> {
+        (0..2)
+            .filter_map(|i| {
+                let offset = SLOTS_START + i * SLOT_SIZE;
+                if checksum(&self.map[offset..offset + 32]) != self.read_u32(offset + 32) {
+                    return None;
+                }
+                let state = RingState {
+                    head: self.read_u64(offset + 8) as usize,
+                    tail: self.read_u64(offset + 16) as usize,
+                    count: self.read_u64(offset + 24) as usize,
+                };
+                let capacity = self.capacity();
+                (state.head < capacity && state.tail <= capacity)
+                    .then_some((self.read_u64(offset), state))
+            })
+            .max_by_key(|(generation, _)| *generation)
+    }
+
+    /// Restore the saved state, keeping only the records that are intact
+    fn recover(&mut self) {
+        let Some((generation, saved)) = self.load_state() else {
+            self.format();
+            return;
+        };
+        self.generation = generation;
+
+        let mut state = RingState {
+            head: saved.head,
+            tail: saved.head,
+            count: 0,
+        };
+        let mut pos = saved.head;
+        while state.count < saved.count {
+            pos = self.skip_wrap(pos);
+            let Some(payload) = self.payload(pos, true) else {
+                break;
+            };
+            pos += RECORD_HEADER + payload.len();
+            state.tail = pos;
+            state.count += 1;
+        }
+
+        self.state = state;
+        if state != saved {
+            self.save_state();
+        }
+    }
+
+    /// Where the record at `pos` really starts, following a wrap to the beginning
+    fn skip_wrap(&self, pos: usize) -> usize {
+        if pos + RECORD_HEADER > self.capacity() || self.read_u32(DATA_START + pos) == WRAP_MAGIC {
+            0
+        } else {
+            pos
+        }
+    }
+
+    /// The payload of the record at `pos`, if there is a well-formed one
+    fn payload(&self, pos: usize, verify: bool) -> Option<&[u8]> {
+        let start = DATA_START + pos;
+        if pos + RECORD_HEADER > self.capacity() || self.read_u32(start) != RECORD_MAGIC {
+            return None;
+        }
+        let len = self.read_u32(start + 4) as usize;
+        if pos + RECORD_HEADER + len > self.capacity() {
+            return None;
+        }
+        let payload = &self.map[start + RECORD_HEADER..start + RECORD_HEADER + len];
+        (!verify || checksum(payload) == self.read_u32(start + 8)).then_some(payload)
+    }
+
+    /// Drop the oldest record
+    fn evict(&mut self) {
+        match self.payload(self.state.head, false).map(<[u8]>::len) {
+            Some(len) => {
+                self.state.count -= 1;
+                self.state.head = self.skip_wrap(self.state.head + RECORD_HEADER + len);
+            }
+            // Unreadable records can't be stepped over, so give up on the rest
+            None => self.state.count = 0,
+        }
+    }
+}
+
+impl HistoryStore for RingFile {
+    fn push(&mut self, event: &LogEvent) {
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.clear();
+        if serde_json::to_writer(&mut scratch, event).is_err() {
+            self.scratch = scratch;
+            return;
+        }
+
+        let len = RECORD_HEADER + scratch.len();
+        let capacity = self.capacity();
+        if len > capacity {
+            self.scratch = scratch;
+            return;
+        }
+
+        let mut pos = self.state.tail;
+        if pos + len > capacity {
+            // Records between here and the end of the file are older than anything at the
+            // start, so they go first
+            while self.state.count > 0 && self.state.head >= pos {
+                self.evict();
+            }
+            if pos + 4 <= capacity {
+                self.write_bytes(DATA_START + pos, &WRAP_MAGIC.to_le_bytes());
+            }
+            pos = 0;
+        }
+        while self.state.count > 0 && self.state.head >= pos && self.state.head < pos + len {
+            self.evict();
+        }
+
+        let start = DATA_START + pos;
+        self.write_bytes(start, &RECORD_MAGIC.to_le_bytes());
+        self.write_bytes(start + 4, &(scratch.len() as u32).to_le_bytes());
+        self.write_bytes(start + 8, &checksum(&scratch).to_le_bytes());
+        self.write_bytes(start + RECORD_HEADER, &scratch);
+        self.scratch = scratch;
+
+        if self.state.count == 0 {
+            self.state.head = pos;
+        }
+        self.state.tail = pos + len;
+        self.state.count += 1;
+        self.save_state();
+    }
+
+    fn events(&self) -> Vec<LogEvent> {
+        let mut events = Vec::with_capacity(self.state.count);
+        let mut pos = self.state.head;
+        for _ in 0..self.state.count {
+            pos = self.skip_wrap(pos);
+            let Some(payload) = self.payload(pos, true) else {
+                break;
+            };
+            if let Ok(event) = serde_json::from_slice(payload) {
+                events.push(event);
+            }
+            pos += RECORD_HEADER + payload.len();
+        }
+        events
+    }
+
+    fn retain(&mut self, keep: &dyn Fn(&LogEvent) -> bool) -> Vec<LogEvent> {
+        let (kept, removed): (Vec<_>, Vec<_>) = self.events().into_iter().partition(keep);
+        if removed.is_empty() {
+            return removed;
+        }
+
+        // Wipe the whole ring rather than unlinking records, so removed events don't
+        // linger in the file
+        self.map[DATA_START..].fill(0);
+        self.state = RingState::default();
+        for event in &kept {
+            self.push(event);
+        }
+        self.save_state();
+        removed
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(bytes);
+    crc.sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn event(seq: u64) -> LogEvent {
+        LogEvent {
+            seq,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: format!("line {} {}", seq, "x".repeat(100)),
+            raw_ansi: None,
+            fields: HashMap::new(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("log-bin-{}.ring", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_ring_wraps_and_persists() {
+        let path = temp_path();
+        let mut ring = RingFile::open(&path, MIN_RING_FILE_SIZE).unwrap();
+        for seq in 1..=100 {
+            ring.push(&event(seq));
+        }
+
+        // Only the newest events fit, and they come back in order
+        let events = ring.events();
+        assert!(events.len() > 10 && events.len() < 100);
+        let seqs: Vec<u64> = events.iter().map(|event| event.seq).collect();
+        let expected: Vec<u64> = (101 - events.len() as u64..=100).collect();
+        assert_eq!(seqs, expected);
+        drop(ring);
+
+        let ring = RingFile::open(&path, MIN_RING_FILE_SIZE).unwrap();
+        assert_eq!(ring.events().len(), events.len());
+        assert_eq!(ring.events().last().unwrap().seq, 100);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ring_retain_wipes_removed_events() {
+        let path = temp_path();
+        let mut ring = RingFile::open(&path, MIN_RING_FILE_SIZE).unwrap();
+        for seq in 1..=5 {
+            ring.push(&event(seq));
+        }
+
+        let removed = ring.retain(&|event| event.seq != 3);
+        assert_eq!(removed.len(), 1);
+        let seqs: Vec<u64> = ring.events().iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 4, 5]);
+        drop(ring);
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(7).any(|window| window == b"line 3 "));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_ring_drops_corrupt_records() {
+        let path = temp_path();
+        let mut ring = RingFile::open(&path, MIN_RING_FILE_SIZE).unwrap();
+        for seq in 1..=3 {
+            ring.push(&event(seq));
+        }
+        drop(ring);
+
+        // Damage the last record's payload, as a crash mid-write might
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.iter().rposition(|&byte| byte == b'x').unwrap();
+        bytes[last] = b'y';
+        std::fs::write(&path, bytes).unwrap();
+
+        let mut ring = RingFile::open(&path, MIN_RING_FILE_SIZE).unwrap();
+        assert_eq!(ring.events().len(), 2);
+        ring.push(&event(4));
+        let seqs: Vec<u64> = ring.events().iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![1, 2, 4]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
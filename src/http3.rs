@@ -0,0 +1,130 @@
+use crate::timeouts::PeerAddr;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http, Router,
+};
+use bytes::{Buf, Bytes};
+use futures_util::StreamExt;
+use h3::server::RequestStream;
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tower::ServiceExt;
+use tracing::debug;
+
+/// ALPN protocol ID for HTTP/3
+const H3_ALPN: &[u8] = b"h3";
+
+fn server_config(cert: &Path, key: &Path) -> Result<quinn::ServerConfig, String> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read {}: {}", cert.display(), e))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("Failed to read {}: {}", key.display(), e))?;
+
+    let mut tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])
+    .map_err(|e| e.to_string())?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .map_err(|e| e.to_string())?;
+    tls.alpn_protocols = vec![H3_ALPN.to_vec()];
+
+    let crypto = QuicServerConfig::try_from(tls).map_err(|e| e.to_string())?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Open a QUIC endpoint on `addr`
+pub fn bind(addr: SocketAddr, cert: &Path, key: &Path) -> Result<quinn::Endpoint, String> {
+    quinn::Endpoint::server(server_config(cert, key)?, addr).map_err(|e| e.to_string())
+}
+
+/// Serve `app` to HTTP/3 clients of `endpoint`
+pub fn spawn(endpoint: quinn::Endpoint, app: Router) {
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            let app = app.clone();
+            tokio::spawn(async move {
+                match incoming.await {
+                    Ok(connection) => {
+                        if let Err(e) = serve_connection(connection, app).await {
+                            debug!("HTTP/3 connection ended: {}", e);
+                        }
+                    }
+                    Err(e) => debug!("HTTP/3 handshake failed: {}", e),
+                }
+            });
+        }
+    });
+}
+
+async fn serve_connection(
+    connection: quinn::Connection,
+    app: Router,
+) -> Result<(), h3::error::ConnectionError> {
+    let peer = connection.remote_address();
+    let mut connection = h3::server::Connection::new(h3_quinn::Connection::new(connection)).await?;
+
+    while let Some(resolver) = connection.accept().await? {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let result = match resolver.resolve_request().await {
+                Ok((request, stream)) => serve_request(app, peer, request, stream).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                debug!("HTTP/3 request from {} failed: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Run one request through the router, streaming the request and response bodies
+async fn serve_request(
+    app: Router,
+    peer: SocketAddr,
+    request: http::Request<()>,
+    stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+) -> Result<(), h3::error::StreamError> {
+    let (mut send, mut recv) = stream.split();
+
+    let body = Body::from_stream(async_stream::stream! {
+        loop {
+            match recv.recv_data().await {
+                Ok(Some(mut data)) => yield Ok(data.copy_to_bytes(data.remaining())),
+                Ok(None) => break,
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            }
+        }
+    });
+    let (parts, ()) = request.into_parts();
+    let mut request = Request::from_parts(parts, body);
+    // Handlers that limit per-client connections need the peer like they get over TCP
+    request.extensions_mut().insert(ConnectInfo(PeerAddr(peer)));
+
+    let Ok(response) = app.oneshot(request).await;
+    let (parts, body) = response.into_parts();
+    send.send_response(http::Response::from_parts(parts, ()))
+        .await?;
+
+    let mut body = body.into_data_stream();
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) => send.send_data(chunk).await?,
+            Err(e) => {
+                debug!("HTTP/3 response body failed: {}", e);
+                break;
+            }
+        }
+    }
+    send.finish().await
+}
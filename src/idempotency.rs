@@ -0,0 +1,152 @@
+use crate::channel_manager::Channel;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// How long a key is remembered after the batch it guarded was accepted
+const IDEMPOTENCY_KEY_TTL_MS: i64 = 10 * 60 * 1000;
+/// Upper bound on remembered keys per bucket
+const MAX_IDEMPOTENCY_KEYS: usize = 1000;
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Where a batch's key stands when the batch arrives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyStatus {
+    /// Not seen before, and now held for this batch
+    Reserved,
+    /// Held by another batch that's still being published
+    InFlight,
+    /// A batch with this key was already accepted
+    Accepted,
+}
+
+/// Recent `Idempotency-Key` values for a bucket, and whether their batch was accepted
+#[derive(Default)]
+pub struct IdempotencyCache {
+    keys: HashMap<String, bool>,
+    order: VecDeque<(i64, String)>,
+}
+
+impl IdempotencyCache {
+    /// Forget keys that have expired or overflowed the cache
+    fn prune(&mut self, now: i64) {
+        while let Some((seen_at, key)) = self.order.front() {
+            if now - seen_at < IDEMPOTENCY_KEY_TTL_MS && self.order.len() <= MAX_IDEMPOTENCY_KEYS {
+                break;
+            }
+            self.keys.remove(key);
+            self.order.pop_front();
+        }
+    }
+
+    /// Hold a key for a batch, in the same step as checking it, so two retries of one
+    /// batch arriving together can't both get through
+    pub fn reserve(&mut self, key: &str, now: i64) -> KeyStatus {
+        self.prune(now);
+        match self.keys.get(key) {
+            Some(true) => KeyStatus::Accepted,
+            Some(false) => KeyStatus::InFlight,
+            None => {
+                self.keys.insert(key.to_string(), false);
+                self.order.push_back((now, key.to_string()));
+                self.prune(now);
+                KeyStatus::Reserved
+            }
+        }
+    }
+
+    /// Remember that a key's batch was accepted
+    pub fn accept(&mut self, key: &str, now: i64) {
+        if self.keys.insert(key.to_string(), true).is_none() {
+            self.order.push_back((now, key.to_string()));
+        }
+        self.prune(now);
+    }
+
+    /// Let a key go after its batch failed, so a retry can try again
+    pub fn release(&mut self, key: &str) {
+        if self.keys.get(key) == Some(&false) {
+            self.keys.remove(key);
+            self.order.retain(|(_, held)| held != key);
+        }
+    }
+}
+
+/// A key held for a batch being published. Dropping it without accepting it releases the
+/// key, whichever way the request ended.
+pub struct Reservation {
+    channel: Arc<Channel>,
+    key: String,
+    accepted: bool,
+}
+
+impl Reservation {
+    /// Reserve a key on a bucket, or say why it can't be
+    pub fn new(channel: Arc<Channel>, key: String) -> Result<Self, KeyStatus> {
+        match channel.reserve_idempotency_key(&key) {
+            KeyStatus::Reserved => Ok(Self {
+                channel,
+                key,
+                accepted: false,
+            }),
+            status => Err(status),
+        }
+    }
+
+    pub fn accept(mut self) {
+        self.channel.accept_idempotency_key(&self.key);
+        self.accepted = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.accepted {
+            self.channel.release_idempotency_key(&self.key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_expire() {
+        let mut cache = IdempotencyCache::default();
+        cache.accept("batch-1", 0);
+
+        assert_eq!(cache.reserve("batch-1", 1_000), KeyStatus::Accepted);
+        assert_eq!(cache.reserve("batch-2", 1_000), KeyStatus::Reserved);
+        assert_eq!(
+            cache.reserve("batch-1", IDEMPOTENCY_KEY_TTL_MS),
+            KeyStatus::Reserved
+        );
+    }
+
+    #[test]
+    fn test_cache_is_bounded() {
+        let mut cache = IdempotencyCache::default();
+        for i in 0..=MAX_IDEMPOTENCY_KEYS {
+            cache.accept(&format!("batch-{}", i), 0);
+        }
+
+        assert_eq!(cache.order.len(), MAX_IDEMPOTENCY_KEYS);
+        assert_eq!(cache.reserve("batch-1", 0), KeyStatus::Accepted);
+        assert_eq!(cache.reserve("batch-0", 0), KeyStatus::Reserved);
+    }
+
+    #[test]
+    fn test_reserved_keys() {
+        let mut cache = IdempotencyCache::default();
+        assert_eq!(cache.reserve("batch-1", 0), KeyStatus::Reserved);
+        assert_eq!(cache.reserve("batch-1", 0), KeyStatus::InFlight);
+
+        // A failed batch gives its key back for the retry
+        cache.release("batch-1");
+        assert_eq!(cache.reserve("batch-1", 0), KeyStatus::Reserved);
+        cache.accept("batch-1", 0);
+        cache.release("batch-1");
+        assert_eq!(cache.reserve("batch-1", 0), KeyStatus::Accepted);
+        assert_eq!(cache.order.len(), 1);
+    }
+}
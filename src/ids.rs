@@ -0,0 +1,274 @@
+use memorable_ids::{generate, suffix_generators, GenerateOptions};
+use std::path::Path;
+use std::str::FromStr;
+use uuid::{NoContext, Timestamp, Uuid};
+
+/// Top-level path segments kept for routes, current and future, so no bucket can shadow them
+const RESERVED_IDS: &[&str] = &[
+    "admin",
+    "api",
+    "assets",
+    "firehose",
+    "metrics",
+    "new",
+    "liveness_check",
+    "logplex",
+    "readiness_check",
+    "services",
+    ".well-known",
+];
+pub const MIN_BUCKET_ID_LENGTH: usize = 10;
+const MAX_GENERATE_ATTEMPTS: usize = 10;
+const WORDLIST_COMPONENTS: usize = 2;
+const WORDLIST_SUFFIX_MAX: u128 = 1000;
+/// The URL-safe alphabet nanoid uses; 64 symbols, so each takes six random bits
+const NANOID_ALPHABET: &[u8; 64] =
+    b"useandom-26T198340PX75pxJACKVERYMINDBUSHWOLF_GQZbfghjklqvwyzrict";
+const NANOID_LENGTH: usize = 21;
+
+/// A way of making bucket IDs; `generate_bucket_id` checks what it makes and retries
+pub trait IdGenerator: Send + Sync {
+    fn generate(&self) -> String;
+}
+
+/// How `/new` names buckets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IdScheme {
+    /// `word-word-number`, from the built-in list or `ID_WORDLIST`
+    #[default]
+    Memorable,
+    Uuidv7,
+    Nanoid,
+}
+
+impl FromStr for IdScheme {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "memorable" => Ok(IdScheme::Memorable),
+            "uuidv7" => Ok(IdScheme::Uuidv7),
+            "nanoid" => Ok(IdScheme::Nanoid),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Build the generator for a scheme, drawing memorable IDs from `wordlist` when given and
+/// starting every ID with `prefix`
+pub fn id_generator(
+    scheme: IdScheme,
+    wordlist: Option<Wordlist>,
+    prefix: Option<String>,
+) -> Box<dyn IdGenerator> {
+    let generator: Box<dyn IdGenerator> = match (scheme, wordlist) {
+        (IdScheme::Memorable, Some(wordlist)) => Box::new(wordlist),
+        (IdScheme::Memorable, None) => Box::new(Memorable),
+        (IdScheme::Uuidv7, _) => Box::new(Uuidv7),
+        (IdScheme::Nanoid, _) => Box::new(Nanoid),
+    };
+    match prefix {
+        Some(prefix) => Box::new(Prefixed { prefix, generator }),
+        None => generator,
+    }
+}
+
+/// `word-word-number` from the built-in English list
+pub struct Memorable;
+
+impl IdGenerator for Memorable {
+    fn generate(&self) -> String {
+        generate(GenerateOptions {
+            components: 2,
+            suffix: Some(suffix_generators::number),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+}
+
+/// Time-ordered UUIDs, so IDs sort by when the bucket was made
+pub struct Uuidv7;
+
+impl IdGenerator for Uuidv7 {
+    fn generate(&self) -> String {
+        let now = chrono::Utc::now();
+        let timestamp = Timestamp::from_unix(
+            NoContext,
+            now.timestamp() as u64,
+            now.timestamp_subsec_nanos(),
+        );
+        Uuid::new_v7(timestamp).to_string()
+    }
+}
+
+/// 21 random URL-safe characters, as nanoid makes by default
+pub struct Nanoid;
+
+impl IdGenerator for Nanoid {
+    fn generate(&self) -> String {
+        // Each symbol takes the low six bits of a byte from a v4 UUID. Byte 6 is skipped
+        // as only four of those bits are random; the fixed bits of byte 8 are all higher.
+        let random = [Uuid::new_v4(), Uuid::new_v4()];
+        random
+            .iter()
+            .flat_map(|uuid| {
+                let bytes = uuid.as_bytes();
+                [&bytes[..6], &bytes[7..]].concat()
+            })
+            .take(NANOID_LENGTH)
+            .map(|byte| NANOID_ALPHABET[(byte & 63) as usize] as char)
+            .collect()
+    }
+}
+
+/// Another generator's IDs behind a fixed prefix, for naming conventions like `teamname-`
+pub struct Prefixed {
+    prefix: String,
+    generator: Box<dyn IdGenerator>,
+}
+
+impl IdGenerator for Prefixed {
+    fn generate(&self) -> String {
+        format!("{}{}", self.prefix, self.generator.generate())
+    }
+}
+
+/// A custom list of words to build bucket IDs from, e.g. for a non-English locale
+pub struct Wordlist {
+    words: Vec<String>,
+}
+
+impl Wordlist {
+    /// Read a file of one word per line, skipping blanks and words that can't appear in an ID
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        Self::from_words(contents.lines())
+    }
+
+    fn from_words<'a>(lines: impl Iterator<Item = &'a str>) -> Result<Self, String> {
+        let words: Vec<String> = lines
+            .map(|line| line.trim().to_lowercase())
+            .filter(|word| !word.is_empty() && word.chars().all(char::is_alphanumeric))
+            .collect();
+
+        if words.len() < 2 {
+            return Err("a wordlist needs at least two usable words".to_string());
+        }
+        Ok(Self { words })
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+impl IdGenerator for Wordlist {
+    /// Pick words at random in the same `word-word-number` shape as the default generator
+    fn generate(&self) -> String {
+        let mut random = Uuid::new_v4().as_u128();
+        let mut parts = Vec::with_capacity(WORDLIST_COMPONENTS + 1);
+        for _ in 0..WORDLIST_COMPONENTS {
+            let len = self.words.len() as u128;
+            parts.push(self.words[(random % len) as usize].clone());
+            random /= len;
+        }
+        parts.push((random % WORDLIST_SUFFIX_MAX).to_string());
+        parts.join("-")
+    }
+}
+
+/// Whether a bucket ID is reserved and can't be used for a bucket
+pub fn is_reserved(bucket_id: &str) -> bool {
+    // Ignore options such as `;max-subs=` appended to the ID
+    let base = bucket_id.split(';').next().unwrap_or_default();
+    RESERVED_IDS
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(base))
+}
+
+/// Whether a generated ID contains a word from the configured block list
+fn is_blocked(candidate: &str, blocked_words: &[String]) -> bool {
+    candidate.split('-').any(|word| {
+        blocked_words
+            .iter()
+            .any(|blocked| blocked.eq_ignore_ascii_case(word))
+    })
+}
+
+/// Generate a bucket ID that isn't in use, reserved or blocked
+pub fn generate_bucket_id(
+    is_taken: impl Fn(&str) -> bool,
+    blocked_words: &[String],
+    generator: &dyn IdGenerator,
+) -> Option<String> {
+    (0..MAX_GENERATE_ATTEMPTS)
+        .map(|_| generator.generate())
+        .find(|candidate| {
+            // Short words from a custom list could produce IDs too short to be served
+            candidate.len() >= MIN_BUCKET_ID_LENGTH
+                && !is_taken(candidate)
+                && !is_reserved(candidate)
+                && !is_blocked(candidate, blocked_words)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_ids() {
+        assert!(is_reserved("metrics"));
+        assert!(is_reserved("API;max-subs=5"));
+        assert!(is_reserved("services"));
+        assert!(!is_reserved("metrics-otter-12"));
+    }
+
+    #[test]
+    fn test_blocked_words() {
+        let blocked = vec!["otter".to_string()];
+        assert!(is_blocked("brave-Otter-12", &blocked));
+        assert!(!is_blocked("brave-falcon-12", &blocked));
+    }
+
+    #[test]
+    fn test_gives_up_when_every_id_is_taken() {
+        assert_eq!(generate_bucket_id(|_| true, &[], &Memorable), None);
+        assert!(generate_bucket_id(|_| false, &[], &Memorable).is_some());
+    }
+
+    #[test]
+    fn test_wordlist() {
+        let wordlist =
+            Wordlist::from_words(["Zorro", "", "Hähnchen", "two words", "café"].into_iter())
+                .unwrap();
+        assert_eq!(wordlist.len(), 3);
+
+        let id = generate_bucket_id(|_| false, &[], &wordlist).unwrap();
+        let parts: Vec<&str> = id.split('-').collect();
+        assert_eq!(parts.len(), 3);
+        assert!(["zorro", "hähnchen", "café"].contains(&parts[0]));
+        assert!(parts[2].parse::<u32>().is_ok());
+
+        assert!(Wordlist::from_words(["only"].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_id_schemes() {
+        assert_eq!("UUIDv7".parse(), Ok(IdScheme::Uuidv7));
+        assert!("sequential".parse::<IdScheme>().is_err());
+
+        let uuid = id_generator(IdScheme::Uuidv7, None, None).generate();
+        assert_eq!(Uuid::parse_str(&uuid).unwrap().get_version_num(), 7);
+
+        let nanoid = id_generator(IdScheme::Nanoid, None, None).generate();
+        assert_eq!(nanoid.len(), NANOID_LENGTH);
+        assert!(nanoid.bytes().all(|byte| NANOID_ALPHABET.contains(&byte)));
+
+        let generator = id_generator(IdScheme::Memorable, None, Some("payments-".to_string()));
+        let id = generate_bucket_id(|_| false, &[], generator.as_ref()).unwrap();
+        assert!(id.starts_with("payments-"));
+        assert_eq!(id.split('-').count(), 4);
+    }
+}
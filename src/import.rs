@@ -0,0 +1,439 @@
+use crate::channel_manager::Channel;
+use crate::compression::{gunzip, is_gzip, DecompressError};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::encoding::{self, Encoding};
+use crate::models::{ImportEvent, ImportStatus, LogEvent};
+use crate::tokens::{self, TokenAccount};
+use crate::upload::publish_paced;
+use crate::{AppState, SUSPENSION_REASON_TEXT};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::{redirect, Url};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const ALLOWED_IMPORT_SCHEMES: &[&str] = &["https", "http"];
+const MAX_IMPORT_DOWNLOAD_SIZE: usize = 10 * 1024 * 1024; // 10MB
+const MAX_IMPORT_DECOMPRESSED_SIZE: usize = 20 * 1024 * 1024; // 20MB
+const IMPORT_TIMEOUT_SECS: u64 = 60;
+/// Most redirects followed while fetching, each checked like the URL it came from
+const MAX_IMPORT_REDIRECTS: usize = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportRequest {
+    url: String,
+}
+
+/// Start fetching a remote log file into a bucket; progress is reported via `import` events
+pub async fn post_import(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ImportRequest>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let url = match check_url(&request.url).await {
+        Ok(url) => url,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let Some(channel) = channel else {
+        // No active viewers, nothing to import into
+        warn!(
+            "Discarding import for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    info!(
+        "Starting import {} of {} into bucket {}",
+        id, url, bucket_id
+    );
+    tokio::spawn(run_import(
+        state,
+        bucket_id,
+        channel,
+        token,
+        id.clone(),
+        url,
+    ));
+
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "id": id }))).into_response())
+}
+
+/// Fetch and ingest a remote file, publishing progress as it goes
+async fn run_import(
+    state: AppState,
+    bucket_id: String,
+    channel: Arc<Channel>,
+    token: Option<Arc<TokenAccount>>,
+    id: String,
+    url: Url,
+) {
+    let mut progress = ImportEvent {
+        id,
+        url: url.to_string(),
+        status: ImportStatus::Fetching,
+        lines: 0,
+        skipped: 0,
+        error: None,
+    };
+    channel.publish_import(progress.clone());
+
+    match import_into(&state, &bucket_id, &channel, token, &url, &mut progress).await {
+        Ok(()) => {
+            info!(
+                "Import {} complete: {} lines, {} already in history",
+                progress.id, progress.lines, progress.skipped
+            );
+            progress.status = ImportStatus::Complete;
+        }
+        Err(error) => {
+            warn!("Import {} failed: {}", progress.id, error);
+            progress.status = ImportStatus::Failed;
+            progress.error = Some(error);
+        }
+    }
+
+    channel.publish_import(progress);
+}
+
+async fn import_into(
+    state: &AppState,
+    bucket_id: &str,
+    channel: &Arc<Channel>,
+    token: Option<Arc<TokenAccount>>,
+    url: &Url,
+    progress: &mut ImportEvent,
+) -> Result<(), String> {
+    let (contents, size) = fetch_text(url).await?;
+    let mut lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+    progress.skipped = skip_retained(channel, &mut lines, state.config.import_dedup_window).await;
+    if let Some(token) = &token {
+        token.record(lines.len() as u64, size as u64, chrono::Utc::now());
+    }
+
+    // Paced like an upload, so a big file doesn't get the bucket suspended
+    progress.status = ImportStatus::Ingesting;
+    publish_paced(state, bucket_id, channel, &lines, None, |count| {
+        progress.lines += count;
+        channel.publish_import(progress.clone());
+    })
+    .await
+}
+
+/// Drop lines that exactly repeat one of the bucket's last `window` retained events, so
+/// importing a file that overlaps an earlier one doesn't duplicate it. Returns the number
+/// of lines dropped.
+pub(crate) async fn skip_retained<T: AsRef<str>>(
+    channel: &Channel,
+    lines: &mut Vec<T>,
+    window: usize,
+) -> usize {
+    if window == 0 {
+        return 0;
+    }
+    retain_unseen(&channel.history().await, lines, window)
+}
+
+fn retain_unseen<T: AsRef<str>>(history: &[LogEvent], lines: &mut Vec<T>, window: usize) -> usize {
+    let seen: HashSet<u64> = history
+        .iter()
+        .rev()
+        .take(window)
+        // Compare against the line as written, escape sequences included
+        .map(|event| content_hash(event.raw_ansi.as_deref().unwrap_or(&event.raw)))
+        .collect();
+    if seen.is_empty() {
+        return 0;
+    }
+    let before = lines.len();
+    lines.retain(|line| !seen.contains(&content_hash(line.as_ref())));
+    before - lines.len()
+}
+
+fn content_hash(line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Parse a URL to fetch, refusing schemes other than http and https and hosts that
+/// aren't on the public internet
+pub(crate) async fn check_url(url: &str) -> Result<Url, String> {
+    let url = Url::parse(url).map_err(|_| "Not a valid URL".to_string())?;
+    if !ALLOWED_IMPORT_SCHEMES.contains(&url.scheme()) {
+        return Err("Only http and https URLs can be imported".to_string());
+    }
+    public_addresses(&url).await?;
+    Ok(url)
+}
+
+/// Resolve a URL's host, failing unless every address it has is public, so a fetch can't
+/// reach loopback, private networks or cloud metadata services
+async fn public_addresses(url: &Url) -> Result<Vec<SocketAddr>, String> {
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| "URL has no port".to_string())?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| "URL has no host".to_string())?;
+    // IPv6 hosts keep their brackets
+    let addresses: Vec<SocketAddr> = match host.trim_matches(['[', ']']).parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("Could not resolve {}: {}", host, e))?
+            .collect(),
+    };
+    if addresses.is_empty() {
+        return Err(format!("{} has no addresses", host));
+    }
+    if let Some(address) = addresses.iter().find(|address| !is_public(address.ip())) {
+        return Err(format!("{} is not a public address", address.ip()));
+    }
+    Ok(addresses)
+}
+
+/// A client for one request to `url`, which fails unless its host is public. It connects
+/// to the addresses just checked, not whatever a second lookup returns, and doesn't follow
+/// redirects, since they could lead anywhere.
+pub(crate) async fn pinned_client(url: &Url, timeout: Duration) -> Result<reqwest::Client, String> {
+    let addresses = public_addresses(url).await?;
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(redirect::Policy::none())
+        .resolve_to_addrs(url.host_str().unwrap_or_default(), &addresses)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                // Shared address space, where some clouds put their metadata services
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(ip.into());
+            }
+            let segments = ip.segments();
+            // NAT64 reaches the IPv4 address in the last 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [.., high, low] = segments;
+                return is_public(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)).into());
+            }
+            !(segments[..6] == [0; 6]
+                || ip.is_multicast()
+                // Unique local, including AWS's metadata service at fd00:ec2::254
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8))
+        }
+    }
+}
+
+/// Download a file and decompress and decode it, returning its text and downloaded size
+pub(crate) async fn fetch_text(url: &Url) -> Result<(String, usize), String> {
+    let mut body = fetch(url).await?;
+    let size = body.len();
+
+    if is_gzip(&body) {
+        body = gunzip(&body, MAX_IMPORT_DECOMPRESSED_SIZE).map_err(|e| match e {
+            DecompressError::TooLarge => "decompressed file is too large".to_string(),
+            DecompressError::Invalid => "file is not valid gzip".to_string(),
+        })?;
+    }
+
+    let (contents, encoding) = encoding::decode(&body);
+    if encoding != Encoding::Utf8 {
+        info!("{} is {:?}, transcoding to UTF-8", url, encoding);
+    }
+    Ok((contents, size))
+}
+
+/// Download a file into memory, enforcing the download size cap. Redirects are followed
+/// by hand, so each hop's host is checked before it's connected to.
+async fn fetch(url: &Url) -> Result<Vec<u8>, String> {
+    let mut url = url.clone();
+    let mut redirects = 0;
+    let mut response = loop {
+        let client = pinned_client(&url, Duration::from_secs(IMPORT_TIMEOUT_SECS)).await?;
+
+        let response = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_redirection() {
+            break response.error_for_status().map_err(|e| e.to_string())?;
+        }
+
+        redirects += 1;
+        if redirects > MAX_IMPORT_REDIRECTS {
+            return Err("too many redirects".to_string());
+        }
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| "redirect has no location".to_string())?;
+        url = url
+            .join(location)
+            .map_err(|_| "redirect location is not a valid URL".to_string())?;
+        if !ALLOWED_IMPORT_SCHEMES.contains(&url.scheme()) {
+            return Err("redirected to a URL that isn't http or https".to_string());
+        }
+    };
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_IMPORT_DOWNLOAD_SIZE as u64)
+    {
+        return Err("file is too large".to_string());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        if body.len() + chunk.len() > MAX_IMPORT_DOWNLOAD_SIZE {
+            return Err("file is too large".to_string());
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_manager::ChannelContext;
+    use crate::config::Config;
+    use crate::history::MemoryHistory;
+    use crate::ingest;
+    use std::collections::HashMap;
+
+    fn event(raw: &str) -> LogEvent {
+        LogEvent {
+            seq: 1,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: raw.to_string(),
+            raw_ansi: None,
+            fields: HashMap::new(),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[test]
+    fn test_is_public() {
+        assert!(is_public("93.184.216.34".parse().unwrap()));
+        assert!(is_public("2606:2800:220:1::1".parse().unwrap()));
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.100.100.200",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "fd00:ec2::254",
+            "fe80::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        assert!(check_url("https://93.184.216.34/app.log").await.is_ok());
+        assert!(check_url("ftp://93.184.216.34/app.log").await.is_err());
+        assert!(check_url("http://127.0.0.1:8080/").await.is_err());
+        assert!(check_url("http://[::1]/").await.is_err());
+        assert!(check_url("http://localhost/").await.is_err());
+    }
+
+    #[test]
+    fn test_retain_unseen() {
+        let history = vec![event("a"), event("b"), event("c")];
+
+        let mut lines = vec!["b", "c", "d", "c"];
+        assert_eq!(retain_unseen(&history, &mut lines, 10), 3);
+        assert_eq!(lines, vec!["d"]);
+
+        // Only the most recent events are checked
+        let mut lines = vec!["a", "c"];
+        assert_eq!(retain_unseen(&history, &mut lines, 1), 1);
+        assert_eq!(lines, vec!["a"]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_retained_colored_lines() {
+        let state = AppState::new(Config::from_lookup(|_| None));
+        let channel = Channel::new(
+            "import".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+        let colored = "\x1b[31mERROR\x1b[0m disk full";
+        ingest::ingest_lines(&state, &channel, &[colored]).await;
+
+        // Importing the same file again skips the line, escape sequences and all
+        let mut lines = vec![colored, "ERROR disk full"];
+        assert_eq!(skip_retained(&channel, &mut lines, 10).await, 1);
+        assert_eq!(lines, vec!["ERROR disk full"]);
+    }
+}
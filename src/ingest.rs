@@ -0,0 +1,920 @@
+use crate::ansi;
+use crate::channel_manager::Channel;
+use crate::compression::{self, DecompressError};
+use crate::encoding::{self, Encoding};
+use crate::journal;
+use crate::models::{LocalTime, LogEvent};
+use crate::multiline::{self, FoldedEvent, PendingEvent};
+use crate::parsers::{self, Columns, ParsedEvent};
+use crate::query::parse_duration_ms;
+use crate::routing;
+use crate::severity::Severity;
+use crate::{AppState, MAX_LOG_LINE_LENGTH};
+use axum::extract::Multipart;
+use axum::http::{header, HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::debug;
+
+const UNSUPPORTED_MEDIA_TYPE_TEXT: &str = "Unsupported Content-Type. Send newline-delimited text as text/plain, newline-delimited JSON as application/x-ndjson, a JSON object or array of objects as application/json, journal exports as application/vnd.fdo.journal, rows under a header as text/csv or text/tab-separated-values, or log files as multipart/form-data.";
+
+const UNSUPPORTED_CONTENT_ENCODING_TEXT: &str =
+    "Unsupported Content-Encoding. Send bodies uncompressed, or compressed with gzip, deflate or zstd.";
+
+/// Lines parsed and published before yielding to other tasks
+const INGEST_CHUNK_SIZE: usize = 256;
+
+/// Header giving every event in a request a time to live
+pub const EVENT_TTL_HEADER: &str = "X-Event-TTL";
+/// Field an event can carry to set its own time to live, overriding the header
+const TTL_FIELD: &str = "_ttl";
+
+/// Pick the time to order an event by, returning it and whether the reported time was clamped
+fn resolve_time(received: i64, reported: Option<i64>, max_skew: i64) -> (i64, bool) {
+    match reported {
+        Some(reported) => {
+            let time = reported.clamp(received - max_skew, received + max_skew);
+            (time, time != reported)
+        }
+        None => (received, false),
+    }
+}
+
+/// Parse a time to live given as whole seconds or a duration such as `30s`, in milliseconds
+pub fn parse_ttl(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let ttl = match value.parse::<i64>() {
+        Ok(secs) => secs.checked_mul(1000)?,
+        Err(_) => parse_duration_ms(value)?,
+    };
+    (ttl > 0).then_some(ttl)
+}
+
+/// Compression applied to a request body, from its `Content-Encoding`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    /// zlib-wrapped as HTTP specifies, or raw deflate as some clients send
+    Deflate,
+    Zstd,
+}
+
+impl ContentEncoding {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, (StatusCode, &'static str)> {
+        let Some(encoding) = headers.get(header::CONTENT_ENCODING) else {
+            return Ok(Self::Identity);
+        };
+        match encoding
+            .to_str()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "" | "identity" => Ok(Self::Identity),
+            "gzip" | "x-gzip" => Ok(Self::Gzip),
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                UNSUPPORTED_CONTENT_ENCODING_TEXT,
+            )),
+        }
+    }
+
+    /// Decompress a body, refusing to produce more than `limit` bytes so a small body
+    /// can't expand without bound
+    pub fn decode(self, body: &[u8], limit: usize) -> Result<Vec<u8>, (StatusCode, &'static str)> {
+        let decoded = match self {
+            Self::Identity => return Ok(body.to_vec()),
+            Self::Gzip => compression::gunzip(body, limit),
+            Self::Deflate if compression::is_zlib(body) => compression::inflate(body, limit),
+            Self::Deflate => compression::inflate_raw(body, limit),
+            Self::Zstd => compression::unzstd(body, limit),
+        };
+        decoded.map_err(|e| match e {
+            DecompressError::TooLarge => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "Decompressed request body is too large",
+            ),
+            DecompressError::Invalid => (
+                StatusCode::BAD_REQUEST,
+                "Request body does not match its Content-Encoding",
+            ),
+        })
+    }
+}
+
+/// Body formats accepted by the ingest endpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyFormat {
+    /// Newline-delimited text, one event per line
+    Text,
+    /// Newline-delimited JSON, one object per line
+    NdJson,
+    /// A single JSON object or an array of them
+    Json,
+    /// Form upload where every part is a batch of newline-delimited text
+    Multipart,
+    /// Entries in the systemd journal export format, as `journalctl -o export` writes them
+    Journal,
+    /// Comma-separated rows, with any delimiter, under an optional header row
+    Csv,
+    /// Tab-separated rows under an optional header row
+    Tsv,
+}
+
+impl BodyFormat {
+    /// Pick a body format from the request's `Content-Type`, defaulting to text
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, (StatusCode, &'static str)> {
+        let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+            return Ok(Self::Text);
+        };
+
+        let mime = content_type
+            .to_str()
+            .unwrap_or_default()
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match mime.as_str() {
+            // Form encoding is what `curl -d` sends by default, so treat it as text
+            ""
+            | "text/plain"
+            | "application/x-www-form-urlencoded"
+            | "application/octet-stream" => Ok(Self::Text),
+            "application/x-ndjson" | "application/jsonl" | "application/json-seq" => {
+                Ok(Self::NdJson)
+            }
+            "application/json" => Ok(Self::Json),
+            "multipart/form-data" => Ok(Self::Multipart),
+            journal::JOURNAL_EXPORT_MEDIA_TYPE => Ok(Self::Journal),
+            "text/csv" | "application/csv" => Ok(Self::Csv),
+            "text/tab-separated-values" => Ok(Self::Tsv),
+            _ => Err((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                UNSUPPORTED_MEDIA_TYPE_TEXT,
+            )),
+        }
+    }
+
+    /// Split a request body into individual event lines
+    pub fn split(self, body: &str) -> Result<Vec<String>, (StatusCode, &'static str)> {
+        match self {
+            Self::Text | Self::NdJson | Self::Multipart | Self::Csv | Self::Tsv => Ok(body
+                .split('\n')
+                .map(|line| line.strip_suffix('\r').unwrap_or(line))
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect()),
+            Self::Json => match serde_json::from_str::<Value>(body) {
+                Ok(Value::Array(items)) if items.iter().all(Value::is_object) => {
+                    Ok(items.iter().map(Value::to_string).collect())
+                }
+                Ok(object @ Value::Object(_)) => Ok(vec![object.to_string()]),
+                Ok(_) => Err((
+                    StatusCode::BAD_REQUEST,
+                    "JSON body must be an object or an array of objects",
+                )),
+                Err(_) => Err((StatusCode::BAD_REQUEST, "Request body is not valid JSON")),
+            },
+            Self::Journal => journal::decode(body.as_bytes())
+                .map_err(|message| (StatusCode::BAD_REQUEST, message)),
+        }
+    }
+
+    /// The columns named by a CSV or TSV body's first line, if it reads as a header row
+    pub fn header_columns(self, line: &str) -> Option<Columns> {
+        match self {
+            Self::Csv => parsers::csv_header(line, None),
+            Self::Tsv => parsers::csv_header(line, Some('\t')),
+            _ => None,
+        }
+    }
+
+    /// Split a raw request body, returning the lines with the positions of any that weren't
+    /// valid UTF-8. Journal exports can hold binary fields, and JSON must be valid UTF-8.
+    pub fn split_bytes(self, body: &[u8]) -> Result<BodyLines, (StatusCode, &'static str)> {
+        match (self, std::str::from_utf8(body)) {
+            (Self::Journal, _) => journal::decode(body)
+                .map(BodyLines::from)
+                .map_err(|message| (StatusCode::BAD_REQUEST, message)),
+            (_, Ok(body)) => self.split(body).map(BodyLines::from),
+            (Self::Json, Err(_)) => Err((StatusCode::BAD_REQUEST, "JSON body is not valid UTF-8")),
+            // Devices with broken encoders still get their lines through, marked as such
+            (_, Err(_)) => Ok(split_lossy(body)),
+        }
+    }
+}
+
+/// Event lines split from a request body
+#[derive(Debug, Default)]
+pub struct BodyLines {
+    pub lines: Vec<String>,
+    /// Positions of the lines that weren't valid UTF-8, in order
+    pub invalid_utf8: Vec<usize>,
+}
+
+impl From<Vec<String>> for BodyLines {
+    fn from(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            invalid_utf8: Vec::new(),
+        }
+    }
+}
+
+/// Split text on newlines, replacing bytes that aren't UTF-8 with U+FFFD
+fn split_lossy(body: &[u8]) -> BodyLines {
+    let mut lines = Vec::new();
+    let mut invalid_utf8 = Vec::new();
+    for line in body.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            continue;
+        }
+        let line = String::from_utf8_lossy(line);
+        if let Cow::Owned(_) = line {
+            invalid_utf8.push(lines.len());
+        }
+        lines.push(line.into_owned());
+    }
+    BodyLines {
+        lines,
+        invalid_utf8,
+    }
+}
+
+/// Result of pushing a batch of lines through the ingest pipeline
+pub enum IngestOutcome {
+    /// All lines were parsed and published
+    Accepted,
+    /// The batch tripped the rate limit and the bucket is now suspended
+    Suspended,
+    /// The bucket is paused and refused the batch
+    Paused,
+}
+
+/// Query parameters accepted by the ingest endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct IngestParams {
+    verbose: Option<String>,
+}
+
+/// Whether a writer asked for per-line results instead of an empty acknowledgement,
+/// with `Prefer: return=representation` (RFC 7240) or `?verbose=1`
+pub fn wants_report(headers: &HeaderMap, params: &IngestParams) -> bool {
+    let prefers = headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| {
+            preference
+                .trim()
+                .eq_ignore_ascii_case("return=representation")
+        });
+    let verbose = params
+        .verbose
+        .as_deref()
+        .is_some_and(|value| matches!(value, "1" | "true"));
+    prefers || verbose
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineStatus {
+    /// Published to the bucket
+    Accepted,
+    /// Moved to another bucket by a routing rule
+    Routed,
+    /// Buffered while the bucket is paused
+    Held,
+    /// Joined to the event of the line before, as part of a stack trace
+    Folded,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LineReport {
+    /// Position of the line in the request, counting from 1
+    pub line: usize,
+    pub status: LineStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parser: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    #[serde(rename = "hadInvalidUtf8", skip_serializing_if = "std::ops::Not::not")]
+    pub had_invalid_utf8: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RejectedLine {
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+/// What happened to each line of a request, for writers diagnosing partial failures
+#[derive(Debug, Default, Serialize)]
+pub struct IngestReport {
+    pub accepted: usize,
+    #[serde(rename = "firstSeq", skip_serializing_if = "Option::is_none")]
+    pub first_seq: Option<u64>,
+    #[serde(rename = "lastSeq", skip_serializing_if = "Option::is_none")]
+    pub last_seq: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub duplicate: bool,
+    pub lines: Vec<LineReport>,
+    pub rejected: Vec<RejectedLine>,
+    #[serde(skip)]
+    next_line: usize,
+}
+
+impl IngestReport {
+    fn next_line(&mut self) -> usize {
+        self.next_line += 1;
+        self.next_line
+    }
+
+    fn record(
+        &mut self,
+        status: LineStatus,
+        seq: Option<u64>,
+        parser: Option<String>,
+        truncated: bool,
+        had_invalid_utf8: bool,
+    ) {
+        let line = self.next_line();
+        if let Some(seq) = seq {
+            self.accepted += 1;
+            self.first_seq.get_or_insert(seq);
+            self.last_seq = Some(seq);
+        }
+        self.lines.push(LineReport {
+            line,
+            status,
+            seq,
+            parser,
+            truncated,
+            had_invalid_utf8,
+        });
+    }
+
+    /// Reject the next `count` lines for the same reason
+    pub fn reject(&mut self, count: usize, reason: &'static str) {
+        for _ in 0..count {
+            let line = self.next_line();
+            self.rejected.push(RejectedLine { line, reason });
+        }
+    }
+}
+
+/// Read every part of a multipart upload as text, enforcing a total size limit
+pub async fn read_multipart(
+    multipart: &mut Multipart,
+    limit: usize,
+) -> Result<Vec<String>, StatusCode> {
+    let mut parts = Vec::new();
+    let mut total = 0;
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        let bytes = field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?;
+        total += bytes.len();
+        if total > limit {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let (text, encoding) = encoding::decode(&bytes);
+        if encoding != Encoding::Utf8 {
+            debug!("Transcoded {:?} upload part to UTF-8", encoding);
+        }
+        parts.push(text);
+    }
+
+    Ok(parts)
+}
+
+/// Refuse a request carrying more events than `MAX_EVENTS_PER_REQUEST` with a 413, and
+/// `advice` on splitting it. Every ingest endpoint checks its request here; background
+/// publishers don't, as their paced batches and a resumed bucket's buffer aren't requests.
+pub fn check_event_count(
+    state: &AppState,
+    count: usize,
+    advice: &str,
+) -> Result<(), (StatusCode, String)> {
+    let max_events = state.config.max_events_per_request;
+    if count > max_events {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "{} events is more than the {} accepted per request; {}",
+                count, max_events, advice
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Rate-limit, parse and publish a batch of log lines to a channel.
+/// Every ingest path (HTTP, demo generator, ...) should go through here.
+pub async fn ingest_lines(state: &AppState, channel: &Channel, lines: &[&str]) -> IngestOutcome {
+    ingest_lines_reporting(state, channel, lines, None, &[], None).await
+}
+
+/// Like [`ingest_lines`], giving events without their own `_ttl` a time to live of `ttl`
+/// milliseconds, flagging the lines at the sorted positions in `invalid_utf8` as having had
+/// bytes replaced, and noting what happened to every line in `report`
+pub async fn ingest_lines_reporting(
+    state: &AppState,
+    channel: &Channel,
+    lines: &[&str],
+    ttl: Option<i64>,
+    invalid_utf8: &[usize],
+    report: Option<&mut IngestReport>,
+) -> IngestOutcome {
+    // Held lines count towards the rate limit when they are released, not now. They are
+    // held as raw lines, so only a `_ttl` field survives the pause.
+    if let Some(buffered) = channel.hold_if_paused(lines).await {
+        if let Some(report) = report {
+            for _ in lines {
+                if buffered {
+                    report.record(LineStatus::Held, None, None, false, false);
+                } else {
+                    report.reject(1, "Bucket is paused and its buffer is full");
+                }
+            }
+        }
+        return if buffered {
+            IngestOutcome::Accepted
+        } else {
+            IngestOutcome::Paused
+        };
+    }
+
+    // Record logs and check rate limit
+    if !channel.record_logs(lines.len() as u64) {
+        // Rate limit exceeded, bucket is now suspended
+        channel.publish_suspension(true).await;
+        if let Some(report) = report {
+            report.reject(
+                lines.len(),
+                "Bucket exceeded its rate limit and was suspended",
+            );
+        }
+        return IngestOutcome::Suspended;
+    }
+
+    let settings = channel.settings().await;
+    let events = if settings.multiline {
+        let pending = channel.take_pending_event().await;
+        let mut events = multiline::fold(pending.map(|pending| pending.event), lines, invalid_utf8);
+        // Writers waiting on a report get every event now, so no report leaves one pending
+        if report.is_none() {
+            if let Some(event) = events.pop() {
+                channel
+                    .hold_pending_event(PendingEvent {
+                        event,
+                        ttl,
+                        held_at: chrono::Utc::now().timestamp_millis(),
+                    })
+                    .await;
+            }
+        }
+        events
+    } else {
+        lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| FoldedEvent::single(line, invalid_utf8.binary_search(&i).is_ok()))
+            .collect()
+    };
+
+    publish_events(state, channel, events, ttl, report).await;
+    IngestOutcome::Accepted
+}
+
+/// Publish an event another bucket routed here, subject to this bucket's pause and rate
+/// limit like anything else sent to it. Returns whether the bucket took the event.
+pub async fn accept_routed_event(channel: &Channel, event: LogEvent) -> bool {
+    if let Some(buffered) = channel
+        .hold_if_paused(&[event.raw_ansi.as_deref().unwrap_or(&event.raw)])
+        .await
+    {
+        return buffered;
+    }
+    if channel.is_suspended() {
+        return false;
+    }
+    if !channel.record_logs(1) {
+        channel.publish_suspension(true).await;
+        return false;
+    }
+    channel.publish_log(event).await.is_some()
+}
+
+/// Publish an event held back in case the next post continued it, once it has waited
+/// long enough. Its lines already counted towards the rate limit when they were posted.
+pub async fn publish_pending_event(state: &AppState, channel: &Channel, held_before: i64) {
+    if let Some(pending) = channel.take_pending_event_held_before(held_before).await {
+        publish_events(state, channel, vec![pending.event], pending.ttl, None).await;
+    }
+}
+
+/// Parse and publish events that have been through the rate limit
+async fn publish_events(
+    state: &AppState,
+    channel: &Channel,
+    events: Vec<FoldedEvent>,
+    ttl: Option<i64>,
+    mut report: Option<&mut IngestReport>,
+) {
+    // Pin one ruleset for the whole batch so a reload mid-batch can't mix versions
+    let rules = state.rules.get();
+    let grok = channel.grok().await;
+    let pattern = channel.pattern().await;
+    // How far a client-reported timestamp may be from the receive time before it is clamped
+    let max_skew = state.config.max_clock_skew.as_millis() as i64;
+    let settings = channel.settings().await;
+    let tz = settings.display_timezone();
+    let received = chrono::Utc::now().timestamp_millis();
+    let mut columns = channel.columns().await;
+
+    for (i, folded) in events.into_iter().enumerate() {
+        // Let other buckets' ingest and broadcasts run between chunks of a large batch
+        if i > 0 && i % INGEST_CHUNK_SIZE == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        // Truncate lines that exceed the maximum size
+        let line = folded.text;
+        let truncated = line.len() > MAX_LOG_LINE_LENGTH;
+        let line = if truncated {
+            // Cut on a character boundary, or multi-byte text would panic the slice
+            let end = (0..=MAX_LOG_LINE_LENGTH)
+                .rev()
+                .find(|&i| line.is_char_boundary(i))
+                .unwrap_or(0);
+            format!("{}[truncated by log-bin]", &line[..end])
+        } else {
+            line
+        };
+
+        let had_invalid_utf8 = folded.had_invalid_utf8;
+        // Colored terminal output is parsed and shown as the text a terminal would show
+        let (line, raw_ansi, colored_level) = match ansi::strip(&line) {
+            Some(stripped) => (stripped.text, Some(line), stripped.level),
+            None => (line, None, None),
+        };
+        // W3C logs name their columns in a directive ahead of the lines that use them
+        if let Some(directive_columns) = parsers::w3c_columns(&line) {
+            let directive_columns = Arc::new(directive_columns);
+            channel.set_columns(directive_columns.clone()).await;
+            columns = Some(directive_columns);
+        }
+        let mut event = ParsedEvent::new(line.clone()).with_columns(columns.clone());
+        event.parse();
+        // A pattern the producer registered is more particular than the bucket's grok parsers
+        if let Some(pattern) = &pattern {
+            pattern.apply(&mut event);
+        }
+        grok.apply(&mut event);
+        if let Some(rules) = &rules {
+            rules.apply(&mut event);
+        }
+        if let Some(level) = colored_level {
+            if !event.fields.keys().any(|key| Severity::is_key(key)) {
+                event.fields.extend(parsers::create_fields(HashMap::from([(
+                    "level".to_string(),
+                    level.to_string(),
+                )])));
+            }
+        }
+        // Read before key normalization, which could rename the field
+        let expires_at = event
+            .fields
+            .get(TTL_FIELD)
+            .and_then(|field| parse_ttl(&field.value))
+            .or(ttl)
+            .map(|ttl| received.saturating_add(ttl));
+        if settings.normalize_keys {
+            event.normalize_keys();
+        }
+        channel.record_parse_outcome(event.outcome);
+
+        // Trust the producer's clock, but not so far that it scrambles the stream order
+        let reported_time = event.embedded_time();
+        let (time, clock_skewed) = resolve_time(event.time, reported_time, max_skew);
+
+        let log_event = LogEvent {
+            seq: 0,
+            time,
+            reported_time,
+            clock_skewed,
+            had_invalid_utf8,
+            raw: line,
+            raw_ansi,
+            fields: event.fields,
+            parser: event.parser,
+            ruleset_version: rules.as_ref().map(|rules| rules.version),
+            parser_confidence: event.confidence,
+            parser_candidates: event.candidates,
+            expires_at,
+            local_time: Some(LocalTime::new(time, tz)),
+        };
+
+        let parser = log_event.parser.clone();
+        let outcome = if routing::route(&state.channel_manager, channel, &log_event).await {
+            channel
+                .publish_log(log_event)
+                .await
+                .map(|seq| (LineStatus::Accepted, Some(seq)))
+        } else {
+            Some((LineStatus::Routed, None))
+        };
+
+        if let Some(report) = report.as_deref_mut() {
+            match outcome {
+                Some((status, seq)) => {
+                    // Only a line that starts an event gets its status; the rest joined it
+                    for n in 0..folded.lines {
+                        let status = match n == 0 && !folded.continued {
+                            true => status,
+                            false => LineStatus::Folded,
+                        };
+                        report.record(status, seq, parser.clone(), truncated, had_invalid_utf8);
+                    }
+                }
+                None => report.reject(folded.lines, "Event could not be serialized"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, content_type.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_resolve_time() {
+        assert_eq!(resolve_time(10_000, None, 1_000), (10_000, false));
+        assert_eq!(resolve_time(10_000, Some(9_500), 1_000), (9_500, false));
+        assert_eq!(resolve_time(10_000, Some(50_000), 1_000), (11_000, true));
+        assert_eq!(resolve_time(10_000, Some(0), 1_000), (9_000, true));
+    }
+
+    #[test]
+    fn test_parse_ttl() {
+        assert_eq!(parse_ttl("30"), Some(30_000));
+        assert_eq!(parse_ttl(" 5m "), Some(300_000));
+        assert_eq!(parse_ttl("250ms"), Some(250));
+        assert_eq!(parse_ttl("0"), None);
+        assert_eq!(parse_ttl("-5"), None);
+        assert_eq!(parse_ttl("soon"), None);
+    }
+
+    #[test]
+    fn test_body_format_from_headers() {
+        assert_eq!(
+            BodyFormat::from_headers(&HeaderMap::new()),
+            Ok(BodyFormat::Text)
+        );
+        assert_eq!(
+            BodyFormat::from_headers(&headers("text/plain; charset=utf-8")),
+            Ok(BodyFormat::Text)
+        );
+        assert_eq!(
+            BodyFormat::from_headers(&headers("application/x-ndjson")),
+            Ok(BodyFormat::NdJson)
+        );
+        assert_eq!(
+            BodyFormat::from_headers(&headers("Application/JSON")),
+            Ok(BodyFormat::Json)
+        );
+        assert_eq!(
+            BodyFormat::from_headers(&headers("text/csv; header=present")),
+            Ok(BodyFormat::Csv)
+        );
+        assert_eq!(
+            BodyFormat::from_headers(&headers("image/png"))
+                .unwrap_err()
+                .0,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        use flate2::write::{DeflateEncoder, GzEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let encoding = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_ENCODING, value.parse().unwrap());
+            ContentEncoding::from_headers(&headers)
+        };
+        assert_eq!(
+            ContentEncoding::from_headers(&HeaderMap::new()),
+            Ok(ContentEncoding::Identity)
+        );
+        assert_eq!(encoding("GZIP"), Ok(ContentEncoding::Gzip));
+        assert_eq!(encoding("zstd"), Ok(ContentEncoding::Zstd));
+        assert_eq!(
+            encoding("br").unwrap_err().0,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&[b'a'; 4096]).unwrap();
+        let gzip = gzip.finish().unwrap();
+        assert_eq!(
+            ContentEncoding::Gzip.decode(&gzip, 4096).unwrap().len(),
+            4096
+        );
+        assert_eq!(
+            ContentEncoding::Gzip.decode(&gzip, 1024).unwrap_err().0,
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            ContentEncoding::Gzip
+                .decode(b"one\ntwo", 1024)
+                .unwrap_err()
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+
+        let mut deflate = DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(b"one\ntwo").unwrap();
+        let deflate = deflate.finish().unwrap();
+        assert_eq!(
+            ContentEncoding::Deflate.decode(&deflate, 1024).unwrap(),
+            b"one\ntwo"
+        );
+    }
+
+    #[test]
+    fn test_split_json_array() {
+        let lines = BodyFormat::Json
+            .split(r#"[{"level":"info"},{"level":"error"}]"#)
+            .unwrap();
+        assert_eq!(lines, vec![r#"{"level":"info"}"#, r#"{"level":"error"}"#]);
+
+        let lines = BodyFormat::Json.split(r#"{"level":"info"}"#).unwrap();
+        assert_eq!(lines.len(), 1);
+
+        assert!(BodyFormat::Json.split("[]").unwrap().is_empty());
+        assert!(BodyFormat::Json.split("42").is_err());
+        assert!(BodyFormat::Json
+            .split(r#"[{"level":"info"},"loose"]"#)
+            .is_err());
+        assert!(BodyFormat::Json.split("not json").is_err());
+    }
+
+    #[test]
+    fn test_split_text() {
+        let lines = BodyFormat::Text.split("one\r\n\ntwo\n").unwrap();
+        assert_eq!(lines, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_split_bytes_lossy() {
+        let body = BodyFormat::Text
+            .split_bytes(b"ok\r\n\nsensor=\xff\xfe temp=21\ncaf\xc3\xa9\n")
+            .unwrap();
+        assert_eq!(
+            body.lines,
+            vec!["ok", "sensor=\u{fffd}\u{fffd} temp=21", "caf\u{e9}"]
+        );
+        assert_eq!(body.invalid_utf8, vec![1]);
+
+        let body = BodyFormat::Text.split_bytes(b"fine\n").unwrap();
+        assert!(body.invalid_utf8.is_empty());
+        assert!(BodyFormat::Json.split_bytes(b"{\"a\":\"\xff\"}").is_err());
+    }
+
+    #[test]
+    fn test_wants_report() {
+        let params = |verbose: &str| IngestParams {
+            verbose: Some(verbose.to_string()),
+        };
+        assert!(!wants_report(&HeaderMap::new(), &IngestParams::default()));
+        assert!(wants_report(&HeaderMap::new(), &params("1")));
+        assert!(!wants_report(&HeaderMap::new(), &params("0")));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("prefer", "wait=5, return=representation".parse().unwrap());
+        assert!(wants_report(&headers, &IngestParams::default()));
+    }
+
+    #[test]
+    fn test_report_numbers_lines() {
+        let mut report = IngestReport::default();
+        report.record(
+            LineStatus::Accepted,
+            Some(7),
+            Some("json".to_string()),
+            false,
+            false,
+        );
+        report.record(LineStatus::Routed, None, None, false, false);
+        report.record(LineStatus::Accepted, Some(8), None, true, false);
+        report.reject(2, "Bucket is paused");
+
+        assert_eq!(report.accepted, 2);
+        assert_eq!((report.first_seq, report.last_seq), (Some(7), Some(8)));
+        assert_eq!(report.lines[2].line, 3);
+        assert_eq!(report.rejected[1].line, 5);
+    }
+
+    #[tokio::test]
+    async fn test_hostile_lines_publish() {
+        use crate::channel_manager::ChannelContext;
+        use crate::config::Config;
+        use crate::history::MemoryHistory;
+
+        const FRAGMENTS: &[&str] = &[
+            "{",
+            "}",
+            "[",
+            "]",
+            "\"",
+            ":",
+            ",",
+            "=",
+            ";",
+            "\\",
+            "1e999",
+            "-0",
+            "NaN",
+            "null",
+            "\u{0}",
+            "\u{202e}",
+            "\u{fffd}",
+            "\u{e9}",
+            "\u{20ac}",
+            "<34>",
+            "Oct 11 22:14:15 ",
+            "?1",
+            ":YWJj:",
+            "(",
+            ")",
+            "*",
+            " ",
+            "\t",
+            "a",
+            "\\ud800",
+        ];
+        let channel = Channel::new(
+            "fuzz".to_string(),
+            Box::new(MemoryHistory::default()),
+            ChannelContext::default(),
+        );
+
+        // A fixed xorshift seed keeps failures reproducible
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut lines: Vec<String> = (0..200)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let length = (state % 40) as usize;
+                (0..length)
+                    .map(|i| FRAGMENTS[((state >> (i % 48)) as usize + i) % FRAGMENTS.len()])
+                    .collect()
+            })
+            .collect();
+        // Multi-byte text straddling the truncation point
+        lines.push("\u{20ac}".repeat(MAX_LOG_LINE_LENGTH));
+        lines.push(format!("{}{}", "[".repeat(1000), "]".repeat(1000)));
+
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let mut report = IngestReport::default();
+        let app_state = AppState::new(Config::from_lookup(|_| None));
+        let outcome =
+            ingest_lines_reporting(&app_state, &channel, &lines, None, &[], Some(&mut report))
+                .await;
+
+        assert!(matches!(outcome, IngestOutcome::Accepted));
+        assert_eq!(report.accepted, lines.len());
+        assert!(report.rejected.is_empty());
+        assert!(report.lines[200].truncated);
+    }
+}
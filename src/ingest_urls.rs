@@ -0,0 +1,265 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{self, IngestParams};
+use crate::{ids, tokens, AppState};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+/// Live ingest URLs a bucket can have at once
+const MAX_INGEST_URLS_PER_BUCKET: usize = 20;
+const DEFAULT_INGEST_URL_TTL_SECS: u64 = 60 * 60;
+const MAX_INGEST_URL_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const MAX_INGEST_URL_LABEL_LENGTH: usize = 100;
+
+/// A revocable URL that lets one producer write to a bucket
+#[derive(Debug, Clone, Serialize)]
+pub struct IngestUrl {
+    pub nonce: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: i64,
+}
+
+/// The ingest URLs handed out for a bucket
+#[derive(Debug, Default)]
+pub struct IngestUrls {
+    urls: Vec<IngestUrl>,
+}
+
+impl IngestUrls {
+    fn prune(&mut self, now: i64) {
+        self.urls.retain(|url| url.expires_at > now);
+    }
+
+    /// Create a URL valid for `ttl_ms`, or `None` if the bucket already has too many
+    pub fn mint(&mut self, label: Option<String>, ttl_ms: i64, now: i64) -> Option<IngestUrl> {
+        self.prune(now);
+        if self.urls.len() >= MAX_INGEST_URLS_PER_BUCKET {
+            return None;
+        }
+
+        let url = IngestUrl {
+            nonce: Uuid::new_v4().simple().to_string(),
+            label,
+            created_at: now,
+            expires_at: now + ttl_ms,
+        };
+        self.urls.push(url.clone());
+        Some(url)
+    }
+
+    pub fn is_valid(&self, nonce: &str, now: i64) -> bool {
+        self.urls
+            .iter()
+            .any(|url| url.nonce == nonce && url.expires_at > now)
+    }
+
+    /// Revoke a URL, returning whether it existed
+    pub fn revoke(&mut self, nonce: &str) -> bool {
+        let before = self.urls.len();
+        self.urls.retain(|url| url.nonce != nonce);
+        self.urls.len() != before
+    }
+
+    pub fn list(&mut self, now: i64) -> Vec<IngestUrl> {
+        self.prune(now);
+        self.urls.clone()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MintParams {
+    /// Seconds until the URL stops working
+    ttl: Option<u64>,
+    label: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MintedIngestUrl {
+    #[serde(flatten)]
+    url: IngestUrl,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestUrlList {
+    urls: Vec<IngestUrl>,
+}
+
+/// Hand out a new ingest URL for a single producer
+pub async fn post_ingest_url(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Query(params): Query<MintParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Only writers may mint URLs that let others write
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let ttl = params.ttl.unwrap_or(DEFAULT_INGEST_URL_TTL_SECS);
+    if ttl == 0 || ttl > MAX_INGEST_URL_TTL_SECS {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Ingest URLs can last between 1 and {} seconds",
+                MAX_INGEST_URL_TTL_SECS
+            ),
+        )
+            .into_response());
+    }
+
+    if params
+        .label
+        .as_ref()
+        .is_some_and(|label| label.len() > MAX_INGEST_URL_LABEL_LENGTH)
+    {
+        return Ok((StatusCode::BAD_REQUEST, "Ingest URL label is too long").into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+    channel.mark_configured();
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let Some(url) = channel
+        .mint_ingest_url(params.label, (ttl * 1000) as i64, now)
+        .await
+    else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "A bucket can have at most {} ingest URLs",
+                MAX_INGEST_URLS_PER_BUCKET
+            ),
+        )
+            .into_response());
+    };
+
+    info!("Minted ingest URL for bucket {}", bucket_id);
+    let path = format!("/{}/ingest/{}", bucket_id, url.nonce);
+    Ok((StatusCode::CREATED, Json(MintedIngestUrl { url, path })).into_response())
+}
+
+pub async fn get_ingest_urls(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<IngestUrlList> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let urls = match channel {
+        Some(channel) => channel.ingest_urls().await,
+        None => Vec::new(),
+    };
+    Json(IngestUrlList { urls })
+}
+
+/// Cut off one producer without affecting any other writers
+pub async fn delete_ingest_url(
+    Path((bucket_id, nonce)): Path<(String, String)>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) if channel.revoke_ingest_url(&nonce).await => {
+            info!("Revoked ingest URL for bucket {}", bucket_id);
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Accept events sent to an ingest URL; the URL itself stands in for a write token
+pub async fn post_ingest(
+    Path((bucket_id, nonce)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Query(params): Query<IngestParams>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<Response, StatusCode> {
+    if ids::is_reserved(&bucket_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let valid = match channel {
+        Some(channel) => channel.ingest_url_valid(&nonce, now).await,
+        None => false,
+    };
+    if !valid {
+        return Ok((
+            StatusCode::NOT_FOUND,
+            "Unknown, expired or revoked ingest URL",
+        )
+            .into_response());
+    }
+
+    let report = ingest::wants_report(&headers, &params);
+    crate::accept_events(bucket_id, state, headers, body, None, report).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mint_and_revoke() {
+        let mut urls = IngestUrls::default();
+        let first = urls.mint(Some("stripe".to_string()), 1_000, 0).unwrap();
+        let second = urls.mint(None, 5_000, 0).unwrap();
+
+        assert!(urls.is_valid(&first.nonce, 500));
+        assert!(!urls.is_valid(&first.nonce, 1_000));
+        assert!(!urls.is_valid("unknown", 500));
+
+        assert!(urls.revoke(&second.nonce));
+        assert!(!urls.revoke(&second.nonce));
+        assert!(!urls.is_valid(&second.nonce, 500));
+        assert_eq!(urls.list(2_000).len(), 0);
+    }
+
+    #[test]
+    fn test_mint_is_bounded() {
+        let mut urls = IngestUrls::default();
+        for _ in 0..MAX_INGEST_URLS_PER_BUCKET {
+            assert!(urls.mint(None, 1_000, 0).is_some());
+        }
+        assert!(urls.mint(None, 1_000, 0).is_none());
+
+        // Expired URLs make room
+        assert!(urls.mint(None, 1_000, 1_000).is_some());
+    }
+}
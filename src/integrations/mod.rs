@@ -0,0 +1,48 @@
+pub mod pagerduty;
+pub mod slack;
+
+use crate::import::pinned_client;
+use reqwest::Url;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+const INTEGRATION_TIMEOUT_SECS: u64 = 10;
+
+/// Shared HTTP client for outbound notifications
+pub fn http_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(INTEGRATION_TIMEOUT_SECS))
+            .build()
+            .expect("Failed to build integration HTTP client")
+    })
+}
+
+/// Post a JSON body to a URL a bucket's owner chose, such as a webhook. Only public hosts
+/// are connected to and redirects count as failures, so a hook can't reach the server's
+/// own network.
+pub async fn post_to_public_host(url: &str, body: String) -> Result<(), String> {
+    let url = Url::parse(url)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "https" | "http"))
+        .ok_or_else(|| "not an http or https URL".to_string())?;
+    let client = pinned_client(&url, Duration::from_secs(INTEGRATION_TIMEOUT_SECS)).await?;
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if response.status().is_redirection() {
+        return Err(format!("redirected with {}", response.status()));
+    }
+    response.error_for_status().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Public link to a bucket's viewer on a server reached at `public_url`
+pub fn bucket_link(public_url: &str, bucket_id: &str) -> String {
+    format!("{}/{}", public_url.trim_end_matches('/'), bucket_id)
+}
@@ -0,0 +1,206 @@
+use super::http_client;
+use crate::alerts::{AlertEvent, AlertStatus};
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PagerDutyConfig {
+    /// Events API v2 integration key
+    pub routing_key: String,
+}
+
+/// Deduplication key so repeated triggers and the eventual resolve refer to the same incident
+fn dedup_key(bucket_id: &str, rule: &str) -> String {
+    format!("log-bin/{}/{}", bucket_id, rule)
+}
+
+/// Build the Events API v2 body for an alert transition
+fn event_body(
+    config: &PagerDutyConfig,
+    bucket_id: &str,
+    link: &str,
+    alert: &AlertEvent,
+) -> serde_json::Value {
+    let dedup_key = dedup_key(bucket_id, &alert.rule);
+
+    match alert.status {
+        AlertStatus::Firing => json!({
+            "routing_key": config.routing_key,
+            "event_action": "trigger",
+            "dedup_key": dedup_key,
+            "payload": {
+                "summary": format!(
+                    "{} fired in log-bin bucket {}: {} matching events in the last {}s",
+                    alert.rule, bucket_id, alert.count, alert.window_secs
+                ),
+                "source": bucket_id,
+                "severity": "critical",
+                "component": "log-bin",
+                "custom_details": {
+                    "count": alert.count,
+                    "window_secs": alert.window_secs,
+                    "samples": alert.samples,
+                },
+            },
+            "links": [{ "href": link, "text": "Open bucket" }],
+        }),
+        AlertStatus::Resolved => json!({
+            "routing_key": config.routing_key,
+            "event_action": "resolve",
+            "dedup_key": dedup_key,
+        }),
+    }
+}
+
+/// Fire-and-forget trigger or resolve for an alert transition
+pub fn notify_alert(config: &PagerDutyConfig, bucket_id: &str, link: &str, alert: &AlertEvent) {
+    let body = event_body(config, bucket_id, link, alert);
+    tokio::spawn(async move {
+        let result = http_client()
+            .post(PAGERDUTY_EVENTS_URL)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+
+        if let Err(e) = result {
+            warn!("Failed to deliver PagerDuty event: {}", e);
+        }
+    });
+}
+
+/// A bucket's PagerDuty integration, without its routing key, which could open incidents
+pub async fn get_pagerduty(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) => channel
+            .pagerduty()
+            .await
+            .map(|config| Json(changes::redacted(&config)))
+            .ok_or(StatusCode::NOT_FOUND),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+pub async fn put_pagerduty(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(config): Json<PagerDutyConfig>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if config.routing_key.trim().is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "A routing key is required").into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!("Configured PagerDuty integration for bucket {}", bucket_id);
+    let config = Some(config);
+    let previous = channel.set_pagerduty(config.clone()).await;
+    channel.publish_config_change(
+        "pagerduty",
+        &previous,
+        &config,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn delete_pagerduty(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    if let Some(channel) = channel {
+        let previous = channel.set_pagerduty(None).await;
+        channel.publish_config_change(
+            "pagerduty",
+            &previous,
+            &None,
+            changes::actor(&state, &headers),
+        );
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertKind, AlertSeverity};
+
+    #[test]
+    fn test_trigger_and_resolve_share_dedup_key() {
+        let config = PagerDutyConfig {
+            routing_key: "key".to_string(),
+        };
+        let mut alert = AlertEvent {
+            rule: "errors".to_string(),
+            kind: AlertKind::Match,
+            status: AlertStatus::Firing,
+            severity: AlertSeverity::Critical,
+            count: 5,
+            window_secs: 60,
+            samples: vec![],
+        };
+
+        let trigger = event_body(
+            &config,
+            "my-bucket",
+            "https://logs.example/my-bucket",
+            &alert,
+        );
+        assert_eq!(trigger["event_action"], "trigger");
+
+        alert.status = AlertStatus::Resolved;
+        let resolve = event_body(
+            &config,
+            "my-bucket",
+            "https://logs.example/my-bucket",
+            &alert,
+        );
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(trigger["dedup_key"], resolve["dedup_key"]);
+    }
+}
@@ -0,0 +1,214 @@
+use super::post_to_public_host;
+use crate::alerts::AlertEvent;
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::import::check_url;
+use crate::tokens;
+use crate::webhooks::is_valid_webhook_url;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// Default message for alerts; see `render_alert` for the available placeholders
+const DEFAULT_ALERT_TEMPLATE: &str = ":rotating_light: *{rule}* fired in bucket `{bucket}`: {count} matching events in the last {window}s\n{samples}\n<{link}|Open bucket>";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlackConfig {
+    /// Slack incoming webhook URL
+    pub webhook_url: String,
+    /// Message template for alerts
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SlackMessage {
+    text: String,
+}
+
+/// Escape the characters Slack treats as control sequences
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Render an alert message, substituting `{rule}`, `{bucket}`, `{count}`,
+/// `{window}`, `{samples}` and `{link}`
+pub fn render_alert(template: &str, bucket_id: &str, link: &str, alert: &AlertEvent) -> String {
+    let samples = if alert.samples.is_empty() {
+        String::new()
+    } else {
+        let lines: Vec<String> = alert
+            .samples
+            .iter()
+            .map(|line| escape(line).replace("```", "'''"))
+            .collect();
+        format!("```\n{}\n```", lines.join("\n"))
+    };
+
+    template
+        .replace("{rule}", &escape(&alert.rule))
+        .replace("{bucket}", &escape(bucket_id))
+        .replace("{count}", &alert.count.to_string())
+        .replace("{window}", &alert.window_secs.to_string())
+        .replace("{link}", link)
+        .replace("{samples}", &samples)
+}
+
+pub fn notify_alert(config: &SlackConfig, bucket_id: &str, link: &str, alert: &AlertEvent) {
+    let template = config.template.as_deref().unwrap_or(DEFAULT_ALERT_TEMPLATE);
+    send(config, render_alert(template, bucket_id, link, alert));
+}
+
+pub fn notify_suspension(config: &SlackConfig, bucket_id: &str, link: &str, suspended: bool) {
+    let text = if suspended {
+        format!(
+            ":no_entry: Bucket `{}` has been suspended for exceeding the rate limit. <{}|Open bucket>",
+            escape(bucket_id),
+            link
+        )
+    } else {
+        format!(
+            ":white_check_mark: Bucket `{}` is no longer suspended. <{}|Open bucket>",
+            escape(bucket_id),
+            link
+        )
+    };
+    send(config, text);
+}
+
+/// Fire-and-forget post to the Slack incoming webhook
+fn send(config: &SlackConfig, text: String) {
+    let url = config.webhook_url.clone();
+    let body = serde_json::to_string(&SlackMessage { text }).unwrap();
+    tokio::spawn(async move {
+        if let Err(e) = post_to_public_host(&url, body).await {
+            warn!("Failed to deliver Slack notification: {}", e);
+        }
+    });
+}
+
+/// A bucket's Slack integration, without its webhook URL, which anyone could post with
+pub async fn get_slack(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) => channel
+            .slack()
+            .await
+            .map(|config| Json(changes::redacted(&config)))
+            .ok_or(StatusCode::NOT_FOUND),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+pub async fn put_slack(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(config): Json<SlackConfig>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if !is_valid_webhook_url(&config.webhook_url) {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Slack webhook URL must be an absolute http or https URL",
+        )
+            .into_response());
+    }
+    if let Err(e) = check_url(&config.webhook_url).await {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!("Slack webhook URL is not allowed: {}", e),
+        )
+            .into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!("Configured Slack integration for bucket {}", bucket_id);
+    let config = Some(config);
+    let previous = channel.set_slack(config.clone()).await;
+    channel.publish_config_change(
+        "slack",
+        &previous,
+        &config,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn delete_slack(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    if let Some(channel) = channel {
+        let previous = channel.set_slack(None).await;
+        channel.publish_config_change("slack", &previous, &None, changes::actor(&state, &headers));
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertKind, AlertSeverity, AlertStatus};
+
+    #[test]
+    fn test_render_alert() {
+        let alert = AlertEvent {
+            rule: "errors".to_string(),
+            kind: AlertKind::Match,
+            status: AlertStatus::Firing,
+            severity: AlertSeverity::Warning,
+            count: 3,
+            window_secs: 60,
+            samples: vec!["<b>boom</b>".to_string()],
+        };
+
+        let text = render_alert(
+            "{rule} in {bucket}: {count}/{window}s {samples} {link}",
+            "my-bucket",
+            "https://logs.example/my-bucket",
+            &alert,
+        );
+        assert!(text.starts_with("errors in my-bucket: 3/60s ```\n&lt;b&gt;boom&lt;/b&gt;\n```"));
+        assert!(text.ends_with("/my-bucket"));
+    }
+}
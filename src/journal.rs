@@ -0,0 +1,312 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::metrics::METRICS;
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat};
+use futures_util::StreamExt;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+/// Media type of the journal export format, as sent by `systemd-journal-upload`
+pub const JOURNAL_EXPORT_MEDIA_TYPE: &str = "application/vnd.fdo.journal";
+/// Journal addressing fields that only mean something to the journal that wrote them
+const SKIPPED_FIELDS: &[&str] = &[
+    "__CURSOR",
+    "__MONOTONIC_TIMESTAMP",
+    "__SEQNUM",
+    "__SEQNUM_ID",
+];
+
+/// Splits a journal export stream into entries. Fields are `NAME=value` lines, or for
+/// binary values the name, a newline, a little-endian 64-bit length and the raw bytes;
+/// a blank line ends each entry.
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+    fields: Vec<(String, Vec<u8>)>,
+}
+
+impl Decoder {
+    /// Add bytes from the stream, returning the entries they complete as JSON lines
+    pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<String>, &'static str> {
+        self.buffer.extend_from_slice(bytes);
+        let mut entries = Vec::new();
+        let mut start = 0;
+        while let Some(consumed) = self.next_field(&self.buffer[start..])? {
+            match consumed {
+                Field::EndOfEntry(length) => {
+                    start += length;
+                    if !self.fields.is_empty() {
+                        entries.push(entry_line(std::mem::take(&mut self.fields)));
+                    }
+                }
+                Field::Value(length, name, value) => {
+                    start += length;
+                    self.fields.push((name, value));
+                }
+            }
+        }
+        self.buffer.drain(..start);
+
+        let pending = self.buffer.len()
+            + self
+                .fields
+                .iter()
+                .map(|(name, value)| name.len() + value.len())
+                .sum::<usize>();
+        if pending > MAX_LOG_BODY_SIZE {
+            return Err("Journal entry is too large");
+        }
+        Ok(entries)
+    }
+
+    /// End the stream, returning a last entry that wasn't followed by a blank line
+    pub fn finish(mut self) -> Result<Option<String>, &'static str> {
+        if !self.buffer.is_empty() {
+            // The final field may be missing only its newline
+            let mut entries = self.push(b"\n")?;
+            if !self.buffer.is_empty() {
+                return Err("Journal export ends partway through a field");
+            }
+            if let Some(entry) = entries.pop() {
+                return Ok(Some(entry));
+            }
+        }
+        Ok((!self.fields.is_empty()).then(|| entry_line(self.fields)))
+    }
+
+    fn next_field(&self, buffer: &[u8]) -> Result<Option<Field>, &'static str> {
+        let Some(newline) = buffer.iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let line = &buffer[..newline];
+        if line.is_empty() {
+            return Ok(Some(Field::EndOfEntry(1)));
+        }
+
+        if let Some(equals) = line.iter().position(|&b| b == b'=') {
+            let name = field_name(&line[..equals])?;
+            let value = line[equals + 1..].to_vec();
+            return Ok(Some(Field::Value(newline + 1, name, value)));
+        }
+
+        let name = field_name(line)?;
+        let data = &buffer[newline + 1..];
+        let Some(length) = data.get(..8) else {
+            return Ok(None);
+        };
+        let length = u64::from_le_bytes(length.try_into().unwrap());
+        if length > MAX_LOG_BODY_SIZE as u64 {
+            return Err("Journal field is too large");
+        }
+        let end = 8 + length as usize;
+        match data.get(end) {
+            None => Ok(None),
+            Some(b'\n') => Ok(Some(Field::Value(
+                newline + 1 + end + 1,
+                name,
+                data[8..end].to_vec(),
+            ))),
+            Some(_) => Err("Binary journal field is missing its trailing newline"),
+        }
+    }
+}
+
+enum Field {
+    /// A blank line, and its length
+    EndOfEntry(usize),
+    /// A field, the bytes it took up, its name and its value
+    Value(usize, String, Vec<u8>),
+}
+
+fn field_name(bytes: &[u8]) -> Result<String, &'static str> {
+    match std::str::from_utf8(bytes) {
+        Ok(name) if !name.is_empty() && !name.contains(' ') => Ok(name.to_string()),
+        _ => Err("Journal export has an invalid field name"),
+    }
+}
+
+/// Decode a whole journal export body into one JSON line per entry
+pub fn decode(body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let mut decoder = Decoder::default();
+    let mut entries = decoder.push(body)?;
+    entries.extend(decoder.finish()?);
+    Ok(entries)
+}
+
+/// Render an entry as JSON, with `MESSAGE`, `PRIORITY` and the wall clock time under the
+/// keys the parsers look for
+fn entry_line(fields: Vec<(String, Vec<u8>)>) -> String {
+    let mut object = Map::new();
+    for (name, value) in fields {
+        if SKIPPED_FIELDS.contains(&name.as_str()) {
+            continue;
+        }
+        // Binary fields are usually text with control characters in, such as colored output
+        let value = String::from_utf8_lossy(&value).into_owned();
+        let key = match name.as_str() {
+            "MESSAGE" => "message".to_string(),
+            // Syslog severities, which levels already understand
+            "PRIORITY" => "level".to_string(),
+            "__REALTIME_TIMESTAMP" => {
+                let time = value
+                    .parse::<i64>()
+                    .ok()
+                    .and_then(DateTime::from_timestamp_micros);
+                if let Some(time) = time {
+                    let time = time.to_rfc3339_opts(SecondsFormat::Micros, true);
+                    object.entry("time").or_insert(time.into());
+                }
+                continue;
+            }
+            _ => name,
+        };
+        // Fields can repeat, but the first value is the one most tools show
+        object.entry(key).or_insert(value.into());
+    }
+    Value::Object(object).to_string()
+}
+
+/// `POST /{bucket_id}/upload`: where `systemd-journal-upload --url` sends entries. Its
+/// uploads stream for as long as it follows the journal, so entries are published as
+/// they arrive, and only a stream that goes quiet for the request timeout is cut off.
+pub async fn post_upload(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if ids::is_reserved(&bucket_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let mut decoder = Decoder::default();
+    let mut body = body.into_data_stream();
+    let mut discarded = 0;
+    let mut finished = false;
+    while !finished {
+        // The request timeout doesn't cover this route, so bound each wait for more instead
+        let Ok(next) = tokio::time::timeout(state.config.request_timeout, body.next()).await else {
+            METRICS.request_timeouts.inc();
+            warn!("Journal upload to bucket {} went quiet", bucket_id);
+            return Ok((StatusCode::REQUEST_TIMEOUT, "Request timed out").into_response());
+        };
+        let (entries, bytes) = match next {
+            Some(Ok(chunk)) => (decoder.push(&chunk), chunk.len()),
+            Some(Err(_)) => return Err(StatusCode::BAD_REQUEST),
+            None => {
+                finished = true;
+                let last = std::mem::take(&mut decoder).finish();
+                (last.map(|entry| entry.into_iter().collect()), 0)
+            }
+        };
+        let entries = match entries {
+            Ok(entries) if entries.is_empty() => continue,
+            Ok(entries) => entries,
+            Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+        };
+
+        // Viewers can come and go during a long upload, so look the bucket up each time
+        let channel = {
+            let manager = state.channel_manager.read().await;
+            manager.get_channel(&bucket_id)
+        };
+        let Some(channel) = channel else {
+            discarded += entries.len();
+            continue;
+        };
+        if let Some(token) = &token {
+            let now = chrono::Utc::now();
+            if token.over_quota(now) {
+                return Ok((
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!("Token {} has used up its quota", token.id()),
+                )
+                    .into_response());
+            }
+            token.record(entries.len() as u64, bytes as u64, now);
+            token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+        }
+
+        info!(
+            "New journal entries for bucket {}: {} events",
+            bucket_id,
+            entries.len()
+        );
+        let lines: Vec<&str> = entries.iter().map(String::as_str).collect();
+        match ingest_lines(&state, &channel, &lines).await {
+            IngestOutcome::Accepted => {}
+            IngestOutcome::Suspended => {
+                return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+            }
+            IngestOutcome::Paused => {
+                return Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response())
+            }
+        }
+    }
+
+    if discarded > 0 {
+        warn!(
+            "Discarded {} journal entries for bucket with no viewers: {}",
+            discarded, bucket_id
+        );
+    }
+
+    // `systemd-journal-remote` answers the same way
+    Ok((StatusCode::ACCEPTED, "OK.\n").into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut body = b"__CURSOR=s=abc;i=1\n__REALTIME_TIMESTAMP=1700000000123456\nPRIORITY=3\n_HOSTNAME=web-1\nMESSAGE=disk full\n\n".to_vec();
+        let binary = b"line\nwith=\x1b[1m";
+        body.extend_from_slice(b"MESSAGE\n");
+        body.extend_from_slice(&(binary.len() as u64).to_le_bytes());
+        body.extend_from_slice(binary);
+        body.extend_from_slice(b"\n_PID=42");
+
+        let entries = decode(&body).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let first: Value = serde_json::from_str(&entries[0]).unwrap();
+        assert_eq!(first["message"], "disk full");
+        assert_eq!(first["level"], "3");
+        assert_eq!(first["_HOSTNAME"], "web-1");
+        assert_eq!(first["time"], "2023-11-14T22:13:20.123456Z");
+        assert!(first.get("__CURSOR").is_none());
+
+        let second: Value = serde_json::from_str(&entries[1]).unwrap();
+        assert_eq!(second["message"], "line\nwith=\u{1b}[1m");
+        assert_eq!(second["_PID"], "42");
+
+        // Fed a byte at a time, the stream splits into the same entries
+        let mut decoder = Decoder::default();
+        let mut streamed = Vec::new();
+        for byte in &body {
+            streamed.extend(decoder.push(&[*byte]).unwrap());
+        }
+        streamed.extend(decoder.finish().unwrap());
+        assert_eq!(streamed, entries);
+
+        assert!(decode(b"MESSAGE\n\x05\x00\x00\x00\x00\x00\x00\x00hi").is_err());
+        assert!(decode(b"").unwrap().is_empty());
+    }
+}
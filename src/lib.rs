@@ -0,0 +1,1196 @@
+mod admin;
+mod alerts;
+mod ansi;
+mod api;
+#[cfg(feature = "viewer")]
+mod assets;
+mod beacon;
+mod bulk;
+mod changes;
+mod channel_manager;
+mod compression;
+pub mod config;
+mod cors;
+mod demo;
+mod encoding;
+mod erase;
+mod export;
+mod feed;
+mod firehose;
+#[cfg(feature = "fluent-forward")]
+mod fluent_forward;
+mod gelf;
+#[cfg(feature = "gelf-udp")]
+mod gelf_udp;
+mod grok;
+mod hec;
+mod history;
+#[cfg(feature = "http3")]
+mod http3;
+mod idempotency;
+mod ids;
+mod import;
+mod ingest;
+mod ingest_urls;
+mod integrations;
+mod journal;
+mod limits;
+mod listener;
+mod logplex;
+mod metrics;
+mod models;
+mod multiline;
+mod multiplex;
+#[cfg(feature = "otlp-grpc")]
+mod otlp_grpc;
+mod pacing;
+mod parsers;
+mod pattern;
+mod pause;
+mod principal;
+mod provision;
+mod proxy;
+mod query;
+mod relp;
+mod remote_write;
+mod replay;
+mod report;
+mod routing;
+mod rules;
+mod settings;
+mod severity;
+mod stats;
+mod syslog_tcp;
+pub mod testing;
+mod timeouts;
+mod tokens;
+mod upload;
+mod vector;
+mod webhooks;
+
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{Html, IntoResponse, Response, Sse},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use futures_util::StreamExt;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use tower_http::set_header::SetResponseHeaderLayer;
+use tracing::{debug, info, warn};
+
+use admin::Overview;
+use channel_manager::{ChannelContext, ChannelManager};
+use config::Config;
+use demo::DEMO_BUCKET_ID;
+use export::TimeOptions;
+use history::HistoryBackend;
+use idempotency::{KeyStatus, Reservation, MAX_IDEMPOTENCY_KEY_LENGTH};
+use ingest::{
+    ingest_lines_reporting, parse_ttl, BodyFormat, BodyLines, ContentEncoding, IngestOutcome,
+    IngestParams, IngestReport, EVENT_TTL_HEADER,
+};
+use limits::{LimitExceeded, SubscriberLimiter};
+use metrics::METRICS;
+use models::CloseReason;
+use principal::PrincipalAccounts;
+use rules::ActiveRules;
+use tokens::TokenRegistry;
+
+pub use principal::Principal;
+
+const MAX_SUBSCRIBERS_PER_STREAM: usize = 30;
+
+const MAX_LOG_LINE_LENGTH: usize = 10_000;
+const MAX_LOG_LINES_PER_MINUTE: u64 = 512;
+const MAX_LOG_BODY_SIZE: usize = 1024 * 1024; // 1MB
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+const SUSPENSION_DURATION_SECS: u64 = 60 * 60;
+const ALERT_SWEEP_SECS: u64 = 10;
+const RETENTION_SWEEP_SECS: u64 = 60;
+const MULTILINE_SWEEP_MILLIS: u64 = 250;
+const AT_CAPACITY_RETRY_AFTER_SECS: u64 = 30;
+
+const SUSPENSION_REASON_TEXT: &str = "This bucket has been suspended due to high traffic volumes. log-bin is intended for development and debugging purposes, and is not designed to handle high volumes of traffic. If you need to inspect logs for a production workload or have any questions about this suspension, please contact Fastly support.";
+
+const PAUSED_TEXT: &str = "This bucket is paused. Try again once it is resumed.";
+
+// Security headers for HTML responses
+const CSP: &str = "default-src 'self'; script-src 'self'; style-src 'self'; img-src 'self' data:; connect-src 'self'; base-uri 'self'; form-action 'self'";
+
+fn security_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CONTENT_SECURITY_POLICY, CSP.parse().unwrap());
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+    headers.insert(
+        header::REFERRER_POLICY,
+        "strict-origin-when-cross-origin".parse().unwrap(),
+    );
+    headers
+}
+
+#[derive(Clone)]
+struct AppState {
+    channel_manager: Arc<RwLock<ChannelManager>>,
+    config: Arc<Config>,
+    subscriber_limiter: SubscriberLimiter,
+    id_generator: Arc<dyn ids::IdGenerator>,
+    tokens: Option<Arc<TokenRegistry>>,
+    principals: Arc<PrincipalAccounts>,
+    /// Custom parser and transform rules from `RULES_FILE`
+    rules: Arc<ActiveRules>,
+    overview: Overview,
+}
+
+/// Serve until a shutdown signal, with the listeners `config` asks for
+pub async fn run(config: Config) {
+    // Initialize tracing
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| "info".into()),
+        )
+        .init();
+
+    let (state, app, _tasks) = start(config);
+
+    #[cfg(feature = "http3")]
+    if let Some(port) = state.config.http3_port {
+        let (cert, key) = state
+            .config
+            .tls_cert
+            .as_ref()
+            .zip(state.config.tls_key.as_ref())
+            .expect("HTTP3_PORT needs TLS_CERT and TLS_KEY");
+        let endpoint = http3::bind(SocketAddr::from(([0, 0, 0, 0], port)), cert, key)
+            .expect("Failed to bind HTTP/3 listener");
+        info!("HTTP/3 listening on UDP port {}", port);
+        http3::spawn(endpoint, app.clone());
+    }
+    #[cfg(not(feature = "http3"))]
+    if state.config.http3_port.is_some() {
+        warn!("HTTP3_PORT is ignored: this binary was built without the http3 feature");
+    }
+
+    #[cfg(feature = "gelf-udp")]
+    if let Some(port) = state.config.gelf_udp_port {
+        // Datagrams can't carry a token, so don't open a way around REQUIRE_WRITE_TOKEN
+        if state.config.require_write_token {
+            warn!("GELF_UDP_PORT is ignored: UDP writes can't present a write token");
+        } else {
+            let socket = tokio::net::UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+                .await
+                .expect("Failed to bind GELF UDP listener");
+            info!("GELF listening on UDP port {}", port);
+            gelf_udp::spawn(socket, state.clone());
+        }
+    }
+    #[cfg(not(feature = "gelf-udp"))]
+    if state.config.gelf_udp_port.is_some() {
+        warn!("GELF_UDP_PORT is ignored: this binary was built without the gelf-udp feature");
+    }
+
+    #[cfg(feature = "fluent-forward")]
+    if let Some(port) = state.config.fluent_forward_port {
+        // Forward's shared-key handshake isn't supported, so there is no way to present a token
+        if state.config.require_write_token {
+            warn!("FLUENT_FORWARD_PORT is ignored: forwarders can't present a write token");
+        } else {
+            let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+                .await
+                .expect("Failed to bind Fluent Forward listener");
+            info!("Fluent Forward listening on TCP port {}", port);
+            fluent_forward::spawn(listener, state.clone());
+        }
+    }
+    #[cfg(not(feature = "fluent-forward"))]
+    if state.config.fluent_forward_port.is_some() {
+        warn!(
+            "FLUENT_FORWARD_PORT is ignored: this binary was built without the fluent-forward feature"
+        );
+    }
+
+    #[cfg(feature = "otlp-grpc")]
+    if let Some(port) = state.config.otlp_grpc_port {
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+            .await
+            .expect("Failed to bind OTLP gRPC listener");
+        info!("OTLP gRPC listening on TCP port {}", port);
+        otlp_grpc::spawn(listener, state.clone());
+    }
+    #[cfg(not(feature = "otlp-grpc"))]
+    if state.config.otlp_grpc_port.is_some() {
+        warn!("OTLP_GRPC_PORT is ignored: this binary was built without the otlp-grpc feature");
+    }
+
+    let syslog_bucket = state
+        .config
+        .syslog_bucket
+        .clone()
+        .filter(|bucket| bucket != DEMO_BUCKET_ID && !ids::is_reserved(bucket));
+    if let Some(port) = state.config.syslog_tcp_port {
+        let bucket = syslog_bucket.clone();
+        // Syslog senders can't present a token either
+        if state.config.require_write_token {
+            warn!("SYSLOG_TCP_PORT is ignored: syslog writes can't present a write token");
+        } else if let Some(bucket) = bucket {
+            let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+                .await
+                .expect("Failed to bind syslog TCP listener");
+            info!(
+                "Syslog listening on TCP port {} for bucket {}",
+                port, bucket
+            );
+            syslog_tcp::spawn(listener, state.clone(), bucket);
+        } else {
+            warn!("SYSLOG_TCP_PORT is ignored: SYSLOG_BUCKET must name a writable bucket");
+        }
+    }
+
+    if let Some(port) = state.config.relp_port {
+        if state.config.require_write_token {
+            warn!("RELP_PORT is ignored: RELP clients can't present a write token");
+        } else if let Some(bucket) = syslog_bucket {
+            let listener = tokio::net::TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+                .await
+                .expect("Failed to bind RELP listener");
+            info!("RELP listening on TCP port {} for bucket {}", port, bucket);
+            relp::spawn(listener, state.clone(), bucket);
+        } else {
+            warn!("RELP_PORT is ignored: SYSLOG_BUCKET must name a writable bucket");
+        }
+    }
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.port));
+    info!("Server listening on {}", addr);
+
+    let listener = timeouts::StallGuardListener::new(
+        tokio::net::TcpListener::from_std(
+            listener::bind(addr, &state.config).expect("Failed to bind"),
+        )
+        .unwrap(),
+        state.config.client_idle_timeout,
+    );
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<timeouts::PeerAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal(state.clone()))
+    .await
+    .unwrap();
+
+    info!("Server shut down gracefully");
+}
+
+/// log-bin's HTTP routes, for a host application to serve or mount in its own router, with
+/// the background tasks they rely on started. The host's auth middleware can insert a
+/// [`Principal`] into each request's extensions for log-bin to act on its behalf, instead
+/// of a write token. None of the optional TCP and UDP listeners are started.
+///
+/// Call it within a Tokio runtime. Serve it with
+/// `into_make_service_with_connect_info::<SocketAddr>()` so subscriber limits apply per
+/// client address.
+pub fn build_router(config: Config) -> Router {
+    let (_, app, mut tasks) = start(config);
+    tasks.detach_all();
+    app.layer(middleware::from_fn(timeouts::peer_from_host))
+}
+
+impl AppState {
+    /// Load what the server needs from `config`, without starting any of its tasks
+    fn new(config: Config) -> Self {
+        let id_wordlist = config.id_wordlist.as_ref().map(|path| {
+            let wordlist = ids::Wordlist::load(path).expect("Failed to load ID wordlist");
+            info!("Loaded {} ID words from {}", wordlist.len(), path.display());
+            wordlist
+        });
+        let id_generator = Arc::from(ids::id_generator(
+            config.id_generator,
+            id_wordlist,
+            config.id_prefix.clone(),
+        ));
+
+        let tokens = config.tokens_file.as_ref().map(|path| {
+            let registry = TokenRegistry::load(path, config.require_write_token)
+                .expect("Failed to load write tokens");
+            info!(
+                "Loaded {} write tokens from {}",
+                registry.len(),
+                path.display()
+            );
+            Arc::new(registry)
+        });
+
+        let history = match &config.history_dir {
+            Some(dir) => {
+                info!("Keeping bucket history in {}", dir.display());
+                HistoryBackend::ring_files(dir, config.history_file_size, config.history_max_files)
+                    .expect("Failed to open history directory")
+            }
+            None => HistoryBackend::Memory,
+        };
+
+        let overview = Overview::default();
+        AppState {
+            channel_manager: Arc::new(RwLock::new(ChannelManager::new(
+                history,
+                ChannelContext {
+                    public_url: config.public_url.as_str().into(),
+                    overview: overview.clone(),
+                },
+            ))),
+            subscriber_limiter: SubscriberLimiter::new(
+                config.max_subscribers_per_ip,
+                config.max_subscribers_total,
+            ),
+            id_generator,
+            tokens,
+            principals: Arc::default(),
+            rules: Arc::default(),
+            overview,
+            config: Arc::new(config),
+        }
+    }
+}
+
+/// Build the app state and router, and start the background tasks they rely on. The tasks
+/// stop when the returned set is dropped.
+fn start(config: Config) -> (AppState, Router, JoinSet<()>) {
+    let state = AppState::new(config);
+    let mut tasks = JoinSet::new();
+
+    // Start garbage collection task
+    let gc_manager = state.channel_manager.clone();
+    tasks.spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(60 * 5)).await;
+            gc_manager.write().await.garbage_collect().await;
+        }
+    });
+
+    // Re-evaluate alert rules so they resolve once their window goes quiet
+    let alert_manager = state.channel_manager.clone();
+    tasks.spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(ALERT_SWEEP_SECS)).await;
+            let channels = alert_manager.read().await.channels();
+            for channel in channels {
+                channel.sweep_alerts().await;
+            }
+        }
+    });
+
+    // Expire history events by severity for buckets with retention rules
+    let retention_manager = state.channel_manager.clone();
+    tasks.spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(RETENTION_SWEEP_SECS)).await;
+            let now = chrono::Utc::now().timestamp_millis();
+            let channels = retention_manager.read().await.channels();
+            for channel in channels {
+                let removed = channel.apply_retention(now).await;
+                if removed > 0 {
+                    debug!("Expired {} events from {}", removed, channel.name());
+                }
+            }
+        }
+    });
+
+    // Publish events held back for a continuation that never came
+    let multiline_state = state.clone();
+    tasks.spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_millis(MULTILINE_SWEEP_MILLIS)).await;
+            let held_before = chrono::Utc::now().timestamp_millis() - multiline::MULTILINE_WAIT_MS;
+            let channels = multiline_state.channel_manager.read().await.channels();
+            for channel in channels {
+                ingest::publish_pending_event(&multiline_state, &channel, held_before).await;
+            }
+        }
+    });
+
+    // Sample rates and subscriber counts for the stats history
+    let stats_manager = state.channel_manager.clone();
+    tasks.spawn(async move {
+        loop {
+            tokio::time::sleep(tokio::time::Duration::from_secs(stats::STATS_SAMPLE_SECS)).await;
+            let channels = stats_manager.read().await.channels();
+            for channel in channels {
+                channel.sample_stats().await;
+            }
+        }
+    });
+
+    if let Some(path) = &state.config.rules_file {
+        tasks.spawn(rules::watch(path.clone(), state.rules.clone()));
+    }
+
+    tasks.spawn(admin::run_rate_sampler(
+        state.channel_manager.clone(),
+        state.overview.clone(),
+    ));
+
+    // Feed the demo bucket with sample logs
+    tasks.spawn(demo::run_generator(state.clone()));
+
+    // Build our application with routes
+    // Routes defined after a layer are affected by that layer
+    // Cache-Control applies to assets and bucket routes only
+    let app = Router::new();
+    #[cfg(feature = "viewer")]
+    let app = if state.config.api_only {
+        app
+    } else {
+        app.route("/assets/{*path}", get(assets::serve_asset))
+    };
+    let app = app
+        .route("/{bucket_id}", get(get_bucket).post(post_events))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static("public, max-age=31536000, immutable"),
+        ))
+        .route("/", get(serve_landing))
+        .route("/new", get(create_random_bucket))
+        .route(
+            "/{bucket_id}/ingest/{nonce}",
+            post(ingest_urls::post_ingest),
+        )
+        .route(
+            &format!("{}/{{bucket_id}}", api::BUCKETS_PREFIX),
+            get(get_bucket).post(post_events).put(provision::put_bucket),
+        )
+        .nest(
+            &format!("{}/{{bucket_id}}", api::BUCKETS_PREFIX),
+            bucket_routes(),
+        )
+        // Unversioned paths stay available for existing clients
+        .nest(
+            "/{bucket_id}",
+            bucket_routes().route_layer(middleware::from_fn(api::mark_deprecated)),
+        )
+        .route(
+            &format!("{}/{{id}}/usage", api::TOKENS_PREFIX),
+            get(tokens::get_usage),
+        )
+        .route("/api/tokens/{id}/usage", get(tokens::get_usage))
+        .route(api::MY_BUCKETS_PATH, get(tokens::get_my_buckets))
+        .route("/api/my/buckets", get(tokens::get_my_buckets))
+        .route("/api/v1/write", post(remote_write::post_write))
+        .route("/firehose", post(firehose::post_delivery))
+        .route("/logplex", post(logplex::post_drain))
+        .route("/services/collector", post(hec::post_event))
+        .route("/services/collector/event", post(hec::post_event))
+        .route(api::STREAM_PATH, get(multiplex::get_stream))
+        .route("/api/stream", get(multiplex::get_stream))
+        .route(
+            &format!("{}/overview", api::ADMIN_PREFIX),
+            get(admin::get_overview),
+        )
+        .route("/liveness_check", get(health_check))
+        .route("/readiness_check", get(readiness_check))
+        .route("/metrics", get(metrics::get_metrics))
+        .route(
+            "/.well-known/fastly/logging/challenge",
+            get(fastly_challenge),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            tokens::track_bucket_use,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            principal::scope,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            timeouts::request_timeout,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            cors::guard_cross_origin_writes,
+        ))
+        .layer(cors::layer(state.clone()))
+        .layer(SetResponseHeaderLayer::if_not_present(
+            header::ALT_SVC,
+            alt_svc(&state.config),
+        ))
+        .with_state(state.clone());
+
+    (state, app, tasks)
+}
+
+/// Point TCP clients at the HTTP/3 listener, when there is one
+fn alt_svc(config: &Config) -> Option<header::HeaderValue> {
+    if !cfg!(feature = "http3") {
+        return None;
+    }
+    let port = config.http3_port?;
+    format!("h3=\":{}\"; ma=86400", port).parse().ok()
+}
+
+/// Programmatic routes under a bucket, mounted at both the versioned and legacy prefixes
+fn bucket_routes() -> Router<AppState> {
+    Router::new()
+        .route("/log", get(beacon::get_beacon).post(beacon::post_beacon))
+        .route("/gelf", post(gelf::post_gelf))
+        .route("/logplex", post(logplex::post_bucket_drain))
+        .route("/firehose", post(firehose::post_bucket_delivery))
+        .route("/_bulk", post(bulk::post_bulk).put(bulk::post_bulk))
+        .route("/export", get(export::get_export))
+        .route("/feed.atom", get(feed::get_feed))
+        .route("/report.html", get(report::get_report))
+        .route("/upload", post(journal::post_upload))
+        .route("/vector", post(vector::post_events))
+        .route("/replay", post(replay::post_replay))
+        .route("/import", post(import::post_import))
+        .route(
+            "/webhooks",
+            get(webhooks::get_webhooks).put(webhooks::put_webhooks),
+        )
+        .route(
+            "/settings",
+            get(settings::get_settings).put(settings::put_settings),
+        )
+        .route("/alerts", get(alerts::get_alerts).put(alerts::put_alerts))
+        .route("/routes", get(routing::get_routes).put(routing::put_routes))
+        .route("/grok", get(grok::get_grok).put(grok::put_grok))
+        .route(
+            "/parser",
+            get(pattern::get_parser)
+                .put(pattern::put_parser)
+                .delete(pattern::delete_parser),
+        )
+        .route("/erase", get(erase::get_erasures).post(erase::post_erase))
+        .route("/events", delete(erase::delete_events))
+        .route("/stats", get(stats::get_stats))
+        .route("/stats/history", get(stats::get_stats_history))
+        .route("/query", post(query::post_query))
+        .route("/count", get(query::get_count))
+        .route("/pause", post(pause::post_pause))
+        .route("/resume", post(pause::post_resume))
+        .route(
+            "/ingest-urls",
+            get(ingest_urls::get_ingest_urls).post(ingest_urls::post_ingest_url),
+        )
+        .route(
+            "/ingest-urls/{nonce}",
+            delete(ingest_urls::delete_ingest_url),
+        )
+        .route(
+            "/integrations/slack",
+            get(integrations::slack::get_slack)
+                .put(integrations::slack::put_slack)
+                .delete(integrations::slack::delete_slack),
+        )
+        .route(
+            "/integrations/pagerduty",
+            get(integrations::pagerduty::get_pagerduty)
+                .put(integrations::pagerduty::put_pagerduty)
+                .delete(integrations::pagerduty::delete_pagerduty),
+        )
+        .route_layer(middleware::from_fn(api::reject_reserved))
+}
+
+/// Wait for a shutdown signal, then close open streams so the server can drain
+async fn shutdown_signal(state: AppState) {
+    use tokio::signal;
+
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {
+            info!("Received Ctrl+C, shutting down...");
+        }
+        _ = terminate => {
+            info!("Received SIGTERM, shutting down...");
+        }
+    }
+
+    state
+        .channel_manager
+        .read()
+        .await
+        .close_subscribers(CloseReason::Shutdown);
+    state.overview.close_subscribers(CloseReason::Shutdown);
+}
+
+async fn health_check() -> &'static str {
+    "OK"
+}
+
+/// Report degraded while shedding subscribers so load balancers can steer new viewers away
+async fn readiness_check(State(state): State<AppState>) -> Response {
+    if state.subscriber_limiter.at_capacity() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "DEGRADED").into_response();
+    }
+
+    "OK".into_response()
+}
+
+/// Answer Fastly's log streaming challenge with the SHA-256 hex digest of each service
+/// ID allowed to stream here, or `*` to allow any service
+async fn fastly_challenge(State(state): State<AppState>) -> String {
+    match &state.config.fastly_service_ids {
+        Some(ids) => ids
+            .iter()
+            .map(|id| format!("{:x}\n", Sha256::digest(id.as_bytes())))
+            .collect(),
+        None => "*".to_string(),
+    }
+}
+
+/// The viewer page, unless it was compiled out or disabled for an API-only deployment
+fn viewer_html(state: &AppState) -> Option<Cow<'static, str>> {
+    #[cfg(feature = "viewer")]
+    let html = Some(assets::index_html());
+    #[cfg(not(feature = "viewer"))]
+    let html = None;
+
+    html.filter(|_| !state.config.api_only)
+}
+
+async fn serve_landing(State(state): State<AppState>) -> Response {
+    let Some(index_html) = viewer_html(&state) else {
+        return Json(serde_json::json!({
+            "service": "log-bin",
+            "version": env!("CARGO_PKG_VERSION"),
+        }))
+        .into_response();
+    };
+
+    // Serve the landing page at root
+    let mut headers = security_headers();
+    headers.insert(
+        header::CACHE_CONTROL,
+        "public, max-age=3600".parse().unwrap(),
+    );
+
+    (headers, Html(index_html)).into_response()
+}
+
+fn accepts_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| {
+            accept.split(',').any(|range| {
+                range
+                    .split(';')
+                    .next()
+                    .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+            })
+        })
+}
+
+/// Summary of a bucket and its retained history, for scripts and API-only deployments
+async fn bucket_info(state: &AppState, bucket_id: &str, time: &TimeOptions) -> Response {
+    let formatter = match time.formatter() {
+        Ok(formatter) => formatter,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+    headers.insert(header::VARY, "Accept".parse().unwrap());
+
+    let paused = match &channel {
+        Some(channel) => channel.is_paused().await,
+        None => false,
+    };
+    let history: Vec<serde_json::Value> = match &channel {
+        Some(channel) => channel
+            .history()
+            .await
+            .iter()
+            .map(|event| formatter.event_json(event))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    (
+        headers,
+        Json(serde_json::json!({
+            "bucket": bucket_id,
+            "subscribers": channel.as_ref().map_or(0, |c| c.subscriber_count()),
+            "suspended": channel.as_ref().is_some_and(|c| c.is_suspended()),
+            "paused": paused,
+            "history": history,
+        })),
+    )
+        .into_response()
+}
+
+async fn create_random_bucket(State(state): State<AppState>) -> Response {
+    let bucket_id = {
+        let manager = state.channel_manager.read().await;
+        ids::generate_bucket_id(
+            |id| manager.get_channel(id).is_some(),
+            &state.config.blocked_id_words,
+            state.id_generator.as_ref(),
+        )
+    };
+
+    let Some(bucket_id) = bucket_id else {
+        warn!("Could not generate an unused bucket ID");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+
+    let mut headers = security_headers();
+    // Words from non-English lists need encoding to be valid in a header
+    let location: String = form_urlencoded::byte_serialize(bucket_id.as_bytes()).collect();
+    headers.insert(header::LOCATION, format!("/{}", location).parse().unwrap());
+
+    (StatusCode::FOUND, headers).into_response()
+}
+
+async fn get_bucket(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    ConnectInfo(timeouts::PeerAddr(peer)): ConnectInfo<timeouts::PeerAddr>,
+    Query(time): Query<TimeOptions>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if (bucket_id.len() < ids::MIN_BUCKET_ID_LENGTH && bucket_id != DEMO_BUCKET_ID)
+        || ids::is_reserved(&bucket_id)
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Check if bucket is suspended
+    {
+        let manager = state.channel_manager.read().await;
+        if let Some(channel) = manager.get_channel(&bucket_id) {
+            if channel.is_suspended() {
+                return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+            }
+        }
+    };
+
+    // Check if client wants event stream
+    if let Some(accept) = headers.get(header::ACCEPT) {
+        if accept == "text/event-stream" {
+            // Parse max subscribers from bucket ID (if specified)
+            let max_subs = bucket_id
+                .split(";max-subs=")
+                .nth(1)
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(MAX_SUBSCRIBERS_PER_STREAM);
+
+            // Cap connections per client and across the server before creating any channel state
+            let ip = limits::client_ip(&headers, peer, state.config.client_ip_header.as_deref());
+            let permit = match state.subscriber_limiter.acquire(ip) {
+                Ok(permit) => permit,
+                Err(LimitExceeded::PerIp) => {
+                    warn!(
+                        "Stream {} rejected: too many connections from {}",
+                        bucket_id, ip
+                    );
+                    return Ok((
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many concurrent connections from this client",
+                    )
+                        .into_response());
+                }
+                Err(LimitExceeded::Global) => {
+                    // Shed load rather than amplify fanout past what the server can handle
+                    METRICS.shed_subscriptions.inc();
+                    warn!("Stream {} rejected: server at capacity", bucket_id);
+                    return Ok((
+                        StatusCode::SERVICE_UNAVAILABLE,
+                        [(
+                            header::RETRY_AFTER,
+                            AT_CAPACITY_RETRY_AFTER_SECS.to_string(),
+                        )],
+                        "Server is at capacity, please try again shortly",
+                    )
+                        .into_response());
+                }
+            };
+
+            let channel = {
+                let mut manager = state.channel_manager.write().await;
+                manager.get_or_create_channel(&bucket_id)
+            };
+
+            if channel.subscriber_count() >= max_subs {
+                warn!("Stream {} rejected: max subscribers reached", bucket_id);
+                return Err(StatusCode::TOO_MANY_REQUESTS);
+            }
+
+            info!("New subscriber to bucket: {}", bucket_id);
+
+            if channel.subscriber_count() == 0 {
+                channel
+                    .notify_webhooks(webhooks::WebhookEvent::FirstSubscriber)
+                    .await;
+            }
+
+            // Browsers send the last event ID they saw when reconnecting
+            let resume_after = headers
+                .get(LAST_EVENT_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+
+            // Subscribe and send stats update
+            let stream = channel.subscribe(resume_after).await;
+            let stats = channel.get_stats();
+            channel.publish_stats(stats).await;
+
+            let sse_stream = stream.map(
+                move |event| -> Result<axum::response::sse::Event, Infallible> {
+                    // Hold the subscriber slot for as long as the stream is alive
+                    let _permit = &permit;
+                    let mut sse_event = axum::response::sse::Event::default()
+                        .event(&event.event_type)
+                        .data(event.data);
+                    if let Some(seq) = event.seq {
+                        sse_event = sse_event.id(seq.to_string());
+                    }
+                    Ok(sse_event)
+                },
+            );
+
+            // Some proxies hold the start of a response until enough bytes arrive
+            let padding = state
+                .config
+                .proxy_profile
+                .early_flush_padding()
+                .map(|bytes| Ok(axum::response::sse::Event::default().comment(" ".repeat(bytes))));
+            let sse_stream = futures_util::stream::iter(padding).chain(sse_stream);
+
+            // Add headers to prevent proxy/CDN caching or buffering
+            let sse_headers = state.config.proxy_profile.stream_headers();
+
+            let sse_response = Sse::new(sse_stream)
+                .keep_alive(
+                    axum::response::sse::KeepAlive::new()
+                        .interval(std::time::Duration::from_secs(15)),
+                )
+                .into_response();
+
+            let (mut parts, body) = sse_response.into_parts();
+            parts.headers.extend(sse_headers);
+            return Ok(Response::from_parts(parts, body));
+        }
+    }
+
+    // Scripts asking for JSON get the same information the viewer shows
+    let Some(index_html) = viewer_html(&state).filter(|_| !accepts_json(&headers)) else {
+        return Ok(bucket_info(&state, &bucket_id, &time).await);
+    };
+
+    // Otherwise serve the HTML viewer with no caching to avoid CDN issues
+    let mut headers = security_headers();
+    headers.insert(
+        header::CACHE_CONTROL,
+        "public, max-age=3600".parse().unwrap(),
+    );
+    headers.insert(header::VARY, "Accept".parse().unwrap());
+
+    Ok((headers, Html(index_html)).into_response())
+}
+
+async fn post_events(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Query(params): Query<IngestParams>,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<Response, StatusCode> {
+    // The demo bucket is fed exclusively by the built-in generator
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let report = ingest::wants_report(&headers, &params);
+    accept_events(bucket_id, state, headers, body, token, report).await
+}
+
+/// Read, split and publish a batch of events once the writer has been authorized
+pub(crate) async fn accept_events(
+    bucket_id: String,
+    state: AppState,
+    headers: HeaderMap,
+    body: axum::body::Body,
+    token: Option<Arc<tokens::TokenAccount>>,
+    report: bool,
+) -> Result<Response, StatusCode> {
+    // Writers that asked for per-line results get them in place of the empty acknowledgement
+    let mut report = report.then(IngestReport::default);
+
+    let format = match BodyFormat::from_headers(&headers) {
+        Ok(format) => format,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let encoding = match ContentEncoding::from_headers(&headers) {
+        Ok(encoding) => encoding,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    // Shippers with at-least-once delivery can tag batches to avoid duplicates on retry
+    let idempotency_key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => {
+            let key = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+            if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            Some(key.to_string())
+        }
+        None => None,
+    };
+
+    // Ephemeral events can be given a lifetime shorter than the bucket's retention
+    let ttl = match headers.get(EVENT_TTL_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(parse_ttl) {
+            Some(ttl) => Some(ttl),
+            None => {
+                return Ok((
+                    StatusCode::BAD_REQUEST,
+                    "X-Event-TTL must be a number of seconds or a duration such as 30s or 5m",
+                )
+                    .into_response())
+            }
+        },
+        None => None,
+    };
+
+    // Producers can name their lines' fields with a regex rather than registering it first
+    let pattern = match headers.get(pattern::LOG_PATTERN_HEADER) {
+        Some(value) => {
+            let compiled = value
+                .to_str()
+                .map_err(|e| e.to_string())
+                .and_then(pattern::BucketPattern::compile);
+            match compiled {
+                Ok(pattern) => Some(Arc::new(pattern)),
+                Err(message) => {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        format!("{}: {}", pattern::LOG_PATTERN_HEADER, message),
+                    )
+                        .into_response())
+                }
+            }
+        }
+        None => None,
+    };
+
+    // Held until the batch is accepted, and given back if the request ends any other way
+    let mut reservation = None;
+    {
+        let manager = state.channel_manager.read().await;
+
+        // Check if bucket is suspended before reading body
+        if let Some(channel) = manager.get_channel(&bucket_id) {
+            if channel.is_suspended() {
+                warn!("Rejected logs for suspended bucket: {}", bucket_id);
+                return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+            }
+
+            if let Some(key) = &idempotency_key {
+                match Reservation::new(channel.clone(), key.clone()) {
+                    Ok(held) => reservation = Some(held),
+                    Err(KeyStatus::InFlight) => {
+                        return Ok((
+                            StatusCode::CONFLICT,
+                            "A batch with this Idempotency-Key is still being published",
+                        )
+                            .into_response());
+                    }
+                    Err(_) => {
+                        info!("Ignoring duplicate batch for bucket {}: {}", bucket_id, key);
+                        return Ok(match report {
+                            Some(mut report) => {
+                                report.duplicate = true;
+                                report_response(StatusCode::OK, report)
+                            }
+                            None => StatusCode::NO_CONTENT.into_response(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Do not read body if there are no active viewers to avoid unnecessary work, unless
+        // the writer wants to know what happened to each line
+        if manager.get_channel(&bucket_id).is_none() && report.is_none() {
+            warn!("Discarding logs for bucket with no viewers: {}", bucket_id);
+            return Ok(StatusCode::NO_CONTENT.into_response());
+        }
+    };
+
+    // Shippers such as Logpush and Fluent Bit compress whole bodies, multipart ones included
+    let limit = match format {
+        BodyFormat::Multipart => upload::MAX_UPLOAD_SIZE,
+        _ => MAX_LOG_BODY_SIZE,
+    };
+    let body = match encoding {
+        ContentEncoding::Identity => body,
+        encoding => {
+            let compressed = axum::body::to_bytes(body, limit)
+                .await
+                .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+            match encoding.decode(&compressed, limit) {
+                Ok(decoded) => axum::body::Body::from(decoded),
+                Err(rejection) => return Ok(rejection.into_response()),
+            }
+        }
+    };
+
+    // Files can be much bigger than a batch, so they publish in the background
+    if format == BodyFormat::Multipart {
+        return upload::accept_upload(bucket_id, state, headers, body, token, ttl, reservation)
+            .await;
+    }
+
+    // Now consume the body
+    let body_bytes = axum::body::to_bytes(body, MAX_LOG_BODY_SIZE)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+
+    if body_bytes.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let body_size = body_bytes.len();
+
+    let BodyLines {
+        mut lines,
+        mut invalid_utf8,
+    } = match format.split_bytes(&body_bytes) {
+        Ok(body_lines) => body_lines,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    // A CSV header row names the columns of the rows after it, in this post and later ones
+    let header = lines.first().and_then(|line| format.header_columns(line));
+    if header.is_some() {
+        lines.remove(0);
+        invalid_utf8 = invalid_utf8
+            .iter()
+            .filter_map(|i| i.checked_sub(1))
+            .collect();
+    }
+
+    let line_count = lines.len();
+    if line_count == 0 && header.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    METRICS.ingest_batch_sizes.observe(line_count as u64);
+    if let Err(rejection) = ingest::check_event_count(&state, line_count, "split the batch") {
+        return Ok(rejection.into_response());
+    }
+
+    info!("New events for bucket {}: {} events", bucket_id, line_count);
+
+    if let Some(token) = &token {
+        token.record(line_count as u64, body_size as u64, chrono::Utc::now());
+    }
+
+    // Only process if there's an active viewer
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let Some(channel) = channel else {
+        // No active viewers, silently accept but don't process
+        return Ok(match report {
+            Some(mut report) => {
+                report.reject(line_count, "Bucket has no viewers");
+                report_response(StatusCode::OK, report)
+            }
+            None => StatusCode::NO_CONTENT.into_response(),
+        });
+    };
+
+    if let Some(columns) = header {
+        channel.set_columns(Arc::new(columns)).await;
+    }
+    if let Some(pattern) = pattern {
+        let current = channel.pattern().await;
+        if current.as_ref().map(|current| &current.config) != Some(&pattern.config) {
+            info!("Registered a pattern for bucket {} from a post", bucket_id);
+            channel.set_pattern(Some(pattern.clone())).await;
+            channel.publish_config_change(
+                "parser",
+                &current.map(|current| current.config.clone()),
+                &Some(pattern.config.clone()),
+                changes::actor(&state, &headers),
+            );
+        }
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let refused = match ingest_lines_reporting(
+        &state,
+        &channel,
+        &lines,
+        ttl,
+        &invalid_utf8,
+        report.as_mut(),
+    )
+    .await
+    {
+        IngestOutcome::Accepted => None,
+        IngestOutcome::Suspended => Some((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT)),
+        IngestOutcome::Paused => Some((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT)),
+    };
+    if let Some((status, text)) = refused {
+        return Ok(match report {
+            Some(report) => report_response(status, report),
+            None => (status, text).into_response(),
+        });
+    }
+
+    if let Some(reservation) = reservation {
+        reservation.accept();
+    }
+
+    Ok(match report {
+        Some(report) => report_response(StatusCode::OK, report),
+        None => StatusCode::NO_CONTENT.into_response(),
+    })
+}
+
+fn report_response(status: StatusCode, report: IngestReport) -> Response {
+    (
+        status,
+        [("preference-applied", "return=representation")],
+        Json(report),
+    )
+        .into_response()
+}
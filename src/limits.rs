@@ -0,0 +1,144 @@
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Why a subscriber slot could not be reserved
+#[derive(Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// This client already holds its share of connections
+    PerIp,
+    /// The server is at its overall subscriber capacity
+    Global,
+}
+
+/// Server-wide accounting of concurrent subscriber connections, in total and per client IP
+#[derive(Clone)]
+pub struct SubscriberLimiter {
+    counts: Arc<Mutex<Counts>>,
+    max_per_ip: usize,
+    max_total: usize,
+}
+
+impl SubscriberLimiter {
+    pub fn new(max_per_ip: usize, max_total: usize) -> Self {
+        Self {
+            counts: Arc::new(Mutex::new(Counts::default())),
+            max_per_ip,
+            max_total,
+        }
+    }
+
+    /// Reserve a subscriber slot for an IP
+    pub fn acquire(&self, ip: IpAddr) -> Result<SubscriberPermit, LimitExceeded> {
+        let mut counts = self.counts.lock().unwrap();
+        if counts.total >= self.max_total {
+            return Err(LimitExceeded::Global);
+        }
+
+        let count = counts.per_ip.entry(ip).or_default();
+        if *count >= self.max_per_ip {
+            return Err(LimitExceeded::PerIp);
+        }
+        *count += 1;
+        counts.total += 1;
+
+        Ok(SubscriberPermit {
+            ip,
+            counts: self.counts.clone(),
+        })
+    }
+
+    /// Whether the server has reached its overall subscriber capacity
+    pub fn at_capacity(&self) -> bool {
+        self.counts.lock().unwrap().total >= self.max_total
+    }
+}
+
+/// A held subscriber slot, released when the connection's stream is dropped
+pub struct SubscriberPermit {
+    ip: IpAddr,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl Drop for SubscriberPermit {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(count) = counts.per_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                counts.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Resolve the client's IP, preferring the configured proxy header over the peer address
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr, ip_header: Option<&str>) -> IpAddr {
+    ip_header
+        .and_then(|name| headers.get(name))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(peer.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_ip_cap() {
+        let limiter = SubscriberLimiter::new(2, 100);
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let first = limiter.acquire(a).unwrap();
+        let _second = limiter.acquire(a).unwrap();
+        assert_eq!(limiter.acquire(a).err(), Some(LimitExceeded::PerIp));
+        assert!(limiter.acquire(b).is_ok());
+
+        // Dropping a connection frees its slot
+        drop(first);
+        assert!(limiter.acquire(a).is_ok());
+    }
+
+    #[test]
+    fn test_global_cap() {
+        let limiter = SubscriberLimiter::new(10, 2);
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+
+        let first = limiter.acquire(a).unwrap();
+        let _second = limiter.acquire(b).unwrap();
+        assert!(limiter.at_capacity());
+        assert_eq!(limiter.acquire(b).err(), Some(LimitExceeded::Global));
+
+        drop(first);
+        assert!(!limiter.at_capacity());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let peer: SocketAddr = "10.0.0.1:443".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("Fastly-Client-IP", "203.0.113.7".parse().unwrap());
+
+        assert_eq!(client_ip(&headers, peer, None), peer.ip());
+        assert_eq!(
+            client_ip(&headers, peer, Some("Fastly-Client-IP")),
+            "203.0.113.7".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            client_ip(&HeaderMap::new(), peer, Some("Fastly-Client-IP")),
+            peer.ip()
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use tracing::info;
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Bind the server's listening socket, or take over one passed in by a supervisor or the
+/// process being replaced
+pub fn bind(addr: SocketAddr, config: &Config) -> io::Result<TcpListener> {
+    #[cfg(unix)]
+    if let Some(listener) = inherited()? {
+        info!("Using inherited listening socket");
+        set_buffer_sizes(&SockRef::from(&listener), config)?;
+        return Ok(listener);
+    }
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    // Lets a new process bind the same port and start accepting before the old one exits
+    #[cfg(unix)]
+    socket.set_reuse_port(config.reuse_port)?;
+    #[cfg(not(unix))]
+    if config.reuse_port {
+        tracing::warn!("REUSE_PORT is only supported on Unix");
+    }
+    set_buffer_sizes(&SockRef::from(&socket), config)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+/// Set kernel buffer sizes on the listening socket, which accepted connections inherit
+fn set_buffer_sizes(socket: &SockRef<'_>, config: &Config) -> io::Result<()> {
+    if let Some(size) = config.socket_send_buffer {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = config.socket_recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+    }
+    Ok(())
+}
+
+/// A listening socket passed in with the systemd socket activation protocol:
+/// `LISTEN_FDS` open descriptors starting at 3, meant for the process in `LISTEN_PID`
+#[cfg(unix)]
+fn inherited() -> io::Result<Option<TcpListener>> {
+    use std::os::fd::FromRawFd;
+
+    const FIRST_LISTEN_FD: i32 = 3;
+
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<u32>().ok());
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id());
+    if !for_us || fds.is_none_or(|fds| fds == 0) {
+        return Ok(None);
+    }
+
+    // SAFETY: the protocol hands this process ownership of descriptor 3, and nothing else
+    // in the process claims it
+    let listener = unsafe { TcpListener::from_raw_fd(FIRST_LISTEN_FD) };
+    listener.set_nonblocking(true)?;
+    Ok(Some(listener))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reuse_port() {
+        let config = Config {
+            reuse_port: true,
+            ..Config::default()
+        };
+        let first = bind("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = first.local_addr().unwrap();
+
+        // A replacement process can bind alongside the old one
+        let second = bind(addr, &config).unwrap();
+        assert_eq!(second.local_addr().unwrap(), addr);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::syslog_tcp::Framer;
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tracing::{info, warn};
+
+/// Number of syslog messages Logplex says are in the body
+const MSG_COUNT_HEADER: &str = "Logplex-Msg-Count";
+/// ID of the drain, such as `d.f2d4e6b0-...`, which is fixed for the drain's lifetime
+const DRAIN_TOKEN_HEADER: &str = "Logplex-Drain-Token";
+
+/// Split an `application/logplex-1` body into its octet-counted syslog messages
+fn decode(body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let mut framer = Framer::default();
+    framer.extend(body);
+
+    let mut lines = Vec::new();
+    loop {
+        match framer.next_frame() {
+            Ok(Some(line)) => lines.push(line),
+            Ok(None) if framer.is_empty() => return Ok(lines),
+            Ok(None) => return Err("Body ends partway through a message"),
+            Err(_) => return Err("Body is not octet-counted syslog messages"),
+        }
+    }
+}
+
+/// `POST /logplex`: a Heroku HTTPS log drain. Events go to the bucket mapped to the drain's
+/// write token in `TOKENS_FILE`, or otherwise to a bucket named after the drain token.
+pub async fn post_drain(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    let mapped =
+        tokens::identify(&state, &headers).and_then(|token| token.bucket().map(String::from));
+    let drain_token = headers
+        .get(DRAIN_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    let Some(bucket_id) = mapped.or(drain_token) else {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            "Missing Logplex-Drain-Token header",
+        )
+            .into_response());
+    };
+    accept_drain(state, headers, bucket_id, body).await
+}
+
+/// `POST /{bucket_id}/logplex`: a Heroku HTTPS log drain into a named bucket
+pub async fn post_bucket_drain(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    accept_drain(state, headers, bucket_id, body).await
+}
+
+async fn accept_drain(
+    state: AppState,
+    headers: HeaderMap,
+    bucket_id: String,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if ids::is_reserved(&bucket_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let lines = match decode(&body) {
+        Ok(lines) => lines,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let expected = headers
+        .get(MSG_COUNT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|count| count.trim().parse::<usize>().ok());
+    if let Some(expected) = expected.filter(|&expected| expected != lines.len()) {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Body holds {} messages but Logplex-Msg-Count is {}",
+                lines.len(),
+                expected
+            ),
+        )
+            .into_response());
+    }
+    if let Err(rejection) = check_event_count(&state, lines.len(), "split the batch") {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel.filter(|_| !lines.is_empty()) else {
+        if !lines.is_empty() {
+            warn!(
+                "Discarding Logplex drain for bucket with no viewers: {}",
+                bucket_id
+            );
+        }
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    info!(
+        "New Logplex drain for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+    if let Some(token) = &token {
+        let now = chrono::Utc::now();
+        token.record(lines.len() as u64, body.len() as u64, now);
+        token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => Ok(StatusCode::NO_CONTENT.into_response()),
+        IngestOutcome::Suspended => {
+            Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+        }
+        IngestOutcome::Paused => Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let body = concat!(
+            "83 <40>1 2012-11-30T06:45:29+00:00 host app web.3 - State changed from starting to up\n",
+            "119 <40>1 2012-11-30T06:45:26+00:00 host app web.3 - Starting process with command `bundle exec rackup config.ru -p 24405`\n",
+        );
+        let lines = decode(body.as_bytes()).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("State changed from starting to up"));
+        assert!(lines[1].starts_with("<40>1 2012-11-30T06:45:26+00:00"));
+
+        assert!(decode(&body.as_bytes()[..100]).is_err());
+        assert!(decode(b"").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::models::CloseReason;
+use crate::parsers::ParseOutcome;
+use axum::{http::header, response::IntoResponse};
+use std::fmt::Write;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Process-wide counters, exposed in Prometheus text format at `/metrics`
+pub static METRICS: Metrics = Metrics::new();
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Counts of observed values at or below each bound, like a Prometheus histogram
+pub struct Histogram<const N: usize> {
+    bounds: [u64; N],
+    buckets: [Counter; N],
+    count: Counter,
+    sum: AtomicU64,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(bounds: [u64; N]) -> Self {
+        Self {
+            bounds,
+            buckets: [const { Counter::new() }; N],
+            count: Counter::new(),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, value: u64) {
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.inc();
+            }
+        }
+        self.count.inc();
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn render(&self, output: &mut String, name: &str, help: &str) {
+        writeln!(output, "# HELP {} {}", name, help).unwrap();
+        writeln!(output, "# TYPE {} histogram", name).unwrap();
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            writeln!(
+                output,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.get()
+            )
+            .unwrap();
+        }
+        let count = self.count.get();
+        writeln!(output, "{}_bucket{{le=\"+Inf\"}} {}", name, count).unwrap();
+        writeln!(output, "{}_sum {}", name, self.sum.load(Ordering::Relaxed)).unwrap();
+        writeln!(output, "{}_count {}", name, count).unwrap();
+    }
+}
+
+/// A set of values that a labelled counter is broken down by
+pub trait MetricLabel: Copy + 'static {
+    /// Name of the Prometheus label
+    const NAME: &'static str;
+    const ALL: &'static [Self];
+
+    fn label(self) -> &'static str;
+
+    /// Position in [`MetricLabel::ALL`]
+    fn index(self) -> usize;
+}
+
+/// One counter per value of a [`MetricLabel`]
+pub struct LabelledCounters<L, const N: usize> {
+    counts: [Counter; N],
+    _label: PhantomData<L>,
+}
+
+impl<L: MetricLabel, const N: usize> LabelledCounters<L, N> {
+    pub const fn new() -> Self {
+        Self {
+            counts: [const { Counter::new() }; N],
+            _label: PhantomData,
+        }
+    }
+
+    pub fn inc(&self, label: L) {
+        self.counts[label.index()].inc();
+    }
+
+    /// Counts keyed by label value
+    pub fn snapshot(&self) -> Vec<(&'static str, u64)> {
+        L::ALL
+            .iter()
+            .map(|label| (label.label(), self.counts[label.index()].get()))
+            .collect()
+    }
+
+    fn render(&self, output: &mut String, name: &str, help: &str) {
+        writeln!(output, "# HELP {} {}", name, help).unwrap();
+        writeln!(output, "# TYPE {} counter", name).unwrap();
+        for (value, count) in self.snapshot() {
+            writeln!(output, "{}{{{}=\"{}\"}} {}", name, L::NAME, value, count).unwrap();
+        }
+    }
+}
+
+impl<L: MetricLabel, const N: usize> Default for LabelledCounters<L, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type ParseOutcomeCounters = LabelledCounters<ParseOutcome, { ParseOutcome::ALL.len() }>;
+
+pub struct Metrics {
+    /// Requests that did not complete within the request timeout
+    pub request_timeouts: Counter,
+    /// Connections dropped because the client stopped reading responses
+    pub stalled_connections: Counter,
+    /// Subscriptions turned away because the server was at capacity
+    pub shed_subscriptions: Counter,
+    /// Events dropped because they could not be encoded for subscribers
+    pub serialization_failures: Counter,
+    /// Lines whose custom parsers were cut short by the parse deadline
+    pub aborted_parses: Counter,
+    /// Events left out of live streams because a bucket's pacing queue was full
+    pub paced_drops: Counter,
+    /// Ingested lines by the parser that understood them
+    pub parse_outcomes: ParseOutcomeCounters,
+    /// Subscriber streams the server ended
+    pub subscriber_closes: LabelledCounters<CloseReason, { CloseReason::ALL.len() }>,
+    /// Events per ingest request
+    pub ingest_batch_sizes: Histogram<6>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            request_timeouts: Counter::new(),
+            stalled_connections: Counter::new(),
+            shed_subscriptions: Counter::new(),
+            serialization_failures: Counter::new(),
+            aborted_parses: Counter::new(),
+            paced_drops: Counter::new(),
+            parse_outcomes: ParseOutcomeCounters::new(),
+            subscriber_closes: LabelledCounters::new(),
+            ingest_batch_sizes: Histogram::new([1, 10, 100, 1_000, 10_000, 100_000]),
+        }
+    }
+
+    fn counters(&self) -> Vec<(&'static str, &'static str, &Counter)> {
+        vec![
+            (
+                "logbin_request_timeouts_total",
+                "Requests that did not complete within the request timeout",
+                &self.request_timeouts,
+            ),
+            (
+                "logbin_stalled_connections_total",
+                "Connections dropped because the client stopped reading",
+                &self.stalled_connections,
+            ),
+            (
+                "logbin_shed_subscriptions_total",
+                "Subscriptions rejected because the server was at capacity",
+                &self.shed_subscriptions,
+            ),
+            (
+                "logbin_serialization_failures_total",
+                "Events dropped because they could not be serialized",
+                &self.serialization_failures,
+            ),
+            (
+                "logbin_aborted_parses_total",
+                "Lines whose custom parsers exceeded the parse deadline",
+                &self.aborted_parses,
+            ),
+            (
+                "logbin_paced_drops_total",
+                "Events skipped by live streams because a pacing queue was full",
+                &self.paced_drops,
+            ),
+        ]
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, help, counter) in self.counters() {
+            writeln!(output, "# HELP {} {}", name, help).unwrap();
+            writeln!(output, "# TYPE {} counter", name).unwrap();
+            writeln!(output, "{} {}", name, counter.get()).unwrap();
+        }
+
+        self.parse_outcomes.render(
+            &mut output,
+            "logbin_parse_outcomes_total",
+            "Ingested lines by parser outcome",
+        );
+        self.subscriber_closes.render(
+            &mut output,
+            "logbin_subscriber_closes_total",
+            "Subscriber streams closed by the server, by reason",
+        );
+        self.ingest_batch_sizes.render(
+            &mut output,
+            "logbin_ingest_batch_size",
+            "Events per ingest request",
+        );
+        output
+    }
+}
+
+pub async fn get_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.request_timeouts.inc();
+        let output = metrics.render();
+        assert!(output.contains("# TYPE logbin_request_timeouts_total counter\n"));
+        assert!(output.contains("logbin_request_timeouts_total 1\n"));
+        assert!(output.contains("logbin_stalled_connections_total 0\n"));
+
+        metrics.parse_outcomes.inc(ParseOutcome::Unparsed);
+        metrics.subscriber_closes.inc(CloseReason::Shutdown);
+        let output = metrics.render();
+        assert!(output.contains("logbin_parse_outcomes_total{parser=\"unparsed\"} 1\n"));
+        assert!(output.contains("logbin_parse_outcomes_total{parser=\"json\"} 0\n"));
+        assert!(output.contains("logbin_subscriber_closes_total{reason=\"shutdown\"} 1\n"));
+
+        metrics.ingest_batch_sizes.observe(5);
+        metrics.ingest_batch_sizes.observe(500);
+        let output = metrics.render();
+        assert!(output.contains("logbin_ingest_batch_size_bucket{le=\"1\"} 0\n"));
+        assert!(output.contains("logbin_ingest_batch_size_bucket{le=\"10\"} 1\n"));
+        assert!(output.contains("logbin_ingest_batch_size_bucket{le=\"+Inf\"} 2\n"));
+        assert!(output.contains("logbin_ingest_batch_size_sum 505\n"));
+    }
+}
@@ -1,28 +1,180 @@
-use serde::Serialize;
-use std::collections::HashMap;
+use crate::metrics::MetricLabel;
+use crate::pause::PauseMode;
+use chrono::{DateTime, SecondsFormat};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldData {
     pub value: String,
     pub color: String,
     pub contrast: f64,
+    /// RFC 8941 list members behind `value`, for fields whose structure a string would lose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub items: Option<Vec<StructuredItem>>,
+    /// Set when `value` is binary data in a text encoding, so viewers can show it another way
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<BinaryHint>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryEncoding {
+    Base64,
+}
+
+/// How a binary field was encoded into its string value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryHint {
+    pub encoding: BinaryEncoding,
+    /// Length of the decoded data in bytes
+    pub length: usize,
+}
+
+/// One member of a Structured Headers list, with the parameters that belong to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructuredItem {
+    pub value: String,
+    /// Members of an inner list, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<StructuredItem>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEvent {
+    /// Position of the event in its bucket, assigned on publish and increasing by one
+    pub seq: u64,
     pub time: i64,
+    /// Timestamp the producer put in the event, if any, before skew clamping
+    #[serde(rename = "reportedTime", skip_serializing_if = "Option::is_none")]
+    pub reported_time: Option<i64>,
+    /// Set when `reported_time` was outside the allowed skew and `time` was clamped
+    #[serde(
+        rename = "clockSkewed",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub clock_skewed: bool,
+    /// Set when the line had bytes that weren't UTF-8, which `raw` shows as U+FFFD
+    #[serde(
+        rename = "hadInvalidUtf8",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub had_invalid_utf8: bool,
     pub raw: String,
+    /// The line as posted, when `raw` is that line with its ANSI escape sequences removed
+    #[serde(rename = "rawAnsi", default, skip_serializing_if = "Option::is_none")]
+    pub raw_ansi: Option<String>,
     pub fields: HashMap<String, FieldData>,
     pub parser: Option<String>,
+    /// Version of the parser/transform ruleset applied to this event, if any
+    #[serde(rename = "rulesetVersion", skip_serializing_if = "Option::is_none")]
+    pub ruleset_version: Option<u64>,
+    /// How sure `parser` was that the line was in its format, from 0 to 1
+    #[serde(rename = "parserConfidence", skip_serializing_if = "Option::is_none")]
+    pub parser_confidence: Option<f32>,
+    /// Other parsers that could also read the line, most confident first
+    #[serde(
+        rename = "parserCandidates",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub parser_candidates: Vec<ParserCandidate>,
+    /// When the producer's time to live runs out (epoch ms), after which history drops the
+    /// event regardless of retention
+    #[serde(rename = "expiresAt", default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// `time` as it reads in the bucket's display time zone, for clients without one
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub local_time: Option<LocalTime>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// An event time rendered for display, so thin clients don't each do time zone math
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalTime {
+    /// RFC 3339 timestamp with the zone's offset at that moment
+    #[serde(rename = "isoTime")]
+    pub iso_time: String,
+    /// IANA name of the zone, such as `Europe/London`
+    pub timezone: String,
+}
+
+impl LocalTime {
+    pub fn new(time: i64, tz: Tz) -> Self {
+        Self {
+            iso_time: DateTime::from_timestamp_millis(time)
+                .unwrap_or_default()
+                .with_timezone(&tz)
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            timezone: tz.name().to_string(),
+        }
+    }
+}
+
+/// A parser that matched a line but lost out to one earlier in priority order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParserCandidate {
+    pub parser: String,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct StatsEvent {
     #[serde(rename = "clientCount")]
     pub client_count: usize,
     #[serde(rename = "connCount")]
     pub conn_count: usize,
     pub clients: Vec<String>,
+    /// Lines ingested into the bucket, by the parser that understood them
+    #[serde(rename = "parseOutcomes")]
+    pub parse_outcomes: BTreeMap<&'static str, u64>,
+}
+
+/// Why the server ended a subscriber's stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    /// The server is shutting down; reconnecting later is expected to work
+    Shutdown,
+    /// The bucket was removed while the stream was open
+    BucketDeleted,
+    /// The client stopped reading. No `close` event can reach it, so this is only counted.
+    IdleTimeout,
+}
+
+impl CloseReason {
+    pub const ALL: [CloseReason; 3] = [
+        CloseReason::Shutdown,
+        CloseReason::BucketDeleted,
+        CloseReason::IdleTimeout,
+    ];
+}
+
+impl MetricLabel for CloseReason {
+    const NAME: &'static str = "reason";
+    const ALL: &'static [Self] = &CloseReason::ALL;
+
+    fn label(self) -> &'static str {
+        match self {
+            CloseReason::Shutdown => "shutdown",
+            CloseReason::BucketDeleted => "bucket_deleted",
+            CloseReason::IdleTimeout => "idle_timeout",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The last event on a stream the server is closing
+#[derive(Debug, Clone, Serialize)]
+pub struct CloseEvent {
+    pub reason: CloseReason,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -30,8 +182,48 @@ pub struct SuspensionEvent {
     pub suspended: bool,
 }
 
+/// Sent when a bucket is paused or resumed
+#[derive(Debug, Clone, Serialize)]
+pub struct PauseEvent {
+    pub paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<PauseMode>,
+    /// Lines held while paused; on resume, these are about to be published
+    pub buffered: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportEvent {
+    pub id: String,
+    pub url: String,
+    pub status: ImportStatus,
+    pub lines: usize,
+    /// Lines left out because the bucket's history already holds them
+    pub skipped: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Fetching,
+    Ingesting,
+    Complete,
+    Failed,
+}
+
 #[derive(Debug, Clone)]
 pub struct SseEvent {
     pub event_type: String,
     pub data: String,
+    /// Sequence number for log events, sent as the SSE event ID
+    pub seq: Option<u64>,
+}
+
+/// A range of log events a subscriber missed, e.g. because it fell behind
+#[derive(Debug, Clone, Serialize)]
+pub struct GapEvent {
+    pub from: u64,
+    pub to: u64,
 }
@@ -0,0 +1,236 @@
+//! Folding stack traces and other continuation lines into the event they belong to, for
+//! buckets with `multiline` set
+
+use crate::MAX_LOG_LINE_LENGTH;
+
+/// How long a post's last event waits for the next post to continue it, in milliseconds
+pub const MULTILINE_WAIT_MS: i64 = 1000;
+
+/// Starts of lines that continue whatever came before them
+const CONTINUATION_PREFIXES: &[&str] = &[
+    // Java frames and causes, which some loggers write unindented
+    "at ",
+    "Caused by:",
+    "Suppressed:",
+    "... ",
+    // Python tracebacks and the links between chained exceptions
+    "Traceback (most recent call last):",
+    "During handling of the above exception",
+    "The above exception was the direct cause",
+    // Rust panics
+    "stack backtrace:",
+    "note: run with `RUST_BACKTRACE",
+    "note: Some details are omitted",
+    // Go panics
+    "created by ",
+];
+
+/// An event's text, made of a line and any continuation lines folded into it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoldedEvent {
+    pub text: String,
+    /// Lines from the current post that went into it
+    pub lines: usize,
+    /// Whether it started in an earlier post
+    pub continued: bool,
+    pub had_invalid_utf8: bool,
+}
+
+impl FoldedEvent {
+    pub fn single(line: &str, had_invalid_utf8: bool) -> Self {
+        Self {
+            text: line.to_string(),
+            lines: 1,
+            continued: false,
+            had_invalid_utf8,
+        }
+    }
+}
+
+/// A post's last event, held back in case the next post continues it
+#[derive(Debug)]
+pub struct PendingEvent {
+    pub event: FoldedEvent,
+    pub ttl: Option<i64>,
+    /// When it was held, in epoch milliseconds
+    pub held_at: i64,
+}
+
+/// Whether `line` continues `event` rather than starting a new one
+pub fn continues(event: &str, line: &str) -> bool {
+    if line.starts_with([' ', '\t'])
+        || CONTINUATION_PREFIXES
+            .iter()
+            .any(|prefix| line.starts_with(prefix))
+    {
+        return true;
+    }
+    let last = event.rsplit('\n').next().unwrap_or(event);
+
+    // Python ends a traceback with the exception, unindented, after the indented frames
+    if event.contains("Traceback (most recent call last):")
+        && last.starts_with(' ')
+        && is_exception(line)
+    {
+        return true;
+    }
+
+    // Go lists each goroutine's frames under a header, as a call then a tab-indented file
+    if line.starts_with("goroutine ") && line.ends_with("]:") {
+        return true;
+    }
+    if event.contains("\ngoroutine ")
+        && (last.starts_with('\t') || last.starts_with("goroutine "))
+        && line.ends_with(')')
+    {
+        return true;
+    }
+
+    // Rust puts the panic message on the line after `thread 'main' panicked at src/main.rs:2:5:`
+    last.starts_with("thread '") && last.contains("' panicked at ") && last.ends_with(':')
+}
+
+/// Whether a line reads as Python's last traceback line, `ValueError: message`
+fn is_exception(line: &str) -> bool {
+    let name = line.split_once(':').map_or(line, |(name, _)| name);
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Fold a post's lines into events, continuing `pending` from the post before. Lines at the
+/// sorted positions in `invalid_utf8` had bytes replaced. An event stops growing before
+/// it would go over the line length limit.
+pub fn fold(
+    pending: Option<FoldedEvent>,
+    lines: &[&str],
+    invalid_utf8: &[usize],
+) -> Vec<FoldedEvent> {
+    let mut events: Vec<FoldedEvent> = pending
+        .map(|event| FoldedEvent {
+            lines: 0,
+            continued: true,
+            ..event
+        })
+        .into_iter()
+        .collect();
+
+    for (i, line) in lines.iter().enumerate() {
+        let had_invalid_utf8 = invalid_utf8.binary_search(&i).is_ok();
+        match events.last_mut() {
+            Some(event)
+                if continues(&event.text, line)
+                    && event.text.len() + 1 + line.len() <= MAX_LOG_LINE_LENGTH =>
+            {
+                event.text.push('\n');
+                event.text.push_str(line);
+                event.lines += 1;
+                event.had_invalid_utf8 |= had_invalid_utf8;
+            }
+            _ => events.push(FoldedEvent::single(line, had_invalid_utf8)),
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(events: &[FoldedEvent]) -> Vec<&str> {
+        events.iter().map(|event| event.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_java() {
+        let events = fold(
+            None,
+            &[
+                "12:00:00 ERROR Request failed",
+                "java.lang.IllegalStateException: closed",
+                "\tat com.example.Db.query(Db.java:42)",
+                "\t... 12 more",
+                "Caused by: java.io.IOException: reset",
+                "at com.example.Net.read(Net.java:7)",
+                "12:00:01 INFO Retrying",
+            ],
+            &[],
+        );
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].text, "12:00:00 ERROR Request failed");
+        assert_eq!(events[1].lines, 5);
+        assert!(events[1].text.ends_with("(Net.java:7)"));
+        assert_eq!(events[2].text, "12:00:01 INFO Retrying");
+    }
+
+    #[test]
+    fn test_python() {
+        let events = fold(
+            None,
+            &[
+                "ERROR:root:Lookup failed",
+                "Traceback (most recent call last):",
+                "  File \"app.py\", line 3, in <module>",
+                "    lookup()",
+                "KeyError: 'id'",
+                "INFO:root:Done",
+            ],
+            &[3],
+        );
+        assert_eq!(texts(&events)[1], "INFO:root:Done");
+        assert_eq!(events[0].lines, 5);
+        assert!(events[0].text.ends_with("KeyError: 'id'"));
+        assert!(events[0].had_invalid_utf8);
+    }
+
+    #[test]
+    fn test_go_and_rust() {
+        let events = fold(
+            None,
+            &[
+                "panic: runtime error: index out of range [3] with length 3",
+                "goroutine 1 [running]:",
+                "main.main()",
+                "\t/app/main.go:8 +0x1d",
+                "exit status 2",
+                "thread 'main' panicked at src/main.rs:2:5:",
+                "explicit panic",
+                "stack backtrace:",
+                "   0: rust_begin_unwind",
+            ],
+            &[],
+        );
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].lines, 4);
+        assert_eq!(events[1].text, "exit status 2");
+        assert_eq!(events[2].lines, 4);
+    }
+
+    #[test]
+    fn test_continues_pending_event() {
+        let pending = FoldedEvent::single("Exception in thread \"main\"", false);
+        let events = fold(Some(pending), &["\tat Main.main(Main.java:3)", "next"], &[]);
+        assert_eq!(events.len(), 2);
+        assert!(events[0].continued);
+        assert_eq!(events[0].lines, 1);
+        assert!(!events[1].continued);
+
+        // Lines that stand alone don't fold
+        let events = fold(None, &["one", "two"], &[]);
+        assert_eq!(texts(&events), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_stops_at_line_limit() {
+        let frame = format!("\tat {}", "x".repeat(1000));
+        let lines: Vec<&str> = std::iter::once("start")
+            .chain(std::iter::repeat_n(frame.as_str(), 20))
+            .collect();
+        let events = fold(None, &lines, &[]);
+        assert_eq!(events.len(), 3);
+        assert!(events
+            .iter()
+            .all(|event| event.text.len() <= MAX_LOG_LINE_LENGTH));
+    }
+}
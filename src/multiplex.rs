@@ -0,0 +1,220 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::limits::{self, LimitExceeded};
+use crate::metrics::METRICS;
+use crate::models::SseEvent;
+use crate::timeouts::PeerAddr;
+use crate::{api, cors, ids, webhooks, AppState, MAX_SUBSCRIBERS_PER_STREAM};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
+    },
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// Buckets one connection may follow at once
+const MAX_MULTIPLEX_SUBSCRIPTIONS: usize = 50;
+/// Events queued for a connection before its subscriptions wait for it to catch up
+const MULTIPLEX_QUEUE_SIZE: usize = 256;
+
+/// A control frame sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum Control {
+    Subscribe {
+        bucket: String,
+        /// Last sequence number seen, to resume like `Last-Event-ID`
+        #[serde(rename = "lastEventId", default)]
+        last_event_id: Option<u64>,
+    },
+    Unsubscribe {
+        bucket: String,
+    },
+}
+
+/// An event from one bucket, tagged so the client can tell its streams apart
+#[derive(Debug, Serialize)]
+struct Frame {
+    bucket: String,
+    event: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    data: String,
+}
+
+impl Frame {
+    fn new(bucket: &str, event: &str, data: impl Into<String>) -> Self {
+        Self {
+            bucket: bucket.to_string(),
+            event: event.to_string(),
+            id: None,
+            data: data.into(),
+        }
+    }
+}
+
+/// Follow several buckets over one WebSocket, subscribing and unsubscribing with control frames
+pub async fn get_stream(
+    State(state): State<AppState>,
+    ConnectInfo(PeerAddr(peer)): ConnectInfo<PeerAddr>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    // CORS doesn't cover WebSockets, so hold them to the same origin policy as streams
+    if let Some(origin) = headers
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+    {
+        if state.config.cors_allowed_origins.is_some()
+            && !cors::origin_allowed(&state, origin, &Method::GET, api::STREAM_PATH).await
+        {
+            return (StatusCode::FORBIDDEN, "Origin not allowed").into_response();
+        }
+    }
+
+    let ip = limits::client_ip(&headers, peer, state.config.client_ip_header.as_deref());
+    upgrade.on_upgrade(move |socket| serve(socket, state, ip))
+}
+
+async fn serve(mut socket: WebSocket, state: AppState, ip: IpAddr) {
+    let (sender, mut receiver) = mpsc::channel::<Frame>(MULTIPLEX_QUEUE_SIZE);
+    let mut subscriptions: HashMap<String, AbortHandle> = HashMap::new();
+
+    loop {
+        let reply = tokio::select! {
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Control>(&text) {
+                    Ok(Control::Subscribe { bucket, last_event_id }) => {
+                        subscribe(&state, ip, &mut subscriptions, &sender, bucket, last_event_id)
+                            .await
+                    }
+                    Ok(Control::Unsubscribe { bucket }) => match subscriptions.remove(&bucket) {
+                        Some(task) => {
+                            task.abort();
+                            Frame::new(&bucket, "unsubscribed", "")
+                        }
+                        None => Frame::new(&bucket, "error", "Not subscribed"),
+                    },
+                    Err(e) => Frame::new("", "error", format!("Invalid control frame: {}", e)),
+                },
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => continue,
+            },
+            Some(frame) = receiver.recv() => frame,
+        };
+
+        let text = serde_json::to_string(&reply).unwrap();
+        if socket.send(Message::Text(text.into())).await.is_err() {
+            break;
+        }
+    }
+
+    for task in subscriptions.into_values() {
+        task.abort();
+    }
+}
+
+/// Start forwarding a bucket's events, returning the frame to acknowledge or refuse it with
+async fn subscribe(
+    state: &AppState,
+    ip: IpAddr,
+    subscriptions: &mut HashMap<String, AbortHandle>,
+    sender: &mpsc::Sender<Frame>,
+    bucket_id: String,
+    resume_after: Option<u64>,
+) -> Frame {
+    let refuse = |message: &str| Frame::new(&bucket_id, "error", message);
+    // Streams end on their own when a bucket is closed
+    subscriptions.retain(|_, task| !task.is_finished());
+
+    if (bucket_id.len() < ids::MIN_BUCKET_ID_LENGTH && bucket_id != DEMO_BUCKET_ID)
+        || ids::is_reserved(&bucket_id)
+    {
+        return refuse("Unknown bucket");
+    }
+    if subscriptions.contains_key(&bucket_id) {
+        return refuse("Already subscribed");
+    }
+    if subscriptions.len() >= MAX_MULTIPLEX_SUBSCRIPTIONS {
+        return refuse("Too many subscriptions on this connection");
+    }
+
+    {
+        let manager = state.channel_manager.read().await;
+        if let Some(channel) = manager.get_channel(&bucket_id) {
+            if channel.is_suspended() {
+                return refuse("Bucket is suspended");
+            }
+        }
+    }
+
+    // Each bucket followed counts as a stream, the same as a separate EventSource would
+    let permit = match state.subscriber_limiter.acquire(ip) {
+        Ok(permit) => permit,
+        Err(LimitExceeded::PerIp) => {
+            return refuse("Too many concurrent connections from this client");
+        }
+        Err(LimitExceeded::Global) => {
+            METRICS.shed_subscriptions.inc();
+            return refuse("Server is at capacity, please try again shortly");
+        }
+    };
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    let max_subs = bucket_id
+        .split(";max-subs=")
+        .nth(1)
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(MAX_SUBSCRIBERS_PER_STREAM);
+    if channel.subscriber_count() >= max_subs {
+        warn!("Stream {} rejected: max subscribers reached", bucket_id);
+        return refuse("Max subscribers reached");
+    }
+
+    info!("New multiplexed subscriber to bucket: {}", bucket_id);
+    if channel.subscriber_count() == 0 {
+        channel
+            .notify_webhooks(webhooks::WebhookEvent::FirstSubscriber)
+            .await;
+    }
+
+    let mut stream = channel.subscribe(resume_after).await;
+    channel.publish_stats(channel.get_stats()).await;
+
+    let sender = sender.clone();
+    let bucket = bucket_id.clone();
+    let task = tokio::spawn(async move {
+        let _permit = permit;
+        while let Some(SseEvent {
+            event_type,
+            data,
+            seq,
+        }) = stream.next().await
+        {
+            let frame = Frame {
+                bucket: bucket.clone(),
+                event: event_type,
+                id: seq,
+                data,
+            };
+            if sender.send(frame).await.is_err() {
+                break;
+            }
+        }
+    });
+    subscriptions.insert(bucket_id.clone(), task.abort_handle());
+
+    Frame::new(&bucket_id, "subscribed", "")
+}
@@ -0,0 +1,535 @@
+use crate::compression::{gunzip, DecompressError};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::{Body, Bytes},
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat};
+use http_body::Frame;
+use prost::{Message, Oneof};
+use serde_json::{Map, Number, Value};
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+/// The unary method OTel collectors call to export logs
+const EXPORT_PATH: &str = "/opentelemetry.proto.collector.logs.v1.LogsService/Export";
+/// gRPC metadata naming the bucket, for tokens that aren't mapped to one in `TOKENS_FILE`
+const BUCKET_METADATA: &str = "x-log-bin-bucket";
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+/// gRPC's default limit on a received message, which collectors batch up to
+const MAX_GRPC_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+/// Length-prefixed message header: a compressed flag and a big-endian length
+const FRAME_HEADER_SIZE: usize = 5;
+
+/// `ExportLogsServiceRequest` from opentelemetry-proto, minus schema URLs and counts
+#[derive(Clone, PartialEq, Message)]
+struct ExportLogsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ExportLogsServiceResponse {
+    #[prost(message, optional, tag = "1")]
+    partial_success: Option<ExportLogsPartialSuccess>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ExportLogsPartialSuccess {
+    #[prost(int64, tag = "1")]
+    rejected_log_records: i64,
+    #[prost(string, tag = "2")]
+    error_message: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ResourceLogs {
+    #[prost(message, optional, tag = "1")]
+    resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    scope_logs: Vec<ScopeLogs>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ScopeLogs {
+    #[prost(message, optional, tag = "1")]
+    scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    log_records: Vec<LogRecord>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct LogRecord {
+    #[prost(fixed64, tag = "1")]
+    time_unix_nano: u64,
+    #[prost(int32, tag = "2")]
+    severity_number: i32,
+    #[prost(string, tag = "3")]
+    severity_text: String,
+    #[prost(message, optional, tag = "5")]
+    body: Option<AnyValue>,
+    #[prost(message, repeated, tag = "6")]
+    attributes: Vec<KeyValue>,
+    #[prost(bytes = "vec", tag = "9")]
+    trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "10")]
+    span_id: Vec<u8>,
+    #[prost(fixed64, tag = "11")]
+    observed_time_unix_nano: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct KeyValue {
+    #[prost(string, tag = "1")]
+    key: String,
+    #[prost(message, optional, tag = "2")]
+    value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct AnyValue {
+    #[prost(oneof = "AnyValueKind", tags = "1, 2, 3, 4, 5, 6, 7")]
+    value: Option<AnyValueKind>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum AnyValueKind {
+    #[prost(string, tag = "1")]
+    String(String),
+    #[prost(bool, tag = "2")]
+    Bool(bool),
+    #[prost(int64, tag = "3")]
+    Int(i64),
+    #[prost(double, tag = "4")]
+    Double(f64),
+    #[prost(message, tag = "5")]
+    Array(ArrayValue),
+    #[prost(message, tag = "6")]
+    KeyValueList(KeyValueList),
+    #[prost(bytes, tag = "7")]
+    Bytes(Vec<u8>),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ArrayValue {
+    #[prost(message, repeated, tag = "1")]
+    values: Vec<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct KeyValueList {
+    #[prost(message, repeated, tag = "1")]
+    values: Vec<KeyValue>,
+}
+
+/// gRPC status codes the receiver answers with
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Code {
+    InvalidArgument = 3,
+    ResourceExhausted = 8,
+    Unimplemented = 12,
+    Unavailable = 14,
+    Unauthenticated = 16,
+}
+
+/// A failed call, as a trailers-only response. Messages are ASCII without `%`, so they
+/// need no percent-encoding.
+fn status(code: Code, message: &str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, GRPC_CONTENT_TYPE.to_string()),
+            (
+                header::HeaderName::from_static("grpc-status"),
+                (code as u8).to_string(),
+            ),
+            (
+                header::HeaderName::from_static("grpc-message"),
+                message.to_string(),
+            ),
+        ],
+        Body::empty(),
+    )
+        .into_response()
+}
+
+/// A successful unary call: one length-prefixed message, then an OK status in trailers
+fn reply(message: &impl Message) -> Response {
+    let message = message.encode_to_vec();
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from_static("0"));
+    (
+        [(header::CONTENT_TYPE, GRPC_CONTENT_TYPE)],
+        Body::new(UnaryBody {
+            message: Some(frame.into()),
+            trailers: Some(trailers),
+        }),
+    )
+        .into_response()
+}
+
+/// A response body that ends in trailers, which gRPC carries its status in
+struct UnaryBody {
+    message: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl http_body::Body for UnaryBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        if let Some(message) = self.message.take() {
+            return Poll::Ready(Some(Ok(Frame::data(message))));
+        }
+        Poll::Ready(
+            self.trailers
+                .take()
+                .map(|trailers| Ok(Frame::trailers(trailers))),
+        )
+    }
+}
+
+/// Unwrap the single length-prefixed message of a unary request
+fn unframe(body: &[u8], gzip: bool) -> Result<Vec<u8>, &'static str> {
+    let Some((header, message)) = body.split_first_chunk::<FRAME_HEADER_SIZE>() else {
+        return Err("Request is not a gRPC message");
+    };
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if message.len() != length {
+        return Err("Request must hold exactly one message");
+    }
+    match header[0] {
+        0 => Ok(message.to_vec()),
+        1 if gzip => gunzip(message, MAX_GRPC_MESSAGE_SIZE).map_err(|e| match e {
+            DecompressError::TooLarge => "Decompressed message is too large",
+            DecompressError::Invalid => "Message is not valid gzip",
+        }),
+        _ => Err("Message is compressed without a supported grpc-encoding"),
+    }
+}
+
+/// Decode an export request into one JSON line per log record
+fn decode(message: &[u8]) -> Result<Vec<String>, &'static str> {
+    let request = ExportLogsServiceRequest::decode(message)
+        .map_err(|_| "Request is not an ExportLogsServiceRequest")?;
+
+    let mut lines = Vec::new();
+    for resource_logs in &request.resource_logs {
+        let resource = resource_logs
+            .resource
+            .as_ref()
+            .map_or(&[][..], |resource| resource.attributes.as_slice());
+        for scope_logs in &resource_logs.scope_logs {
+            let scope = scope_logs.scope.as_ref().map(|scope| scope.name.as_str());
+            for record in &scope_logs.log_records {
+                lines.push(record_line(record, resource, scope));
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Render a log record as its body, with its attributes, then its resource's, merged in
+fn record_line(record: &LogRecord, resource: &[KeyValue], scope: Option<&str>) -> String {
+    let mut object = match record.body.as_ref().map(to_json) {
+        Some(Value::Object(body)) => body,
+        Some(Value::Null) | None => Map::new(),
+        Some(message) => Map::from_iter([("message".to_string(), message)]),
+    };
+    for attribute in record.attributes.iter().chain(resource) {
+        if let Some(value) = &attribute.value {
+            object
+                .entry(attribute.key.clone())
+                .or_insert_with(|| to_json(value));
+        }
+    }
+    if let Some(scope) = scope.filter(|scope| !scope.is_empty()) {
+        object.entry("scope").or_insert(scope.into());
+    }
+
+    let level = match record.severity_text.as_str() {
+        "" => severity_name(record.severity_number),
+        text => Some(text),
+    };
+    if let Some(level) = level {
+        object.entry("level").or_insert(level.into());
+    }
+
+    let time = match record.time_unix_nano {
+        0 => record.observed_time_unix_nano,
+        time => time,
+    };
+    if time > 0 {
+        let time = DateTime::from_timestamp_nanos(time as i64)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        object.entry("time").or_insert(time.into());
+    }
+
+    for (key, id) in [("trace_id", &record.trace_id), ("span_id", &record.span_id)] {
+        if !id.is_empty() {
+            let hex: String = id.iter().map(|byte| format!("{:02x}", byte)).collect();
+            object.entry(key).or_insert(hex.into());
+        }
+    }
+
+    Value::Object(object).to_string()
+}
+
+/// The short name of an OpenTelemetry severity number, which counts up in fours from TRACE
+fn severity_name(number: i32) -> Option<&'static str> {
+    match number {
+        1..=4 => Some("TRACE"),
+        5..=8 => Some("DEBUG"),
+        9..=12 => Some("INFO"),
+        13..=16 => Some("WARN"),
+        17..=20 => Some("ERROR"),
+        21..=24 => Some("FATAL"),
+        _ => None,
+    }
+}
+
+fn to_json(value: &AnyValue) -> Value {
+    match &value.value {
+        None => Value::Null,
+        Some(AnyValueKind::String(text)) => Value::String(text.clone()),
+        Some(AnyValueKind::Bool(flag)) => Value::Bool(*flag),
+        Some(AnyValueKind::Int(number)) => Value::from(*number),
+        Some(AnyValueKind::Double(number)) => {
+            Number::from_f64(*number).map_or(Value::Null, Value::Number)
+        }
+        Some(AnyValueKind::Array(array)) => {
+            Value::Array(array.values.iter().map(to_json).collect())
+        }
+        Some(AnyValueKind::KeyValueList(list)) => Value::Object(
+            list.values
+                .iter()
+                .map(|pair| {
+                    (
+                        pair.key.clone(),
+                        pair.value.as_ref().map_or(Value::Null, to_json),
+                    )
+                })
+                .collect(),
+        ),
+        Some(AnyValueKind::Bytes(bytes)) => {
+            Value::String(base64::engine::general_purpose::STANDARD.encode(bytes))
+        }
+    }
+}
+
+/// `LogsService/Export`: OTLP logs from collectors that only speak gRPC. The bucket is
+/// named by `x-log-bin-bucket` metadata, or the one the write token is mapped to.
+async fn export(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response {
+    let grpc = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(GRPC_CONTENT_TYPE));
+    if !grpc {
+        return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+    }
+    let gzip = match headers.get("grpc-encoding").map(HeaderValue::as_bytes) {
+        None | Some(b"identity") => false,
+        Some(b"gzip") => true,
+        Some(_) => return status(Code::Unimplemented, "Only gzip compression is supported"),
+    };
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err((StatusCode::TOO_MANY_REQUESTS, message)) => {
+            return status(Code::ResourceExhausted, &message)
+        }
+        Err((_, message)) => return status(Code::Unauthenticated, &message),
+    };
+    let bucket_id = headers
+        .get(BUCKET_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| token.as_ref().and_then(|token| token.bucket()))
+        .filter(|bucket| !bucket.is_empty())
+        .map(String::from);
+    let Some(bucket_id) =
+        bucket_id.filter(|bucket| bucket != DEMO_BUCKET_ID && !ids::is_reserved(bucket))
+    else {
+        return status(
+            Code::InvalidArgument,
+            "Name a writable bucket with x-log-bin-bucket metadata",
+        );
+    };
+
+    let lines = match unframe(&body, gzip).and_then(|message| decode(&message)) {
+        Ok(lines) => lines,
+        Err(message) => return status(Code::InvalidArgument, message),
+    };
+    if let Err((_, message)) = check_event_count(&state, lines.len(), "lower the batch size") {
+        return status(Code::InvalidArgument, &message);
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel.filter(|_| !lines.is_empty()) else {
+        if !lines.is_empty() {
+            warn!(
+                "Discarding OTLP logs for bucket with no viewers: {}",
+                bucket_id
+            );
+        }
+        return reply(&ExportLogsServiceResponse::default());
+    };
+
+    // Collectors give up on RESOURCE_EXHAUSTED but retry UNAVAILABLE, which suits a pause
+    if channel.is_suspended() {
+        return status(Code::ResourceExhausted, SUSPENSION_REASON_TEXT);
+    }
+
+    info!(
+        "New OTLP logs for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+    if let Some(token) = &token {
+        let now = chrono::Utc::now();
+        token.record(lines.len() as u64, body.len() as u64, now);
+        token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => reply(&ExportLogsServiceResponse::default()),
+        IngestOutcome::Suspended => status(Code::ResourceExhausted, SUSPENSION_REASON_TEXT),
+        IngestOutcome::Paused => status(Code::Unavailable, PAUSED_TEXT),
+    }
+}
+
+/// Serve the OTLP logs service over HTTP/2 without TLS, as collectors dial it
+pub fn spawn(listener: TcpListener, state: AppState) {
+    let app = Router::new()
+        .route(EXPORT_PATH, post(export))
+        .layer(DefaultBodyLimit::max(
+            FRAME_HEADER_SIZE + MAX_GRPC_MESSAGE_SIZE,
+        ))
+        .with_state(state);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("OTLP gRPC listener stopped: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(text: &str) -> Option<AnyValue> {
+        Some(AnyValue {
+            value: Some(AnyValueKind::String(text.to_string())),
+        })
+    }
+
+    fn attribute(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: string(value),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource {
+                    attributes: vec![
+                        attribute("service.name", "checkout"),
+                        attribute("host", "resource"),
+                    ],
+                }),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(InstrumentationScope {
+                        name: "app.payments".to_string(),
+                    }),
+                    log_records: vec![
+                        LogRecord {
+                            time_unix_nano: 1_700_000_000_500_000_000,
+                            severity_number: 17,
+                            body: string("card declined"),
+                            attributes: vec![attribute("host", "web-1")],
+                            trace_id: vec![0xab; 16],
+                            ..Default::default()
+                        },
+                        LogRecord {
+                            severity_text: "notice".to_string(),
+                            body: Some(AnyValue {
+                                value: Some(AnyValueKind::KeyValueList(KeyValueList {
+                                    values: vec![attribute("msg", "structured")],
+                                })),
+                            }),
+                            ..Default::default()
+                        },
+                    ],
+                }],
+            }],
+        };
+        let lines = decode(&request.encode_to_vec()).unwrap();
+
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["message"], "card declined");
+        assert_eq!(first["host"], "web-1");
+        assert_eq!(first["service.name"], "checkout");
+        assert_eq!(first["scope"], "app.payments");
+        assert_eq!(first["level"], "ERROR");
+        assert_eq!(first["time"], "2023-11-14T22:13:20.500Z");
+        assert_eq!(first["trace_id"], "ab".repeat(16));
+
+        let second: Value = serde_json::from_str(&lines[1]).unwrap();
+        assert_eq!(second["msg"], "structured");
+        assert_eq!(second["level"], "notice");
+        assert!(second.get("time").is_none());
+    }
+
+    #[test]
+    fn test_unframe() {
+        let message = ExportLogsServiceRequest::default().encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&message);
+        assert_eq!(unframe(&frame, false).unwrap(), message);
+
+        assert!(unframe(&frame[..4], false).is_err());
+        frame.push(0);
+        assert!(unframe(&frame, false).is_err());
+        frame.pop();
+        frame[0] = 1;
+        assert!(unframe(&frame, false).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use crate::metrics::METRICS;
+use crate::models::SseEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+/// How often queued events are released to subscribers
+const PACING_TICK: Duration = Duration::from_millis(50);
+const DEFAULT_MAX_BUFFERED: usize = 10_000;
+pub const MAX_PACING_BUFFER: usize = 100_000;
+
+fn default_max_buffered() -> usize {
+    DEFAULT_MAX_BUFFERED
+}
+
+/// Smoothing of ingest bursts into a bucket's live stream, trading latency for fewer
+/// lagging subscribers when a producer sends thousands of lines at once
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pacing {
+    /// Most events a second sent to subscribers, with up to a second's worth sent at once
+    pub max_events_per_sec: u32,
+    /// Most events held back at a time; events beyond it skip the live stream, which
+    /// subscribers see as a gap, but are still kept in history
+    #[serde(default = "default_max_buffered")]
+    pub max_buffered: usize,
+}
+
+impl Pacing {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_events_per_sec == 0 {
+            return Err("Pacing needs a non-zero max_events_per_sec".to_string());
+        }
+        if !(1..=MAX_PACING_BUFFER).contains(&self.max_buffered) {
+            return Err(format!(
+                "Pacing max_buffered must be between 1 and {}",
+                MAX_PACING_BUFFER
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What happens to a log event offered to the pacer
+#[derive(Debug)]
+enum Admission {
+    /// Within the rate, so send it now
+    Send(SseEvent),
+    /// Held back; `drain` is set when nothing is releasing the queue yet
+    Queued { drain: bool },
+    /// The queue is full
+    Dropped,
+}
+
+/// A token bucket in front of a queue of held-back events
+struct PacerState {
+    pacing: Option<Pacing>,
+    tokens: f64,
+    refilled_at: Instant,
+    queue: VecDeque<SseEvent>,
+    draining: bool,
+}
+
+impl PacerState {
+    fn new(now: Instant) -> Self {
+        Self {
+            pacing: None,
+            tokens: 0.0,
+            refilled_at: now,
+            queue: VecDeque::new(),
+            draining: false,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(pacing) = &self.pacing {
+            let rate = f64::from(pacing.max_events_per_sec);
+            let elapsed = now
+                .saturating_duration_since(self.refilled_at)
+                .as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(rate);
+        }
+        self.refilled_at = now;
+    }
+
+    fn admit(&mut self, event: SseEvent, now: Instant) -> Admission {
+        self.refill(now);
+        // Keep events in order behind any that are already waiting
+        if self.queue.is_empty() {
+            match &self.pacing {
+                None => return Admission::Send(event),
+                Some(_) if self.tokens >= 1.0 => {
+                    self.tokens -= 1.0;
+                    return Admission::Send(event);
+                }
+                Some(_) => {}
+            }
+        }
+
+        let max_buffered = self
+            .pacing
+            .as_ref()
+            .map_or(MAX_PACING_BUFFER, |pacing| pacing.max_buffered);
+        if self.queue.len() >= max_buffered {
+            return Admission::Dropped;
+        }
+        self.queue.push_back(event);
+        let drain = !self.draining;
+        self.draining = true;
+        Admission::Queued { drain }
+    }
+
+    /// Take the queued events the rate allows by `now`, marking the queue idle once empty
+    fn release(&mut self, now: Instant) -> Vec<SseEvent> {
+        self.refill(now);
+        let count = match &self.pacing {
+            None => self.queue.len(),
+            Some(_) => (self.tokens as usize).min(self.queue.len()),
+        };
+        if self.pacing.is_some() {
+            self.tokens -= count as f64;
+        }
+        let released = self.queue.drain(..count).collect();
+        self.draining = !self.queue.is_empty();
+        released
+    }
+}
+
+/// Releases a bucket's log events to its subscribers no faster than its pacing allows
+pub struct Pacer {
+    state: Mutex<PacerState>,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(PacerState::new(Instant::now())),
+        }
+    }
+}
+
+impl Pacer {
+    /// Change the rate, starting with a full second's allowance
+    pub fn configure(&self, pacing: Option<Pacing>) {
+        let mut state = self.state.lock().unwrap();
+        state.refill(Instant::now());
+        state.tokens = pacing
+            .as_ref()
+            .map_or(0.0, |pacing| f64::from(pacing.max_events_per_sec));
+        state.pacing = pacing;
+    }
+
+    /// Broadcast an event now if the rate allows, or queue it for a background task to
+    /// release later
+    pub fn send(self: &Arc<Self>, sender: &broadcast::Sender<SseEvent>, event: SseEvent) {
+        let mut state = self.state.lock().unwrap();
+        match state.admit(event, Instant::now()) {
+            Admission::Send(event) => {
+                let _ = sender.send(event);
+            }
+            Admission::Queued { drain: true } => {
+                tokio::spawn(drain(self.clone(), sender.clone()));
+            }
+            Admission::Queued { drain: false } => {}
+            Admission::Dropped => METRICS.paced_drops.inc(),
+        }
+    }
+}
+
+async fn drain(pacer: Arc<Pacer>, sender: broadcast::Sender<SseEvent>) {
+    let mut ticks = tokio::time::interval(PACING_TICK);
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        let draining = {
+            let mut state = pacer.state.lock().unwrap();
+            for event in state.release(Instant::now()) {
+                let _ = sender.send(event);
+            }
+            state.draining
+        };
+        if !draining {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(seq: u64) -> SseEvent {
+        SseEvent {
+            event_type: "log".to_string(),
+            data: String::new(),
+            seq: Some(seq),
+        }
+    }
+
+    #[test]
+    fn test_pacing() {
+        let start = Instant::now();
+        let mut state = PacerState::new(start);
+        state.pacing = Some(Pacing {
+            max_events_per_sec: 10,
+            max_buffered: 5,
+        });
+        let later = start + Duration::from_secs(1);
+
+        let admitted: Vec<Admission> = (1..=16).map(|seq| state.admit(event(seq), later)).collect();
+        assert!(admitted[..10]
+            .iter()
+            .all(|admission| matches!(admission, Admission::Send(_))));
+        assert!(matches!(admitted[10], Admission::Queued { drain: true }));
+        assert!(matches!(admitted[14], Admission::Queued { drain: false }));
+        assert!(matches!(admitted[15], Admission::Dropped));
+
+        let released = state.release(later + Duration::from_millis(300));
+        let seqs: Vec<u64> = released.iter().filter_map(|event| event.seq).collect();
+        assert_eq!(seqs, [11, 12, 13]);
+        assert!(state.draining);
+
+        assert_eq!(state.release(later + Duration::from_secs(2)).len(), 2);
+        assert!(!state.draining);
+
+        // Turning pacing off lets everything through again
+        state.pacing = None;
+        assert!(matches!(state.admit(event(17), later), Admission::Send(_)));
+    }
+}
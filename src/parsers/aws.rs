@@ -0,0 +1,205 @@
+//! AWS access logs: Application Load Balancer lines (space-separated, with quoted values)
+//! and CloudFront standard logs (tab-separated), with each value under its documented name
+
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// ALB access log fields in the order they are written. Older load balancers write fewer.
+const ALB_FIELDS: &[&str] = &[
+    "type",
+    "time",
+    "elb",
+    "client:port",
+    "target:port",
+    "request_processing_time",
+    "target_processing_time",
+    "response_processing_time",
+    "elb_status_code",
+    "target_status_code",
+    "received_bytes",
+    "sent_bytes",
+    "request",
+    "user_agent",
+    "ssl_cipher",
+    "ssl_protocol",
+    "target_group_arn",
+    "trace_id",
+    "domain_name",
+    "chosen_cert_arn",
+    "matched_rule_priority",
+    "request_creation_time",
+    "actions_executed",
+    "redirect_url",
+    "error_reason",
+    "target:port_list",
+    "target_status_code_list",
+    "classification",
+    "classification_reason",
+    "conn_trace_id",
+];
+/// Up to and including `user_agent`, which every version of the format has
+const MIN_ALB_FIELDS: usize = 14;
+const ALB_TYPES: &[&str] = &["http", "https", "h2", "grpcs", "ws", "wss"];
+
+/// CloudFront standard log fields in the order they are written. Older distributions
+/// write fewer.
+const CLOUDFRONT_FIELDS: &[&str] = &[
+    "date",
+    "time",
+    "x-edge-location",
+    "sc-bytes",
+    "c-ip",
+    "cs-method",
+    "cs(Host)",
+    "cs-uri-stem",
+    "sc-status",
+    "cs(Referer)",
+    "cs(User-Agent)",
+    "cs-uri-query",
+    "cs(Cookie)",
+    "x-edge-result-type",
+    "x-edge-request-id",
+    "x-host-header",
+    "cs-protocol",
+    "cs-bytes",
+    "time-taken",
+    "x-forwarded-for",
+    "ssl-protocol",
+    "ssl-cipher",
+    "x-edge-response-result-type",
+    "cs-protocol-version",
+    "fle-status",
+    "fle-encrypted-fields",
+    "c-port",
+    "time-to-first-byte",
+    "x-edge-detailed-result-type",
+    "sc-content-type",
+    "sc-content-len",
+    "sc-range-start",
+    "sc-range-end",
+];
+/// Up to and including `time-taken`
+const MIN_CLOUDFRONT_FIELDS: usize = 19;
+
+pub struct AccessLog {
+    pub fields: HashMap<String, String>,
+    /// When the request was made, in epoch milliseconds
+    pub time: i64,
+}
+
+/// Parse an Application Load Balancer access log line
+pub fn parse_alb(input: &str) -> Option<AccessLog> {
+    let values = split_quoted(input)?;
+    if values.len() < MIN_ALB_FIELDS || !ALB_TYPES.contains(&values[0]) {
+        return None;
+    }
+    let time = DateTime::parse_from_rfc3339(values[1]).ok()?;
+    if !values[2].starts_with("app/") {
+        return None;
+    }
+
+    Some(AccessLog {
+        fields: named(ALB_FIELDS, &values),
+        time: time.timestamp_millis(),
+    })
+}
+
+/// Parse a CloudFront standard log line. Times in these logs are UTC.
+pub fn parse_cloudfront(input: &str) -> Option<AccessLog> {
+    let values: Vec<&str> = input.split('\t').collect();
+    if values.len() < MIN_CLOUDFRONT_FIELDS {
+        return None;
+    }
+    let time =
+        NaiveDateTime::parse_from_str(&format!("{} {}", values[0], values[1]), "%Y-%m-%d %H:%M:%S")
+            .ok()?;
+
+    Some(AccessLog {
+        fields: named(CLOUDFRONT_FIELDS, &values),
+        time: Utc.from_utc_datetime(&time).timestamp_millis(),
+    })
+}
+
+/// Pair values with the names for their positions, dropping any the format doesn't name yet
+fn named(names: &[&str], values: &[&str]) -> HashMap<String, String> {
+    names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Split on spaces, keeping double-quoted values whole and dropping their quotes
+fn split_quoted(input: &str) -> Option<Vec<&str>> {
+    let mut values = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let (value, after) = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                if !after.is_empty() && !after.starts_with(' ') {
+                    return None;
+                }
+                (value, after)
+            }
+            None => rest.split_at(rest.find(' ').unwrap_or(rest.len())),
+        };
+        values.push(value);
+        rest = after.strip_prefix(' ').unwrap_or(after);
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_alb() {
+        let line = r#"https 2018-07-02T22:23:00.186641Z app/my-loadbalancer/50dc6c495c0c9188 192.168.131.39:2817 10.0.0.1:80 0.086 0.048 0.037 200 200 0 57 "GET https://www.example.com:443/ HTTP/1.1" "curl/7.46.0" ECDHE-RSA-AES128-GCM-SHA256 TLSv1.2 arn:aws:elasticloadbalancing:us-east-2:123456789012:targetgroup/my-targets/73e2d6bc24d8a067 "Root=1-58337281-1d84f3d73c47ec4e58577259" "www.example.com" "arn:aws:acm:us-east-2:123456789012:certificate/12345678-1234-1234-1234-123456789012" 1 2018-07-02T22:22:48.364000Z "authenticate,forward" "-" "-" "10.0.0.1:80" "200" "-" "-" TID_1234abcd5678ef90"#;
+        let log = parse_alb(line).unwrap();
+
+        assert_eq!(log.fields["type"], "https");
+        assert_eq!(log.fields["client:port"], "192.168.131.39:2817");
+        assert_eq!(log.fields["elb_status_code"], "200");
+        assert_eq!(
+            log.fields["request"],
+            "GET https://www.example.com:443/ HTTP/1.1"
+        );
+        assert_eq!(log.fields["user_agent"], "curl/7.46.0");
+        assert_eq!(log.fields["actions_executed"], "authenticate,forward");
+        assert_eq!(log.fields["conn_trace_id"], "TID_1234abcd5678ef90");
+        assert_eq!(
+            log.time,
+            Utc.with_ymd_and_hms(2018, 7, 2, 22, 23, 0)
+                .unwrap()
+                .timestamp_millis()
+                + 186
+        );
+
+        assert!(parse_alb("https 2018-07-02T22:23:00Z app/lb/1 too short").is_none());
+        assert!(parse_alb(&line.replacen("https", "smtp", 1)).is_none());
+    }
+
+    #[test]
+    fn test_parse_cloudfront() {
+        let line = "2019-12-04\t21:02:31\tLAX1\t392\t192.0.2.100\tGET\td111111abcdef8.cloudfront.net\t/index.html\t200\t-\tMozilla/5.0%20(Windows%20NT%2010.0)\t-\t-\tHit\tSOX4xwn4XV6Q4rgb7XiVGOHms_BGlTAC4KyHmureZmBNrjGdRLiNIQ==\td111111abcdef8.cloudfront.net\thttps\t23\t0.001\t-\tTLSv1.2\tECDHE-RSA-AES128-GCM-SHA256\tHit\tHTTP/2.0\t-\t-\t11040\t0.001\tHit\ttext/html\t78\t-\t-";
+        let log = parse_cloudfront(line).unwrap();
+
+        assert_eq!(log.fields["x-edge-location"], "LAX1");
+        assert_eq!(log.fields["cs-uri-stem"], "/index.html");
+        assert_eq!(log.fields["sc-status"], "200");
+        assert_eq!(log.fields["x-edge-result-type"], "Hit");
+        assert_eq!(log.fields["sc-range-end"], "-");
+        assert_eq!(log.fields.len(), CLOUDFRONT_FIELDS.len());
+        assert_eq!(
+            log.time,
+            Utc.with_ymd_and_hms(2019, 12, 4, 21, 2, 31)
+                .unwrap()
+                .timestamp_millis()
+        );
+
+        assert!(parse_cloudfront("2019-12-04\t21:02:31\tLAX1").is_none());
+        assert!(parse_cloudfront(&line.replacen("21:02:31", "late", 1)).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! CSV and TSV rows, read against the column names of a header row posted earlier. Values
+//! are quoted as in RFC 4180, though a quoted value can't span lines.
+
+use std::collections::HashMap;
+
+/// Delimiters tried on a header row, in order of preference when counts tie
+const DELIMITERS: [char; 4] = [',', '\t', ';', '|'];
+
+/// The column names of a header row, and the delimiter it was split on. Without a
+/// delimiter, the most common one outside quotes is used.
+pub fn header(input: &str, delimiter: Option<char>) -> Option<(char, Vec<String>)> {
+    let delimiter = delimiter.or_else(|| detect_delimiter(input))?;
+    let names: Vec<String> = split_row(input, delimiter)?
+        .into_iter()
+        .map(|name| name.trim().to_string())
+        .collect();
+    if names.len() < 2 {
+        return None;
+    }
+
+    // Numbers and blanks are data, and a header names each column once
+    for (i, name) in names.iter().enumerate() {
+        if name.is_empty() || name.parse::<f64>().is_ok() || names[..i].contains(name) {
+            return None;
+        }
+    }
+    Some((delimiter, names))
+}
+
+/// Read a row against the header's columns, which it must match one for one
+pub fn parse(input: &str, delimiter: char, columns: &[String]) -> Option<HashMap<String, String>> {
+    let values = split_row(input, delimiter)?;
+    if values.len() != columns.len() {
+        return None;
+    }
+    Some(columns.iter().cloned().zip(values).collect())
+}
+
+fn detect_delimiter(input: &str) -> Option<char> {
+    let mut counts = [0; DELIMITERS.len()];
+    let mut quoted = false;
+    for c in input.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if let Some(i) = DELIMITERS.iter().position(|&d| d == c).filter(|_| !quoted) {
+            counts[i] += 1;
+        }
+    }
+    // Reversed so the earliest delimiter wins a tie
+    (0..DELIMITERS.len())
+        .rev()
+        .filter(|&i| counts[i] > 0)
+        .max_by_key(|&i| counts[i])
+        .map(|i| DELIMITERS[i])
+}
+
+/// Split on the delimiter, unquoting quoted values. `None` if a quote is left open or
+/// text follows a closing quote.
+fn split_row(input: &str, delimiter: char) -> Option<Vec<String>> {
+    let mut values = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' if chars.next_if_eq(&'"').is_some() => value.push('"'),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            values.push(value);
+            match chars.next() {
+                None => return Some(values),
+                Some(c) if c == delimiter => continue,
+                Some(_) => return None,
+            }
+        }
+
+        loop {
+            match chars.next() {
+                None => {
+                    values.push(value);
+                    return Some(values);
+                }
+                Some(c) if c == delimiter => break,
+                Some(c) => value.push(c),
+            }
+        }
+        values.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header() {
+        let (delimiter, names) = header("time,level,message", None).unwrap();
+        assert_eq!(delimiter, ',');
+        assert_eq!(names, vec!["time", "level", "message"]);
+
+        let (delimiter, names) = header("\"user, name\";\"id\";region", None).unwrap();
+        assert_eq!(delimiter, ';');
+        assert_eq!(names, vec!["user, name", "id", "region"]);
+
+        assert_eq!(header("a\tb", None).unwrap().0, '\t');
+        assert!(header("a,b", Some('\t')).is_none());
+        assert!(header("2024-01-01,42,ok", None).is_none());
+        assert!(header("name,name", None).is_none());
+        assert!(header("name,,id", None).is_none());
+        assert!(header("just some words", None).is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let columns: Vec<String> = ["time", "level", "message"].map(String::from).to_vec();
+        let row = parse(
+            r#"2024-01-01T12:00:00Z,warn,"disk ""/var"" is 91%, cleaning up""#,
+            ',',
+            &columns,
+        )
+        .unwrap();
+        assert_eq!(row["level"], "warn");
+        assert_eq!(row["message"], r#"disk "/var" is 91%, cleaning up"#);
+
+        assert_eq!(parse("a,,", ',', &columns).unwrap()["level"], "");
+        assert!(parse("a,b", ',', &columns).is_none());
+        assert!(parse("a,b,\"c", ',', &columns).is_none());
+        assert!(parse("a,\"b\"c,d", ',', &columns).is_none());
+    }
+}
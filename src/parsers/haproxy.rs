@@ -0,0 +1,184 @@
+//! HAProxy's HTTP log format (`option httplog`):
+//! `ip:port [date] frontend backend/server Tq/Tw/Tc/Tr/Tt status bytes cookies state
+//! conns queues {headers} "request"`
+
+use super::is_number;
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// The tag HAProxy logs under, which may prefix lines read from a file
+const TAG: &str = "haproxy";
+
+pub struct HaproxyRequest {
+    pub fields: HashMap<String, String>,
+    /// When HAProxy accepted the connection, in epoch milliseconds
+    pub time: i64,
+}
+
+/// Parse an HTTP log line, with or without the `haproxy[pid]: ` syslog tag in front.
+/// HAProxy writes local time without a zone, so times are taken as UTC, as for syslog.
+pub fn parse(input: &str) -> Option<HaproxyRequest> {
+    let input = strip_tag(input);
+    let mut fields = HashMap::new();
+
+    let (client, rest) = input.split_once(' ')?;
+    let (ip, port) = client.rsplit_once(':')?;
+    if ip.is_empty() || !is_number(port) {
+        return None;
+    }
+    insert(&mut fields, "client_ip", ip);
+    insert(&mut fields, "client_port", port);
+
+    let (date, rest) = rest.strip_prefix('[')?.split_once("] ")?;
+    let time = NaiveDateTime::parse_from_str(date, "%d/%b/%Y:%H:%M:%S%.3f").ok()?;
+    insert(&mut fields, "accept_date", date);
+
+    let (frontend, rest) = rest.split_once(' ')?;
+    let (backend, rest) = rest.split_once(' ')?;
+    let (backend, server) = backend.split_once('/')?;
+    insert(&mut fields, "frontend", frontend);
+    insert(&mut fields, "backend", backend);
+    insert(&mut fields, "server", server);
+
+    let (timers, rest) = rest.split_once(' ')?;
+    slashed(&mut fields, timers, &["Tq", "Tw", "Tc", "Tr", "Tt"])?;
+
+    let (status, rest) = rest.split_once(' ')?;
+    let (bytes, rest) = rest.split_once(' ')?;
+    if !is_number(status) || !is_number(bytes.trim_start_matches('+')) {
+        return None;
+    }
+    insert(&mut fields, "status", status);
+    insert(&mut fields, "bytes", bytes);
+
+    let (request_cookie, rest) = rest.split_once(' ')?;
+    let (response_cookie, rest) = rest.split_once(' ')?;
+    insert(&mut fields, "request_cookie", request_cookie);
+    insert(&mut fields, "response_cookie", response_cookie);
+
+    let (state, rest) = rest.split_once(' ')?;
+    if state.len() != 4 {
+        return None;
+    }
+    insert(&mut fields, "termination_state", state);
+
+    let (connections, rest) = rest.split_once(' ')?;
+    slashed(
+        &mut fields,
+        connections,
+        &["actconn", "feconn", "beconn", "srv_conn", "retries"],
+    )?;
+    let (queues, mut rest) = rest.split_once(' ')?;
+    slashed(&mut fields, queues, &["srv_queue", "backend_queue"])?;
+
+    // Captured request headers, then response headers, each in braces when configured
+    for key in ["request_headers", "response_headers"] {
+        let Some(captured) = rest.strip_prefix('{') else {
+            break;
+        };
+        let (headers, after) = captured.split_once("} ")?;
+        insert(&mut fields, key, headers);
+        rest = after;
+    }
+
+    // Long requests are cut off before their closing quote
+    let request = rest.strip_prefix('"')?;
+    let request = request.strip_suffix('"').unwrap_or(request);
+    insert(&mut fields, "request", request);
+    let mut parts = request.splitn(3, ' ');
+    if let (Some(method), Some(path)) = (parts.next(), parts.next()) {
+        insert(&mut fields, "method", method);
+        insert(&mut fields, "path", path);
+    }
+
+    Some(HaproxyRequest {
+        fields,
+        time: Utc.from_utc_datetime(&time).timestamp_millis(),
+    })
+}
+
+/// Drop a leading `haproxy[pid]: `, as in lines logged to a file by syslog
+fn strip_tag(input: &str) -> &str {
+    input
+        .strip_prefix(TAG)
+        .and_then(|rest| rest.strip_prefix('['))
+        .and_then(|rest| rest.split_once("]: "))
+        .filter(|(pid, _)| is_number(pid))
+        .map_or(input, |(_, rest)| rest)
+}
+
+/// Insert the `/`-separated numbers of `value` under `keys`. Timers are -1 for phases the
+/// request never reached, and totals are prefixed with `+` when logged early.
+fn slashed(fields: &mut HashMap<String, String>, value: &str, keys: &[&str]) -> Option<()> {
+    let values: Vec<&str> = value.split('/').collect();
+    if values.len() != keys.len() {
+        return None;
+    }
+    for (key, value) in keys.iter().zip(values) {
+        if value != "-1" && !is_number(value.trim_start_matches('+')) {
+            return None;
+        }
+        insert(fields, key, value);
+    }
+    Some(())
+}
+
+fn insert(fields: &mut HashMap<String, String>, key: &str, value: &str) {
+    fields.insert(key.to_string(), value.to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let line = r#"haproxy[14389]: 10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 {1wt.eu} {} "GET /index.html HTTP/1.1""#;
+        let request = parse(line).unwrap();
+
+        assert_eq!(request.fields["client_ip"], "10.0.1.2");
+        assert_eq!(request.fields["client_port"], "33317");
+        assert_eq!(request.fields["frontend"], "http-in");
+        assert_eq!(request.fields["backend"], "static");
+        assert_eq!(request.fields["server"], "srv1");
+        assert_eq!(request.fields["Tq"], "10");
+        assert_eq!(request.fields["Tr"], "69");
+        assert_eq!(request.fields["Tt"], "109");
+        assert_eq!(request.fields["status"], "200");
+        assert_eq!(request.fields["termination_state"], "----");
+        assert_eq!(request.fields["retries"], "0");
+        assert_eq!(request.fields["request_headers"], "1wt.eu");
+        assert_eq!(request.fields["response_headers"], "");
+        assert_eq!(request.fields["method"], "GET");
+        assert_eq!(request.fields["path"], "/index.html");
+        assert_eq!(
+            request.time,
+            Utc.with_ymd_and_hms(2009, 2, 6, 12, 14, 14)
+                .unwrap()
+                .timestamp_millis()
+                + 655
+        );
+    }
+
+    #[test]
+    fn test_aborted_request() {
+        let line = r#"2001:db8::1:443 [06/Feb/2009:12:12:51.443] https-in~ px-http/<NOSRV> 0/-1/-1/-1/+8245 408 +212 - - cR-- 2/2/2/0/0 0/0 "<BADREQ>""#;
+        let request = parse(line).unwrap();
+
+        assert_eq!(request.fields["client_ip"], "2001:db8::1");
+        assert_eq!(request.fields["server"], "<NOSRV>");
+        assert_eq!(request.fields["Tw"], "-1");
+        assert_eq!(request.fields["Tt"], "+8245");
+        assert_eq!(request.fields["termination_state"], "cR--");
+        assert_eq!(request.fields["request"], "<BADREQ>");
+        assert!(!request.fields.contains_key("request_headers"));
+        assert!(!request.fields.contains_key("method"));
+    }
+
+    #[test]
+    fn test_rejects_other_shapes() {
+        assert!(parse("10.0.1.2:33317 [06/Feb/2009:12:14:14.655] fe be/srv 1/2/3 200 0 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"").is_none());
+        assert!(parse("10.0.1.2:33317 [yesterday] fe be/srv 1/2/3/4/5 200 0 - - ---- 1/1/1/1/0 0/0 \"GET / HTTP/1.1\"").is_none());
+        assert!(parse("Connect from 10.0.1.2:33317 to 10.0.3.31:8012 (www/HTTP)").is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Android logcat's threadtime format, the `adb logcat` default:
+//! `mm-dd hh:mm:ss.mmm  pid  tid L tag     : message`
+
+use super::is_number;
+use super::syslog::nearest_year;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// Priority letters and the levels they stand for
+const PRIORITIES: [(&str, &str); 7] = [
+    ("V", "verbose"),
+    ("D", "debug"),
+    ("I", "info"),
+    ("W", "warn"),
+    ("E", "error"),
+    ("F", "fatal"),
+    ("A", "assert"),
+];
+
+pub struct LogcatLine {
+    pub fields: HashMap<String, String>,
+    /// When the line was logged, in epoch milliseconds
+    pub time: i64,
+}
+
+/// Parse a threadtime line, with or without the year `logcat -v year` adds. The device's
+/// local time has no zone, so times are taken as UTC, and in the year closest to `now`
+/// when the line doesn't give one.
+pub fn parse(input: &str, now: DateTime<Utc>) -> Option<LogcatLine> {
+    let (date, rest) = input.split_once(' ')?;
+    let (time, rest) = rest.split_once(' ')?;
+    let (pid, rest) = rest.trim_start().split_once(' ')?;
+    let (tid, rest) = rest.trim_start().split_once(' ')?;
+    let (priority, rest) = rest.trim_start().split_once(' ')?;
+    if !is_number(pid) || !is_number(tid) {
+        return None;
+    }
+    let (_, level) = PRIORITIES.iter().find(|(letter, _)| *letter == priority)?;
+
+    // Tags are padded to eight characters before the colon
+    let (tag, message) = match rest.split_once(": ") {
+        Some((tag, message)) => (tag, message),
+        None => (rest.strip_suffix(':')?, ""),
+    };
+
+    let clock = NaiveTime::parse_from_str(time, "%H:%M:%S%.3f").ok()?;
+    let time = match NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        Ok(date) => Utc
+            .from_utc_datetime(&date.and_time(clock))
+            .timestamp_millis(),
+        Err(_) => {
+            let (month, day) = date.split_once('-')?;
+            if month.len() != 2 || day.len() != 2 {
+                return None;
+            }
+            nearest_year(month.parse().ok()?, day.parse().ok()?, clock, now)?
+        }
+    };
+
+    let fields = HashMap::from([
+        ("pid".to_string(), pid.to_string()),
+        ("tid".to_string(), tid.to_string()),
+        ("priority".to_string(), priority.to_string()),
+        ("level".to_string(), level.to_string()),
+        ("tag".to_string(), tag.trim_end().to_string()),
+        ("msg".to_string(), message.to_string()),
+    ]);
+    Some(LogcatLine { fields, time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let line = parse(
+            "02-29 12:34:56.789  1234  5678 I ActivityManager: Start proc 4321:com.example/u0a123",
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(line.fields["pid"], "1234");
+        assert_eq!(line.fields["tid"], "5678");
+        assert_eq!(line.fields["priority"], "I");
+        assert_eq!(line.fields["level"], "info");
+        assert_eq!(line.fields["tag"], "ActivityManager");
+        assert_eq!(line.fields["msg"], "Start proc 4321:com.example/u0a123");
+        assert_eq!(
+            line.time,
+            Utc.with_ymd_and_hms(2024, 2, 29, 12, 34, 56)
+                .unwrap()
+                .timestamp_millis()
+                + 789
+        );
+    }
+
+    #[test]
+    fn test_padded_tag_and_year() {
+        let now = Utc::now();
+        let line = parse(
+            "2023-12-31 23:59:59.000   812   830 E chatty  : uid=1000(system) expire 3 lines",
+            now,
+        )
+        .unwrap();
+        assert_eq!(line.fields["tag"], "chatty");
+        assert_eq!(line.fields["level"], "error");
+        assert_eq!(
+            line.time,
+            Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59)
+                .unwrap()
+                .timestamp_millis()
+        );
+
+        let empty = parse("01-01 00:00:00.000  1  1 W Zygote:", now).unwrap();
+        assert_eq!(empty.fields["msg"], "");
+    }
+
+    #[test]
+    fn test_rejects_other_shapes() {
+        let now = Utc::now();
+        assert!(parse("01-01 00:00:00.000  1  1 X Tag: unknown priority", now).is_none());
+        assert!(parse("01-01 00:00:00.000  pid  1 I Tag: msg", now).is_none());
+        assert!(parse("I/ActivityManager( 1234): brief format", now).is_none());
+        assert!(parse("2024-01-01 12:00:00 INFO started", now).is_none());
+    }
+}
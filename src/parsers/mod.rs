@@ -1,15 +1,130 @@
+mod aws;
 mod color_utils;
+mod csv;
+mod haproxy;
+mod logcat;
+mod nginx_error;
+mod syslog;
+mod w3c;
 
-use crate::models::FieldData;
+use crate::metrics::MetricLabel;
+use crate::models::{BinaryEncoding, BinaryHint, FieldData, ParserCandidate, StructuredItem};
+use base64::prelude::{Engine, BASE64_STANDARD};
 use color_utils::{color_for_string, contrast_ratio};
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+/// Field names that may carry a producer-supplied timestamp, in priority order
+const TIME_KEYS: &[&str] = &[
+    "time",
+    "datetime",
+    "timestamp",
+    "logtime",
+    "eventtime",
+    "datestamp",
+    "ts",
+    "@timestamp",
+];
+
+/// Which parser, if any, understood a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseOutcome {
+    Json,
+    StructuredHeaders,
+    /// BSD syslog, RFC 3164
+    Syslog,
+    /// nginx's error log
+    NginxError,
+    /// HAProxy's HTTP log, on its own or in a syslog message
+    Haproxy,
+    /// Android logcat's threadtime format
+    Logcat,
+    /// AWS Application Load Balancer access log
+    Alb,
+    /// CloudFront standard log
+    CloudFront,
+    /// W3C extended log format, read against the bucket's `#Fields:` directive
+    W3c,
+    /// CSV or TSV rows, read against the bucket's header row
+    Csv,
+    /// The pre-RFC 8941 `key=value; key=value` format
+    LegacyStructuredHeaders,
+    /// A parser from the rules file
+    Custom,
+    /// One of the bucket's grok parsers
+    Grok,
+    /// The regex a producer registered for the bucket
+    Pattern,
+    Unparsed,
+}
+
+impl ParseOutcome {
+    pub const ALL: [ParseOutcome; 15] = [
+        ParseOutcome::Json,
+        ParseOutcome::StructuredHeaders,
+        ParseOutcome::Syslog,
+        ParseOutcome::NginxError,
+        ParseOutcome::Haproxy,
+        ParseOutcome::Logcat,
+        ParseOutcome::Alb,
+        ParseOutcome::CloudFront,
+        ParseOutcome::W3c,
+        ParseOutcome::Csv,
+        ParseOutcome::LegacyStructuredHeaders,
+        ParseOutcome::Custom,
+        ParseOutcome::Grok,
+        ParseOutcome::Pattern,
+        ParseOutcome::Unparsed,
+    ];
+}
+
+impl MetricLabel for ParseOutcome {
+    const NAME: &'static str = "parser";
+    const ALL: &'static [Self] = &ParseOutcome::ALL;
+
+    fn label(self) -> &'static str {
+        match self {
+            ParseOutcome::Json => "json",
+            ParseOutcome::StructuredHeaders => "structuredHeaders",
+            ParseOutcome::Syslog => "syslog",
+            ParseOutcome::NginxError => "nginxError",
+            ParseOutcome::Haproxy => "haproxy",
+            ParseOutcome::Logcat => "logcat",
+            ParseOutcome::Alb => "alb",
+            ParseOutcome::CloudFront => "cloudfront",
+            ParseOutcome::W3c => "w3c",
+            ParseOutcome::Csv => "csv",
+            ParseOutcome::LegacyStructuredHeaders => "legacy",
+            ParseOutcome::Custom => "custom",
+            ParseOutcome::Grok => "grok",
+            ParseOutcome::Pattern => "pattern",
+            ParseOutcome::Unparsed => "unparsed",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
 
 pub struct ParsedEvent {
     pub input_string: String,
     pub parser: Option<String>,
+    pub outcome: ParseOutcome,
     pub fields: HashMap<String, FieldData>,
     pub time: i64,
+    /// When the line says it was written, for formats whose timestamp isn't a plain field
+    pub reported_time: Option<i64>,
+    /// How sure the chosen parser was, from 0 to 1
+    pub confidence: Option<f32>,
+    /// Parsers that also matched but lost out on priority
+    pub candidates: Vec<ParserCandidate>,
+    /// The bucket's columns, from its last W3C `#Fields:` directive or CSV header row
+    columns: Option<Arc<Columns>>,
 }
 
 impl ParsedEvent {
@@ -17,84 +132,541 @@ impl ParsedEvent {
         Self {
             input_string,
             parser: None,
+            outcome: ParseOutcome::Unparsed,
+            confidence: None,
+            candidates: Vec::new(),
             fields: HashMap::new(),
             time: chrono::Utc::now().timestamp_millis(),
+            reported_time: None,
+            columns: None,
         }
     }
 
+    /// Read lines that fit them against a W3C `#Fields:` directive or CSV header row
+    pub fn with_columns(mut self, columns: Option<Arc<Columns>>) -> Self {
+        self.columns = columns;
+        self
+    }
+
     pub fn parse(&mut self) {
         // Try JSON parser first
+        // A JSON object can't be mistaken for anything else
         if let Some(data) = parse_json(&self.input_string) {
             self.parser = Some("json".to_string());
+            self.outcome = ParseOutcome::Json;
+            self.confidence = Some(1.0);
             self.fields = create_fields(data);
             return;
         }
 
+        // A `<PRI>` prefix followed by a BSD timestamp is unmistakably syslog
+        let received = chrono::DateTime::from_timestamp_millis(self.time).unwrap_or_default();
+        if let Some(mut message) = syslog::parse(&self.input_string, received) {
+            // HAProxy usually logs through syslog, with the request line as the message
+            if let Some(request) = message
+                .fields
+                .get("msg")
+                .and_then(|msg| haproxy::parse(msg))
+            {
+                message.fields.remove("msg");
+                message.fields.extend(request.fields);
+                self.parser = Some("haproxy".to_string());
+                self.outcome = ParseOutcome::Haproxy;
+                self.confidence = Some(1.0);
+                self.reported_time = Some(request.time);
+                self.fields = create_fields(message.fields);
+                return;
+            }
+            self.parser = Some("syslog".to_string());
+            self.outcome = ParseOutcome::Syslog;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(message.time);
+            self.fields = create_fields(message.fields);
+            return;
+        }
+
+        // HAProxy lines are a long run of fixed fields, down to the timers' slashes
+        if let Some(request) = haproxy::parse(&self.input_string) {
+            self.parser = Some("haproxy".to_string());
+            self.outcome = ParseOutcome::Haproxy;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(request.time);
+            self.fields = create_fields(request.fields);
+            return;
+        }
+
+        // As is nginx's `yyyy/mm/dd hh:mm:ss [level] pid#tid: ` prefix
+        if let Some(error) = nginx_error::parse(&self.input_string) {
+            self.parser = Some("nginxError".to_string());
+            self.outcome = ParseOutcome::NginxError;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(error.time);
+            self.fields = create_fields(error.fields);
+            return;
+        }
+
+        // And logcat's `mm-dd hh:mm:ss.mmm pid tid L tag: `
+        if let Some(line) = logcat::parse(&self.input_string, received) {
+            self.parser = Some("logcat".to_string());
+            self.outcome = ParseOutcome::Logcat;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(line.time);
+            self.fields = create_fields(line.fields);
+            return;
+        }
+
+        // AWS access logs are recognized by their leading fields and their field count
+        if let Some(log) = aws::parse_alb(&self.input_string) {
+            self.parser = Some("alb".to_string());
+            self.outcome = ParseOutcome::Alb;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(log.time);
+            self.fields = create_fields(log.fields);
+            return;
+        }
+        if let Some(log) = aws::parse_cloudfront(&self.input_string) {
+            self.parser = Some("cloudfront".to_string());
+            self.outcome = ParseOutcome::CloudFront;
+            self.confidence = Some(1.0);
+            self.reported_time = Some(log.time);
+            self.fields = create_fields(log.fields);
+            return;
+        }
+
+        if let Some(directive) = w3c::directive(&self.input_string) {
+            self.parser = Some("w3c".to_string());
+            self.outcome = ParseOutcome::W3c;
+            self.confidence = Some(1.0);
+            self.fields = create_fields(HashMap::from([
+                ("directive".to_string(), directive.name.to_string()),
+                ("value".to_string(), directive.value.to_string()),
+            ]));
+            return;
+        }
+
+        // Ahead of the key=value parsers, which would take a query string column for theirs
+        let entry = self
+            .columns
+            .as_ref()
+            .filter(|columns| columns.delimiter.is_none())
+            .and_then(|columns| w3c::parse(&self.input_string, &columns.names));
+        if let Some(entry) = entry {
+            self.parser = Some("w3c".to_string());
+            self.outcome = ParseOutcome::W3c;
+            // Any line with the right number of words fits
+            self.confidence = Some(0.8);
+            self.reported_time = entry.time;
+            self.fields = create_fields(entry.fields);
+            return;
+        }
+        let row = self
+            .columns
+            .as_ref()
+            .and_then(|columns| csv::parse(&self.input_string, columns.delimiter?, &columns.names));
+        if let Some(row) = row {
+            self.parser = Some("csv".to_string());
+            self.outcome = ParseOutcome::Csv;
+            // Any line with the right number of delimiters fits
+            self.confidence = Some(0.8);
+            self.fields = create_fields(row);
+            return;
+        }
+
         // Try HTTP Structured Headers parser
         // Note: This is a simplified version. For full HTTP-SH support,
         // you'd need to implement or use a proper parser crate
         if let Some(data) = parse_structured_headers(&self.input_string) {
             self.parser = Some("structuredHeaders".to_string());
+            self.outcome = ParseOutcome::StructuredHeaders;
+            self.confidence = Some(data.confidence);
+            // Many legacy lines are also valid RFC 8941, so report when it could be either
+            if let Some(confidence) = legacy_confidence(&self.input_string) {
+                self.candidates.push(ParserCandidate {
+                    parser: ParseOutcome::LegacyStructuredHeaders.label().to_string(),
+                    confidence,
+                });
+            }
+            self.fields = data.into_fields();
+            return;
+        }
+
+        // Fallback: legacy semicolon-separated format (not RFC-compliant but previously supported)
+        if let Some(data) = parse_legacy_structured_headers(&self.input_string) {
+            self.parser = Some("structuredHeaders".to_string());
+            self.outcome = ParseOutcome::LegacyStructuredHeaders;
+            self.confidence = legacy_confidence(&self.input_string);
             self.fields = create_fields(data);
             return;
         }
 
         // No parser matched
         self.parser = None;
+        self.outcome = ParseOutcome::Unparsed;
         self.fields = HashMap::new();
     }
+
+    /// Timestamp embedded in the parsed fields, in epoch milliseconds
+    pub fn embedded_time(&self) -> Option<i64> {
+        self.reported_time.or_else(|| {
+            TIME_KEYS
+                .iter()
+                .filter_map(|key| self.fields.get(*key))
+                .find_map(|field| parse_timestamp(&field.value))
+        })
+    }
+
+    /// Lowercase field keys and replace `-` with `_`, so producers that spell a key
+    /// differently share one field. When keys collide, the first in sorted order wins.
+    pub fn normalize_keys(&mut self) {
+        let mut keys: Vec<String> = self.fields.keys().cloned().collect();
+        keys.sort();
+
+        let mut fields = HashMap::new();
+        for key in keys {
+            let field = self.fields.remove(&key).unwrap();
+            if let Entry::Vacant(entry) = fields.entry(normalize_key(&key)) {
+                let color = color_for_string(entry.key());
+                entry.insert(FieldData {
+                    contrast: contrast_ratio(&color, "#000000"),
+                    color,
+                    ..field
+                });
+            }
+        }
+        self.fields = fields;
+    }
+}
+
+/// Column names a bucket's lines are read against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Columns {
+    pub names: Vec<String>,
+    /// What separates a CSV row's values, or `None` for W3C lines
+    pub delimiter: Option<char>,
+}
+
+/// The columns a W3C `#Fields:` directive line sets, if it is one
+pub fn w3c_columns(input: &str) -> Option<Columns> {
+    Some(Columns {
+        names: w3c::directive(input)?.fields()?,
+        delimiter: None,
+    })
+}
+
+/// The columns a CSV header row names, if the line reads as one. Without a delimiter, the
+/// likeliest of comma, tab, semicolon and pipe is used.
+pub fn csv_header(input: &str, delimiter: Option<char>) -> Option<Columns> {
+    let (delimiter, names) = csv::header(input, delimiter)?;
+    Some(Columns {
+        names,
+        delimiter: Some(delimiter),
+    })
+}
+
+fn normalize_key(key: &str) -> String {
+    key.to_lowercase().replace('-', "_")
+}
+
+/// Whether a field is a plain run of digits, like a pid or a status code
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parse an epoch (seconds or milliseconds) or RFC 3339 timestamp into epoch milliseconds
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    if let Ok(number) = value.parse::<f64>() {
+        // A time that is too small to be in milliseconds is treated as seconds
+        return if number < 100_000_000_000.0 {
+            Some((number * 1000.0) as i64)
+        } else {
+            Some(number as i64)
+        };
+    }
+
+    chrono::DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|dt| dt.timestamp_millis())
+}
+
+/// Insert a field without overwriting: repeats of `key` become `key.2`, `key.3`, ...
+/// in the order they were seen. Returns the key the value ended up under.
+fn insert_unique(result: &mut HashMap<String, String>, key: String, value: String) -> String {
+    let key = match result.entry(key) {
+        Entry::Vacant(entry) => {
+            let key = entry.key().clone();
+            entry.insert(value);
+            return key;
+        }
+        Entry::Occupied(entry) => entry.key().clone(),
+    };
+
+    let suffixed = (2..)
+        .map(|n| format!("{}.{}", key, n))
+        .find(|candidate| !result.contains_key(candidate))
+        .unwrap();
+    result.insert(suffixed.clone(), value);
+    suffixed
+}
+
+/// The members of a top-level JSON object in document order, keeping repeated keys
+/// that `serde_json::Map` would collapse
+struct JsonMembers(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for JsonMembers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MembersVisitor;
+
+        impl<'de> Visitor<'de> for MembersVisitor {
+            type Value = JsonMembers;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON object")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonMembers, A::Error> {
+                let mut members = Vec::new();
+                while let Some(member) = map.next_entry()? {
+                    members.push(member);
+                }
+                Ok(JsonMembers(members))
+            }
+        }
+
+        deserializer.deserialize_map(MembersVisitor)
+    }
 }
 
 fn parse_json(input: &str) -> Option<HashMap<String, String>> {
-    serde_json::from_str::<Value>(input).ok().and_then(|v| {
-        if let Value::Object(map) = v {
-            let mut result = HashMap::new();
-            for (key, value) in map {
-                let value_str = match value {
-                    Value::String(s) => s,
-                    Value::Number(n) => n.to_string(),
-                    Value::Bool(b) => b.to_string(),
-                    Value::Null => "null".to_string(),
-                    _ => serde_json::to_string(&value).unwrap_or_default(),
-                };
-                result.insert(key, value_str);
+    let JsonMembers(members) = serde_json::from_str(input).ok()?;
+    let mut result = HashMap::new();
+    for (key, value) in members {
+        let value_str = match value {
+            Value::String(s) => s,
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Null => "null".to_string(),
+            _ => serde_json::to_string(&value).unwrap_or_default(),
+        };
+        insert_unique(&mut result, key, value_str);
+    }
+    Some(result)
+}
+
+/// Field values from a parser, plus detail about some of them that a plain string loses
+#[derive(Default)]
+struct ParsedFields {
+    values: HashMap<String, String>,
+    /// List structure behind a field's value
+    items: Vec<(String, Vec<StructuredItem>)>,
+    /// Fields whose value is encoded binary data
+    binary: Vec<(String, BinaryHint)>,
+    /// How well the line fit the parser's format, from 0 to 1
+    confidence: f32,
+}
+
+impl ParsedFields {
+    /// Insert a field without overwriting, noting when it holds binary data
+    fn insert_bare(&mut self, key: String, item: &sfv::BareItem) -> String {
+        let key = insert_unique(&mut self.values, key, bare_item_to_string(item));
+        if let sfv::BareItem::ByteSequence(bytes) = item {
+            self.mark_binary(key.clone(), bytes.len());
+        }
+        key
+    }
+
+    /// Note that `key` holds `length` bytes of base64, e.g. an RFC 8941 byte sequence
+    fn mark_binary(&mut self, key: String, length: usize) {
+        self.binary.push((
+            key,
+            BinaryHint {
+                encoding: BinaryEncoding::Base64,
+                length,
+            },
+        ));
+    }
+
+    fn into_fields(self) -> HashMap<String, FieldData> {
+        let mut fields = create_fields(self.values);
+        for (key, items) in self.items {
+            if let Some(field) = fields.get_mut(&key) {
+                field.items = Some(items);
             }
-            Some(result)
-        } else {
-            None
         }
-    })
+        for (key, hint) in self.binary {
+            if let Some(field) = fields.get_mut(&key) {
+                field.binary = Some(hint);
+            }
+        }
+        fields
+    }
+}
+
+fn structured_params(params: &sfv::Parameters) -> BTreeMap<String, String> {
+    params
+        .iter()
+        .map(|(key, value)| (key.to_string(), bare_item_to_string(value)))
+        .collect()
+}
+
+fn structured_item(item: &sfv::Item) -> StructuredItem {
+    StructuredItem {
+        value: bare_item_to_string(&item.bare_item),
+        items: Vec::new(),
+        params: structured_params(&item.params),
+    }
+}
+
+fn structured_entry(entry: &sfv::ListEntry) -> StructuredItem {
+    match entry {
+        sfv::ListEntry::Item(item) => structured_item(item),
+        sfv::ListEntry::InnerList(inner) => StructuredItem {
+            value: inner_list_to_string(inner),
+            items: inner.items.iter().map(structured_item).collect(),
+            params: structured_params(&inner.params),
+        },
+    }
+}
+
+fn inner_list_to_string(inner: &sfv::InnerList) -> String {
+    inner
+        .items
+        .iter()
+        .map(|i| bare_item_to_string(&i.bare_item))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn insert_dictionary_member(result: &mut ParsedFields, key: &str, member: &sfv::ListEntry) {
+    let has_structure = match member {
+        sfv::ListEntry::Item(item) => !item.params.is_empty(),
+        sfv::ListEntry::InnerList(_) => true,
+    };
+
+    let key = match member {
+        sfv::ListEntry::Item(item) => {
+            let key = result.insert_bare(key.to_string(), &item.bare_item);
+            // Also extract parameters as separate fields
+            for (param_key, param_val) in item.params.iter() {
+                result.insert_bare(param_key.to_string(), param_val);
+            }
+            key
+        }
+        sfv::ListEntry::InnerList(inner) => {
+            let key = insert_unique(
+                &mut result.values,
+                key.to_string(),
+                inner_list_to_string(inner),
+            );
+            // Also extract inner list parameters
+            for (param_key, param_val) in inner.params.iter() {
+                result.insert_bare(param_key.to_string(), param_val);
+            }
+            key
+        }
+    };
+
+    // Flattened parameters lose track of which member they belonged to
+    if has_structure {
+        result.items.push((key, vec![structured_entry(member)]));
+    }
+}
+
+/// Split a dictionary into its members on top-level commas, ignoring commas in strings
+fn split_dictionary_members(input: &str) -> Vec<&str> {
+    let mut members = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                members.push(input[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    members.push(input[start..].trim());
+    members
+}
+
+/// Round a confidence score to two decimal places
+fn confidence(score: f64) -> f32 {
+    ((score.clamp(0.0, 1.0) * 100.0).round() / 100.0) as f32
+}
+
+/// Legacy lines separate pairs with `; `, which RFC 8941 reads as parameters
+fn looks_legacy(input: &str) -> bool {
+    input.contains("; ")
+}
+
+/// How much a line that parsed as an SH dictionary looks like one. Bare keys parse as
+/// `true`, so a line of plain words is a valid dictionary but an unlikely one.
+fn dictionary_confidence(input: &str) -> f32 {
+    let members = split_dictionary_members(input);
+    let explicit = members
+        .iter()
+        .filter(|member| {
+            member
+                .split(';')
+                .next()
+                .is_some_and(|key| key.contains('='))
+        })
+        .count();
+    let mut score = 0.3 + 0.6 * explicit as f64 / members.len() as f64;
+    if looks_legacy(input) {
+        score -= 0.3;
+    }
+    confidence(score.max(0.1))
 }
 
-fn parse_structured_headers(input: &str) -> Option<HashMap<String, String>> {
+/// How much a line looks like the legacy `key=value; key=value` format, or `None` if the
+/// legacy parser wouldn't take it
+fn legacy_confidence(input: &str) -> Option<f32> {
+    if !input.contains('=') {
+        return None;
+    }
+
+    let pairs = input.split(';').collect::<Vec<_>>();
+    let explicit = pairs.iter().filter(|pair| pair.contains('=')).count();
+    let mut score = 0.2 + 0.4 * explicit as f64 / pairs.len() as f64;
+    if looks_legacy(input) {
+        score += 0.2;
+    }
+    Some(confidence(score))
+}
+
+fn parse_structured_headers(input: &str) -> Option<ParsedFields> {
     // Try parsing as a Dictionary (most common for structured logs)
     if let Ok(dict) = sfv::Parser::new(input).parse::<sfv::Dictionary>() {
-        let mut result = HashMap::new();
-        for (key, member) in dict.iter() {
-            match member {
-                sfv::ListEntry::Item(item) => {
-                    result.insert(key.to_string(), bare_item_to_string(&item.bare_item));
-                    // Also extract parameters as separate fields
-                    for (param_key, param_val) in item.params.iter() {
-                        result.insert(param_key.to_string(), bare_item_to_string(param_val));
-                    }
+        let mut result = ParsedFields {
+            confidence: dictionary_confidence(input),
+            ..Default::default()
+        };
+
+        // RFC 8941 keeps only the last of a repeated key, so parse members one at a time
+        // to hold on to every value
+        let members: Option<Vec<sfv::Dictionary>> = split_dictionary_members(input)
+            .into_iter()
+            .map(|member| sfv::Parser::new(member).parse::<sfv::Dictionary>().ok())
+            .collect();
+        match members {
+            Some(members) => {
+                for (key, member) in members.iter().flat_map(|member| member.iter()) {
+                    insert_dictionary_member(&mut result, key.as_str(), member);
                 }
-                sfv::ListEntry::InnerList(inner) => {
-                    let value = inner
-                        .items
-                        .iter()
-                        .map(|i| bare_item_to_string(&i.bare_item))
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    result.insert(key.to_string(), value);
-                    // Also extract inner list parameters
-                    for (param_key, param_val) in inner.params.iter() {
-                        result.insert(param_key.to_string(), bare_item_to_string(param_val));
-                    }
+            }
+            None => {
+                for (key, member) in dict.iter() {
+                    insert_dictionary_member(&mut result, key.as_str(), member);
                 }
             }
         }
-        if !result.is_empty() {
+
+        if !result.values.is_empty() {
             return Some(result);
         }
     }
@@ -108,40 +680,42 @@ fn parse_structured_headers(input: &str) -> Option<HashMap<String, String>> {
             });
 
         if has_structure {
-            let mut result = HashMap::new();
-            for (idx, entry) in list.iter().enumerate() {
-                let value = match entry {
-                    sfv::ListEntry::Item(item) => bare_item_to_string(&item.bare_item),
-                    sfv::ListEntry::InnerList(inner) => inner
-                        .items
-                        .iter()
-                        .map(|i| bare_item_to_string(&i.bare_item))
-                        .collect::<Vec<_>>()
-                        .join(", "),
-                };
-                result.insert(format!("item{}", idx), value);
-            }
-            if !result.is_empty() {
-                return Some(result);
-            }
+            // One field for the whole list, keeping each member's parameters with it
+            let items: Vec<StructuredItem> = list.iter().map(structured_entry).collect();
+            let value = items
+                .iter()
+                .map(|item| item.value.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Some(ParsedFields {
+                values: HashMap::from([("list".to_string(), value)]),
+                items: vec![("list".to_string(), items)],
+                confidence: 0.6,
+                ..Default::default()
+            });
         }
     }
 
     // Try parsing as a single Item (only if it has parameters, otherwise it's just plain text)
     if let Ok(item) = sfv::Parser::new(input).parse::<sfv::Item>() {
         if !item.params.is_empty() {
-            let mut result = HashMap::new();
-            result.insert("value".to_string(), bare_item_to_string(&item.bare_item));
+            let mut result = ParsedFields {
+                confidence: 0.5,
+                ..Default::default()
+            };
+            result.insert_bare("value".to_string(), &item.bare_item);
             // Include parameters as additional fields
             for (key, val) in item.params.iter() {
-                result.insert(key.to_string(), bare_item_to_string(val));
+                result.insert_bare(key.to_string(), val);
             }
+            result
+                .items
+                .push(("value".to_string(), vec![structured_item(&item)]));
             return Some(result);
         }
     }
 
-    // Fallback: legacy semicolon-separated format (not RFC-compliant but previously supported)
-    parse_legacy_structured_headers(input)
+    None
 }
 
 fn parse_legacy_structured_headers(input: &str) -> Option<HashMap<String, String>> {
@@ -168,7 +742,7 @@ fn parse_legacy_structured_headers(input: &str) -> Option<HashMap<String, String
                 value.to_string()
             };
 
-            result.insert(key, value);
+            insert_unique(&mut result, key, value);
         }
     }
 
@@ -185,37 +759,14 @@ fn bare_item_to_string(item: &sfv::BareItem) -> String {
         sfv::BareItem::Decimal(d) => d.to_string(),
         sfv::BareItem::String(s) => s.to_string(),
         sfv::BareItem::Token(t) => t.to_string(),
-        sfv::BareItem::ByteSequence(b) => base64_encode(b),
+        sfv::BareItem::ByteSequence(b) => BASE64_STANDARD.encode(b),
         sfv::BareItem::Boolean(b) => if *b { "true" } else { "false" }.to_string(),
         sfv::BareItem::Date(d) => d.to_string(),
         sfv::BareItem::DisplayString(s) => s.to_string(),
     }
 }
 
-fn base64_encode(bytes: &[u8]) -> String {
-    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-    let mut result = String::new();
-    for chunk in bytes.chunks(3) {
-        let b0 = chunk[0] as usize;
-        let b1 = chunk.get(1).copied().unwrap_or(0) as usize;
-        let b2 = chunk.get(2).copied().unwrap_or(0) as usize;
-        result.push(ALPHABET[b0 >> 2] as char);
-        result.push(ALPHABET[((b0 & 0x03) << 4) | (b1 >> 4)] as char);
-        if chunk.len() > 1 {
-            result.push(ALPHABET[((b1 & 0x0f) << 2) | (b2 >> 6)] as char);
-        } else {
-            result.push('=');
-        }
-        if chunk.len() > 2 {
-            result.push(ALPHABET[b2 & 0x3f] as char);
-        } else {
-            result.push('=');
-        }
-    }
-    result
-}
-
-fn create_fields(data: HashMap<String, String>) -> HashMap<String, FieldData> {
+pub fn create_fields(data: HashMap<String, String>) -> HashMap<String, FieldData> {
     data.into_iter()
         .map(|(key, value)| {
             let color = color_for_string(&key);
@@ -226,6 +777,8 @@ fn create_fields(data: HashMap<String, String>) -> HashMap<String, FieldData> {
                     value,
                     color,
                     contrast,
+                    items: None,
+                    binary: None,
                 },
             )
         })
@@ -271,6 +824,159 @@ mod tests {
         assert_eq!(event.fields.get("message").unwrap().value, "test message");
     }
 
+    #[test]
+    fn test_duplicate_keys() {
+        let mut event = ParsedEvent::new(r#"{"tag":"a","tag":"b","tag.2":"c"}"#.to_string());
+        event.parse();
+        assert_eq!(event.fields["tag"].value, "a");
+        assert_eq!(event.fields["tag.2"].value, "b");
+        assert_eq!(event.fields["tag.2.2"].value, "c");
+
+        let mut event = ParsedEvent::new(r#"tag=a, msg="x, y", tag=b"#.to_string());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::StructuredHeaders);
+        assert_eq!(event.fields["tag"].value, "a");
+        assert_eq!(event.fields["tag.2"].value, "b");
+        assert_eq!(event.fields["msg"].value, "x, y");
+
+        let mut event = ParsedEvent::new("tag=a; tag=b c".to_string());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::LegacyStructuredHeaders);
+        assert_eq!(event.fields["tag"].value, "a");
+        assert_eq!(event.fields["tag.2"].value, "b c");
+    }
+
+    #[test]
+    fn test_structured_headers_list() {
+        let mut event = ParsedEvent::new(r#"gzip;q=1, br;q=0.5, (a b);lvl=2"#.to_string());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::StructuredHeaders);
+
+        let list = &event.fields["list"];
+        assert_eq!(list.value, "gzip, br, a, b");
+        let items = list.items.as_ref().unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].value, "gzip");
+        assert_eq!(items[1].params["q"], "0.5");
+        assert_eq!(items[2].items.len(), 2);
+        assert_eq!(items[2].params["lvl"], "2");
+
+        // Dictionary members with parameters keep them alongside the flattened fields
+        let mut event = ParsedEvent::new("status=500;retry, level=error".to_string());
+        event.parse();
+        event.normalize_keys();
+        let items = event.fields["status"].items.as_ref().unwrap();
+        assert_eq!(items[0].params["retry"], "true");
+        assert!(event.fields["level"].items.is_none());
+    }
+
+    #[test]
+    fn test_binary_fields() {
+        let mut event = ParsedEvent::new("level=info, body=:aGVsbG8=:;sig=:AQID:".to_string());
+        event.parse();
+
+        assert_eq!(event.fields["body"].value, "aGVsbG8=");
+        let hint = event.fields["body"].binary.unwrap();
+        assert_eq!(hint.encoding, BinaryEncoding::Base64);
+        assert_eq!(hint.length, 5);
+        assert_eq!(event.fields["sig"].value, "AQID");
+        assert_eq!(event.fields["sig"].binary.unwrap().length, 3);
+        assert!(event.fields["level"].binary.is_none());
+    }
+
+    #[test]
+    fn test_normalize_keys() {
+        let mut event =
+            ParsedEvent::new(r#"{"Content-Type":"text/html","User_ID":"42"}"#.to_string());
+        event.parse();
+        event.normalize_keys();
+
+        assert_eq!(event.fields["content_type"].value, "text/html");
+        assert_eq!(event.fields["user_id"].value, "42");
+        // Colors follow the normalized key
+        assert_eq!(
+            event.fields["content_type"].color,
+            color_for_string("content_type")
+        );
+
+        let mut event = ParsedEvent::new(r#"{"content_type":"b","Content-Type":"a"}"#.to_string());
+        event.parse();
+        event.normalize_keys();
+        assert_eq!(event.fields.len(), 1);
+        assert_eq!(event.fields["content_type"].value, "a");
+    }
+
+    #[test]
+    fn test_parse_outcomes() {
+        let outcome = |input: &str| {
+            let mut event = ParsedEvent::new(input.to_string());
+            event.parse();
+            event.outcome
+        };
+
+        assert_eq!(outcome(r#"{"level":"info"}"#), ParseOutcome::Json);
+        assert_eq!(
+            outcome("level=info, user=alice"),
+            ParseOutcome::StructuredHeaders
+        );
+        // Unquoted spaces aren't valid RFC 8941, so only the legacy parser accepts this
+        assert_eq!(
+            outcome("level=info; message=disk full"),
+            ParseOutcome::LegacyStructuredHeaders
+        );
+        assert_eq!(outcome("just some text"), ParseOutcome::Unparsed);
+    }
+
+    #[test]
+    fn test_parser_confidence() {
+        let parse = |input: &str| {
+            let mut event = ParsedEvent::new(input.to_string());
+            event.parse();
+            event
+        };
+
+        let event = parse(r#"{"level":"info"}"#);
+        assert_eq!(event.confidence, Some(1.0));
+        assert!(event.candidates.is_empty());
+
+        let event = parse("level=info, user=alice");
+        assert_eq!(event.confidence, Some(0.9));
+        assert_eq!(event.candidates[0].parser, "legacy");
+
+        // A lone word is a valid dictionary, but not a convincing one
+        let event = parse("hello");
+        assert_eq!(event.outcome, ParseOutcome::StructuredHeaders);
+        assert_eq!(event.confidence, Some(0.3));
+        assert!(event.candidates.is_empty());
+
+        // A legacy line that RFC 8941 also accepts leaves the runner-up more confident
+        let event = parse(r#"level=info; message="test message"; timestamp=1"#);
+        assert_eq!(event.outcome, ParseOutcome::StructuredHeaders);
+        assert_eq!(event.confidence, Some(0.6));
+        assert_eq!(event.candidates[0].confidence, 0.8);
+
+        let event = parse("level=info; message=disk full");
+        assert_eq!(event.outcome, ParseOutcome::LegacyStructuredHeaders);
+        assert_eq!(event.confidence, Some(0.8));
+
+        assert_eq!(parse("just some text").confidence, None);
+    }
+
+    #[test]
+    fn test_embedded_time() {
+        let mut event = ParsedEvent::new(r#"{"timestamp":1700000000}"#.to_string());
+        event.parse();
+        assert_eq!(event.embedded_time(), Some(1_700_000_000_000));
+
+        let mut event = ParsedEvent::new(r#"{"time":"2024-01-01T00:00:01.5Z"}"#.to_string());
+        event.parse();
+        assert_eq!(event.embedded_time(), Some(1_704_067_201_500));
+
+        let mut event = ParsedEvent::new(r#"{"message":"no time here"}"#.to_string());
+        event.parse();
+        assert_eq!(event.embedded_time(), None);
+    }
+
     #[test]
     fn test_plain_text_not_parsed_as_structured() {
         // Plain text should not be parsed as structured data
@@ -281,4 +987,110 @@ mod tests {
         assert_eq!(event.parser, None);
         assert!(event.fields.is_empty());
     }
+
+    #[test]
+    fn test_syslog_parser() {
+        let mut event =
+            ParsedEvent::new("<86>Mar  4 09:10:11 gw sshd[42]: Accepted key".to_string());
+        event.parse();
+
+        assert_eq!(event.outcome, ParseOutcome::Syslog);
+        assert_eq!(event.parser, Some("syslog".to_string()));
+        assert_eq!(event.fields["severity"].value, "info");
+        assert_eq!(event.fields["tag"].value, "sshd");
+        assert!(event.embedded_time().is_some());
+    }
+
+    #[test]
+    fn test_nginx_error_parser() {
+        let mut event = ParsedEvent::new(
+            "2024/01/01 12:00:00 [warn] 7#7: *3 an upstream response is buffered, client: 10.0.0.1"
+                .to_string(),
+        );
+        event.parse();
+
+        assert_eq!(event.outcome, ParseOutcome::NginxError);
+        assert_eq!(event.fields["level"].value, "warn");
+        assert_eq!(event.fields["client"].value, "10.0.0.1");
+        assert!(event.embedded_time().is_some());
+    }
+
+    #[test]
+    fn test_haproxy_parser() {
+        let line = r#"10.0.1.2:33317 [06/Feb/2009:12:14:14.655] http-in static/srv1 10/0/30/69/109 200 2750 - - ---- 1/1/1/1/0 0/0 "GET /index.html HTTP/1.1""#;
+        let mut event = ParsedEvent::new(line.to_string());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::Haproxy);
+        assert_eq!(event.fields["Tt"].value, "109");
+        assert!(event.embedded_time().is_some());
+
+        // Through syslog, the syslog fields stay alongside the request's
+        let mut event = ParsedEvent::new(format!(
+            "<134>Feb  6 12:14:14 lb-1 haproxy[14389]: {}",
+            line
+        ));
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::Haproxy);
+        assert_eq!(event.fields["host"].value, "lb-1");
+        assert_eq!(event.fields["status"].value, "200");
+        assert!(!event.fields.contains_key("msg"));
+    }
+
+    #[test]
+    fn test_logcat_parser() {
+        let mut event = ParsedEvent::new(
+            "01-15 08:00:01.250  2211  2298 W OkHttp  : Retrying request to api.example.com"
+                .to_string(),
+        );
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::Logcat);
+        assert_eq!(event.fields["level"].value, "warn");
+        assert_eq!(event.fields["tag"].value, "OkHttp");
+        assert!(event.embedded_time().is_some());
+    }
+
+    #[test]
+    fn test_w3c_parser() {
+        let columns = w3c_columns("#Fields: date time cs-uri-stem cs-uri-query sc-status");
+        let columns = Some(Arc::new(columns.unwrap()));
+
+        let mut event = ParsedEvent::new("2024-01-01 12:00:00 /search q=logs 200".to_string())
+            .with_columns(columns.clone());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::W3c);
+        assert_eq!(event.fields["cs-uri-query"].value, "q=logs");
+        assert!(event.embedded_time().is_some());
+
+        // Lines that don't fit the columns go to the other parsers
+        let mut event = ParsedEvent::new("status=ok".to_string()).with_columns(columns);
+        event.parse();
+        assert_ne!(event.outcome, ParseOutcome::W3c);
+
+        let mut event = ParsedEvent::new("#Software: Microsoft IIS 10.0".to_string());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::W3c);
+        assert_eq!(event.fields["value"].value, "Microsoft IIS 10.0");
+    }
+
+    #[test]
+    fn test_csv_parser() {
+        let columns = Some(Arc::new(csv_header("time,level,msg", None).unwrap()));
+
+        let mut event = ParsedEvent::new("2024-01-01T12:00:00Z,info,\"ok, done\"".to_string())
+            .with_columns(columns.clone());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::Csv);
+        assert_eq!(event.fields["msg"].value, "ok, done");
+        assert!(event.embedded_time().is_some());
+
+        // JSON is still JSON, commas and all
+        let mut event =
+            ParsedEvent::new(r#"{"a":1,"b":2,"c":3}"#.to_string()).with_columns(columns.clone());
+        event.parse();
+        assert_eq!(event.outcome, ParseOutcome::Json);
+
+        let mut event = ParsedEvent::new("one,two".to_string()).with_columns(columns);
+        event.parse();
+        assert_ne!(event.outcome, ParseOutcome::Csv);
+    }
 }
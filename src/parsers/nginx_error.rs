@@ -0,0 +1,157 @@
+//! nginx error log: `yyyy/mm/dd hh:mm:ss [level] pid#tid: *cid message, key: value, ...`
+
+use super::is_number;
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// Levels nginx writes, from `error_log`'s documentation
+const LEVELS: [&str; 8] = [
+    "debug", "info", "notice", "warn", "error", "crit", "alert", "emerg",
+];
+
+/// `2024/01/01 12:00:00`
+const TIMESTAMP_LENGTH: usize = 19;
+
+pub struct NginxError {
+    pub fields: HashMap<String, String>,
+    /// When the line was written, in epoch milliseconds
+    pub time: i64,
+}
+
+/// Parse an nginx error log line. nginx writes local time without a zone, so times are
+/// taken as UTC, as for syslog.
+pub fn parse(input: &str) -> Option<NginxError> {
+    let timestamp = input.get(..TIMESTAMP_LENGTH)?;
+    let time = NaiveDateTime::parse_from_str(timestamp, "%Y/%m/%d %H:%M:%S").ok()?;
+    let rest = input[TIMESTAMP_LENGTH..].strip_prefix(" [")?;
+
+    let (level, rest) = rest.split_once("] ")?;
+    if !LEVELS.contains(&level) {
+        return None;
+    }
+    let (pid, rest) = rest.split_once('#')?;
+    let (tid, rest) = rest.split_once(": ")?;
+    if !is_number(pid) || !is_number(tid) {
+        return None;
+    }
+
+    let mut fields = HashMap::from([
+        ("timestamp".to_string(), timestamp.to_string()),
+        ("level".to_string(), level.to_string()),
+        ("pid".to_string(), pid.to_string()),
+        ("tid".to_string(), tid.to_string()),
+    ]);
+
+    // Messages about a client connection start with its number
+    let message = match rest.strip_prefix('*').and_then(|rest| rest.split_once(' ')) {
+        Some((connection, message)) if is_number(connection) => {
+            fields.insert("connection".to_string(), connection.to_string());
+            message
+        }
+        _ => rest,
+    };
+
+    let message = match split_context(message) {
+        Some((message, context)) => {
+            for (key, value) in context {
+                fields.entry(key.to_string()).or_insert(value.to_string());
+            }
+            message
+        }
+        None => message,
+    };
+    fields.insert("msg".to_string(), message.to_string());
+
+    Some(NginxError {
+        fields,
+        time: Utc.from_utc_datetime(&time).timestamp_millis(),
+    })
+}
+
+/// Split off the `, client: ..., request: "..."` context nginx appends to a message. The
+/// message itself can contain commas and colons, so the context starts at the first
+/// `, key: ` from which the rest of the line reads as context.
+fn split_context(message: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    message
+        .match_indices(", ")
+        .find_map(|(i, _)| Some((&message[..i], parse_context(&message[i..])?)))
+}
+
+fn parse_context(mut rest: &str) -> Option<Vec<(&str, &str)>> {
+    let mut pairs = Vec::new();
+    while !rest.is_empty() {
+        let (key, after) = rest.strip_prefix(", ")?.split_once(": ")?;
+        if key.is_empty() || !key.bytes().all(|b| b.is_ascii_lowercase() || b == b'_') {
+            return None;
+        }
+
+        // Values with spaces, such as the request line, are quoted
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, after) = quoted.split_once('"')?;
+                if !after.is_empty() && !after.starts_with(", ") {
+                    return None;
+                }
+                (value, after)
+            }
+            None => match after.find(", ") {
+                Some(end) => after.split_at(end),
+                None => (after, ""),
+            },
+        };
+        pairs.push((key, value));
+        rest = after;
+    }
+    Some(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let line = r#"2024/01/01 12:00:00 [error] 123#0: *45 open() "/srv/www/favicon.ico" failed (2: No such file or directory), client: 10.0.0.7, server: example.com, request: "GET /favicon.ico HTTP/1.1", host: "example.com""#;
+        let error = parse(line).unwrap();
+
+        assert_eq!(error.fields["level"], "error");
+        assert_eq!(error.fields["pid"], "123");
+        assert_eq!(error.fields["tid"], "0");
+        assert_eq!(error.fields["connection"], "45");
+        assert_eq!(
+            error.fields["msg"],
+            r#"open() "/srv/www/favicon.ico" failed (2: No such file or directory)"#
+        );
+        assert_eq!(error.fields["client"], "10.0.0.7");
+        assert_eq!(error.fields["server"], "example.com");
+        assert_eq!(error.fields["request"], "GET /favicon.ico HTTP/1.1");
+        assert_eq!(error.fields["host"], "example.com");
+        assert_eq!(
+            error.time,
+            Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+                .unwrap()
+                .timestamp_millis()
+        );
+    }
+
+    #[test]
+    fn test_message_without_context() {
+        let error = parse(
+            "2024/01/01 12:00:00 [emerg] 1#1: bind() to 0.0.0.0:80 failed (98: Address in use), retrying",
+        )
+        .unwrap();
+        assert!(!error.fields.contains_key("connection"));
+        assert_eq!(
+            error.fields["msg"],
+            "bind() to 0.0.0.0:80 failed (98: Address in use), retrying"
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_shapes() {
+        assert!(parse("2024/01/01 12:00:00 [loud] 1#1: hi").is_none());
+        assert!(parse("2024-01-01 12:00:00 [error] 1#1: hi").is_none());
+        assert!(parse("2024/01/01 12:00:00 [error] hi").is_none());
+        assert!(parse("short").is_none());
+    }
+}
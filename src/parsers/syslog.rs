@@ -0,0 +1,200 @@
+//! BSD syslog (RFC 3164): `<PRI>Mmm dd hh:mm:ss host tag[pid]: message`
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+const FACILITIES: [&str; 24] = [
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+/// Keyword names from RFC 3164, which [`crate::severity::Severity::parse`] understands
+const SEVERITIES: [&str; 8] = [
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// RFC 3164 tags are at most 32 characters
+const MAX_TAG_LENGTH: usize = 32;
+
+pub struct SyslogMessage {
+    pub fields: HashMap<String, String>,
+    /// When the message was sent, in epoch milliseconds
+    pub time: i64,
+}
+
+/// Parse a BSD syslog line received at `now`
+pub fn parse(input: &str, now: DateTime<Utc>) -> Option<SyslogMessage> {
+    let rest = input.strip_prefix('<')?;
+    let (pri, rest) = rest.split_once('>')?;
+    if pri.is_empty() || pri.len() > 3 || !pri.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let pri: usize = pri.parse().ok()?;
+    let facility = FACILITIES.get(pri / 8)?;
+    let severity = SEVERITIES[pri % 8];
+
+    // The timestamp is fixed width, with the day padded by a space: `Oct  1 02:03:04`
+    let timestamp = rest.get(..15)?;
+    let time = parse_timestamp(timestamp, now)?;
+    let rest = rest[15..].strip_prefix(' ')?;
+
+    let (host, rest) = rest.split_once(' ')?;
+    if host.is_empty() {
+        return None;
+    }
+
+    let mut fields = HashMap::from([
+        ("facility".to_string(), facility.to_string()),
+        ("severity".to_string(), severity.to_string()),
+        ("timestamp".to_string(), timestamp.to_string()),
+        ("host".to_string(), host.to_string()),
+    ]);
+
+    let message = match split_tag(rest) {
+        Some((tag, pid, message)) => {
+            fields.insert("tag".to_string(), tag.to_string());
+            if let Some(pid) = pid {
+                fields.insert("pid".to_string(), pid.to_string());
+            }
+            message
+        }
+        None => rest,
+    };
+    fields.insert("msg".to_string(), message.to_string());
+
+    Some(SyslogMessage { fields, time })
+}
+
+/// Read an RFC 3164 timestamp, which has neither a year nor a zone. Times are taken as
+/// UTC in the year that puts them closest to `now`, so December lines that arrive in
+/// January land in the right year.
+fn parse_timestamp(timestamp: &str, now: DateTime<Utc>) -> Option<i64> {
+    let month = MONTHS
+        .iter()
+        .position(|month| timestamp.starts_with(month))? as u32
+        + 1;
+    if timestamp.as_bytes()[3] != b' ' || timestamp.as_bytes()[6] != b' ' {
+        return None;
+    }
+    let day: u32 = timestamp[4..6].trim_start().parse().ok()?;
+    let time = NaiveTime::parse_from_str(&timestamp[7..], "%H:%M:%S").ok()?;
+    nearest_year(month, day, time, now)
+}
+
+/// A UTC time on `month`/`day` in whichever year puts it closest to `now`
+pub(super) fn nearest_year(
+    month: u32,
+    day: u32,
+    time: NaiveTime,
+    now: DateTime<Utc>,
+) -> Option<i64> {
+    [now.year(), now.year() - 1, now.year() + 1]
+        .into_iter()
+        .filter_map(|year| NaiveDate::from_ymd_opt(year, month, day))
+        .map(|date| {
+            Utc.from_utc_datetime(&date.and_time(time))
+                .timestamp_millis()
+        })
+        .min_by_key(|millis| (millis - now.timestamp_millis()).abs())
+}
+
+/// Split `tag[pid]: message` into its parts, or `None` if the line has no tag
+fn split_tag(input: &str) -> Option<(&str, Option<&str>, &str)> {
+    let end = input.find(['[', ':', ' '])?;
+    let tag = &input[..end];
+    if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+        return None;
+    }
+
+    let (pid, rest) = match input[end..].strip_prefix('[') {
+        Some(rest) => {
+            let (pid, rest) = rest.split_once(']')?;
+            (Some(pid), rest)
+        }
+        None => (None, &input[end..]),
+    };
+    let message = rest.strip_prefix(':')?;
+    Some((tag, pid, message.strip_prefix(' ').unwrap_or(message)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let now = Utc.with_ymd_and_hms(2024, 10, 12, 0, 0, 0).unwrap();
+        let message = parse(
+            "<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed for lonvick on /dev/pts/8",
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(message.fields["facility"], "auth");
+        assert_eq!(message.fields["severity"], "crit");
+        assert_eq!(message.fields["host"], "mymachine");
+        assert_eq!(message.fields["tag"], "su");
+        assert_eq!(message.fields["pid"], "123");
+        assert_eq!(
+            message.fields["msg"],
+            "'su root' failed for lonvick on /dev/pts/8"
+        );
+        assert_eq!(
+            message.time,
+            Utc.with_ymd_and_hms(2024, 10, 11, 22, 14, 15)
+                .unwrap()
+                .timestamp_millis()
+        );
+    }
+
+    #[test]
+    fn test_year_rollover() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 5).unwrap();
+        let message = parse("<13>Dec 31 23:59:58 host app: done", now).unwrap();
+        assert_eq!(
+            message.time,
+            Utc.with_ymd_and_hms(2024, 12, 31, 23, 59, 58)
+                .unwrap()
+                .timestamp_millis()
+        );
+    }
+
+    #[test]
+    fn test_rejects_other_shapes() {
+        let now = Utc::now();
+        assert!(parse("<999>Oct 11 22:14:15 host app: hi", now).is_none());
+        assert!(parse("<13>2024-10-11T22:14:15Z host app: hi", now).is_none());
+        assert!(parse("Oct 11 22:14:15 host app: hi", now).is_none());
+
+        // Messages without a tag keep the whole remainder
+        let message = parse("<13>Oct  1 02:03:04 host just a message", now).unwrap();
+        assert!(!message.fields.contains_key("tag"));
+        assert_eq!(message.fields["msg"], "just a message");
+    }
+}
@@ -0,0 +1,126 @@
+//! W3C extended log format, as written by IIS and some CDNs: `#Directive: value` lines,
+//! with `#Fields:` naming the columns of the data lines that follow
+
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+/// Directives from the W3C working draft
+const DIRECTIVES: &[&str] = &[
+    "Version",
+    "Fields",
+    "Software",
+    "Start-Date",
+    "End-Date",
+    "Date",
+    "Remark",
+];
+
+/// A `#Directive: value` line
+pub struct Directive<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+impl Directive<'_> {
+    /// The column names, for a `#Fields:` directive
+    pub fn fields(&self) -> Option<Vec<String>> {
+        if self.name != "Fields" {
+            return None;
+        }
+        let fields: Vec<String> = self.value.split_whitespace().map(String::from).collect();
+        (!fields.is_empty()).then_some(fields)
+    }
+}
+
+/// Parse a directive line
+pub fn directive(input: &str) -> Option<Directive<'_>> {
+    let (name, value) = input.strip_prefix('#')?.split_once(':')?;
+    if !DIRECTIVES.contains(&name) {
+        return None;
+    }
+    Some(Directive {
+        name,
+        value: value.trim(),
+    })
+}
+
+pub struct W3cEntry {
+    pub fields: HashMap<String, String>,
+    /// From the `date` and `time` columns, which the format defines as UTC
+    pub time: Option<i64>,
+}
+
+/// Read a data line against the columns of the last `#Fields:` directive. Values are
+/// separated by spaces, or by tabs in files that use them, and must match the columns
+/// one for one.
+pub fn parse(input: &str, columns: &[String]) -> Option<W3cEntry> {
+    if input.starts_with('#') {
+        return None;
+    }
+    let values: Vec<&str> = match input.contains('\t') {
+        true => input.split('\t').collect(),
+        false => input.split(' ').collect(),
+    };
+    if values.len() != columns.len() {
+        return None;
+    }
+
+    let fields: HashMap<String, String> = columns
+        .iter()
+        .zip(values)
+        .map(|(column, value)| (column.clone(), value.to_string()))
+        .collect();
+    let time = match (fields.get("date"), fields.get("time")) {
+        (Some(date), Some(time)) => {
+            NaiveDateTime::parse_from_str(&format!("{} {}", date, time), "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|time| Utc.from_utc_datetime(&time).timestamp_millis())
+        }
+        _ => None,
+    };
+    Some(W3cEntry { fields, time })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directive() {
+        let fields = directive("#Fields: date time cs-method cs-uri-stem sc-status").unwrap();
+        assert_eq!(fields.name, "Fields");
+        assert_eq!(
+            fields.fields().unwrap(),
+            vec!["date", "time", "cs-method", "cs-uri-stem", "sc-status"]
+        );
+
+        let version = directive("#Version: 1.0").unwrap();
+        assert_eq!(version.value, "1.0");
+        assert!(version.fields().is_none());
+
+        assert!(directive("#include <stdio.h>").is_none());
+        assert!(directive("Fields: date").is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        let columns: Vec<String> = ["date", "time", "cs-method", "cs-uri-stem", "sc-status"]
+            .map(String::from)
+            .to_vec();
+        let entry = parse("2024-01-01 12:00:00 GET /index.html 200", &columns).unwrap();
+        assert_eq!(entry.fields["cs-method"], "GET");
+        assert_eq!(entry.fields["sc-status"], "200");
+        assert_eq!(
+            entry.time,
+            Some(
+                Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+                    .unwrap()
+                    .timestamp_millis()
+            )
+        );
+
+        assert!(parse("2024-01-01\t12:00:00\tGET\t/\t404", &columns).is_some());
+        assert!(parse("2024-01-01 12:00:00 GET /index.html", &columns).is_none());
+        assert!(parse("#Remark: five values in this line", &columns).is_none());
+    }
+}
@@ -0,0 +1,201 @@
+//! A producer's own parser for a bucket: a regex whose named capture groups become fields,
+//! registered with `PUT .../parser` or sent along with a post in `X-Log-Pattern`
+
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::parsers::{create_fields, ParseOutcome, ParsedEvent};
+use crate::rules::compile_pattern;
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+/// Registers a bucket's pattern from an ingest request, for that post's lines and later ones
+pub const LOG_PATTERN_HEADER: &str = "X-Log-Pattern";
+/// What events read with a bucket's pattern give as their parser
+const PATTERN_PARSER_NAME: &str = "pattern";
+
+/// The regex a bucket's pattern is given as
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternConfig {
+    pub pattern: String,
+}
+
+/// A bucket's pattern, compiled
+#[derive(Debug)]
+pub struct BucketPattern {
+    pub config: PatternConfig,
+    regex: Regex,
+}
+
+impl BucketPattern {
+    /// Compile a pattern, which needs a named group for there to be fields
+    pub fn compile(pattern: &str) -> Result<Self, String> {
+        let regex = compile_pattern(pattern).map_err(|e| e.to_string())?;
+        if regex.capture_names().flatten().next().is_none() {
+            return Err("A pattern needs a named group, such as (?P<level>\\w+)".to_string());
+        }
+        Ok(Self {
+            config: PatternConfig {
+                pattern: pattern.to_string(),
+            },
+            regex,
+        })
+    }
+
+    /// Parse an event none of the built-in parsers understood, if the pattern matches it
+    pub fn apply(&self, event: &mut ParsedEvent) {
+        if event.parser.is_some() {
+            return;
+        }
+        let Some(captures) = self.regex.captures(&event.input_string) else {
+            return;
+        };
+        let data = self
+            .regex
+            .capture_names()
+            .flatten()
+            .filter_map(|group| {
+                captures
+                    .name(group)
+                    .map(|m| (group.to_string(), m.as_str().to_string()))
+            })
+            .collect();
+        // A pattern that only matches part of the line is less sure of it
+        let matched = captures.get(0).map_or(0, |m| m.len());
+        let coverage = matched as f32 / event.input_string.len().max(1) as f32;
+        event.parser = Some(PATTERN_PARSER_NAME.to_string());
+        event.outcome = ParseOutcome::Pattern;
+        event.confidence = Some((coverage * 100.0).round() / 100.0);
+        event.fields = create_fields(data);
+    }
+}
+
+pub async fn get_parser(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<Option<PatternConfig>> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) => Json(
+            channel
+                .pattern()
+                .await
+                .map(|pattern| pattern.config.clone()),
+        ),
+        None => Json(None),
+    }
+}
+
+pub async fn put_parser(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(config): Json<PatternConfig>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let pattern = match BucketPattern::compile(&config.pattern) {
+        Ok(pattern) => Arc::new(pattern),
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!("Registered a pattern for bucket {}", bucket_id);
+    let previous = channel.set_pattern(Some(pattern)).await;
+    channel.publish_config_change(
+        "parser",
+        &previous.map(|pattern| pattern.config.clone()),
+        &Some(config),
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn delete_parser(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    if let Some(channel) = channel {
+        if let Some(previous) = channel.set_pattern(None).await {
+            info!("Removed the pattern for bucket {}", bucket_id);
+            channel.publish_config_change(
+                "parser",
+                &Some(previous.config.clone()),
+                &None::<PatternConfig>,
+                changes::actor(&state, &headers),
+            );
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let pattern =
+            BucketPattern::compile(r"^(?P<user>\w+) paid (?P<amount>[0-9.]+)(?: via \w+)?")
+                .unwrap();
+
+        let mut event = ParsedEvent::new("alice paid 12.50 via card".to_string());
+        event.parse();
+        pattern.apply(&mut event);
+        assert_eq!(event.parser.as_deref(), Some("pattern"));
+        assert_eq!(event.outcome, ParseOutcome::Pattern);
+        assert_eq!(event.fields["user"].value, "alice");
+        assert_eq!(event.fields["amount"].value, "12.50");
+        assert_eq!(event.confidence, Some(1.0));
+
+        let mut event = ParsedEvent::new("nothing to see".to_string());
+        event.parse();
+        pattern.apply(&mut event);
+        assert!(event.parser.is_none());
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert!(BucketPattern::compile(r"(?P<user>\w+").is_err());
+        assert!(BucketPattern::compile(r"(\w+) paid").is_err());
+    }
+}
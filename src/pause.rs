@@ -0,0 +1,138 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::tokens;
+use crate::{AppState, SUSPENSION_REASON_TEXT};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Lines a buffering pause holds before it starts rejecting writes too
+pub const MAX_PAUSE_BUFFER_LINES: usize = 1000;
+
+/// What happens to writes while a bucket is paused
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PauseMode {
+    /// Writes fail with 503 so producers retry later
+    #[default]
+    Reject,
+    /// Writes are accepted and held, then published on resume
+    Buffer,
+}
+
+/// A paused bucket and the lines it is holding
+#[derive(Debug, Default)]
+pub struct PauseState {
+    pub mode: PauseMode,
+    pub buffered: Vec<String>,
+}
+
+impl PauseState {
+    /// Hold `lines` if buffering and there's room, returning whether they were taken
+    pub fn hold(&mut self, lines: &[&str]) -> bool {
+        if self.mode != PauseMode::Buffer
+            || self.buffered.len() + lines.len() > MAX_PAUSE_BUFFER_LINES
+        {
+            return false;
+        }
+        self.buffered
+            .extend(lines.iter().map(|line| line.to_string()));
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PauseParams {
+    #[serde(default)]
+    mode: PauseMode,
+}
+
+/// Stop publishing a bucket's incoming writes until it is resumed
+pub async fn post_pause(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Query(params): Query<PauseParams>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!("Paused bucket {} ({:?})", bucket_id, params.mode);
+    channel.pause(params.mode).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Start publishing writes again, releasing any that were buffered
+pub async fn post_resume(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    let buffered = channel.resume().await;
+    info!(
+        "Resumed bucket {}, releasing {} buffered lines",
+        bucket_id,
+        buffered.len()
+    );
+
+    let lines: Vec<&str> = buffered.iter().map(String::as_str).collect();
+    if !lines.is_empty() {
+        if let IngestOutcome::Suspended = ingest_lines(&state, &channel, &lines).await {
+            return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+        }
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hold() {
+        let mut pause = PauseState::default();
+        assert!(!pause.hold(&["a"]));
+
+        let mut pause = PauseState {
+            mode: PauseMode::Buffer,
+            ..Default::default()
+        };
+        assert!(pause.hold(&["a", "b"]));
+        assert_eq!(pause.buffered, vec!["a", "b"]);
+
+        let full = vec!["x"; MAX_PAUSE_BUFFER_LINES - 2];
+        assert!(pause.hold(&full));
+        assert!(!pause.hold(&["overflow"]));
+        assert_eq!(pause.buffered.len(), MAX_PAUSE_BUFFER_LINES);
+    }
+}
@@ -0,0 +1,74 @@
+//! Callers an embedding application has already authenticated. A host that mounts
+//! [`build_router`](crate::build_router) behind its own auth middleware inserts a
+//! [`Principal`] into each request's extensions, and log-bin treats it as it would a valid
+//! write token: writes are attributed to it, `/api/my/buckets` lists its buckets, config
+//! changes name it as their actor and its usage is reported under its ID.
+
+use crate::tokens::{Quotas, TokenAccount};
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+tokio::task_local! {
+    /// The account of the principal whose request is being handled
+    static CURRENT: Arc<TokenAccount>;
+}
+
+/// An authenticated caller, identified by the host application's own ID for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    id: String,
+}
+
+impl Principal {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self { id: id.into() }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// Accounts for the principals seen so far, so their buckets and usage add up across
+/// requests. Principals have no quotas; the host decides who may write.
+#[derive(Default)]
+pub struct PrincipalAccounts {
+    accounts: Mutex<HashMap<String, Arc<TokenAccount>>>,
+}
+
+impl PrincipalAccounts {
+    fn account(&self, principal: &Principal) -> Arc<TokenAccount> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(principal.id.clone())
+            .or_insert_with(|| {
+                Arc::new(TokenAccount::new(
+                    principal.id.clone(),
+                    Quotas::default(),
+                    None,
+                ))
+            })
+            .clone()
+    }
+}
+
+/// Handle a request as its principal, when the host vouched for one
+pub async fn scope(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(principal) = request.extensions().get::<Principal>() else {
+        return next.run(request).await;
+    };
+    let account = state.principals.account(principal);
+    CURRENT.scope(account, next.run(request)).await
+}
+
+/// The account of the principal the current request is made by, if any
+pub fn current() -> Option<Arc<TokenAccount>> {
+    CURRENT.try_with(Arc::clone).ok()
+}
@@ -0,0 +1,196 @@
+use crate::alerts::{validate_alert_rules, AlertRule};
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::grok::{GrokConfig, GrokSet};
+use crate::pattern::{BucketPattern, PatternConfig};
+use crate::routing::{validate_routes, RouteRule};
+use crate::settings::BucketSettings;
+use crate::webhooks::{check_webhook_hosts, validate_webhooks, Webhook};
+use crate::{ids, tokens, AppState};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+/// Configuration to provision a bucket with; sections left out keep their current values
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BucketConfig {
+    settings: Option<BucketSettings>,
+    webhooks: Option<Vec<Webhook>>,
+    alerts: Option<Vec<AlertRule>>,
+    routes: Option<Vec<RouteRule>>,
+    grok: Option<GrokConfig>,
+    parser: Option<PatternConfig>,
+}
+
+/// The configuration a bucket has once provisioned
+#[derive(Serialize)]
+struct ProvisionedBucket {
+    id: String,
+    settings: BucketSettings,
+    webhooks: Vec<Webhook>,
+    alerts: Vec<AlertRule>,
+    routes: Vec<RouteRule>,
+    grok: GrokConfig,
+    parser: Option<PatternConfig>,
+}
+
+impl BucketConfig {
+    fn validate(&self, bucket_id: &str) -> Result<(), String> {
+        if let Some(settings) = &self.settings {
+            settings.validate()?;
+        }
+        if let Some(webhooks) = &self.webhooks {
+            validate_webhooks(webhooks)?;
+        }
+        if let Some(rules) = &self.alerts {
+            validate_alert_rules(rules)?;
+        }
+        if let Some(routes) = &self.routes {
+            validate_routes(bucket_id, routes)?;
+        }
+        Ok(())
+    }
+}
+
+/// `PUT /api/v1/buckets/{bucket_id}`: create a bucket ahead of any traffic and configure
+/// it. Answers 201 for a new bucket and 200 for an existing one, so deploys can rerun it.
+pub async fn put_bucket(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    config: Option<Json<BucketConfig>>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+    if ids::is_reserved(&bucket_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if bucket_id.len() < ids::MIN_BUCKET_ID_LENGTH {
+        return Ok((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Bucket IDs need at least {} characters",
+                ids::MIN_BUCKET_ID_LENGTH
+            ),
+        )
+            .into_response());
+    }
+
+    let mut config = config.map(|Json(config)| config).unwrap_or_default();
+    if let Err(message) = config.validate(&bucket_id) {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    if let Some(webhooks) = &config.webhooks {
+        if let Err(message) = check_webhook_hosts(webhooks).await {
+            return Ok((StatusCode::BAD_REQUEST, message).into_response());
+        }
+    }
+    // Compiling grok patterns and the bucket's pattern is how they're checked
+    let grok = match config.grok.take().map(GrokSet::compile).transpose() {
+        Ok(grok) => grok.map(Arc::new),
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    let pattern = match config
+        .parser
+        .take()
+        .map(|parser| BucketPattern::compile(&parser.pattern))
+        .transpose()
+    {
+        Ok(pattern) => pattern.map(Arc::new),
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+
+    let (channel, created) = {
+        let mut manager = state.channel_manager.write().await;
+        let created = manager.get_channel(&bucket_id).is_none();
+        (manager.get_or_create_channel(&bucket_id), created)
+    };
+    channel.mark_configured();
+    info!(
+        "Provisioned bucket {} ({})",
+        bucket_id,
+        if created { "created" } else { "existing" }
+    );
+
+    let actor = changes::actor(&state, &headers);
+    if let Some(settings) = config.settings {
+        let previous = channel.set_settings(settings.clone()).await;
+        channel.publish_config_change("settings", &previous, &settings, actor.clone());
+    }
+    if let Some(webhooks) = config.webhooks {
+        let previous = channel.set_webhooks(webhooks.clone()).await;
+        channel.publish_config_change("webhooks", &previous, &webhooks, actor.clone());
+    }
+    if let Some(rules) = config.alerts {
+        let previous = channel.set_alert_rules(rules.clone()).await;
+        channel.publish_config_change("alerts", &previous, &rules, actor.clone());
+    }
+    if let Some(routes) = config.routes {
+        let previous = channel.set_routes(routes.clone()).await;
+        channel.publish_config_change("routes", &previous, &routes, actor.clone());
+    }
+    if let Some(grok) = grok {
+        let previous = channel.set_grok(grok.clone()).await;
+        channel.publish_config_change("grok", &previous.config, &grok.config, actor.clone());
+    }
+    if let Some(pattern) = pattern {
+        let previous = channel.set_pattern(Some(pattern.clone())).await;
+        channel.publish_config_change(
+            "parser",
+            &previous.map(|previous| previous.config.clone()),
+            &Some(pattern.config.clone()),
+            actor,
+        );
+    }
+
+    let bucket = ProvisionedBucket {
+        id: bucket_id,
+        settings: channel.settings().await,
+        webhooks: channel.webhooks().await,
+        alerts: channel.alert_rules().await,
+        routes: channel.routes().await,
+        grok: channel.grok().await.config.clone(),
+        parser: channel
+            .pattern()
+            .await
+            .map(|pattern| pattern.config.clone()),
+    };
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(changes::redacted(&bucket))).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let config: BucketConfig = serde_json::from_str(
+            r#"{"settings": {"timezone": "Europe/Paris"}, "routes": [{"target": "other-bucket", "field": "level", "value": "error"}]}"#,
+        )
+        .unwrap();
+        assert!(config.validate("my-bucket-01").is_ok());
+        assert!(config.validate("other-bucket").is_err());
+
+        let config: BucketConfig =
+            serde_json::from_str(r#"{"webhooks": [{"url": "ftp://example.com"}]}"#).unwrap();
+        assert!(config.validate("my-bucket-01").is_err());
+        assert!(BucketConfig::default().validate("my-bucket-01").is_ok());
+    }
+}
@@ -0,0 +1,91 @@
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
+use std::str::FromStr;
+
+/// Size of the comment sent ahead of a stream to push it through buffering proxies
+const EARLY_FLUSH_PADDING_BYTES: usize = 2048;
+
+/// The CDN or reverse proxy in front of the server, which decides how stream responses
+/// are marked so they aren't cached or buffered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProxyProfile {
+    /// Headers most proxies understand
+    #[default]
+    Generic,
+    Fastly,
+    Nginx,
+    Cloudflare,
+}
+
+impl FromStr for ProxyProfile {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "generic" => Ok(ProxyProfile::Generic),
+            "fastly" => Ok(ProxyProfile::Fastly),
+            "nginx" => Ok(ProxyProfile::Nginx),
+            "cloudflare" => Ok(ProxyProfile::Cloudflare),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ProxyProfile {
+    /// Headers for event stream responses
+    pub fn stream_headers(self) -> HeaderMap {
+        let cache_control = match self {
+            ProxyProfile::Generic => "no-cache",
+            ProxyProfile::Fastly | ProxyProfile::Nginx => "private, no-store",
+            // Stop Cloudflare compressing the stream, which buffers it
+            ProxyProfile::Cloudflare => "private, no-store, no-transform",
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+        headers.insert(
+            HeaderName::from_static("x-accel-buffering"),
+            HeaderValue::from_static("no"),
+        );
+        if self == ProxyProfile::Fastly {
+            // Surrogate-Control applies to Fastly only and is stripped before the client
+            headers.insert(
+                HeaderName::from_static("surrogate-control"),
+                HeaderValue::from_static("no-store"),
+            );
+        }
+        headers
+    }
+
+    /// Bytes of padding to send before the first event, for proxies that hold the
+    /// start of a response until a buffer fills
+    pub fn early_flush_padding(self) -> Option<usize> {
+        match self {
+            ProxyProfile::Cloudflare => Some(EARLY_FLUSH_PADDING_BYTES),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_headers() {
+        let headers = ProxyProfile::Generic.stream_headers();
+        assert_eq!(headers[header::CACHE_CONTROL], "no-cache");
+        assert_eq!(headers["x-accel-buffering"], "no");
+        assert!(!headers.contains_key("surrogate-control"));
+
+        let headers = ProxyProfile::Fastly.stream_headers();
+        assert_eq!(headers[header::CACHE_CONTROL], "private, no-store");
+        assert_eq!(headers["surrogate-control"], "no-store");
+
+        assert_eq!("Cloudflare".parse(), Ok(ProxyProfile::Cloudflare));
+        assert!("varnish".parse::<ProxyProfile>().is_err());
+        assert!(ProxyProfile::Nginx.early_flush_padding().is_none());
+    }
+}
@@ -0,0 +1,630 @@
+use crate::models::LogEvent;
+use crate::parsers::parse_timestamp;
+use crate::AppState;
+use axum::{
+    extract::{Path, Query as QueryParams, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Rows returned when a query has no `LIMIT`, and the most it may ask for
+const MAX_QUERY_ROWS: usize = 1000;
+/// Name of the count column
+const COUNT_COLUMN: &str = "count";
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub query: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &["!=", ">=", "<=", "=", ">", "<", "~", ",", "(", ")", "*"];
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if let Some(quote) = rest.chars().next().filter(|c| *c == '\'' || *c == '"') {
+            let end = rest[1..]
+                .find(quote)
+                .ok_or_else(|| "Unterminated string".to_string())?;
+            tokens.push(Token::Quoted(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "!=<>~,()*'\"".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("Unexpected character in {:?}", rest));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+#[derive(Debug, PartialEq)]
+struct Condition {
+    field: String,
+    op: Op,
+    value: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Column {
+    All,
+    Field(String),
+    Count,
+}
+
+#[derive(Debug, PartialEq)]
+struct Query {
+    columns: Vec<Column>,
+    conditions: Vec<Condition>,
+    group_by: Vec<String>,
+    limit: usize,
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>,
+    peeked: Option<Token>,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = self.tokens.next();
+        }
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.peek();
+        self.peeked.take()
+    }
+
+    fn at_keyword(&mut self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.at_keyword(keyword) {
+            self.next();
+            Ok(())
+        } else {
+            Err(format!("Expected {}", keyword.to_uppercase()))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn field(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(word)) | Some(Token::Quoted(word)) => Ok(word),
+            _ => Err("Expected a field name".to_string()),
+        }
+    }
+
+    fn column(&mut self) -> Result<Column, String> {
+        if self.symbol("*") {
+            return Ok(Column::All);
+        }
+        let field = self.field()?;
+        if field.eq_ignore_ascii_case(COUNT_COLUMN) && self.symbol("(") {
+            if !self.symbol("*") || !self.symbol(")") {
+                return Err("Only count(*) is supported".to_string());
+            }
+            return Ok(Column::Count);
+        }
+        Ok(Column::Field(field))
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let field = self.field()?;
+        let op = match self.next() {
+            Some(Token::Symbol("=")) => Op::Eq,
+            Some(Token::Symbol("!=")) => Op::Ne,
+            Some(Token::Symbol(">")) => Op::Gt,
+            Some(Token::Symbol(">=")) => Op::Ge,
+            Some(Token::Symbol("<")) => Op::Lt,
+            Some(Token::Symbol("<=")) => Op::Le,
+            Some(Token::Symbol("~")) => Op::Contains,
+            _ => return Err(format!("Expected a comparison after {}", field)),
+        };
+        let value = match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value,
+            _ => return Err(format!("Expected a value to compare {} with", field)),
+        };
+        Ok(Condition { field, op, value })
+    }
+}
+
+fn parse(input: &str) -> Result<Query, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?.into_iter(),
+        peeked: None,
+    };
+
+    parser.keyword("select")?;
+    let mut columns = vec![parser.column()?];
+    while parser.symbol(",") {
+        columns.push(parser.column()?);
+    }
+
+    let mut conditions = Vec::new();
+    if parser.at_keyword("where") {
+        parser.next();
+        conditions.push(parser.condition()?);
+        while parser.at_keyword("and") {
+            parser.next();
+            conditions.push(parser.condition()?);
+        }
+    }
+
+    let mut group_by = Vec::new();
+    if parser.at_keyword("group") {
+        parser.next();
+        parser.keyword("by")?;
+        group_by.push(parser.field()?);
+        while parser.symbol(",") {
+            group_by.push(parser.field()?);
+        }
+    }
+
+    let mut limit = MAX_QUERY_ROWS;
+    if parser.at_keyword("limit") {
+        parser.next();
+        limit = match parser.next() {
+            Some(Token::Word(n)) => n.parse().map_err(|_| "LIMIT must be a number")?,
+            _ => return Err("LIMIT must be a number".to_string()),
+        };
+        limit = limit.min(MAX_QUERY_ROWS);
+    }
+
+    if let Some(token) = parser.next() {
+        return Err(format!("Unexpected {:?}", token));
+    }
+
+    let query = Query {
+        columns,
+        conditions,
+        group_by,
+        limit,
+    };
+    query.validate()?;
+    Ok(query)
+}
+
+impl Query {
+    fn counts(&self) -> bool {
+        self.columns.contains(&Column::Count)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.columns.contains(&Column::All) && (self.counts() || !self.group_by.is_empty()) {
+            return Err("* can't be combined with count(*) or GROUP BY".to_string());
+        }
+        if self.counts() || !self.group_by.is_empty() {
+            // Every other column has to be one value per group
+            for column in &self.columns {
+                if let Column::Field(field) = column {
+                    if !self.group_by.contains(field) {
+                        return Err(format!("{} must appear in GROUP BY", field));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, event: &LogEvent, now: i64) -> Result<bool, String> {
+        for condition in &self.conditions {
+            if !condition.matches(event, now)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Evaluate the query over `events`, returning one JSON object per row
+    fn run(&self, events: &[LogEvent], now: i64) -> Result<Vec<Value>, String> {
+        let mut matching = Vec::new();
+        for event in events {
+            if self.matches(event, now)? {
+                matching.push(event);
+            }
+        }
+
+        if !self.counts() && self.group_by.is_empty() {
+            return Ok(matching
+                .iter()
+                .rev()
+                .take(self.limit)
+                .map(|event| self.event_row(event))
+                .collect());
+        }
+
+        if self.group_by.is_empty() {
+            let count = matching.len() as u64;
+            return Ok(vec![serde_json::json!({ COUNT_COLUMN: count })]);
+        }
+
+        // Group in first-seen order so equal counts keep a stable order
+        let mut groups: Vec<(Vec<Value>, u64)> = Vec::new();
+        let mut index: HashMap<Vec<Option<String>>, usize> = HashMap::new();
+        for event in matching {
+            let key: Vec<Option<String>> = self
+                .group_by
+                .iter()
+                .map(|field| field_value(event, field))
+                .collect();
+            let position = *index.entry(key.clone()).or_insert_with(|| {
+                let values = key
+                    .into_iter()
+                    .map(|value| value.map_or(Value::Null, Value::String))
+                    .collect();
+                groups.push((values, 0));
+                groups.len() - 1
+            });
+            groups[position].1 += 1;
+        }
+        groups.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        Ok(groups
+            .into_iter()
+            .take(self.limit)
+            .map(|(values, count)| {
+                let mut row: Map<String, Value> =
+                    self.group_by.iter().cloned().zip(values).collect();
+                if self.counts() {
+                    row.insert(COUNT_COLUMN.to_string(), Value::from(count));
+                }
+                Value::Object(row)
+            })
+            .collect())
+    }
+
+    fn event_row(&self, event: &LogEvent) -> Value {
+        let mut row = Map::new();
+        for column in &self.columns {
+            match column {
+                Column::All => {
+                    row.insert("seq".to_string(), Value::from(event.seq));
+                    row.insert("time".to_string(), Value::from(event.time));
+                    for (key, data) in &event.fields {
+                        row.insert(key.clone(), Value::String(data.value.clone()));
+                    }
+                }
+                Column::Field(field) => {
+                    let value = field_value(event, field).map_or(Value::Null, Value::String);
+                    row.insert(field.clone(), value);
+                }
+                Column::Count => {}
+            }
+        }
+        Value::Object(row)
+    }
+}
+
+impl Condition {
+    fn matches(&self, event: &LogEvent, now: i64) -> Result<bool, String> {
+        // `age` compares how long ago the event happened against a duration like `10m`
+        if self.field == "age" {
+            let limit = parse_duration_ms(&self.value)
+                .ok_or_else(|| format!("{} is not a duration like 30s, 10m or 1h", self.value))?;
+            return Ok(compare(self.op, (now - event.time) as f64, limit as f64));
+        }
+
+        let Some(actual) = field_value(event, &self.field) else {
+            // A missing field only satisfies "not equal"
+            return Ok(self.op == Op::Ne);
+        };
+        Ok(match self.op {
+            Op::Contains => actual.contains(&self.value),
+            op => match (actual.parse::<f64>(), self.value.parse::<f64>()) {
+                (Ok(actual), Ok(expected)) => compare(op, actual, expected),
+                _ => compare(op, actual.as_str(), self.value.as_str()),
+            },
+        })
+    }
+}
+
+fn compare<T: PartialOrd>(op: Op, actual: T, expected: T) -> bool {
+    match op {
+        Op::Eq => actual == expected,
+        Op::Ne => actual != expected,
+        Op::Gt => actual > expected,
+        Op::Ge => actual >= expected,
+        Op::Lt => actual < expected,
+        Op::Le => actual <= expected,
+        Op::Contains => false,
+    }
+}
+
+/// A parsed field, or one of the event's own properties
+fn field_value(event: &LogEvent, field: &str) -> Option<String> {
+    if let Some(data) = event.fields.get(field) {
+        return Some(data.value.clone());
+    }
+    match field {
+        "seq" => Some(event.seq.to_string()),
+        "time" => Some(event.time.to_string()),
+        "raw" => Some(event.raw.clone()),
+        "parser" => event.parser.clone(),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_duration_ms(value: &str) -> Option<i64> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = value[..split].parse().ok()?;
+    let unit = match &value[split..] {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(amount * unit)
+}
+
+/// Answer a small SQL-like query over the bucket's retained events
+pub async fn post_query(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    Json(request): Json<QueryRequest>,
+) -> Response {
+    let query = match parse(&request.query) {
+        Ok(query) => query,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let events = match channel {
+        Some(channel) => channel.history().await,
+        None => Vec::new(),
+    };
+
+    match query.run(&events, chrono::Utc::now().timestamp_millis()) {
+        Ok(rows) => Json(serde_json::json!({ "rows": rows })).into_response(),
+        Err(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CountParams {
+    /// Epoch time, RFC 3339 time or a duration like `5m` back from now
+    since: Option<String>,
+    /// Comma-separated `field:value` pairs that must all match
+    #[serde(rename = "where")]
+    filter: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountResult {
+    count: usize,
+    /// Time of the oldest retained event, since older ones can't be counted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oldest: Option<i64>,
+}
+
+fn parse_since(value: &str, now: i64) -> Option<i64> {
+    parse_duration_ms(value)
+        .map(|duration| now - duration)
+        .or_else(|| parse_timestamp(value))
+}
+
+fn parse_filter(value: &str) -> Result<Vec<(&str, &str)>, String> {
+    value
+        .split(',')
+        .map(|pair| {
+            pair.split_once(':')
+                .filter(|(field, _)| !field.is_empty())
+                .ok_or_else(|| format!("{:?} is not a field:value pair", pair))
+        })
+        .collect()
+}
+
+fn count(events: &[LogEvent], since: Option<i64>, filter: &[(&str, &str)]) -> usize {
+    events
+        .iter()
+        .filter(|event| since.is_none_or(|since| event.time >= since))
+        .filter(|event| {
+            filter
+                .iter()
+                .all(|(field, value)| field_value(event, field).as_deref() == Some(*value))
+        })
+        .count()
+}
+
+/// Count retained events without subscribing, e.g. `?since=5m&where=level:error`
+pub async fn get_count(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    QueryParams(params): QueryParams<CountParams>,
+) -> Response {
+    let now = chrono::Utc::now().timestamp_millis();
+    let since = match params
+        .since
+        .as_deref()
+        .map(|since| (since, parse_since(since, now)))
+    {
+        Some((since, None)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("{} is not a time or a duration like 30s, 10m or 1h", since),
+            )
+                .into_response()
+        }
+        Some((_, since)) => since,
+        None => None,
+    };
+    let filter = match params.filter.as_deref().map(parse_filter) {
+        Some(Ok(filter)) => filter,
+        Some(Err(message)) => return (StatusCode::BAD_REQUEST, message).into_response(),
+        None => Vec::new(),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let events = match channel {
+        Some(channel) => channel.history().await,
+        None => Vec::new(),
+    };
+
+    Json(CountResult {
+        count: count(&events, since, &filter),
+        oldest: events.first().map(|event| event.time),
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::create_fields;
+
+    fn event(seq: u64, time: i64, fields: &[(&str, &str)]) -> LogEvent {
+        LogEvent {
+            seq,
+            time,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: String::new(),
+            raw_ansi: None,
+            fields: create_fields(
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            ),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let query =
+            parse("select path, count(*) where status >= 500 and path ~ '/api' group by path")
+                .unwrap();
+        assert_eq!(
+            query.columns,
+            vec![Column::Field("path".to_string()), Column::Count]
+        );
+        assert_eq!(query.conditions.len(), 2);
+        assert_eq!(query.conditions[1].op, Op::Contains);
+        assert_eq!(query.group_by, vec!["path"]);
+
+        assert!(parse("select path, count(*)").is_err());
+        assert!(parse("select * group by path").is_err());
+        assert!(parse("select path where").is_err());
+        assert!(parse("delete from bucket").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let now = 10 * 60 * 1000;
+        let events = vec![
+            event(1, 0, &[("status", "500"), ("path", "/a")]),
+            event(2, now, &[("status", "500"), ("path", "/b")]),
+            event(3, now, &[("status", "502"), ("path", "/b")]),
+            event(4, now, &[("status", "200"), ("path", "/a")]),
+        ];
+
+        let query =
+            parse("SELECT path, COUNT(*) WHERE status >= 500 AND age < 5m GROUP BY path").unwrap();
+        assert_eq!(
+            query.run(&events, now).unwrap(),
+            vec![serde_json::json!({"path": "/b", "count": 2})]
+        );
+
+        let query = parse("select count(*) where status = 500").unwrap();
+        assert_eq!(
+            query.run(&events, now).unwrap(),
+            vec![serde_json::json!({"count": 2})]
+        );
+        let query = parse("select count(*) where status = 404").unwrap();
+        assert_eq!(
+            query.run(&events, now).unwrap(),
+            vec![serde_json::json!({"count": 0})]
+        );
+
+        // Plain selects return the newest events first
+        let query = parse("select seq, path where path != '/b' limit 1").unwrap();
+        assert_eq!(
+            query.run(&events, now).unwrap(),
+            vec![serde_json::json!({"seq": "4", "path": "/a"})]
+        );
+
+        let query = parse("select * where age < soon").unwrap();
+        assert!(query.run(&events, now).is_err());
+    }
+
+    #[test]
+    fn test_count() {
+        let events = vec![
+            event(1, 1_000, &[("level", "error")]),
+            event(2, 2_000, &[("level", "info")]),
+            event(3, 3_000, &[("level", "error"), ("service", "api")]),
+        ];
+        assert_eq!(count(&events, None, &[]), 3);
+        assert_eq!(count(&events, None, &[("level", "error")]), 2);
+        assert_eq!(count(&events, Some(2_000), &[("level", "error")]), 1);
+        assert_eq!(
+            count(
+                &events,
+                None,
+                &parse_filter("level:error,service:api").unwrap()
+            ),
+            1
+        );
+        assert!(parse_filter("level").is_err());
+        assert_eq!(parse_since("5m", 600_000), Some(300_000));
+        assert_eq!(parse_since("1700000000", 0), Some(1_700_000_000_000));
+    }
+}
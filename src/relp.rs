@@ -0,0 +1,273 @@
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::AppState;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Largest frame accepted; rsyslog's default maximum message size is well under it
+const MAX_RELP_DATA_SIZE: usize = 128 * 1024;
+/// Transaction numbers and data lengths are at most nine digits, and commands 32 letters
+const MAX_RELP_HEADER_SIZE: usize = 9 + 1 + 32 + 1 + 9 + 1;
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+/// Offered back when a client opens a session; `syslog` is the only command besides the
+/// session ones
+const OPEN_RESPONSE: &str = "200 OK\nrelp_version=0\nrelp_software=log-bin\ncommands=syslog";
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    /// A header that doesn't parse, or data over the size limit
+    Invalid,
+}
+
+/// A RELP frame: `TXNR SP COMMAND SP DATALEN [SP DATA] LF`
+#[derive(Debug, PartialEq)]
+pub struct Frame {
+    pub txnr: u64,
+    pub command: String,
+    pub data: Vec<u8>,
+}
+
+/// Splits a RELP byte stream into frames
+#[derive(Default)]
+pub struct Framer {
+    buffer: Vec<u8>,
+}
+
+impl Framer {
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete frame, if one has arrived
+    pub fn next_frame(&mut self) -> Result<Option<Frame>, FrameError> {
+        // Senders may put newlines between frames
+        let leading = self
+            .buffer
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+        self.buffer.drain(..leading);
+
+        let header_end = self.buffer.len().min(MAX_RELP_HEADER_SIZE);
+        let mut fields = Vec::with_capacity(3);
+        let mut start = 0;
+        let mut data_start = None;
+        for (i, &byte) in self.buffer[..header_end].iter().enumerate() {
+            if byte == b' ' || byte == b'\n' {
+                fields.push(&self.buffer[start..i]);
+                start = i + 1;
+                if fields.len() == 3 {
+                    data_start = Some((i + 1, byte == b' '));
+                    break;
+                }
+                if byte == b'\n' {
+                    return Err(FrameError::Invalid);
+                }
+            }
+        }
+        let Some((data_start, has_data)) = data_start else {
+            if self.buffer.len() >= MAX_RELP_HEADER_SIZE {
+                return Err(FrameError::Invalid);
+            }
+            return Ok(None);
+        };
+
+        let txnr = number(fields[0])?;
+        let command = match std::str::from_utf8(fields[1]) {
+            Ok(command)
+                if !command.is_empty() && command.bytes().all(|b| b.is_ascii_alphabetic()) =>
+            {
+                command.to_string()
+            }
+            _ => return Err(FrameError::Invalid),
+        };
+        let length = number(fields[2])? as usize;
+        if length > MAX_RELP_DATA_SIZE || (length > 0 && !has_data) {
+            return Err(FrameError::Invalid);
+        }
+
+        // Without data the header's own newline ends the frame
+        let end = match has_data {
+            true => data_start + length,
+            false => data_start - 1,
+        };
+        match self.buffer.get(end) {
+            None => Ok(None),
+            Some(b'\n') => {
+                let data = self.buffer[data_start.min(end)..end].to_vec();
+                self.buffer.drain(..end + 1);
+                Ok(Some(Frame {
+                    txnr,
+                    command,
+                    data,
+                }))
+            }
+            Some(_) => Err(FrameError::Invalid),
+        }
+    }
+}
+
+fn number(bytes: &[u8]) -> Result<u64, FrameError> {
+    match std::str::from_utf8(bytes) {
+        Ok(digits) if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) => {
+            digits.parse().map_err(|_| FrameError::Invalid)
+        }
+        _ => Err(FrameError::Invalid),
+    }
+}
+
+/// A response frame acknowledging transaction `txnr`
+fn response(txnr: u64, data: &str) -> String {
+    match data.is_empty() {
+        true => format!("{} rsp 0\n", txnr),
+        false => format!("{} rsp {} {}\n", txnr, data.len(), data),
+    }
+}
+
+/// Accept RELP clients such as rsyslog's `omrelp` on `listener` and publish their
+/// messages to `bucket_id`
+pub fn spawn(listener: TcpListener, state: AppState, bucket_id: String) {
+    let bucket_id: Arc<str> = bucket_id.into();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!("RELP client connected from {}", peer);
+                    tokio::spawn(serve(stream, state.clone(), bucket_id.clone()));
+                }
+                Err(e) => warn!("RELP accept failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn serve(mut stream: TcpStream, state: AppState, bucket_id: Arc<str>) {
+    let mut framer = Framer::default();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let read = match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        framer.extend(&buffer[..read]);
+
+        // Clients send a window of frames before waiting, so take every complete one
+        let mut frames = Vec::new();
+        loop {
+            match framer.next_frame() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) => break,
+                Err(FrameError::Invalid) => {
+                    info!("Dropping RELP client with a malformed or oversized frame");
+                    let _ = stream.write_all(b"0 serverclose 0\n").await;
+                    return;
+                }
+            }
+        }
+
+        let lines: Vec<String> = frames
+            .iter()
+            .filter(|frame| frame.command == "syslog")
+            .map(|frame| {
+                let line = String::from_utf8_lossy(&frame.data);
+                line.trim_end_matches(['\r', '\n']).to_string()
+            })
+            .filter(|line| !line.is_empty())
+            .collect();
+        let syslog_response = match publish(&state, &bucket_id, &lines).await {
+            IngestOutcome::Accepted => "200 OK",
+            // Refusing the messages makes the client keep and resend them
+            IngestOutcome::Suspended => "500 bucket suspended",
+            IngestOutcome::Paused => "500 bucket paused",
+        };
+
+        let mut responses = String::new();
+        let mut closing = false;
+        for frame in &frames {
+            let data = match frame.command.as_str() {
+                "open" => OPEN_RESPONSE,
+                "syslog" => syslog_response,
+                "close" => {
+                    closing = true;
+                    ""
+                }
+                _ => "500 unknown command",
+            };
+            responses.push_str(&response(frame.txnr, data));
+        }
+        if stream.write_all(responses.as_bytes()).await.is_err() || closing {
+            return;
+        }
+    }
+}
+
+async fn publish(state: &AppState, bucket_id: &str, lines: &[String]) -> IngestOutcome {
+    if lines.is_empty() {
+        return IngestOutcome::Accepted;
+    }
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(bucket_id)
+    };
+    // Without viewers there is nowhere to publish, so acknowledge and discard
+    let Some(channel) = channel else {
+        return IngestOutcome::Accepted;
+    };
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    let outcome = ingest_lines(state, &channel, &lines).await;
+    if let IngestOutcome::Suspended = outcome {
+        warn!("RELP messages for suspended bucket {} refused", bucket_id);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(txnr: u64, command: &str, data: &[u8]) -> Option<Frame> {
+        Some(Frame {
+            txnr,
+            command: command.to_string(),
+            data: data.to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_framing() {
+        let offer = "relp_version=0\nrelp_software=librelp,1.2.16\ncommands=syslog";
+        let mut framer = Framer::default();
+        framer.extend(format!("1 open {} {}\n", offer.len(), offer).as_bytes());
+        assert_eq!(framer.next_frame(), Ok(frame(1, "open", offer.as_bytes())));
+
+        // Frames split across reads, and frames without data
+        framer.extend(b"2 syslog 10 <13>hello\n\n\n3 sys");
+        assert_eq!(framer.next_frame(), Ok(frame(2, "syslog", b"<13>hello\n")));
+        assert_eq!(framer.next_frame(), Ok(None));
+        framer.extend(b"log 4 <13>\n4 close 0\n");
+        assert_eq!(framer.next_frame(), Ok(frame(3, "syslog", b"<13>")));
+        assert_eq!(framer.next_frame(), Ok(frame(4, "close", b"")));
+        assert_eq!(framer.next_frame(), Ok(None));
+
+        assert_eq!(response(1, "200 OK"), "1 rsp 6 200 OK\n");
+        assert_eq!(response(4, ""), "4 rsp 0\n");
+    }
+
+    #[test]
+    fn test_framing_rejects_malformed() {
+        for bytes in [
+            &b"x syslog 1 a\n"[..],
+            b"1 syslog 5 ab\nxyz",
+            b"1 syslog 999999 ",
+            b"1 syslog 3\n",
+            b"1 sys-log 0\n",
+            b"1\n",
+        ] {
+            let mut framer = Framer::default();
+            framer.extend(bytes);
+            assert_eq!(framer.next_frame(), Err(FrameError::Invalid), "{:?}", bytes);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, IngestOutcome};
+use crate::{tokens, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use prost::Message;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+/// Prometheus stores the metric name as this label
+const METRIC_NAME_LABEL: &str = "__name__";
+
+/// `prometheus.WriteRequest` from the remote write 1.0 protocol, minus metadata
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Sample {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the epoch
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoteWriteParams {
+    bucket: Option<String>,
+}
+
+/// Decode a snappy-compressed `WriteRequest` into one JSON line per sample
+fn decode(body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let length = snap::raw::decompress_len(body).map_err(|_| "Body is not snappy-compressed")?;
+    if length > MAX_LOG_BODY_SIZE {
+        return Err("Decompressed body is too large");
+    }
+    let body = snap::raw::Decoder::new()
+        .decompress_vec(body)
+        .map_err(|_| "Body is not snappy-compressed")?;
+    let request =
+        WriteRequest::decode(body.as_slice()).map_err(|_| "Body is not a WriteRequest")?;
+
+    Ok(request
+        .timeseries
+        .iter()
+        .flat_map(|series| {
+            series
+                .samples
+                .iter()
+                .map(|sample| sample_line(&series.labels, sample))
+        })
+        .collect())
+}
+
+/// Render a sample as a JSON object of its metric name, labels, value and timestamp
+fn sample_line(labels: &[Label], sample: &Sample) -> String {
+    let mut object = Map::new();
+    for label in labels {
+        let key = if label.name == METRIC_NAME_LABEL {
+            "metric"
+        } else {
+            label.name.as_str()
+        };
+        object.insert(key.to_string(), Value::String(label.value.clone()));
+    }
+
+    // JSON has no NaN or infinities, so keep those in Prometheus' own spelling
+    let value = match serde_json::Number::from_f64(sample.value) {
+        Some(number) => Value::Number(number),
+        None if sample.value.is_nan() => Value::String("NaN".to_string()),
+        None if sample.value > 0.0 => Value::String("+Inf".to_string()),
+        None => Value::String("-Inf".to_string()),
+    };
+    object.insert("value".to_string(), value);
+    object.insert("timestamp".to_string(), Value::from(sample.timestamp));
+
+    Value::Object(object).to_string()
+}
+
+/// `POST /api/v1/write?bucket=<id>`: Prometheus remote write, one event per sample
+pub async fn post_write(
+    Query(params): Query<RemoteWriteParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    let Some(bucket_id) = params.bucket.filter(|bucket| !bucket.is_empty()) else {
+        return Ok((StatusCode::BAD_REQUEST, "Missing bucket parameter").into_response());
+    };
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let Some(channel) = channel else {
+        warn!(
+            "Discarding remote write for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    // Prometheus drops batches rejected with a 4xx instead of retrying them
+    let lines = match decode(&body) {
+        Ok(lines) => lines,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    if lines.is_empty() {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+    if let Err(rejection) = check_event_count(&state, lines.len(), "lower max_samples_per_send") {
+        return Ok(rejection.into_response());
+    }
+
+    info!(
+        "New remote write for bucket {}: {} samples",
+        bucket_id,
+        lines.len()
+    );
+
+    if let Some(token) = &token {
+        token.record(lines.len() as u64, body.len() as u64, chrono::Utc::now());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => Ok(StatusCode::NO_CONTENT.into_response()),
+        IngestOutcome::Suspended => {
+            Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+        }
+        IngestOutcome::Paused => Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label(name: &str, value: &str) -> Label {
+        Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let request = WriteRequest {
+            timeseries: vec![TimeSeries {
+                labels: vec![label("__name__", "up"), label("job", "api")],
+                samples: vec![
+                    Sample {
+                        value: 1.0,
+                        timestamp: 1_700_000_000_000,
+                    },
+                    Sample {
+                        value: f64::NAN,
+                        timestamp: 1_700_000_015_000,
+                    },
+                ],
+            }],
+        };
+        let body = snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .unwrap();
+
+        let lines = decode(&body).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"job":"api","metric":"up","timestamp":1700000000000,"value":1.0}"#,
+                r#"{"job":"api","metric":"up","timestamp":1700000015000,"value":"NaN"}"#,
+            ]
+        );
+
+        assert!(decode(b"not snappy").is_err());
+    }
+}
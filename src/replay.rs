@@ -0,0 +1,231 @@
+use crate::channel_manager::Channel;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::import::{check_url, fetch_text, skip_retained};
+use crate::ingest::{check_event_count, ingest_lines, read_multipart, IngestOutcome};
+use crate::parsers::ParsedEvent;
+use crate::tokens;
+use crate::upload::{paced_budget, publish_paced, until_next_minute};
+use crate::{AppState, MAX_LOG_BODY_SIZE, SUSPENSION_REASON_TEXT};
+use axum::{
+    extract::{FromRequest, Multipart, Path, Query, Request, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Longest pause between two replayed lines, regardless of the gap in the source file
+const MAX_REPLAY_GAP_MS: u64 = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayParams {
+    speed: Option<String>,
+    /// Fetch the file from here instead of reading an upload
+    url: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplaySpeed {
+    /// Publish every line immediately
+    Instant,
+    /// Pace lines by their embedded timestamps, sped up by the given factor
+    Paced(f64),
+}
+
+impl ReplaySpeed {
+    /// Parse a speed such as `instant`, `1x`, `2x` or `0.5`
+    fn parse(speed: Option<&str>) -> Option<Self> {
+        let Some(speed) = speed else {
+            return Some(Self::Instant);
+        };
+        if speed == "instant" {
+            return Some(Self::Instant);
+        }
+
+        let factor = speed
+            .strip_suffix('x')
+            .unwrap_or(speed)
+            .parse::<f64>()
+            .ok()?;
+        if factor.is_finite() && factor > 0.0 {
+            Some(Self::Paced(factor))
+        } else {
+            None
+        }
+    }
+}
+
+/// Replay a historical log file into a bucket, from a multipart upload or `?url=`
+pub async fn post_replay(
+    Path(bucket_id): Path<String>,
+    Query(params): Query<ReplayParams>,
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let speed = ReplaySpeed::parse(params.speed.as_deref()).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let token = match tokens::authorize(&state, request.headers()) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let Some(channel) = channel else {
+        // No active viewers, nothing to replay into
+        warn!(
+            "Discarding replay for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+
+    let contents = match &params.url {
+        Some(url) => {
+            let url = match check_url(url).await {
+                Ok(url) => url,
+                Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+            };
+            match fetch_text(&url).await {
+                Ok((contents, _)) => contents,
+                Err(message) => {
+                    warn!("Replay of {} failed: {}", url, message);
+                    return Ok((StatusCode::BAD_GATEWAY, message).into_response());
+                }
+            }
+        }
+        None => {
+            let mut multipart = Multipart::from_request(request, &state)
+                .await
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            // Concatenate every uploaded file into one log
+            read_multipart(&mut multipart, MAX_LOG_BODY_SIZE)
+                .await?
+                .join("\n")
+        }
+    };
+
+    let mut lines: Vec<String> = contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect();
+
+    if lines.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Err(rejection) = check_event_count(&state, lines.len(), "split the file") {
+        return Ok(rejection.into_response());
+    }
+
+    if let Some(token) = &token {
+        token.record(
+            lines.len() as u64,
+            contents.len() as u64,
+            chrono::Utc::now(),
+        );
+    }
+
+    let skipped = skip_retained(&channel, &mut lines, state.config.import_dedup_window).await;
+    info!(
+        "Replaying {} lines into bucket {} ({:?}), skipping {} already in history",
+        lines.len(),
+        bucket_id,
+        speed,
+        skipped
+    );
+
+    // Both speeds publish in the background, within the bucket's rate limit
+    match speed {
+        ReplaySpeed::Instant => {
+            tokio::spawn(async move {
+                if let Err(reason) =
+                    publish_paced(&state, &bucket_id, &channel, &lines, None, |_| {}).await
+                {
+                    warn!("Replay into bucket {} stopped: {}", bucket_id, reason);
+                }
+            });
+        }
+        ReplaySpeed::Paced(factor) => {
+            tokio::spawn(replay_paced(state, channel, lines, factor));
+        }
+    }
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// Publish lines one at a time, sleeping for the gap between their embedded timestamps
+async fn replay_paced(state: AppState, channel: Arc<Channel>, lines: Vec<String>, factor: f64) {
+    let _turn = channel.upload_turn().await;
+    let mut previous_time: Option<i64> = None;
+
+    for line in lines {
+        let mut event = ParsedEvent::new(line.clone());
+        event.parse();
+
+        if let Some(time) = event.embedded_time() {
+            if let Some(previous) = previous_time {
+                let gap_ms = (time - previous).max(0) as f64 / factor;
+                let gap_ms = (gap_ms as u64).min(MAX_REPLAY_GAP_MS);
+                tokio::time::sleep(tokio::time::Duration::from_millis(gap_ms)).await;
+            }
+            previous_time = Some(time);
+        }
+
+        // A replay sped up past the rate limit waits for the next minute, like an upload
+        while paced_budget(&channel) == 0 {
+            if channel.is_suspended() {
+                warn!("Replay stopped: bucket was suspended");
+                return;
+            }
+            tokio::time::sleep(until_next_minute()).await;
+        }
+
+        match ingest_lines(&state, &channel, &[line.as_str()]).await {
+            IngestOutcome::Accepted => {}
+            IngestOutcome::Suspended => {
+                warn!("Replay stopped: bucket was suspended");
+                return;
+            }
+            IngestOutcome::Paused => {
+                warn!("Replay stopped: bucket was paused");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_speed() {
+        assert_eq!(ReplaySpeed::parse(None), Some(ReplaySpeed::Instant));
+        assert_eq!(
+            ReplaySpeed::parse(Some("instant")),
+            Some(ReplaySpeed::Instant)
+        );
+        assert_eq!(
+            ReplaySpeed::parse(Some("2x")),
+            Some(ReplaySpeed::Paced(2.0))
+        );
+        assert_eq!(
+            ReplaySpeed::parse(Some("0.5")),
+            Some(ReplaySpeed::Paced(0.5))
+        );
+        assert_eq!(ReplaySpeed::parse(Some("0x")), None);
+        assert_eq!(ReplaySpeed::parse(Some("fast")), None);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::export::if_none_match;
+use crate::feed::escape;
+use crate::models::LogEvent;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+const TEMPLATE: &str = include_str!("templates/report.html");
+/// Fields the viewer shows as an event's message instead of among its fields
+const MESSAGE_KEYS: &[&str] = &["msg", "message", ""];
+/// Gap between events, in milliseconds, that the viewer marks with a separator
+const SEPARATOR_GAP_MS: i64 = 3000;
+/// Nothing in a report loads from elsewhere or runs, whoever ends up opening it
+const REPORT_CSP: &str = "default-src 'none'; style-src 'unsafe-inline'";
+
+/// `GET /{bucket_id}/report.html`: the bucket's retained events as one self-contained HTML
+/// page, laid out and colored like the viewer, to attach to an incident ticket
+pub async fn get_report(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let (events, tz) = match channel {
+        Some(channel) => (
+            channel.history().await,
+            channel.settings().await.display_timezone(),
+        ),
+        None => (Vec::new(), Tz::UTC),
+    };
+
+    let etag = format!("\"{}\"", events.last().map_or(0, |event| event.seq));
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::ETAG, etag.parse().unwrap());
+    response_headers.insert(header::CACHE_CONTROL, "no-cache".parse().unwrap());
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    response_headers.insert(
+        header::CONTENT_TYPE,
+        "text/html; charset=utf-8".parse().unwrap(),
+    );
+    response_headers.insert(header::CONTENT_SECURITY_POLICY, REPORT_CSP.parse().unwrap());
+    response_headers.insert(header::X_CONTENT_TYPE_OPTIONS, "nosniff".parse().unwrap());
+    response_headers.insert(
+        header::CONTENT_DISPOSITION,
+        format!("inline; filename=\"{}.html\"", bucket_id)
+            .parse()
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+    );
+    let generated = chrono::Utc::now().timestamp_millis();
+    Ok((response_headers, render(&bucket_id, &events, tz, generated)).into_response())
+}
+
+fn render(bucket_id: &str, events: &[LogEvent], tz: Tz, generated: i64) -> String {
+    let summary = match (events.first(), events.last()) {
+        (Some(first), Some(last)) => format!(
+            "{} events from {} to {} ({})",
+            events.len(),
+            timestamp(first.time, tz),
+            timestamp(last.time, tz),
+            tz.name()
+        ),
+        _ => "No events".to_string(),
+    };
+
+    let mut items = String::new();
+    for (i, event) in events.iter().enumerate() {
+        let separator = i > 0 && event.time > events[i - 1].time + SEPARATOR_GAP_MS;
+        items.push_str(&event_item(event, tz, separator));
+    }
+
+    // Events go in last so their text can't be mistaken for a placeholder
+    TEMPLATE
+        .replace("{{title}}", &escape(&format!("log-bin: {}", bucket_id)))
+        .replace("{{summary}}", &escape(&summary))
+        .replace("{{generated}}", &escape(&timestamp(generated, tz)))
+        .replace("{{events}}", &items)
+}
+
+/// One event as the viewer lists it: time, message, then its fields with their colors
+fn event_item(event: &LogEvent, tz: Tz, separator: bool) -> String {
+    let mut item = match separator {
+        true => String::from("<li class=\"separator\">"),
+        false => String::from("<li>"),
+    };
+    item.push_str(&format!(
+        "<span class=\"timestamp\">{}</span>",
+        timestamp(event.time, tz)
+    ));
+
+    let message_key = MESSAGE_KEYS
+        .iter()
+        .find(|key| event.fields.contains_key(**key));
+    let message = match message_key {
+        Some(key) => Some(event.fields[*key].value.as_str()),
+        None if event.fields.is_empty() => Some(event.raw.as_str()),
+        None => None,
+    };
+    if let Some(message) = message {
+        item.push_str(&format!(
+            "<span class=\"message\">{}</span>",
+            escape(message)
+        ));
+    }
+
+    let mut fields: Vec<_> = event
+        .fields
+        .iter()
+        .filter(|(key, _)| message_key.is_none_or(|message_key| key != message_key))
+        .collect();
+    fields.sort_by_key(|(key, _)| *key);
+    if !fields.is_empty() {
+        item.push_str("<ul class=\"meta\">");
+        for (key, field) in fields {
+            item.push_str(&format!(
+                "<li><label title=\"{}\"><i style=\"background-color: {}\"></i>{}</label>{}</li>",
+                escape(key),
+                escape(&field.color),
+                escape(key),
+                escape(&field.value)
+            ));
+        }
+        item.push_str("</ul>");
+    }
+    item.push_str("</li>\n");
+    item
+}
+
+fn timestamp(ms: i64, tz: Tz) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .unwrap_or_default()
+        .with_timezone(&tz)
+        .format("%Y-%m-%d %H:%M:%S%.3f")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsers::ParsedEvent;
+
+    fn event(seq: u64, time: i64, raw: &str) -> LogEvent {
+        let mut parsed = ParsedEvent::new(raw.to_string());
+        parsed.parse();
+        LogEvent {
+            seq,
+            time,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: raw.to_string(),
+            raw_ansi: None,
+            fields: parsed.fields,
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let events = [
+            event(1, 1_704_067_200_000, "<script>alert(1)</script>"),
+            event(
+                2,
+                1_704_067_210_000,
+                r#"{"msg":"disk full","host":"web-1"}"#,
+            ),
+        ];
+        let html = render("my-bucket-01", &events, Tz::UTC, 1_704_067_260_000);
+
+        assert!(html.contains("<title>log-bin: my-bucket-01</title>"));
+        assert!(html.contains("2 events from 2024-01-01 00:00:00.000 to 2024-01-01 00:00:10.000"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<li class=\"separator\"><span class=\"timestamp\">"));
+        assert!(html.contains("<span class=\"message\">disk full</span>"));
+
+        let color = &events[1].fields["host"].color;
+        assert!(html.contains(&format!(
+            "background-color: {}\"></i>host</label>web-1",
+            color
+        )));
+        assert!(!html.contains("{{"));
+    }
+}
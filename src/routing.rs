@@ -0,0 +1,231 @@
+use crate::changes;
+use crate::channel_manager::{Channel, ChannelManager};
+use crate::demo::DEMO_BUCKET_ID;
+use crate::models::LogEvent;
+use crate::{ids, ingest, tokens, AppState};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::info;
+
+const MAX_ROUTES_PER_BUCKET: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteAction {
+    /// Publish to both buckets
+    #[default]
+    Copy,
+    /// Publish to the target bucket only
+    Move,
+}
+
+/// Send events whose `field` is `value` on to another bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRule {
+    pub field: String,
+    pub value: String,
+    pub target: String,
+    #[serde(default)]
+    pub action: RouteAction,
+}
+
+impl RouteRule {
+    fn matches(&self, event: &LogEvent) -> bool {
+        event
+            .fields
+            .get(&self.field)
+            .is_some_and(|data| data.value == self.value)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RouteRuleList {
+    pub routes: Vec<RouteRule>,
+}
+
+/// Publish `event` to the targets of any matching routes, returning whether it should
+/// still be published to `channel`. Targets pause and rate-limit routed events as they do
+/// their own, but routed events don't pass through the target's own routes, so rules
+/// can't loop. A move to a bucket with no channel, or one that refuses the event, keeps
+/// the event where it is rather than losing it.
+pub async fn route(manager: &RwLock<ChannelManager>, channel: &Channel, event: &LogEvent) -> bool {
+    let routes = channel.routes().await;
+    if routes.is_empty() {
+        return true;
+    }
+
+    let mut keep = true;
+    for rule in routes.iter().filter(|rule| rule.matches(event)) {
+        let target = {
+            let manager = manager.read().await;
+            manager.get_channel(&rule.target)
+        };
+        let Some(target) = target else {
+            continue;
+        };
+
+        if ingest::accept_routed_event(&target, event.clone()).await
+            && rule.action == RouteAction::Move
+        {
+            keep = false;
+        }
+    }
+    keep
+}
+
+pub async fn get_routes(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<RouteRuleList> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let routes = match channel {
+        Some(channel) => channel.routes().await,
+        None => Vec::new(),
+    };
+    Json(RouteRuleList { routes })
+}
+
+/// Check a bucket's routing rules before they replace its current ones
+pub fn validate_routes(bucket_id: &str, routes: &[RouteRule]) -> Result<(), String> {
+    if routes.len() > MAX_ROUTES_PER_BUCKET {
+        return Err(format!(
+            "A bucket can have at most {} routing rules",
+            MAX_ROUTES_PER_BUCKET
+        ));
+    }
+
+    if let Some(rule) = routes.iter().find(|rule| {
+        rule.target == bucket_id
+            || rule.target == DEMO_BUCKET_ID
+            || rule.target.is_empty()
+            || ids::is_reserved(&rule.target)
+    }) {
+        return Err(format!("Events can't be routed to {:?}", rule.target));
+    }
+
+    Ok(())
+}
+
+/// Replace the set of routing rules for a bucket
+pub async fn put_routes(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(list): Json<RouteRuleList>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if let Err(message) = validate_routes(&bucket_id, &list.routes) {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!(
+        "Registered {} routing rules for bucket {}",
+        list.routes.len(),
+        bucket_id
+    );
+    let previous = channel.set_routes(list.routes.clone()).await;
+    channel.publish_config_change(
+        "routes",
+        &previous,
+        &list.routes,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_manager::ChannelContext;
+    use crate::history::HistoryBackend;
+    use crate::parsers::create_fields;
+    use crate::MAX_LOG_LINES_PER_MINUTE;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    fn event(level: &str) -> LogEvent {
+        LogEvent {
+            seq: 0,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: format!("level={}", level),
+            raw_ansi: None,
+            fields: create_fields(HashMap::from([("level".to_string(), level.to_string())])),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let manager = Arc::new(RwLock::new(ChannelManager::new(
+            HistoryBackend::Memory,
+            ChannelContext::default(),
+        )));
+        let (source, errors, audit) = {
+            let mut manager = manager.write().await;
+            (
+                manager.get_or_create_channel("routing-source"),
+                manager.get_or_create_channel("routing-errors"),
+                manager.get_or_create_channel("routing-audit"),
+            )
+        };
+        let rule = |value: &str, target: &str, action| RouteRule {
+            field: "level".to_string(),
+            value: value.to_string(),
+            target: target.to_string(),
+            action,
+        };
+        source
+            .set_routes(vec![
+                rule("error", "routing-errors", RouteAction::Copy),
+                rule("audit", "routing-audit", RouteAction::Move),
+                rule("debug", "routing-missing", RouteAction::Move),
+            ])
+            .await;
+
+        assert!(route(&manager, &source, &event("info")).await);
+        assert!(route(&manager, &source, &event("error")).await);
+        assert!(!route(&manager, &source, &event("audit")).await);
+        // Moves to a bucket that doesn't exist keep the event
+        assert!(route(&manager, &source, &event("debug")).await);
+
+        assert_eq!(errors.last_seq(), 1);
+        assert_eq!(audit.last_seq(), 1);
+
+        // A target suspended by its rate limit refuses routed events, so moves keep them
+        audit.record_logs(MAX_LOG_LINES_PER_MINUTE);
+        assert!(audit.is_suspended());
+        assert!(route(&manager, &source, &event("audit")).await);
+        assert_eq!(audit.last_seq(), 1);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::metrics::METRICS;
+use crate::parsers::{create_fields, ParseOutcome, ParsedEvent};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+const RULES_POLL_SECS: u64 = 2;
+const MASKED_VALUE: &str = "[masked]";
+
+// The regex engine matches in linear time, so a pattern's cost is bounded by its compiled
+// size and the line length; these keep both small enough that one line can't stall ingest
+/// Largest compiled program a custom parser may have, in bytes
+pub(crate) const MAX_PATTERN_SIZE: usize = 2 * 1024 * 1024;
+/// Cache each custom parser's lazy DFA may grow to before it falls back to slower matching
+const MAX_PATTERN_DFA_SIZE: usize = 4 * 1024 * 1024;
+/// Deepest nesting of groups and repetitions a custom parser may use
+const MAX_PATTERN_NESTING: u32 = 32;
+/// Time a line may spend in custom parsers before the remaining ones are skipped
+const PARSE_DEADLINE: Duration = Duration::from_millis(20);
+
+/// A regex with named capture groups, tried when none of the built-in parsers match
+#[derive(Debug, Deserialize)]
+struct ParserRule {
+    name: String,
+    pattern: String,
+}
+
+/// A change applied to the fields of every parsed event
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+enum TransformRule {
+    Rename { field: String, to: String },
+    Drop { field: String },
+    Mask { field: String },
+    Set { field: String, value: String },
+}
+
+/// On-disk format of the rules file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct RulesFile {
+    parsers: Vec<ParserRule>,
+    transforms: Vec<TransformRule>,
+}
+
+pub struct RuleSet {
+    pub version: u64,
+    parsers: Vec<(String, Regex)>,
+    transforms: Vec<TransformRule>,
+}
+
+impl RuleSet {
+    fn compile(file: RulesFile, version: u64) -> Result<Self, String> {
+        let parsers = file
+            .parsers
+            .into_iter()
+            .map(|rule| {
+                compile_pattern(&rule.pattern)
+                    .map(|regex| (rule.name.clone(), regex))
+                    .map_err(|e| format!("parser {}: {}", rule.name, e))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            version,
+            parsers,
+            transforms: file.transforms,
+        })
+    }
+
+    /// Run custom parsers over unparsed events, then apply transforms to the fields
+    pub fn apply(&self, event: &mut ParsedEvent) {
+        if event.parser.is_none() {
+            let started = Instant::now();
+            for (name, regex) in &self.parsers {
+                // Checked between patterns, since a match in progress can't be interrupted
+                if started.elapsed() > PARSE_DEADLINE {
+                    METRICS.aborted_parses.inc();
+                    break;
+                }
+                if let Some(captures) = regex.captures(&event.input_string) {
+                    let data = regex
+                        .capture_names()
+                        .flatten()
+                        .filter_map(|group| {
+                            captures
+                                .name(group)
+                                .map(|m| (group.to_string(), m.as_str().to_string()))
+                        })
+                        .collect();
+                    // A pattern that only matches part of the line is less sure of it
+                    let matched = captures.get(0).map_or(0, |m| m.len());
+                    let coverage = matched as f32 / event.input_string.len().max(1) as f32;
+                    event.parser = Some(name.clone());
+                    event.outcome = ParseOutcome::Custom;
+                    event.confidence = Some((coverage * 100.0).round() / 100.0);
+                    event.fields = create_fields(data);
+                    break;
+                }
+            }
+        }
+
+        for transform in &self.transforms {
+            let fields = &mut event.fields;
+            match transform {
+                TransformRule::Rename { field, to } => {
+                    if let Some(data) = fields.remove(field) {
+                        fields.extend(create_fields(HashMap::from([(to.clone(), data.value)])));
+                    }
+                }
+                TransformRule::Drop { field } => {
+                    fields.remove(field);
+                }
+                TransformRule::Mask { field } => {
+                    if let Some(data) = fields.get_mut(field) {
+                        data.value = MASKED_VALUE.to_string();
+                    }
+                }
+                TransformRule::Set { field, value } => {
+                    fields.extend(create_fields(HashMap::from([(
+                        field.clone(),
+                        value.clone(),
+                    )])));
+                }
+            }
+        }
+    }
+}
+
+/// Compile a user-supplied pattern within the limits that keep it from stalling ingest
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(MAX_PATTERN_SIZE)
+        .dfa_size_limit(MAX_PATTERN_DFA_SIZE)
+        .nest_limit(MAX_PATTERN_NESTING)
+        .build()
+}
+
+/// The ruleset applied to newly ingested events, swapped out whenever the rules file changes
+#[derive(Default)]
+pub struct ActiveRules {
+    rules: RwLock<Option<Arc<RuleSet>>>,
+}
+
+impl ActiveRules {
+    /// The currently loaded ruleset, if a rules file is configured and valid
+    pub fn get(&self) -> Option<Arc<RuleSet>> {
+        self.rules.read().unwrap().clone()
+    }
+
+    fn set(&self, rules: RuleSet) {
+        *self.rules.write().unwrap() = Some(Arc::new(rules));
+    }
+}
+
+fn load(path: &Path, version: u64) -> Result<RuleSet, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let file: RulesFile = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    RuleSet::compile(file, version)
+}
+
+/// Load the rules file into `active` and reload it whenever it changes, for as long as the
+/// task runs; a broken file keeps the previous rules
+pub async fn watch(path: PathBuf, active: Arc<ActiveRules>) {
+    let mut version = 0;
+    let mut last_modified: Option<SystemTime> = None;
+
+    loop {
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            match load(&path, version + 1) {
+                Ok(rules) => {
+                    version = rules.version;
+                    info!(
+                        "Loaded ruleset v{} from {}: {} parsers, {} transforms",
+                        version,
+                        path.display(),
+                        rules.parsers.len(),
+                        rules.transforms.len()
+                    );
+                    active.set(rules);
+                }
+                Err(e) => warn!("Ignoring invalid rules file {}: {}", path.display(), e),
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(RULES_POLL_SECS)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ruleset(json: &str) -> RuleSet {
+        RuleSet::compile(serde_json::from_str(json).unwrap(), 1).unwrap()
+    }
+
+    #[test]
+    fn test_custom_parser_and_transforms() {
+        let rules = ruleset(
+            r#"{
+                "parsers": [{"name": "access", "pattern": "^(?P<level>[A-Z]+) (?P<user>\\w+) (?P<token>\\S+)$"}],
+                "transforms": [
+                    {"action": "rename", "field": "user", "to": "username"},
+                    {"action": "mask", "field": "token"},
+                    {"action": "drop", "field": "missing"},
+                    {"action": "set", "field": "env", "value": "dev"}
+                ]
+            }"#,
+        );
+
+        let mut event = ParsedEvent::new("WARN alice s3cret".to_string());
+        event.parse();
+        rules.apply(&mut event);
+
+        assert_eq!(event.parser.as_deref(), Some("access"));
+        assert_eq!(event.outcome, ParseOutcome::Custom);
+        assert_eq!(event.confidence, Some(1.0));
+        assert_eq!(event.fields["level"].value, "WARN");
+        assert_eq!(event.fields["username"].value, "alice");
+        assert!(!event.fields.contains_key("user"));
+        assert_eq!(event.fields["token"].value, MASKED_VALUE);
+        assert_eq!(event.fields["env"].value, "dev");
+    }
+
+    #[test]
+    fn test_builtin_parsers_take_precedence() {
+        let rules = ruleset(r#"{"parsers": [{"name": "any", "pattern": "(?P<all>.*)"}]}"#);
+
+        let mut event = ParsedEvent::new(r#"{"level":"info"}"#.to_string());
+        event.parse();
+        rules.apply(&mut event);
+        assert_eq!(event.parser.as_deref(), Some("json"));
+    }
+
+    #[test]
+    fn test_invalid_pattern_rejected() {
+        let file = serde_json::from_str(r#"{"parsers": [{"name": "bad", "pattern": "("}]}"#);
+        assert!(RuleSet::compile(file.unwrap(), 1).is_err());
+    }
+
+    #[test]
+    fn test_oversized_pattern_rejected() {
+        let compile = |pattern: &str| {
+            let rule = serde_json::json!({"parsers": [{"name": "big", "pattern": pattern}]});
+            RuleSet::compile(serde_json::from_value(rule).unwrap(), 1)
+        };
+        assert!(compile(r"(?P<word>\w{20}) (?P<rest>\w+)").is_ok());
+        assert!(compile(r"(?P<word>\w{5000})").is_err());
+        assert!(compile(&format!("{}a{}", "(".repeat(64), ")".repeat(64))).is_err());
+    }
+}
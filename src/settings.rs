@@ -0,0 +1,236 @@
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::models::LogEvent;
+use crate::pacing::Pacing;
+use crate::severity::Severity;
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// How long events of one severity are kept in a bucket's history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetentionRule {
+    pub severity: Severity,
+    pub max_age_secs: u64,
+}
+
+/// Per-bucket behaviour that producers and viewers can configure
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BucketSettings {
+    /// Origins allowed to post events from a browser when the server restricts CORS; `*` allows any
+    pub cors_origins: Vec<String>,
+    /// Lowercase field keys and turn `-` into `_`, so `Content-Type` and `content_type`
+    /// show up as one field
+    pub normalize_keys: bool,
+    /// Fold stack traces and other continuation lines into the event before them. A post's
+    /// last event waits a moment in case the next post continues it.
+    pub multiline: bool,
+    /// Per-severity limits on how long history keeps events; events without a matching
+    /// rule are kept until newer events push them out
+    pub retention: Vec<RetentionRule>,
+    /// IANA time zone that events' `isoTime` is given in, such as `Europe/London`; UTC
+    /// when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Cap on how fast events reach viewers, queuing bursts instead of sending them at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pacing: Option<Pacing>,
+}
+
+impl BucketSettings {
+    /// Whether `event` is still within the retention rule for its severity at `now` (epoch ms)
+    pub fn retains(&self, event: &LogEvent, now: i64) -> bool {
+        let Some(severity) = Severity::of(event) else {
+            return true;
+        };
+        self.retention
+            .iter()
+            .find(|rule| rule.severity == severity)
+            .is_none_or(|rule| now - event.time < (rule.max_age_secs * 1000) as i64)
+    }
+
+    /// Check settings before they replace a bucket's current ones
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retention.iter().any(|rule| rule.max_age_secs == 0) {
+            return Err("Retention rules need a non-zero max_age_secs".to_string());
+        }
+
+        if let Some(tz) = &self.timezone {
+            if tz.parse::<Tz>().is_err() {
+                return Err(format!("Unknown time zone: {}", tz));
+            }
+        }
+
+        if let Some(pacing) = &self.pacing {
+            pacing.validate()?;
+        }
+
+        let mut severities: Vec<Severity> =
+            self.retention.iter().map(|rule| rule.severity).collect();
+        severities.sort();
+        severities.dedup();
+        if severities.len() != self.retention.len() {
+            return Err("Only one retention rule is allowed per severity".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn display_timezone(&self) -> Tz {
+        self.timezone
+            .as_deref()
+            .and_then(|tz| tz.parse().ok())
+            .unwrap_or(Tz::UTC)
+    }
+
+    pub fn allows_origin(&self, origin: &str) -> bool {
+        self.cors_origins
+            .iter()
+            .any(|allowed| allowed == "*" || allowed.trim_end_matches('/') == origin)
+    }
+}
+
+pub async fn get_settings(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<BucketSettings> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    match channel {
+        Some(channel) => Json(channel.settings().await),
+        None => Json(BucketSettings::default()),
+    }
+}
+
+/// Replace a bucket's settings
+pub async fn put_settings(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(settings): Json<BucketSettings>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if let Err(message) = settings.validate() {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!("Updated settings for bucket {}", bucket_id);
+    let previous = channel.set_settings(settings.clone()).await;
+    channel.publish_config_change(
+        "settings",
+        &previous,
+        &settings,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LocalTime;
+    use crate::parsers::create_fields;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_allows_origin() {
+        let settings = BucketSettings {
+            cors_origins: vec!["https://app.example/".to_string()],
+            ..Default::default()
+        };
+        assert!(settings.allows_origin("https://app.example"));
+        assert!(!settings.allows_origin("https://evil.example"));
+
+        let settings = BucketSettings {
+            cors_origins: vec!["*".to_string()],
+            ..Default::default()
+        };
+        assert!(settings.allows_origin("https://anything.example"));
+        assert!(!BucketSettings::default().allows_origin("https://app.example"));
+    }
+
+    #[test]
+    fn test_retention() {
+        let settings = BucketSettings {
+            retention: vec![
+                RetentionRule {
+                    severity: Severity::Info,
+                    max_age_secs: 60,
+                },
+                RetentionRule {
+                    severity: Severity::Error,
+                    max_age_secs: 3600,
+                },
+            ],
+            ..Default::default()
+        };
+        let event = |level: &str| LogEvent {
+            seq: 1,
+            time: 0,
+            reported_time: None,
+            clock_skewed: false,
+            had_invalid_utf8: false,
+            raw: String::new(),
+            raw_ansi: None,
+            fields: create_fields(HashMap::from([("level".to_string(), level.to_string())])),
+            parser: None,
+            ruleset_version: None,
+            parser_confidence: None,
+            parser_candidates: Vec::new(),
+            expires_at: None,
+            local_time: None,
+        };
+
+        assert!(settings.retains(&event("info"), 59_000));
+        assert!(!settings.retains(&event("INFO"), 60_000));
+        assert!(settings.retains(&event("error"), 60_000));
+        assert!(!settings.retains(&event("err"), 3_600_000));
+        // No rule for debug, and no level at all, means no age limit
+        assert!(settings.retains(&event("debug"), 3_600_000));
+        assert!(settings.retains(&event("loud"), 3_600_000));
+    }
+
+    #[test]
+    fn test_display_timezone() {
+        let settings = BucketSettings {
+            timezone: Some("Europe/London".to_string()),
+            ..Default::default()
+        };
+        // 2024-07-01T12:00:00Z, during British Summer Time
+        let local = LocalTime::new(1_719_835_200_000, settings.display_timezone());
+        assert_eq!(local.iso_time, "2024-07-01T13:00:00.000+01:00");
+        assert_eq!(local.timezone, "Europe/London");
+
+        let local = LocalTime::new(
+            1_719_835_200_000,
+            BucketSettings::default().display_timezone(),
+        );
+        assert_eq!(local.iso_time, "2024-07-01T12:00:00.000Z");
+        assert_eq!(local.timezone, "UTC");
+    }
+}
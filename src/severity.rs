@@ -0,0 +1,103 @@
+use crate::models::LogEvent;
+use serde::{Deserialize, Serialize};
+
+/// Field keys that may carry an event's level, in priority order
+const SEVERITY_KEYS: &[&str] = &[
+    "level",
+    "severity",
+    "lvl",
+    "loglevel",
+    "log_level",
+    "log.level",
+    "levelname",
+];
+
+/// An event's level, normalized across the spellings and numbering schemes producers use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Trace,
+    Debug,
+    Info,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Severity {
+    /// Read a level name, a syslog severity (0-7) or a pino/bunyan level (10-60)
+    pub fn parse(value: &str) -> Option<Self> {
+        if let Ok(number) = value.trim().parse::<u32>() {
+            return match number {
+                0..=2 => Some(Severity::Critical),
+                3 => Some(Severity::Error),
+                4 => Some(Severity::Warning),
+                5 | 6 => Some(Severity::Info),
+                7 => Some(Severity::Debug),
+                10 => Some(Severity::Trace),
+                20 => Some(Severity::Debug),
+                30 => Some(Severity::Info),
+                40 => Some(Severity::Warning),
+                50 => Some(Severity::Error),
+                60 => Some(Severity::Critical),
+                _ => None,
+            };
+        }
+
+        match value.trim().to_lowercase().as_str() {
+            "trace" | "verbose" => Some(Severity::Trace),
+            "debug" | "dbg" => Some(Severity::Debug),
+            "info" | "information" | "informational" | "notice" => Some(Severity::Info),
+            "warn" | "warning" => Some(Severity::Warning),
+            "error" | "err" => Some(Severity::Error),
+            "critical" | "crit" | "fatal" | "alert" | "emerg" | "emergency" | "panic" => {
+                Some(Severity::Critical)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Trace => "trace",
+            Severity::Debug => "debug",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+            Severity::Critical => "critical",
+        }
+    }
+
+    /// Whether a field key is one that can carry an event's level
+    pub fn is_key(key: &str) -> bool {
+        SEVERITY_KEYS
+            .iter()
+            .any(|name| key.eq_ignore_ascii_case(name))
+    }
+
+    /// The severity of a parsed event, if it has a recognizable level field
+    pub fn of(event: &LogEvent) -> Option<Self> {
+        SEVERITY_KEYS.iter().find_map(|key| {
+            event
+                .fields
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .and_then(|(_, field)| Severity::parse(&field.value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Severity::parse("WARN"), Some(Severity::Warning));
+        assert_eq!(Severity::parse("fatal"), Some(Severity::Critical));
+        assert_eq!(Severity::parse("3"), Some(Severity::Error));
+        assert_eq!(Severity::parse("30"), Some(Severity::Info));
+        assert_eq!(Severity::parse("loud"), None);
+        assert_eq!(Severity::parse("99"), None);
+    }
+}
@@ -0,0 +1,236 @@
+use crate::models::StatsEvent;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{sse, IntoResponse, Response, Sse},
+    Json,
+};
+use futures_util::StreamExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::time::Duration;
+
+/// Seconds between stats samples
+pub const STATS_SAMPLE_SECS: u64 = 10;
+/// Samples kept per bucket: one hour at the sample interval
+const STATS_HISTORY_SAMPLES: usize = 60 * 60 / STATS_SAMPLE_SECS as usize;
+/// How often `stats+patch` streams check for changed counts
+pub const STATS_PATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct StatsSample {
+    /// End of the sample interval, in epoch milliseconds
+    pub time: i64,
+    /// Log events published during the interval
+    pub events: u64,
+    /// Subscribers connected at the end of the interval
+    pub subscribers: usize,
+}
+
+/// A rolling window of a bucket's event counts and subscriber counts
+#[derive(Debug)]
+pub struct StatsHistory {
+    samples: VecDeque<StatsSample>,
+    /// Sequence number at the previous sample, to count events since
+    last_seq: u64,
+}
+
+impl StatsHistory {
+    pub fn new(last_seq: u64) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(STATS_HISTORY_SAMPLES),
+            last_seq,
+        }
+    }
+
+    pub fn record(&mut self, time: i64, last_seq: u64, subscribers: usize) {
+        if self.samples.len() >= STATS_HISTORY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(StatsSample {
+            time,
+            events: last_seq.saturating_sub(self.last_seq),
+            subscribers,
+        });
+        self.last_seq = last_seq;
+    }
+
+    /// Samples, oldest first
+    pub fn samples(&self) -> Vec<StatsSample> {
+        self.samples.iter().copied().collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsHistoryResponse {
+    #[serde(rename = "intervalSecs")]
+    pub interval_secs: u64,
+    pub samples: Vec<StatsSample>,
+}
+
+/// Recent event rates and subscriber counts, so a new viewer can draw them straight away
+pub async fn get_stats_history(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Json<StatsHistoryResponse> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let samples = match channel {
+        Some(channel) => channel.stats_history().await,
+        None => Vec::new(),
+    };
+    Json(StatsHistoryResponse {
+        interval_secs: STATS_SAMPLE_SECS,
+        samples,
+    })
+}
+
+/// `GET /{bucket_id}/stats`: the bucket's current stats, or with `Accept: text/event-stream`
+/// the `stats+patch` stream: a `stats` snapshot, then `patch` events holding JSON Patch
+/// (RFC 6902) operations that bring it up to date as counts change
+pub async fn get_stats(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let wants_stream = headers
+        .get(header::ACCEPT)
+        .is_some_and(|accept| accept == "text/event-stream");
+    if !wants_stream {
+        return match channel {
+            Some(channel) => Json(channel.get_stats()).into_response(),
+            None => Json(StatsEvent::default()).into_response(),
+        };
+    }
+    let Some(channel) = channel else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let stream = channel
+        .subscribe_stats()
+        .map(|event| -> Result<sse::Event, Infallible> {
+            Ok(sse::Event::default()
+                .event(&event.event_type)
+                .data(event.data))
+        });
+    let (mut parts, body) = Sse::new(stream)
+        .keep_alive(sse::KeepAlive::new().interval(Duration::from_secs(15)))
+        .into_response()
+        .into_parts();
+    parts
+        .headers
+        .extend(state.config.proxy_profile.stream_headers());
+    Response::from_parts(parts, body)
+}
+
+/// JSON Patch operations that turn `before` into `after`, comparing object keys and array
+/// items one by one
+pub fn json_patch(before: &Value, after: &Value) -> Vec<Value> {
+    let mut operations = Vec::new();
+    patch_into("", before, after, &mut operations);
+    operations
+}
+
+fn patch_into(path: &str, before: &Value, after: &Value, operations: &mut Vec<Value>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in before {
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match after.get(key) {
+                    Some(after) => patch_into(&path, value, after, operations),
+                    None => operations.push(json!({"op": "remove", "path": path})),
+                }
+            }
+            for (key, value) in after.iter().filter(|(key, _)| !before.contains_key(*key)) {
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                operations.push(json!({"op": "add", "path": path, "value": value}));
+            }
+        }
+        (Value::Array(before), Value::Array(after)) => {
+            for (i, (before, after)) in before.iter().zip(after).enumerate() {
+                patch_into(&format!("{}/{}", path, i), before, after, operations);
+            }
+            for (i, value) in after.iter().enumerate().skip(before.len()) {
+                let path = format!("{}/{}", path, i);
+                operations.push(json!({"op": "add", "path": path, "value": value}));
+            }
+            // Remove from the end so earlier indices stay valid
+            for i in (after.len()..before.len()).rev() {
+                operations.push(json!({"op": "remove", "path": format!("{}/{}", path, i)}));
+            }
+        }
+        (before, after) if before != after => {
+            operations.push(json!({"op": "replace", "path": path, "value": after}));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_patch() {
+        let before = json!({
+            "clientCount": 2,
+            "clients": ["a", "b"],
+            "parseOutcomes": {"json": 3},
+        });
+        let after = json!({
+            "clientCount": 1,
+            "clients": ["b"],
+            "parseOutcomes": {"json": 3, "logfmt/x": 1},
+        });
+        assert_eq!(
+            json_patch(&before, &after),
+            vec![
+                json!({"op": "replace", "path": "/clientCount", "value": 1}),
+                json!({"op": "replace", "path": "/clients/0", "value": "b"}),
+                json!({"op": "remove", "path": "/clients/1"}),
+                json!({"op": "add", "path": "/parseOutcomes/logfmt~1x", "value": 1}),
+            ]
+        );
+        assert!(json_patch(&after, &after).is_empty());
+    }
+
+    #[test]
+    fn test_stats_history_window() {
+        let mut history = StatsHistory::new(5);
+        history.record(1_000, 8, 1);
+        history.record(2_000, 8, 2);
+        assert_eq!(
+            history.samples(),
+            vec![
+                StatsSample {
+                    time: 1_000,
+                    events: 3,
+                    subscribers: 1
+                },
+                StatsSample {
+                    time: 2_000,
+                    events: 0,
+                    subscribers: 2
+                },
+            ]
+        );
+
+        for i in 0..STATS_HISTORY_SAMPLES as i64 {
+            history.record(3_000 + i, 8, 0);
+        }
+        let samples = history.samples();
+        assert_eq!(samples.len(), STATS_HISTORY_SAMPLES);
+        assert_eq!(samples[0].time, 3_000);
+    }
+}
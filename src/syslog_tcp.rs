@@ -0,0 +1,169 @@
+use crate::ingest::{ingest_lines, IngestOutcome};
+use crate::AppState;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info, warn};
+
+/// Largest message accepted in either framing; senders beyond it are disconnected
+const MAX_SYSLOG_FRAME_SIZE: usize = 64 * 1024;
+/// Octet counts longer than this can't be under the frame limit
+const MAX_OCTET_COUNT_DIGITS: usize = 6;
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum FrameError {
+    /// An octet count that isn't a number, or a frame over the size limit
+    Invalid,
+}
+
+/// Splits a TCP byte stream into syslog messages. A frame starting with a digit is
+/// octet-counted (`<length> <message>`); anything else runs to the next LF or NUL.
+#[derive(Default)]
+pub struct Framer {
+    buffer: Vec<u8>,
+}
+
+impl Framer {
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Whether every byte so far has been returned in a frame
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// The next complete message, if one has arrived
+    pub fn next_frame(&mut self) -> Result<Option<String>, FrameError> {
+        let Some(&first) = self.buffer.first() else {
+            return Ok(None);
+        };
+
+        let (start, end, consumed) = if first.is_ascii_digit() {
+            let Some(space) = self.buffer.iter().position(|&b| b == b' ') else {
+                if self.buffer.len() > MAX_OCTET_COUNT_DIGITS {
+                    return Err(FrameError::Invalid);
+                }
+                return Ok(None);
+            };
+            let length: usize = std::str::from_utf8(&self.buffer[..space])
+                .ok()
+                .and_then(|count| count.parse().ok())
+                .filter(|&length| length <= MAX_SYSLOG_FRAME_SIZE)
+                .ok_or(FrameError::Invalid)?;
+            let end = space + 1 + length;
+            if self.buffer.len() < end {
+                return Ok(None);
+            }
+            (space + 1, end, end)
+        } else {
+            let Some(end) = self.buffer.iter().position(|&b| b == b'\n' || b == 0) else {
+                if self.buffer.len() > MAX_SYSLOG_FRAME_SIZE {
+                    return Err(FrameError::Invalid);
+                }
+                return Ok(None);
+            };
+            (0, end, end + 1)
+        };
+
+        let frame = String::from_utf8_lossy(&self.buffer[start..end]);
+        let frame = frame.trim_end_matches(['\r', '\n']).to_string();
+        self.buffer.drain(..consumed);
+        Ok(Some(frame))
+    }
+}
+
+/// Accept syslog senders on `listener` and publish their messages to `bucket_id`
+pub fn spawn(listener: TcpListener, state: AppState, bucket_id: String) {
+    let bucket_id: Arc<str> = bucket_id.into();
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    debug!("Syslog sender connected from {}", peer);
+                    tokio::spawn(serve(stream, state.clone(), bucket_id.clone()));
+                }
+                Err(e) => warn!("Syslog TCP accept failed: {}", e),
+            }
+        }
+    });
+}
+
+async fn serve(mut stream: TcpStream, state: AppState, bucket_id: Arc<str>) {
+    let mut framer = Framer::default();
+    let mut buffer = vec![0; READ_BUFFER_SIZE];
+    loop {
+        let read = match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        framer.extend(&buffer[..read]);
+
+        let mut lines = Vec::new();
+        loop {
+            match framer.next_frame() {
+                Ok(Some(line)) if line.is_empty() => {}
+                Ok(Some(line)) => lines.push(line),
+                Ok(None) => break,
+                Err(FrameError::Invalid) => {
+                    info!("Dropping syslog sender with a malformed or oversized frame");
+                    return;
+                }
+            }
+        }
+        if lines.is_empty() {
+            continue;
+        }
+
+        let channel = {
+            let manager = state.channel_manager.read().await;
+            manager.get_channel(&bucket_id)
+        };
+        // Without viewers there is nowhere to publish, so keep reading and discard
+        let Some(channel) = channel else {
+            continue;
+        };
+
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        if let IngestOutcome::Suspended = ingest_lines(&state, &channel, &lines).await {
+            warn!("Syslog messages for suspended bucket {} dropped", bucket_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing() {
+        let mut framer = Framer::default();
+        framer.extend(b"11 <13>Oct 1 a");
+        assert_eq!(framer.next_frame(), Ok(Some("<13>Oct 1 a".to_string())));
+        assert_eq!(framer.next_frame(), Ok(None));
+
+        // Non-transparent frames, split across reads
+        framer.extend(b"<13>first\r\n<13>sec");
+        assert_eq!(framer.next_frame(), Ok(Some("<13>first".to_string())));
+        assert_eq!(framer.next_frame(), Ok(None));
+        framer.extend(b"ond\0");
+        assert_eq!(framer.next_frame(), Ok(Some("<13>second".to_string())));
+
+        // Octet counts may include trailing newlines, which are dropped
+        framer.extend(b"6 <13>x\n5 <13>");
+        assert_eq!(framer.next_frame(), Ok(Some("<13>x".to_string())));
+        assert_eq!(framer.next_frame(), Ok(None));
+    }
+
+    #[test]
+    fn test_framing_rejects_oversized() {
+        let mut framer = Framer::default();
+        framer.extend(b"99999999 <13>");
+        assert_eq!(framer.next_frame(), Err(FrameError::Invalid));
+
+        let mut framer = Framer::default();
+        framer.extend(&vec![b'a'; MAX_SYSLOG_FRAME_SIZE + 1]);
+        assert_eq!(framer.next_frame(), Err(FrameError::Invalid));
+    }
+}
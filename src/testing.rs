@@ -0,0 +1,324 @@
+//! Helpers for end-to-end tests: a server on an ephemeral port, a client to post lines
+//! with, a reader for a bucket's event stream, and golden files for parser output.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use log_bin::testing::{TestServer, DEFAULT_TIMEOUT};
+//!
+//! let server = TestServer::start().await;
+//! let mut stream = server.subscribe("my-test-bucket").await;
+//! server.post_lines("my-test-bucket", &["level=info msg=hello"]).await;
+//! let logs = stream.logs(1, DEFAULT_TIMEOUT).await;
+//! assert_eq!(logs[0]["raw"], "level=info msg=hello");
+//! # }
+//! ```
+
+use crate::config::Config;
+use crate::parsers::ParsedEvent;
+use crate::timeouts;
+use reqwest::StatusCode;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+/// Long enough for a loaded CI machine, short enough that a missing event fails quickly
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Set to rewrite golden files from the current output instead of comparing against them
+pub const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+/// A server on a local ephemeral port with all the HTTP routes, but none of the optional
+/// TCP and UDP listeners. It shuts down, background tasks included, when dropped.
+///
+/// Each server has its own buckets and configuration. Only the `/metrics` counters are
+/// process-wide, so they add up across the servers in a test binary.
+pub struct TestServer {
+    addr: SocketAddr,
+    client: reqwest::Client,
+    shutdown: Option<oneshot::Sender<()>>,
+    _tasks: JoinSet<()>,
+}
+
+impl TestServer {
+    /// Start a server with the default configuration
+    pub async fn start() -> Self {
+        Self::with_config(Config::default()).await
+    }
+
+    /// Start a server configured as if `vars` were its environment, e.g.
+    /// `[("API_ONLY", "1")]`
+    pub async fn with_env(vars: &[(&str, &str)]) -> Self {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Self::with_config(Config::from_lookup(|key| vars.get(key).cloned())).await
+    }
+
+    pub async fn with_config(config: Config) -> Self {
+        let (state, app, tasks) = crate::start(config);
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .expect("Failed to bind test server");
+        let addr = listener.local_addr().unwrap();
+        let listener =
+            timeouts::StallGuardListener::new(listener, state.config.client_idle_timeout);
+
+        let (shutdown, signal) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let _ = axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<timeouts::PeerAddr>(),
+            )
+            .with_graceful_shutdown(async {
+                let _ = signal.await;
+            })
+            .await;
+        });
+
+        Self {
+            addr,
+            // Proxy settings in the environment would send requests for localhost elsewhere
+            client: reqwest::Client::builder().no_proxy().build().unwrap(),
+            shutdown: Some(shutdown),
+            _tasks: tasks,
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The full URL of `path`, which should start with a slash
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// A client for requests the other helpers don't cover
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Post lines to a bucket as a plain text body, one per line. Lines only go anywhere
+    /// once something has subscribed to the bucket.
+    pub async fn post_lines(&self, bucket_id: &str, lines: &[&str]) -> StatusCode {
+        self.client
+            .post(self.url(&format!("/{}", bucket_id)))
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+            .body(lines.join("\n"))
+            .send()
+            .await
+            .expect("Failed to post lines")
+            .status()
+    }
+
+    /// Subscribe to a bucket's event stream. The bucket exists by the time this returns.
+    pub async fn subscribe(&self, bucket_id: &str) -> EventStream {
+        let response = self
+            .client
+            .get(self.url(&format!("/{}", bucket_id)))
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .send()
+            .await
+            .expect("Failed to subscribe");
+        assert_eq!(
+            response.status(),
+            StatusCode::OK,
+            "Subscribing to {} failed",
+            bucket_id
+        );
+        EventStream {
+            response,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+/// One server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestEvent {
+    pub event: String,
+    pub data: String,
+    pub id: Option<String>,
+}
+
+impl TestEvent {
+    /// The event's data parsed as JSON
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.data).expect("Event data is not JSON")
+    }
+}
+
+/// A bucket's event stream, read an event at a time
+pub struct EventStream {
+    response: reqwest::Response,
+    buffer: Vec<u8>,
+}
+
+impl EventStream {
+    /// The next event of any type, or `None` if the stream ends or `timeout` passes first
+    pub async fn next_event(&mut self, timeout: Duration) -> Option<TestEvent> {
+        tokio::time::timeout(timeout, self.read_event())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// The next event of type `event_type`, skipping others
+    pub async fn next_of_type(&mut self, event_type: &str, timeout: Duration) -> Option<TestEvent> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let event = tokio::time::timeout_at(deadline, self.read_event())
+                .await
+                .ok()??;
+            if event.event == event_type {
+                return Some(event);
+            }
+        }
+    }
+
+    /// The data of the next `count` log events, skipping other events. Fewer are returned
+    /// if `timeout` passes first, so compare the length to fail clearly.
+    pub async fn logs(&mut self, count: usize, timeout: Duration) -> Vec<Value> {
+        let deadline = Instant::now() + timeout;
+        let mut logs = Vec::with_capacity(count);
+        while logs.len() < count {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.next_of_type("log", remaining).await {
+                Some(event) => logs.push(event.json()),
+                None => break,
+            }
+        }
+        logs
+    }
+
+    async fn read_event(&mut self) -> Option<TestEvent> {
+        loop {
+            while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+                let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+                if let Some(event) = parse_event(&String::from_utf8_lossy(&block)) {
+                    return Some(event);
+                }
+            }
+            let chunk = self.response.chunk().await.ok()??;
+            self.buffer.extend_from_slice(&chunk);
+        }
+    }
+}
+
+/// Parse an event block, returning nothing for comments such as keep-alives
+fn parse_event(block: &str) -> Option<TestEvent> {
+    let mut event = None;
+    let mut data: Option<String> = None;
+    let mut id = None;
+    for line in block.lines() {
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => event = Some(value.to_string()),
+            "id" => id = Some(value.to_string()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            _ => {}
+        }
+    }
+    if event.is_none() && data.is_none() {
+        return None;
+    }
+    Some(TestEvent {
+        event: event.unwrap_or_else(|| "message".to_string()),
+        data: data.unwrap_or_default(),
+        id,
+    })
+}
+
+/// What the parsers make of a line: the parser that matched and the field values, with
+/// keys sorted so the output is stable enough to keep in a golden file
+pub fn parse_line(line: &str) -> Value {
+    let mut parsed = ParsedEvent::new(line.to_string());
+    parsed.parse();
+    let fields: BTreeMap<&String, &String> = parsed
+        .fields
+        .iter()
+        .map(|(key, field)| (key, &field.value))
+        .collect();
+
+    let mut output = Map::new();
+    output.insert("line".to_string(), line.into());
+    output.insert("parser".to_string(), parsed.parser.into());
+    output.insert("fields".to_string(), serde_json::to_value(fields).unwrap());
+    Value::Object(output)
+}
+
+/// Parse each line of `input` and compare the results with the JSON golden file at
+/// `golden`. With `UPDATE_GOLDEN` set, the golden file is written instead.
+pub fn assert_golden(input: &Path, golden: &Path) {
+    let lines = std::fs::read_to_string(input)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", input.display(), e));
+    let actual: Vec<Value> = lines
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(parse_line)
+        .collect();
+    let actual = serde_json::to_string_pretty(&actual).unwrap() + "\n";
+
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        std::fs::write(golden, actual)
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", golden.display(), e));
+        return;
+    }
+    let expected = std::fs::read_to_string(golden).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read {}: {}; run with {}=1 to create it",
+            golden.display(),
+            e,
+            UPDATE_GOLDEN_VAR
+        )
+    });
+    assert!(
+        expected == actual,
+        "Parser output for {} no longer matches {}; run with {}=1 to update it if the change is intended\n\nexpected:\n{}\nactual:\n{}",
+        input.display(),
+        golden.display(),
+        UPDATE_GOLDEN_VAR,
+        expected,
+        actual
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        let event = parse_event("event: log\nid: 7\ndata: {\"a\":1}\n\n").unwrap();
+        assert_eq!(event.event, "log");
+        assert_eq!(event.id.as_deref(), Some("7"));
+        assert_eq!(event.json()["a"], 1);
+
+        let event = parse_event("data: one\ndata: two\n\n").unwrap();
+        assert_eq!(event.event, "message");
+        assert_eq!(event.data, "one\ntwo");
+
+        assert_eq!(parse_event(": keep-alive\n\n"), None);
+    }
+}
@@ -0,0 +1,227 @@
+use crate::api::bucket_path;
+use crate::metrics::METRICS;
+use crate::models::CloseReason;
+use crate::AppState;
+use axum::{
+    extract::{connect_info::Connected, ConnectInfo, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    serve::{IncomingStream, Listener},
+};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Sleep;
+use tracing::warn;
+
+/// Bucket routes whose request bodies stream for as long as the client keeps sending, such
+/// as `systemd-journal-upload` following the journal. Their handlers time out each read
+/// instead of the whole request.
+const STREAMING_ROUTES: &[&str] = &["upload"];
+
+/// Bound how long a request may take to produce a response, including reading its body
+///
+/// Streaming responses such as SSE are unaffected: their handlers return as soon as the
+/// stream is set up.
+pub async fn request_timeout(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    if bucket_path(&path).is_some_and(|(_, rest)| STREAMING_ROUTES.contains(&rest)) {
+        return next.run(request).await;
+    }
+    match tokio::time::timeout(state.config.request_timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            METRICS.request_timeouts.inc();
+            warn!("Request to {} timed out", path);
+            (StatusCode::REQUEST_TIMEOUT, "Request timed out").into_response()
+        }
+    }
+}
+
+/// TCP listener that drops connections whose client stops reading for too long
+pub struct StallGuardListener {
+    inner: TcpListener,
+    timeout: Duration,
+}
+
+impl StallGuardListener {
+    pub fn new(inner: TcpListener, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+}
+
+impl Listener for StallGuardListener {
+    type Io = StallGuardIo<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (io, addr) = Listener::accept(&mut self.inner).await;
+        (StallGuardIo::new(io, self.timeout), addr)
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+/// Remote address of a connection accepted by [`StallGuardListener`]
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub SocketAddr);
+
+impl Connected<IncomingStream<'_, StallGuardListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, StallGuardListener>) -> Self {
+        Self(*stream.remote_addr())
+    }
+}
+
+/// Fill in [`PeerAddr`] for a host application's server, which knows the peer as a plain
+/// `SocketAddr` if it was served with connect info, and not at all otherwise
+pub async fn peer_from_host(mut request: Request, next: Next) -> Response {
+    if request
+        .extensions()
+        .get::<ConnectInfo<PeerAddr>>()
+        .is_none()
+    {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or(SocketAddr::from(([0, 0, 0, 0], 0)), |ConnectInfo(peer)| {
+                *peer
+            });
+        request.extensions_mut().insert(ConnectInfo(PeerAddr(peer)));
+    }
+    next.run(request).await
+}
+
+/// Connection wrapper that fails writes once they have been blocked for `timeout`
+///
+/// A client that never reads (an idle SSE tab, a stuck proxy) eventually fills the socket
+/// buffer; without this the connection and its subscription would be held forever.
+pub struct StallGuardIo<T> {
+    inner: T,
+    timeout: Duration,
+    stalled: Option<Pin<Box<Sleep>>>,
+}
+
+impl<T> StallGuardIo<T> {
+    pub fn new(inner: T, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            stalled: None,
+        }
+    }
+
+    /// Track a write that could not make progress, failing once it has been blocked too long
+    fn poll_stalled<R>(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<R>> {
+        let timeout = self.timeout;
+        let deadline = self
+            .stalled
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        ready!(deadline.as_mut().poll(cx));
+
+        METRICS.stalled_connections.inc();
+        METRICS.subscriber_closes.inc(CloseReason::IdleTimeout);
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "client stopped reading",
+        )))
+    }
+
+    fn guard<R>(&mut self, cx: &mut Context<'_>, poll: Poll<io::Result<R>>) -> Poll<io::Result<R>> {
+        match poll {
+            Poll::Pending => self.poll_stalled(cx),
+            ready => {
+                self.stalled = None;
+                ready
+            }
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for StallGuardIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for StallGuardIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        self.guard(cx, poll)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        self.guard(cx, poll)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let poll = Pin::new(&mut self.inner).poll_flush(cx);
+        self.guard(cx, poll)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A peer that never reads, so every write blocks
+    struct Blocked;
+
+    impl AsyncWrite for Blocked {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stalled_write_times_out() {
+        let mut io = StallGuardIo::new(Blocked, Duration::from_millis(20));
+        let result =
+            std::future::poll_fn(|cx| Pin::new(&mut io).poll_write(cx, b"data: hello\n\n")).await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+}
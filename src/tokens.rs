@@ -0,0 +1,508 @@
+use crate::api::bucket_path;
+use crate::{cors, ids, principal, AppState};
+use axum::{
+    extract::{Path, Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::Engine;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path as FilePath;
+use std::sync::{Arc, Mutex};
+
+/// Header Kinesis Firehose HTTP destinations carry their access key in
+pub const FIREHOSE_ACCESS_KEY_HEADER: &str = "x-amz-firehose-access-key";
+
+/// Limits for one token; any left unset are unlimited
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quotas {
+    pub daily_events: Option<u64>,
+    pub monthly_events: Option<u64>,
+    pub daily_bytes: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEntry {
+    /// Public name for the token, used in usage URLs and logs
+    id: String,
+    /// The secret sent as `Authorization: Bearer <token>`
+    token: String,
+    #[serde(default)]
+    quotas: Quotas,
+    /// Bucket for writes that name only a token, like Splunk HEC events
+    #[serde(default)]
+    bucket: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokensFile {
+    tokens: Vec<TokenEntry>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Usage {
+    pub events: u64,
+    pub bytes: u64,
+}
+
+/// Usage in the current UTC day and month
+#[derive(Debug, Default)]
+struct Periods {
+    day: (i32, u32),
+    month: (i32, u32),
+    daily: Usage,
+    monthly: Usage,
+}
+
+impl Periods {
+    /// Start new periods if the day or month has changed
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let day = (now.year(), now.ordinal());
+        if self.day != day {
+            self.day = day;
+            self.daily = Usage::default();
+        }
+        let month = (now.year(), now.month());
+        if self.month != month {
+            self.month = month;
+            self.monthly = Usage::default();
+        }
+    }
+}
+
+/// Buckets remembered per token; the least recently used are forgotten first
+const MAX_TRACKED_BUCKETS_PER_TOKEN: usize = 1000;
+
+/// How a token has been used with a bucket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketRelation {
+    Created,
+    Written,
+    Configured,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BucketUse {
+    pub bucket: String,
+    pub created: bool,
+    pub written: bool,
+    pub configured: bool,
+    #[serde(rename = "lastUsed")]
+    pub last_used: i64,
+}
+
+/// A write token and what it has used so far
+#[derive(Debug)]
+pub struct TokenAccount {
+    id: String,
+    quotas: Quotas,
+    bucket: Option<String>,
+    periods: Mutex<Periods>,
+    buckets: Mutex<HashMap<String, BucketUse>>,
+}
+
+impl TokenAccount {
+    pub fn new(id: String, quotas: Quotas, bucket: Option<String>) -> Self {
+        Self {
+            id,
+            quotas,
+            bucket,
+            periods: Mutex::default(),
+            buckets: Mutex::default(),
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The bucket this token's HEC writes go to, if it has one
+    pub fn bucket(&self) -> Option<&str> {
+        self.bucket.as_deref()
+    }
+
+    /// Whether any quota has already been used up
+    pub fn over_quota(&self, now: DateTime<Utc>) -> bool {
+        let mut periods = self.periods.lock().unwrap();
+        periods.roll(now);
+        let exceeded = |used: u64, limit: Option<u64>| limit.is_some_and(|limit| used >= limit);
+        exceeded(periods.daily.events, self.quotas.daily_events)
+            || exceeded(periods.monthly.events, self.quotas.monthly_events)
+            || exceeded(periods.daily.bytes, self.quotas.daily_bytes)
+            || exceeded(periods.monthly.bytes, self.quotas.monthly_bytes)
+    }
+
+    pub fn record(&self, events: u64, bytes: u64, now: DateTime<Utc>) {
+        let mut guard = self.periods.lock().unwrap();
+        let periods = &mut *guard;
+        periods.roll(now);
+        for usage in [&mut periods.daily, &mut periods.monthly] {
+            usage.events += events;
+            usage.bytes += bytes;
+        }
+    }
+
+    /// Remember that this token was used with `bucket`
+    pub fn associate(&self, bucket: &str, relation: BucketRelation, now: i64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.contains_key(bucket) && buckets.len() >= MAX_TRACKED_BUCKETS_PER_TOKEN {
+            if let Some(oldest) = buckets
+                .values()
+                .min_by_key(|entry| entry.last_used)
+                .map(|entry| entry.bucket.clone())
+            {
+                buckets.remove(&oldest);
+            }
+        }
+
+        let entry = buckets
+            .entry(bucket.to_string())
+            .or_insert_with(|| BucketUse {
+                bucket: bucket.to_string(),
+                ..Default::default()
+            });
+        match relation {
+            BucketRelation::Created => entry.created = true,
+            BucketRelation::Written => entry.written = true,
+            BucketRelation::Configured => entry.configured = true,
+        }
+        entry.last_used = now;
+    }
+
+    /// Buckets this token has been used with, most recent first
+    pub fn buckets(&self) -> Vec<BucketUse> {
+        let mut buckets: Vec<BucketUse> = self.buckets.lock().unwrap().values().cloned().collect();
+        buckets.sort_by_key(|entry| std::cmp::Reverse(entry.last_used));
+        buckets
+    }
+
+    fn report(&self, now: DateTime<Utc>) -> UsageReport {
+        let mut periods = self.periods.lock().unwrap();
+        periods.roll(now);
+        UsageReport {
+            id: self.id.clone(),
+            daily: periods.daily,
+            monthly: periods.monthly,
+            quotas: self.quotas.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct UsageReport {
+    id: String,
+    daily: Usage,
+    monthly: Usage,
+    quotas: Quotas,
+}
+
+/// The write tokens configured for the server
+#[derive(Debug)]
+pub struct TokenRegistry {
+    by_secret: HashMap<String, Arc<TokenAccount>>,
+    by_id: HashMap<String, Arc<TokenAccount>>,
+    /// Reject writes that don't present a token
+    required: bool,
+}
+
+impl TokenRegistry {
+    pub fn load(path: &FilePath, required: bool) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let file: TokensFile = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+        Ok(Self::new(file.tokens, required))
+    }
+
+    fn new(entries: Vec<TokenEntry>, required: bool) -> Self {
+        let mut registry = Self {
+            by_secret: HashMap::new(),
+            by_id: HashMap::new(),
+            required,
+        };
+        for entry in entries {
+            let account = Arc::new(TokenAccount::new(
+                entry.id.clone(),
+                entry.quotas,
+                entry.bucket,
+            ));
+            registry.by_secret.insert(entry.token, account.clone());
+            registry.by_id.insert(entry.id, account);
+        }
+        registry
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    fn lookup(&self, headers: &HeaderMap) -> Option<Result<Arc<TokenAccount>, ()>> {
+        // Kinesis Firehose HTTP destinations send their configured access key in a header
+        let Some(value) = headers.get(header::AUTHORIZATION) else {
+            let key = headers.get(FIREHOSE_ACCESS_KEY_HEADER)?;
+            let account = key
+                .to_str()
+                .ok()
+                .and_then(|key| self.by_secret.get(key.trim()));
+            return Some(account.cloned().ok_or(()));
+        };
+        // Splunk HEC clients send `Authorization: Splunk <token>`, and senders configured
+        // with only a URL, like Heroku drains, send the token as a basic auth password
+        let secret = value.to_str().ok().and_then(|value| {
+            value
+                .strip_prefix("Bearer ")
+                .or_else(|| value.strip_prefix("Splunk "))
+                .map(String::from)
+                .or_else(|| basic_password(value.strip_prefix("Basic ")?))
+        });
+        Some(
+            secret
+                .and_then(|secret| self.by_secret.get(secret.trim()))
+                .cloned()
+                .ok_or(()),
+        )
+    }
+}
+
+fn basic_password(credentials: &str) -> Option<String> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    credentials
+        .split_once(':')
+        .map(|(_, password)| password.to_string())
+}
+
+/// Identify the token a write is made with, rejecting unknown and over-quota tokens. A
+/// principal the embedding application vouched for stands in for a token.
+pub fn authorize(
+    state: &AppState,
+    headers: &HeaderMap,
+) -> Result<Option<Arc<TokenAccount>>, (StatusCode, String)> {
+    if let Some(account) = principal::current() {
+        return Ok(Some(account));
+    }
+    let Some(registry) = &state.tokens else {
+        return Ok(None);
+    };
+
+    match registry.lookup(headers) {
+        Some(Ok(account)) => {
+            if account.over_quota(Utc::now()) {
+                return Err((
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!("Token {} has used up its quota", account.id()),
+                ));
+            }
+            Ok(Some(account))
+        }
+        Some(Err(())) => Err((StatusCode::UNAUTHORIZED, "Unknown write token".to_string())),
+        None if registry.required => Err((
+            StatusCode::UNAUTHORIZED,
+            "A write token is required".to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The valid token presented with a request, or its principal, without checking quotas
+pub fn identify(state: &AppState, headers: &HeaderMap) -> Option<Arc<TokenAccount>> {
+    principal::current().or_else(|| {
+        state
+            .tokens
+            .as_ref()
+            .and_then(|registry| registry.lookup(headers))
+            .and_then(Result::ok)
+    })
+}
+
+/// Remember which buckets a token creates, writes to or configures, for `/api/my/buckets`
+pub async fn track_bucket_use(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+        return next.run(request).await;
+    }
+    let Some(account) = identify(&state, request.headers()) else {
+        return next.run(request).await;
+    };
+    let path = request.uri().path().to_string();
+    let Some((bucket_id, _)) = bucket_path(&path).filter(|(id, _)| !ids::is_reserved(id)) else {
+        return next.run(request).await;
+    };
+    let bucket_id = bucket_id.to_string();
+
+    let existed = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id).is_some()
+    };
+    let response = next.run(request).await;
+    if !response.status().is_success() {
+        return response;
+    }
+
+    let now = Utc::now().timestamp_millis();
+    if !existed
+        && state
+            .channel_manager
+            .read()
+            .await
+            .get_channel(&bucket_id)
+            .is_some()
+    {
+        account.associate(&bucket_id, BucketRelation::Created, now);
+    }
+    let relation = if cors::is_config_request(&method, &path) {
+        BucketRelation::Configured
+    } else {
+        BucketRelation::Written
+    };
+    account.associate(&bucket_id, relation, now);
+    response
+}
+
+#[derive(Debug, Serialize)]
+struct MyBuckets {
+    token: String,
+    buckets: Vec<BucketUse>,
+}
+
+/// Buckets the presented token has created, written to or configured
+pub async fn get_my_buckets(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let account = identify(&state, &headers);
+    if state.tokens.is_none() && account.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match account {
+        Some(account) => Json(MyBuckets {
+            token: account.id().to_string(),
+            buckets: account.buckets(),
+        })
+        .into_response(),
+        None => (StatusCode::UNAUTHORIZED, "A valid token is required").into_response(),
+    }
+}
+
+/// Usage and quotas for a token; callers must present the token itself
+pub async fn get_usage(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let account = identify(&state, &headers).filter(|account| account.id() == id);
+
+    match account {
+        Some(account) => Json(account.report(Utc::now())).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn account(quotas: Quotas) -> Arc<TokenAccount> {
+        let registry = TokenRegistry::new(
+            vec![TokenEntry {
+                id: "team-a".to_string(),
+                token: "secret".to_string(),
+                quotas,
+                bucket: None,
+            }],
+            false,
+        );
+        registry.by_id["team-a"].clone()
+    }
+
+    #[test]
+    fn test_quotas_reset_each_period() {
+        let account = account(Quotas {
+            daily_events: Some(10),
+            monthly_bytes: Some(1000),
+            ..Default::default()
+        });
+        let day_one = Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap();
+        let day_two = Utc.with_ymd_and_hms(2024, 2, 1, 12, 0, 0).unwrap();
+
+        account.record(9, 100, day_one);
+        assert!(!account.over_quota(day_one));
+        account.record(1, 100, day_one);
+        assert!(account.over_quota(day_one));
+
+        // A new day (and month) starts fresh
+        assert!(!account.over_quota(day_two));
+        account.record(1, 1000, day_two);
+        assert!(account.over_quota(day_two));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let registry = TokenRegistry::new(
+            vec![TokenEntry {
+                id: "team-a".to_string(),
+                token: "secret".to_string(),
+                quotas: Quotas::default(),
+                bucket: Some("hec-bucket".to_string()),
+            }],
+            true,
+        );
+        let mut headers = HeaderMap::new();
+        assert!(registry.lookup(&headers).is_none());
+
+        headers.insert(header::AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert_eq!(registry.lookup(&headers).unwrap().unwrap().id(), "team-a");
+
+        headers.insert(header::AUTHORIZATION, "Splunk secret".parse().unwrap());
+        let account = registry.lookup(&headers).unwrap().unwrap();
+        assert_eq!(account.bucket(), Some("hec-bucket"));
+
+        // "drain:secret"
+        headers.insert(
+            header::AUTHORIZATION,
+            "Basic ZHJhaW46c2VjcmV0".parse().unwrap(),
+        );
+        assert_eq!(registry.lookup(&headers).unwrap().unwrap().id(), "team-a");
+
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(registry.lookup(&headers).unwrap().is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(FIREHOSE_ACCESS_KEY_HEADER, "secret".parse().unwrap());
+        assert_eq!(registry.lookup(&headers).unwrap().unwrap().id(), "team-a");
+    }
+
+    #[test]
+    fn test_bucket_associations() {
+        let account = account(Quotas::default());
+        account.associate("bucket-one", BucketRelation::Created, 1);
+        account.associate("bucket-one", BucketRelation::Written, 2);
+        account.associate("bucket-two", BucketRelation::Configured, 3);
+
+        let buckets = account.buckets();
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets[0].bucket, "bucket-two");
+        assert!(buckets[0].configured && !buckets[0].written);
+        assert!(buckets[1].created && buckets[1].written);
+        assert_eq!(buckets[1].last_used, 2);
+
+        for i in 0..MAX_TRACKED_BUCKETS_PER_TOKEN {
+            account.associate(
+                &format!("bucket-{}", i),
+                BucketRelation::Written,
+                10 + i as i64,
+            );
+        }
+        let buckets = account.buckets();
+        assert_eq!(buckets.len(), MAX_TRACKED_BUCKETS_PER_TOKEN);
+        assert!(!buckets.iter().any(|entry| entry.bucket == "bucket-one"));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::channel_manager::Channel;
+use crate::encoding::{self, Encoding};
+use crate::idempotency::Reservation;
+use crate::ingest::{ingest_lines_reporting, BodyFormat, IngestOutcome};
+use crate::tokens::{BucketRelation, TokenAccount};
+use crate::{AppState, MAX_LOG_LINES_PER_MINUTE};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info, warn};
+
+/// Largest file upload accepted; parts are split into lines as they arrive
+pub const MAX_UPLOAD_SIZE: usize = 16 * 1024 * 1024;
+/// Most lines published at once while an upload drains
+const UPLOAD_BATCH_LINES: usize = 64;
+/// Lines of each minute's rate limit left to live writers while an upload drains
+const UPLOAD_HEADROOM_LINES: u64 = MAX_LOG_LINES_PER_MINUTE / 4;
+/// Pause between batches, so viewers see a file scroll in rather than land all at once
+const UPLOAD_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Splits a byte stream into lines, skipping blank ones. Lines that aren't UTF-8 are read
+/// as Latin-1, the same fallback as whole files.
+#[derive(Default)]
+pub struct LineSplitter {
+    buffer: Vec<u8>,
+    started: bool,
+}
+
+impl LineSplitter {
+    /// Add bytes from the stream, returning the lines they complete
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        while let Some(newline) = self.buffer[start..].iter().position(|&b| b == b'\n') {
+            let end = start + newline;
+            lines.extend(self.line(start, end));
+            start = end + 1;
+        }
+        self.buffer.drain(..start);
+        lines
+    }
+
+    /// End the stream, returning a last line that had no newline after it
+    pub fn finish(mut self) -> Option<String> {
+        let end = self.buffer.len();
+        self.line(0, end)
+    }
+
+    fn line(&mut self, start: usize, end: usize) -> Option<String> {
+        let mut line = &self.buffer[start..end];
+        if !std::mem::replace(&mut self.started, true) {
+            line = line.strip_prefix(&[0xef, 0xbb, 0xbf]).unwrap_or(line);
+        }
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(match std::str::from_utf8(line) {
+            Ok(line) => line.to_string(),
+            Err(_) => line.iter().map(|&b| b as char).collect(),
+        })
+    }
+}
+
+/// Read every part of a multipart upload into lines, a chunk at a time, returning them
+/// with the number of bytes uploaded. UTF-16 parts are read whole and then transcoded.
+pub async fn read_upload_lines(
+    headers: &HeaderMap,
+    body: Body,
+    limit: usize,
+) -> Result<(Vec<String>, usize), StatusCode> {
+    // Axum's own extractor caps bodies well below an upload's size
+    let boundary = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|content_type| multer::parse_boundary(content_type).ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+    let mut multipart = multer::Multipart::new(body.into_data_stream(), boundary);
+
+    let mut lines = Vec::new();
+    let mut total = 0;
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?
+    {
+        let mut splitter = LineSplitter::default();
+        let mut utf16: Option<Vec<u8>> = None;
+        let mut first = true;
+        while let Some(chunk) = field.chunk().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+            total += chunk.len();
+            if total > limit {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            if std::mem::take(&mut first) && encoding::is_utf16(&chunk) {
+                utf16 = Some(Vec::new());
+            }
+            match &mut utf16 {
+                Some(bytes) => bytes.extend_from_slice(&chunk),
+                None => lines.extend(splitter.push(&chunk)),
+            }
+        }
+
+        match utf16 {
+            Some(bytes) => {
+                let (text, encoding) = encoding::decode(&bytes);
+                if encoding != Encoding::Utf8 {
+                    debug!("Transcoded {:?} upload part to UTF-8", encoding);
+                }
+                lines.extend(
+                    BodyFormat::Text
+                        .split(&text)
+                        .map_err(|(status, _)| status)?,
+                );
+            }
+            None => lines.extend(splitter.finish()),
+        }
+    }
+
+    Ok((lines, total))
+}
+
+/// Accept a log file uploaded as `multipart/form-data` to the bucket route, such as from
+/// `curl -F file=@app.log`. Its lines are published in the background, in file order and
+/// within the bucket's rate limit, so a big file doesn't get the bucket suspended.
+pub(crate) async fn accept_upload(
+    bucket_id: String,
+    state: AppState,
+    headers: HeaderMap,
+    body: Body,
+    token: Option<Arc<TokenAccount>>,
+    ttl: Option<i64>,
+    reservation: Option<Reservation>,
+) -> Result<Response, StatusCode> {
+    let (lines, size) = read_upload_lines(&headers, body, MAX_UPLOAD_SIZE).await?;
+    if lines.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(token) = &token {
+        let now = chrono::Utc::now();
+        token.record(lines.len() as u64, size as u64, now);
+        token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel else {
+        warn!(
+            "Discarding upload for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    if let Some(reservation) = reservation {
+        reservation.accept();
+    }
+
+    info!(
+        "Uploaded file for bucket {}: {} lines",
+        bucket_id,
+        lines.len()
+    );
+    let count = lines.len();
+    tokio::spawn(publish_upload(state, bucket_id, channel, lines, ttl));
+    Ok((
+        StatusCode::ACCEPTED,
+        format!("Publishing {} lines\n", count),
+    )
+        .into_response())
+}
+
+/// Publish an upload's lines in the background, then say why if it stopped early
+async fn publish_upload(
+    state: AppState,
+    bucket_id: String,
+    channel: Arc<Channel>,
+    lines: Vec<String>,
+    ttl: Option<i64>,
+) {
+    if let Err(reason) = publish_paced(&state, &bucket_id, &channel, &lines, ttl, |_| {}).await {
+        warn!("Upload to bucket {} stopped: {}", bucket_id, reason);
+    }
+}
+
+/// Publish lines in batches, waiting for the next minute whenever the bucket's rate limit
+/// is nearly used up, and calling `published` with the size of each batch. Stops if the
+/// bucket goes away or refuses them, giving the reason.
+pub(crate) async fn publish_paced<T: AsRef<str>>(
+    state: &AppState,
+    bucket_id: &str,
+    channel: &Arc<Channel>,
+    lines: &[T],
+    ttl: Option<i64>,
+    mut published: impl FnMut(usize),
+) -> Result<(), String> {
+    let _turn = channel.upload_turn().await;
+    let mut remaining = lines;
+    while !remaining.is_empty() {
+        // Viewers can all leave during a long upload, which closes the bucket
+        let current = {
+            let manager = state.channel_manager.read().await;
+            manager.get_channel(bucket_id)
+        };
+        if !current.is_some_and(|current| Arc::ptr_eq(&current, channel)) {
+            return Err(format!(
+                "it has no viewers, with {} lines left",
+                remaining.len()
+            ));
+        }
+        if channel.is_suspended() {
+            return Err("bucket was suspended".to_string());
+        }
+
+        let budget = paced_budget(channel);
+        if budget == 0 {
+            tokio::time::sleep(until_next_minute()).await;
+            continue;
+        }
+
+        let (batch, rest) = remaining.split_at(budget.min(UPLOAD_BATCH_LINES).min(remaining.len()));
+        let batch_lines: Vec<&str> = batch.iter().map(AsRef::as_ref).collect();
+        match ingest_lines_reporting(state, channel, &batch_lines, ttl, &[], None).await {
+            IngestOutcome::Accepted => {}
+            IngestOutcome::Suspended => return Err("bucket was suspended".to_string()),
+            IngestOutcome::Paused => return Err("bucket is paused".to_string()),
+        }
+        published(batch.len());
+        remaining = rest;
+        if !remaining.is_empty() {
+            tokio::time::sleep(UPLOAD_BATCH_INTERVAL).await;
+        }
+    }
+    Ok(())
+}
+
+/// Lines a background publisher can take from this minute's rate limit, leaving
+/// headroom for live writers
+pub(crate) fn paced_budget(channel: &Channel) -> usize {
+    channel.rate_budget().saturating_sub(UPLOAD_HEADROOM_LINES) as usize
+}
+
+/// Time until the rate limit's minute rolls over
+pub(crate) fn until_next_minute() -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_secs(60) - Duration::from_millis(now.as_millis() as u64 % 60_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_splitter() {
+        let mut splitter = LineSplitter::default();
+        assert!(splitter.push(b"\xef\xbb\xbfone\r").is_empty());
+        assert_eq!(splitter.push(b"\n\ntw"), vec!["one"]);
+        assert_eq!(splitter.push(b"o\ncaf\xe9\nthr"), vec!["two", "caf\u{e9}"]);
+        assert_eq!(splitter.finish(), Some("thr".to_string()));
+
+        assert_eq!(LineSplitter::default().finish(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_upload_lines() {
+        let body = "--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.log\"\r\n\r\none\ntwo\r\n--XYZ\r\nContent-Disposition: form-data; name=\"log\"\r\n\r\nthree\r\n--XYZ--\r\n";
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            "multipart/form-data; boundary=XYZ".parse().unwrap(),
+        );
+
+        let (lines, size) = read_upload_lines(&headers, Body::from(body), 1024)
+            .await
+            .unwrap();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+        assert_eq!(size, 12);
+
+        let result = read_upload_lines(&headers, Body::from(body), 4).await;
+        assert_eq!(result, Err(StatusCode::PAYLOAD_TOO_LARGE));
+
+        let utf16: Vec<u8> = "\u{feff}four\r\nfive"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let mut body = b"--XYZ\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\n".to_vec();
+        body.extend_from_slice(&utf16);
+        body.extend_from_slice(b"\r\n--XYZ--\r\n");
+        let (lines, _) = read_upload_lines(&headers, Body::from(body), 1024)
+            .await
+            .unwrap();
+        assert_eq!(lines, vec!["four", "five"]);
+    }
+}
@@ -0,0 +1,373 @@
+use crate::demo::DEMO_BUCKET_ID;
+use crate::ingest::{check_event_count, ingest_lines, ContentEncoding, IngestOutcome};
+use crate::tokens::{self, BucketRelation};
+use crate::{ids, AppState, MAX_LOG_BODY_SIZE, PAUSED_TEXT, SUSPENSION_REASON_TEXT};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat};
+use prost::{Message, Oneof};
+use serde_json::{Map, Number, Value};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Each `native` event is prefixed with its length as a big-endian 32-bit number
+const LENGTH_PREFIX_SIZE: usize = 4;
+
+/// `EventWrapper` from Vector's `event.proto`. Only logs are decoded; metrics and traces
+/// are left unset and skipped.
+#[derive(Clone, PartialEq, Message)]
+struct EventWrapper {
+    #[prost(oneof = "Event", tags = "1")]
+    event: Option<Event>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum Event {
+    #[prost(message, tag = "1")]
+    Log(Log),
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Log {
+    /// Where Vector before 0.26 put the event's fields
+    #[prost(map = "string, message", tag = "1")]
+    fields: HashMap<String, VectorValue>,
+    #[prost(message, optional, tag = "2")]
+    value: Option<VectorValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct VectorValue {
+    #[prost(oneof = "ValueKind", tags = "1, 2, 4, 5, 6, 7, 8, 9")]
+    kind: Option<ValueKind>,
+}
+
+#[derive(Clone, PartialEq, Oneof)]
+enum ValueKind {
+    #[prost(bytes, tag = "1")]
+    RawBytes(Vec<u8>),
+    #[prost(message, tag = "2")]
+    Timestamp(Timestamp),
+    #[prost(int64, tag = "4")]
+    Integer(i64),
+    #[prost(bool, tag = "5")]
+    Boolean(bool),
+    #[prost(message, tag = "6")]
+    Map(ValueMap),
+    #[prost(message, tag = "7")]
+    Array(ValueArray),
+    #[prost(int32, tag = "8")]
+    Null(i32),
+    #[prost(double, tag = "9")]
+    Float(f64),
+}
+
+/// `google.protobuf.Timestamp`
+#[derive(Clone, PartialEq, Message)]
+struct Timestamp {
+    #[prost(int64, tag = "1")]
+    seconds: i64,
+    #[prost(int32, tag = "2")]
+    nanos: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueMap {
+    #[prost(map = "string, message", tag = "1")]
+    fields: HashMap<String, VectorValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ValueArray {
+    #[prost(message, repeated, tag = "1")]
+    items: Vec<VectorValue>,
+}
+
+fn to_json(value: &VectorValue) -> Value {
+    match &value.kind {
+        None | Some(ValueKind::Null(_)) => Value::Null,
+        // Vector keeps strings as bytes
+        Some(ValueKind::RawBytes(bytes)) => String::from_utf8_lossy(bytes).into(),
+        Some(ValueKind::Timestamp(time)) => {
+            DateTime::from_timestamp(time.seconds, time.nanos.max(0) as u32)
+                .map_or(Value::Null, |time| {
+                    time.to_rfc3339_opts(SecondsFormat::AutoSi, true).into()
+                })
+        }
+        Some(ValueKind::Integer(number)) => Value::from(*number),
+        Some(ValueKind::Boolean(flag)) => Value::Bool(*flag),
+        Some(ValueKind::Float(number)) => {
+            Number::from_f64(*number).map_or(Value::Null, Value::Number)
+        }
+        Some(ValueKind::Map(map)) => Value::Object(object(&map.fields)),
+        Some(ValueKind::Array(array)) => Value::Array(array.items.iter().map(to_json).collect()),
+    }
+}
+
+fn object(fields: &HashMap<String, VectorValue>) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|(key, value)| (key.clone(), to_json(value)))
+        .collect()
+}
+
+/// Render a log event as a JSON line. A log whose value isn't an object, as a `message`.
+fn log_line(log: &Log) -> String {
+    let event = match &log.value {
+        Some(value) => match to_json(value) {
+            Value::Object(object) => object,
+            Value::String(message) => Map::from_iter([("message".to_string(), message.into())]),
+            other => Map::from_iter([("message".to_string(), other)]),
+        },
+        None => object(&log.fields),
+    };
+    Value::Object(event).to_string()
+}
+
+/// Decode the `native` codec: length-prefixed protobuf `EventWrapper`s
+fn decode_native(mut body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let mut lines = Vec::new();
+    while !body.is_empty() {
+        let Some((prefix, rest)) = body.split_first_chunk::<LENGTH_PREFIX_SIZE>() else {
+            return Err("Body ends partway through a length prefix");
+        };
+        let length = u32::from_be_bytes(*prefix) as usize;
+        let Some((event, rest)) = rest.split_at_checked(length) else {
+            return Err("Body ends partway through an event");
+        };
+        let event = EventWrapper::decode(event).map_err(|_| "Body is not Vector native events")?;
+        if let Some(Event::Log(log)) = &event.event {
+            lines.push(log_line(log));
+        }
+        body = rest;
+    }
+    Ok(lines)
+}
+
+/// Decode the `native_json` codec, framed as a JSON array or as one event per line
+fn decode_native_json(body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let events: Vec<Value> = match serde_json::from_slice(body) {
+        Ok(Value::Array(events)) => events,
+        Ok(event @ Value::Object(_)) => vec![event],
+        _ => {
+            let text = std::str::from_utf8(body).map_err(|_| "Body is not valid UTF-8")?;
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()
+                .map_err(|_| "Body is not Vector native JSON events")?
+        }
+    };
+
+    let mut lines = Vec::new();
+    for event in events {
+        let Value::Object(mut event) = event else {
+            return Err("Body is not Vector native JSON events");
+        };
+        match event.remove("log") {
+            Some(Value::Object(log)) => lines.push(Value::Object(log).to_string()),
+            Some(Value::String(message)) => {
+                let log = Map::from_iter([("message".to_string(), message.into())]);
+                lines.push(Value::Object(log).to_string());
+            }
+            Some(_) => return Err("Body is not Vector native JSON events"),
+            // Metrics and traces
+            None => {}
+        }
+    }
+    Ok(lines)
+}
+
+/// Pick the decoder from the content type Vector sends for each codec, looking at the
+/// body when there isn't one
+fn decode(headers: &HeaderMap, body: &[u8]) -> Result<Vec<String>, &'static str> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let json = match content_type {
+        "" => matches!(body.first(), Some(b'[' | b'{')),
+        content_type => content_type.contains("json"),
+    };
+    match json {
+        true => decode_native_json(body),
+        false => decode_native(body),
+    }
+}
+
+/// `POST /{bucket_id}/vector`: events from a Vector `http` sink using the `native` or
+/// `native_json` codec, so no remapping is needed to send to a bucket
+pub async fn post_events(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if ids::is_reserved(&bucket_id) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if body.len() > MAX_LOG_BODY_SIZE {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let token = match tokens::authorize(&state, &headers) {
+        Ok(token) => token,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    // The sink compresses bodies with its `compression` setting, zstd included
+    let encoding = match ContentEncoding::from_headers(&headers) {
+        Ok(encoding) => encoding,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let body = match encoding.decode(&body, MAX_LOG_BODY_SIZE) {
+        Ok(body) => body,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let lines = match decode(&headers, &body) {
+        Ok(lines) => lines,
+        Err(message) => return Ok((StatusCode::BAD_REQUEST, message).into_response()),
+    };
+    if let Err(rejection) = check_event_count(&state, lines.len(), "lower the batch size") {
+        return Ok(rejection.into_response());
+    }
+
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+    let Some(channel) = channel else {
+        warn!(
+            "Discarding Vector events for bucket with no viewers: {}",
+            bucket_id
+        );
+        return Ok(StatusCode::OK.into_response());
+    };
+
+    if channel.is_suspended() {
+        return Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response());
+    }
+    if lines.is_empty() {
+        return Ok(StatusCode::OK.into_response());
+    }
+
+    info!(
+        "New Vector events for bucket {}: {} events",
+        bucket_id,
+        lines.len()
+    );
+    if let Some(token) = &token {
+        let now = chrono::Utc::now();
+        token.record(lines.len() as u64, body.len() as u64, now);
+        token.associate(&bucket_id, BucketRelation::Written, now.timestamp_millis());
+    }
+
+    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+    match ingest_lines(&state, &channel, &lines).await {
+        IngestOutcome::Accepted => Ok(StatusCode::OK.into_response()),
+        IngestOutcome::Suspended => {
+            Ok((StatusCode::TOO_MANY_REQUESTS, SUSPENSION_REASON_TEXT).into_response())
+        }
+        IngestOutcome::Paused => Ok((StatusCode::SERVICE_UNAVAILABLE, PAUSED_TEXT).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(text: &str) -> VectorValue {
+        VectorValue {
+            kind: Some(ValueKind::RawBytes(text.as_bytes().to_vec())),
+        }
+    }
+
+    fn frame(event: &EventWrapper) -> Vec<u8> {
+        let event = event.encode_to_vec();
+        let mut frame = (event.len() as u32).to_be_bytes().to_vec();
+        frame.extend(event);
+        frame
+    }
+
+    #[test]
+    fn test_decode_native() {
+        let fields = HashMap::from([
+            ("message".to_string(), bytes("disk full")),
+            (
+                "timestamp".to_string(),
+                VectorValue {
+                    kind: Some(ValueKind::Timestamp(Timestamp {
+                        seconds: 1_700_000_000,
+                        nanos: 500_000_000,
+                    })),
+                },
+            ),
+            (
+                "tags".to_string(),
+                VectorValue {
+                    kind: Some(ValueKind::Array(ValueArray {
+                        items: vec![
+                            bytes("a"),
+                            VectorValue {
+                                kind: Some(ValueKind::Integer(2)),
+                            },
+                        ],
+                    })),
+                },
+            ),
+        ]);
+        let log = EventWrapper {
+            event: Some(Event::Log(Log {
+                fields: HashMap::new(),
+                value: Some(VectorValue {
+                    kind: Some(ValueKind::Map(ValueMap { fields })),
+                }),
+            })),
+        };
+        let legacy = EventWrapper {
+            event: Some(Event::Log(Log {
+                fields: HashMap::from([("message".to_string(), bytes("old"))]),
+                value: None,
+            })),
+        };
+        let metric = EventWrapper { event: None };
+
+        let mut body = frame(&log);
+        body.extend(frame(&metric));
+        body.extend(frame(&legacy));
+        let lines = decode_native(&body).unwrap();
+        assert_eq!(lines.len(), 2);
+
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["message"], "disk full");
+        assert_eq!(first["timestamp"], "2023-11-14T22:13:20.500Z");
+        assert_eq!(first["tags"], serde_json::json!(["a", 2]));
+        assert_eq!(lines[1], r#"{"message":"old"}"#);
+
+        assert!(decode_native(&body[..body.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_native_json() {
+        let array = br#"[{"log":{"message":"one","host":"web-1"}},{"metric":{"name":"up"}}]"#;
+        assert_eq!(
+            decode_native_json(array).unwrap(),
+            [r#"{"host":"web-1","message":"one"}"#]
+        );
+
+        let ndjson = b"{\"log\":{\"message\":\"one\"}}\n{\"log\":{\"message\":\"two\"}}\n";
+        assert_eq!(decode_native_json(ndjson).unwrap().len(), 2);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(decode(&headers, ndjson).unwrap().len(), 2);
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        assert!(decode(&headers, b"[1]").is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::changes;
+use crate::demo::DEMO_BUCKET_ID;
+use crate::import::check_url;
+use crate::integrations::post_to_public_host;
+use crate::tokens;
+use crate::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{info, warn};
+
+const MAX_WEBHOOKS_PER_BUCKET: usize = 5;
+
+/// Bucket lifecycle events that can trigger a webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Suspended,
+    Unsuspended,
+    Expired,
+    FirstSubscriber,
+    /// A heartbeat alert rule saw too few events in its window
+    HeartbeatMissed,
+}
+
+const ALL_EVENTS: &[WebhookEvent] = &[
+    WebhookEvent::Suspended,
+    WebhookEvent::Unsuspended,
+    WebhookEvent::Expired,
+    WebhookEvent::FirstSubscriber,
+    WebhookEvent::HeartbeatMissed,
+];
+
+fn all_events() -> Vec<WebhookEvent> {
+    ALL_EVENTS.to_vec()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    /// Events this webhook is interested in; defaults to all of them
+    #[serde(default = "all_events")]
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookList {
+    pub webhooks: Vec<Webhook>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    event: WebhookEvent,
+    bucket: &'a str,
+    timestamp: i64,
+}
+
+/// Fire-and-forget delivery of an event to every webhook subscribed to it
+pub fn deliver(bucket_id: &str, webhooks: &[Webhook], event: WebhookEvent) {
+    let payload = serde_json::to_string(&WebhookPayload {
+        event,
+        bucket: bucket_id,
+        timestamp: chrono::Utc::now().timestamp_millis(),
+    })
+    .unwrap();
+
+    for webhook in webhooks.iter().filter(|w| w.events.contains(&event)) {
+        let url = webhook.url.clone();
+        let bucket = bucket_id.to_string();
+        let payload = payload.clone();
+        tokio::spawn(async move {
+            match post_to_public_host(&url, payload).await {
+                Ok(()) => info!("Delivered {:?} webhook for bucket {}", event, bucket),
+                Err(e) => warn!(
+                    "Failed to deliver {:?} webhook for bucket {}: {}",
+                    event, bucket, e
+                ),
+            }
+        });
+    }
+}
+
+pub fn is_valid_webhook_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "https" | "http"))
+}
+
+/// A bucket's webhooks, without their URLs, which often embed a secret
+pub async fn get_webhooks(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, StatusCode> {
+    let channel = {
+        let manager = state.channel_manager.read().await;
+        manager.get_channel(&bucket_id)
+    };
+
+    let webhooks = match channel {
+        Some(channel) => channel.webhooks().await,
+        None => Vec::new(),
+    };
+
+    Ok(Json(changes::redacted(&WebhookList { webhooks })))
+}
+
+/// Check a bucket's webhooks before they replace its current ones
+pub fn validate_webhooks(webhooks: &[Webhook]) -> Result<(), String> {
+    if webhooks.len() > MAX_WEBHOOKS_PER_BUCKET {
+        return Err(format!(
+            "A bucket can have at most {} webhooks",
+            MAX_WEBHOOKS_PER_BUCKET
+        ));
+    }
+
+    if !webhooks.iter().all(|w| is_valid_webhook_url(&w.url)) {
+        return Err("Webhook URLs must be absolute http or https URLs".to_string());
+    }
+
+    Ok(())
+}
+
+/// Check that every webhook's host is on the public internet, so deliveries can't reach
+/// the server's own network. Delivery checks again, as the host may since resolve elsewhere.
+pub async fn check_webhook_hosts(webhooks: &[Webhook]) -> Result<(), String> {
+    for webhook in webhooks {
+        check_url(&webhook.url)
+            .await
+            .map_err(|e| format!("Webhook URL is not allowed: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Replace the set of webhooks registered for a bucket
+pub async fn put_webhooks(
+    Path(bucket_id): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(list): Json<WebhookList>,
+) -> Result<Response, StatusCode> {
+    if bucket_id == DEMO_BUCKET_ID {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let Err(rejection) = tokens::authorize(&state, &headers) {
+        return Ok(rejection.into_response());
+    }
+
+    if let Err(message) = validate_webhooks(&list.webhooks) {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    if let Err(message) = check_webhook_hosts(&list.webhooks).await {
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+
+    let channel = {
+        let mut manager = state.channel_manager.write().await;
+        manager.get_or_create_channel(&bucket_id)
+    };
+
+    info!(
+        "Registered {} webhooks for bucket {}",
+        list.webhooks.len(),
+        bucket_id
+    );
+    let previous = channel.set_webhooks(list.webhooks.clone()).await;
+    channel.publish_config_change(
+        "webhooks",
+        &previous,
+        &list.webhooks,
+        changes::actor(&state, &headers),
+    );
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webhook_events_default_to_all() {
+        let list: WebhookList =
+            serde_json::from_str(r#"{"webhooks":[{"url":"https://example.com/hook"}]}"#).unwrap();
+        assert_eq!(list.webhooks[0].events, ALL_EVENTS);
+
+        let list: WebhookList = serde_json::from_str(
+            r#"{"webhooks":[{"url":"https://example.com/hook","events":["first_subscriber"]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(list.webhooks[0].events, [WebhookEvent::FirstSubscriber]);
+    }
+
+    #[test]
+    fn test_webhook_url_validation() {
+        assert!(is_valid_webhook_url("https://example.com/hook"));
+        assert!(!is_valid_webhook_url("ftp://example.com/hook"));
+        assert!(!is_valid_webhook_url("/relative"));
+    }
+
+    #[tokio::test]
+    async fn test_check_webhook_hosts() {
+        let webhook = |url: &str| Webhook {
+            url: url.to_string(),
+            events: all_events(),
+        };
+        assert!(
+            check_webhook_hosts(&[webhook("https://93.184.216.34/hook")])
+                .await
+                .is_ok()
+        );
+        assert!(
+            check_webhook_hosts(&[webhook("http://127.0.0.1:8080/hook")])
+                .await
+                .is_err()
+        );
+        assert!(
+            check_webhook_hosts(&[webhook("http://169.254.169.254/latest/meta-data")])
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,26 @@
+use log_bin::testing::assert_golden;
+use std::path::PathBuf;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/parsers")
+        .join(name)
+}
+
+#[test]
+fn test_parser_golden_files() {
+    for name in [
+        "json",
+        "syslog",
+        "structured_headers",
+        "nginx_error",
+        "haproxy",
+        "aws",
+        "logcat",
+    ] {
+        assert_golden(
+            &fixture(&format!("{}.log", name)),
+            &fixture(&format!("{}.json", name)),
+        );
+    }
+}
@@ -0,0 +1,693 @@
+use log_bin::testing::{TestServer, DEFAULT_TIMEOUT};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_posted_lines_reach_subscribers() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-01").await;
+
+    let status = server
+        .post_lines(
+            "harness-bucket-01",
+            &[r#"{"msg":"hello","level":"info"}"#, "plain line"],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0]["parser"], "json");
+    assert_eq!(logs[0]["fields"]["msg"]["value"], "hello");
+    assert_eq!(logs[1]["raw"], "plain line");
+    assert!(logs[1]["seq"].as_u64() > logs[0]["seq"].as_u64());
+
+    // Nothing else was posted
+    assert!(stream.logs(1, Duration::from_millis(200)).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_demo_bucket_is_read_only() {
+    let server = TestServer::with_env(&[("API_ONLY", "1")]).await;
+    let status = server.post_lines("demo", &["nope"]).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_json_array_body_is_one_event_per_element() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-02").await;
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-02"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(r#"[{"msg":"first"},{"msg":"second","n":2}]"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0]["fields"]["msg"]["value"], "first");
+    assert_eq!(logs[1]["fields"]["n"]["value"], "2");
+}
+
+#[tokio::test]
+async fn test_multipart_upload_publishes_file_in_order() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-03").await;
+
+    let file: Vec<String> = (1..=100).map(|n| format!("line {}", n)).collect();
+    let body = format!(
+        "--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"app.log\"\r\n\r\n{}\r\n--XYZ--\r\n",
+        file.join("\n")
+    );
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-03"))
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "multipart/form-data; boundary=XYZ",
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    let logs = stream.logs(100, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 100);
+    for (log, line) in logs.iter().zip(&file) {
+        assert_eq!(&log["raw"], line.as_str());
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_utf8_lines_come_through_flagged() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-04").await;
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-04"))
+        .header(reqwest::header::CONTENT_TYPE, "text/plain")
+        .body(&b"sensor=\xff temp=21\nclean line"[..])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0]["raw"], "sensor=\u{fffd} temp=21");
+    assert_eq!(logs[0]["hadInvalidUtf8"], true);
+    assert!(logs[1].get("hadInvalidUtf8").is_none());
+}
+
+#[tokio::test]
+async fn test_w3c_fields_directive_names_later_lines() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-05").await;
+
+    let status = server
+        .post_lines(
+            "harness-bucket-05",
+            &[
+                "#Version: 1.0",
+                "#Fields: date time cs-method cs-uri-stem cs-uri-query sc-status",
+            ],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // The columns carry over to later requests
+    let status = server
+        .post_lines(
+            "harness-bucket-05",
+            &["2024-01-01 12:00:00 GET /search q=logs 200"],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(3, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 3);
+    assert_eq!(logs[1]["fields"]["directive"]["value"], "Fields");
+    assert_eq!(logs[2]["parser"], "w3c");
+    assert_eq!(logs[2]["fields"]["cs-uri-stem"]["value"], "/search");
+    assert_eq!(logs[2]["fields"]["sc-status"]["value"], "200");
+    assert_eq!(logs[2]["reportedTime"], 1_704_110_400_000i64);
+}
+
+#[tokio::test]
+async fn test_csv_header_row_names_later_rows() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-06").await;
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-06"))
+        .header(reqwest::header::CONTENT_TYPE, "text/csv")
+        .body("time;level;message\n2024-01-01T12:00:00Z;warn;\"disk; 91% full\"\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    // The header isn't an event, and its columns carry over to later requests
+    let status = server
+        .post_lines(
+            "harness-bucket-06",
+            &["2024-01-01T12:00:05Z;info;cleaned up"],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0]["parser"], "csv");
+    assert_eq!(logs[0]["fields"]["message"]["value"], "disk; 91% full");
+    assert_eq!(logs[1]["parser"], "csv");
+    assert_eq!(logs[1]["fields"]["level"]["value"], "info");
+}
+
+#[tokio::test]
+async fn test_multiline_bucket_folds_stack_traces() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-07").await;
+
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-07/settings"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(r#"{"multiline":true}"#)
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let status = server
+        .post_lines(
+            "harness-bucket-07",
+            &[
+                "ERROR Request failed",
+                "java.lang.IllegalStateException: closed",
+                "\tat com.example.Db.query(Db.java:42)",
+            ],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    // The trace carries on into the next post
+    let status = server
+        .post_lines(
+            "harness-bucket-07",
+            &["\tat com.example.Main.main(Main.java:7)", "INFO Retrying"],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(3, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 3);
+    assert_eq!(logs[0]["raw"], "ERROR Request failed");
+    assert_eq!(
+        logs[1]["raw"],
+        "java.lang.IllegalStateException: closed\n\tat com.example.Db.query(Db.java:42)\n\tat com.example.Main.main(Main.java:7)"
+    );
+    // The last event is published once nothing continues it
+    assert_eq!(logs[2]["raw"], "INFO Retrying");
+}
+
+#[tokio::test]
+async fn test_ansi_colors_are_stripped_and_suggest_a_level() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-08").await;
+
+    let colored = "\u{1b}[2m12:00:00\u{1b}[0m \u{1b}[31mconnection refused\u{1b}[0m";
+    let status = server
+        .post_lines(
+            "harness-bucket-08",
+            &[colored, "\u{1b}[33mlevel=info\u{1b}[0m"],
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0]["raw"], "12:00:00 connection refused");
+    assert_eq!(logs[0]["rawAnsi"], colored);
+    assert_eq!(logs[0]["fields"]["level"]["value"], "error");
+    // A level the line spells out wins over its color
+    assert_eq!(logs[1]["fields"]["level"]["value"], "info");
+}
+
+#[tokio::test]
+async fn test_grok_parser_reads_bespoke_lines() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-09").await;
+
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-09/grok"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(
+            r#"{"patterns": {"ORDER": "ord-[0-9]+"}, "parsers": [{"name": "orders", "pattern": "^%{WORD:action} %{ORDER:order} in %{NUMBER:ms}ms$"}]}"#,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let status = server
+        .post_lines("harness-bucket-09", &["shipped ord-42 in 18ms"])
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(1, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs[0]["parser"], "orders");
+    assert_eq!(logs[0]["fields"]["order"]["value"], "ord-42");
+    assert_eq!(logs[0]["fields"]["ms"]["value"], "18");
+
+    // Patterns that don't compile are turned away
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-09/grok"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(r#"{"parsers": [{"name": "broken", "pattern": "%{NOPE}"}]}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_log_pattern_header_registers_a_parser() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-10").await;
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-10"))
+        .header(reqwest::header::CONTENT_TYPE, "text/plain")
+        .header("X-Log-Pattern", r"^(?P<user>\w+) paid (?P<amount>[0-9.]+)$")
+        .body("alice paid 12.50")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    // Later posts are read with the pattern without sending it again
+    let status = server
+        .post_lines("harness-bucket-10", &["bob paid 3"])
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = stream.logs(2, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs[0]["parser"], "pattern");
+    assert_eq!(logs[0]["fields"]["amount"]["value"], "12.50");
+    assert_eq!(logs[1]["fields"]["user"]["value"], "bob");
+
+    let parser: serde_json::Value = server
+        .client()
+        .get(server.url("/api/v1/buckets/harness-bucket-10/parser"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        parser["pattern"],
+        r"^(?P<user>\w+) paid (?P<amount>[0-9.]+)$"
+    );
+
+    // A pattern without named groups has no fields to give
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-10/parser"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(r#"{"pattern": "paid"}"#)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_replay_publishes_within_the_rate_limit() {
+    let server = TestServer::start().await;
+    let mut stream = server.subscribe("harness-bucket-11").await;
+
+    let file: Vec<String> = (0..600).map(|n| format!("replayed line {}", n)).collect();
+    let body = format!(
+        "--XYZ\r\nContent-Disposition: form-data; name=\"file\"; filename=\"app.log\"\r\n\r\n{}\r\n--XYZ--\r\n",
+        file.join("\n")
+    );
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-11/replay"))
+        .header(
+            reqwest::header::CONTENT_TYPE,
+            "multipart/form-data; boundary=XYZ",
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+    // The file is more than a minute's worth, so only part of it goes out now and the
+    // bucket stays up
+    let logs = stream.logs(600, Duration::from_secs(2)).await;
+    assert!(!logs.is_empty() && logs.len() < 600);
+    assert_eq!(logs[0]["raw"], "replayed line 0");
+
+    // Files can't be fetched from the server's own network
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-11/replay?url=http://127.0.0.1:9/app.log"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_cross_origin_config_writes_are_refused() {
+    let server = TestServer::start().await;
+
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-12/settings"))
+        .header("Origin", "https://evil.example")
+        .json(&serde_json::json!({"multiline": true}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Ingest stays open to any origin when no origin list is configured
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-12"))
+        .header("Origin", "https://evil.example")
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn test_every_ingest_endpoint_caps_events_per_request() {
+    let server = TestServer::with_env(&[("MAX_EVENTS_PER_REQUEST", "2")]).await;
+    let mut stream = server.subscribe("harness-bucket-13").await;
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-13/log"))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("m=one&m=two&m=three")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = server
+        .client()
+        .post(server.url("/harness-bucket-13/gelf"))
+        .body("{\"short_message\":\"one\"}\n{\"short_message\":\"two\"}\n{\"short_message\":\"three\"}")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Nothing from the refused requests was published
+    assert!(stream.logs(1, Duration::from_millis(200)).await.is_empty());
+
+    let status = server
+        .post_lines("harness-bucket-13", &["one", "two"])
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(stream.logs(2, DEFAULT_TIMEOUT).await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_journal_upload_streams_past_the_request_timeout() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let server = TestServer::with_env(&[("REQUEST_TIMEOUT", "1")]).await;
+    let mut stream = server.subscribe("harness-bucket-14").await;
+
+    // reqwest can't stream a request body here, so speak chunked HTTP/1.1 by hand
+    let mut socket = tokio::net::TcpStream::connect(server.addr()).await.unwrap();
+    socket
+        .write_all(
+            b"POST /harness-bucket-14/upload HTTP/1.1\r\nHost: localhost\r\n\
+              Content-Type: application/vnd.fdo.journal\r\nTransfer-Encoding: chunked\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    for message in ["first", "second", "third"] {
+        let entry = format!("MESSAGE={}\n\n", message);
+        let chunk = format!("{:x}\r\n{}\r\n", entry.len(), entry);
+        socket.write_all(chunk.as_bytes()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(600)).await;
+    }
+    socket.write_all(b"0\r\n\r\n").await.unwrap();
+
+    let mut response = vec![0; 64];
+    let read = socket.read(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response[..read]).to_string();
+    assert!(response.starts_with("HTTP/1.1 202"), "{}", response);
+
+    let logs = stream.logs(3, DEFAULT_TIMEOUT).await;
+    let messages: Vec<&str> = logs
+        .iter()
+        .map(|log| log["fields"]["message"]["value"].as_str().unwrap())
+        .collect();
+    assert_eq!(messages, vec!["first", "second", "third"]);
+}
+
+#[tokio::test]
+async fn test_host_principal_stands_in_for_a_write_token() {
+    use axum::{extract::Request, middleware::Next};
+    use log_bin::{config::Config, Principal};
+
+    // The host's own auth middleware, vouching for whoever it authenticated
+    let app = log_bin::build_router(Config::default()).layer(axum::middleware::from_fn(
+        |mut request: Request, next: Next| async move {
+            let user = request
+                .headers()
+                .get("x-host-user")
+                .and_then(|value| value.to_str().ok())
+                .map(Principal::new);
+            if let Some(user) = user {
+                request.extensions_mut().insert(user);
+            }
+            next.run(request).await
+        },
+    ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+        )
+        .await
+    });
+    let client = reqwest::Client::builder().no_proxy().build().unwrap();
+    let url = |path: &str| format!("http://{}{}", addr, path);
+    let _subscription = client
+        .get(url("/harness-bucket-15"))
+        .header("Accept", "text/event-stream")
+        .send()
+        .await
+        .unwrap();
+
+    let response = client
+        .post(url("/harness-bucket-15"))
+        .header("x-host-user", "alice")
+        .body("one\ntwo")
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    let mine: serde_json::Value = client
+        .get(url("/api/v1/my/buckets"))
+        .header("x-host-user", "alice")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(mine["token"], "alice");
+    assert_eq!(mine["buckets"][0]["bucket"], "harness-bucket-15");
+    assert_eq!(mine["buckets"][0]["written"], true);
+
+    let usage: serde_json::Value = client
+        .get(url("/api/v1/tokens/alice/usage"))
+        .header("x-host-user", "alice")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(usage["daily"]["events"], 2);
+
+    // Someone else can't read alice's usage, and without a principal there's no one to ask about
+    let response = client
+        .get(url("/api/v1/tokens/alice/usage"))
+        .header("x-host-user", "bob")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = client.get(url("/api/v1/my/buckets")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+/// A server that only accepts writes made with the token `secret`
+async fn server_requiring_token(name: &str) -> TestServer {
+    let path = std::env::temp_dir().join(format!("log-bin-{}-{}.json", name, std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"tokens": [{"id": "team-a", "token": "secret"}]}"#,
+    )
+    .unwrap();
+    TestServer::with_env(&[
+        ("TOKENS_FILE", path.to_str().unwrap()),
+        ("REQUIRE_WRITE_TOKEN", "1"),
+    ])
+    .await
+}
+
+#[tokio::test]
+async fn test_deleting_events_needs_a_write_token() {
+    let server = server_requiring_token("delete-events").await;
+    let mut stream = server.subscribe("harness-bucket-16").await;
+    let status = server
+        .client()
+        .post(server.url("/harness-bucket-16"))
+        .bearer_auth("secret")
+        .body("keep me")
+        .send()
+        .await
+        .unwrap()
+        .status();
+    assert!(status.is_success());
+    assert_eq!(stream.logs(1, DEFAULT_TIMEOUT).await.len(), 1);
+
+    let delete = |token: Option<&str>| {
+        let request = server
+            .client()
+            .delete(server.url("/api/v1/buckets/harness-bucket-16/events?from=1&to=1"));
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    };
+    let response = delete(None).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = delete(Some("wrong")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = delete(Some("secret")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let redaction: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(redaction["count"], 1);
+}
+
+#[tokio::test]
+async fn test_servers_route_within_their_own_buckets() {
+    // Started first, so a server-wide routing table would point here
+    let _other = TestServer::start().await;
+    let server = TestServer::start().await;
+    let mut errors = server.subscribe("harness-bucket-18").await;
+    let _source = server.subscribe("harness-bucket-17").await;
+
+    let response = server
+        .client()
+        .put(server.url("/api/v1/buckets/harness-bucket-17/routes"))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(
+            r#"{"routes": [{"field": "level", "value": "error", "target": "harness-bucket-18"}]}"#,
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let status = server
+        .post_lines("harness-bucket-17", &[r#"{"level":"error","msg":"boom"}"#])
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let logs = errors.logs(1, DEFAULT_TIMEOUT).await;
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0]["fields"]["msg"]["value"], "boom");
+}
+
+#[tokio::test]
+async fn test_bucket_configuration_needs_a_write_token() {
+    let server = server_requiring_token("configure").await;
+    let bucket = "/api/v1/buckets/harness-bucket-19";
+    let writes: &[(reqwest::Method, &str, &str)] = &[
+        (reqwest::Method::PUT, "", "{}"),
+        (reqwest::Method::PUT, "/settings", "{}"),
+        (reqwest::Method::PUT, "/alerts", r#"{"rules": []}"#),
+        (reqwest::Method::PUT, "/routes", r#"{"routes": []}"#),
+        (reqwest::Method::PUT, "/webhooks", r#"{"webhooks": []}"#),
+        (reqwest::Method::PUT, "/grok", "{}"),
+        (
+            reqwest::Method::PUT,
+            "/parser",
+            r#"{"pattern": "%{WORD:word}"}"#,
+        ),
+        (reqwest::Method::DELETE, "/parser", ""),
+        (
+            reqwest::Method::PUT,
+            "/integrations/slack",
+            r#"{"webhook_url": "https://93.184.216.34/hook"}"#,
+        ),
+        (reqwest::Method::DELETE, "/integrations/slack", ""),
+        (
+            reqwest::Method::PUT,
+            "/integrations/pagerduty",
+            r#"{"routing_key": "key"}"#,
+        ),
+        (reqwest::Method::DELETE, "/integrations/pagerduty", ""),
+        (reqwest::Method::DELETE, "/ingest-urls/unknown", ""),
+    ];
+
+    for (method, path, body) in writes {
+        let request = |token: Option<&str>| {
+            let request = server
+                .client()
+                .request(method.clone(), server.url(&format!("{}{}", bucket, path)))
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        };
+        let response = request(None).send().await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{} {}",
+            method,
+            path
+        );
+        let response = request(Some("secret")).send().await.unwrap();
+        assert_ne!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{} {}",
+            method,
+            path
+        );
+    }
+}